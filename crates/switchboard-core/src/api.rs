@@ -0,0 +1,438 @@
+//! Embedded HTTP API so other tools can list commands, start runs and
+//! follow them. Every request needs the configured token, either as
+//! `Authorization: Bearer <token>` or as a `token` query parameter (for
+//! `EventSource`, which can't set headers).
+//!
+//! - `GET  /api/commands`, `/api/workflows`, `/api/hosts`
+//! - `GET  /api/commands/{id}/runs`: the command's run history
+//! - `POST /api/commands/{id}/runs` with `{"env": {..}, "password": ..}`
+//! - `GET  /api/workflows/{id}/runs`: the workflow's run history
+//! - `POST /api/workflows/{id}/runs`: start all its steps, same body
+//! - `GET  /api/runs/{id}?since=N&wait=SECS`: record plus updates from N on
+//! - `GET  /api/runs/{id}/events?since=N`: the same updates as Server-Sent Events
+//! - `POST /api/runs/{id}/kill`
+//! - `GET  /api/runs/{id}/log`: output as plain text
+
+use crate::models::{ApiSettings, ExecutionUpdate, HostKind};
+use crate::runs::{RunError, RunManager, RunRequest};
+use serde_json::{Value, json};
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use thiserror::Error;
+use tiny_http::{Header, Method, Request, Response, Server};
+use uuid::Uuid;
+
+/// Longest a poll may ask to wait for news.
+const MAX_WAIT: Duration = Duration::from_secs(30);
+/// How often an idle event stream sends a comment, which also notices
+/// callers that went away.
+const KEEPALIVE: Duration = Duration::from_secs(15);
+const MAX_BODY: u64 = 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("No API token is set")]
+    NoToken,
+    #[error("Could not listen on {0}: {1}")]
+    Bind(String, String),
+}
+
+/// A running API server; stops when dropped.
+pub struct ApiServer {
+    server: Arc<Server>,
+    addr: SocketAddr,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ApiServer {
+    pub fn start(settings: &ApiSettings, runs: RunManager) -> Result<Self, ApiError> {
+        if settings.token.is_empty() {
+            return Err(ApiError::NoToken);
+        }
+        let server = Server::http(&settings.bind)
+            .map_err(|e| ApiError::Bind(settings.bind.clone(), e.to_string()))?;
+        let addr = server.server_addr().to_ip().ok_or_else(|| {
+            ApiError::Bind(settings.bind.clone(), "not an IP address".to_string())
+        })?;
+        let server = Arc::new(server);
+
+        let token = settings.token.clone();
+        let listener = server.clone();
+        let thread = std::thread::spawn(move || {
+            for request in listener.incoming_requests() {
+                let runs = runs.clone();
+                let token = token.clone();
+                // Event streams stay open, so each request gets a thread
+                std::thread::spawn(move || handle(request, &runs, &token));
+            }
+        });
+
+        Ok(ApiServer {
+            server,
+            addr,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Undo a query string's encoding: `+` for spaces and `%XX` escapes.
+/// Malformed escapes are kept as they are.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Split `url` into path segments and decoded query parameters.
+fn parse_url(url: &str) -> (Vec<&str>, Vec<(String, String)>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments = path.split('/').filter(|s| !s.is_empty()).collect();
+    let params = query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (key, value) = p.split_once('=').unwrap_or((p, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+    (segments, params)
+}
+
+/// Compare without bailing out at the first difference.
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn is_authorized(request: &Request, params: &[(String, String)], token: &str) -> bool {
+    let bearer = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| h.value.as_str().strip_prefix("Bearer "));
+    let query = params
+        .iter()
+        .find(|(k, _)| k == "token")
+        .map(|(_, v)| v.as_str());
+    bearer
+        .or(query)
+        .is_some_and(|given| token_matches(given.trim(), token))
+}
+
+/// A `wait` parameter in seconds, capped at `MAX_WAIT`; `None` unless it's
+/// a number of seconds that isn't negative.
+fn parse_wait(value: &str) -> Option<Duration> {
+    let seconds: f64 = value.parse().ok()?;
+    if !seconds.is_finite() {
+        return None;
+    }
+    Duration::try_from_secs_f64(seconds.min(MAX_WAIT.as_secs_f64())).ok()
+}
+
+fn json_response(status: u16, body: &Value) -> Response<std::io::Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(header)
+}
+
+fn error(status: u16, message: impl std::fmt::Display) -> Response<std::io::Cursor<Vec<u8>>> {
+    json_response(status, &json!({ "error": message.to_string() }))
+}
+
+/// A start request's body; an empty one asks for the defaults.
+fn read_run_request(
+    request: &mut Request,
+) -> Result<RunRequest, Response<std::io::Cursor<Vec<u8>>>> {
+    let mut body = String::new();
+    if request
+        .as_reader()
+        .take(MAX_BODY)
+        .read_to_string(&mut body)
+        .is_err()
+    {
+        return Err(error(400, "Could not read the request body"));
+    }
+    if body.trim().is_empty() {
+        return Ok(RunRequest::default());
+    }
+    serde_json::from_str(&body).map_err(|e| error(400, format!("Invalid request body: {}", e)))
+}
+
+fn run_error(e: RunError) -> Response<std::io::Cursor<Vec<u8>>> {
+    match e {
+        RunError::UnknownCommand(_) | RunError::UnknownWorkflow(_) => error(404, e),
+        RunError::MissingParameters(_) | RunError::PasswordRequired => error(400, e),
+        RunError::Execute(_) => error(500, e),
+    }
+}
+
+/// An update as `(event name, JSON payload)`.
+fn update_json(update: &ExecutionUpdate) -> (&'static str, Value) {
+    match update {
+        ExecutionUpdate::Started(command_id) => ("started", json!({ "command_id": command_id })),
+        ExecutionUpdate::Stdout(text) => ("stdout", json!({ "text": text })),
+        ExecutionUpdate::Stderr(text) => ("stderr", json!({ "text": text })),
+        ExecutionUpdate::Detached(job) => ("detached", json!({ "pid": job.pid })),
+        ExecutionUpdate::Artifact { name, .. } => ("artifact", json!({ "name": name })),
+        ExecutionUpdate::Exit(code) => ("exit", json!({ "code": code })),
+    }
+}
+
+fn handle(mut request: Request, runs: &RunManager, token: &str) {
+    let url = request.url().to_string();
+    let (segments, params) = parse_url(&url);
+    if !is_authorized(&request, &params, token) {
+        let _ = request.respond(error(401, "Missing or wrong API token"));
+        return;
+    }
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    };
+    let since = param("since").and_then(|v| v.parse().ok()).unwrap_or(0);
+
+    let store = runs.store();
+    let method = request.method().clone();
+    let response = match (&method, segments.as_slice()) {
+        (Method::Get, ["api", "commands"]) => {
+            let commands: Vec<Value> = store
+                .list_commands()
+                .iter()
+                .map(|c| {
+                    json!({
+                        "id": c.id,
+                        "name": c.name,
+                        "description": c.description,
+                        "background": c.background,
+                        // Defaults only; "ask" ones must be passed to run
+                        "parameters": c.env_vars.iter().map(|v| json!({
+                            "key": v.key,
+                            "default": v.value,
+                            "required": v.ask_user,
+                        })).collect::<Vec<_>>(),
+                        "needs_password": c.run_as.as_ref().is_some_and(|r| r.password == crate::models::EscalationPassword::Prompt),
+                    })
+                })
+                .collect();
+            json_response(200, &json!(commands))
+        }
+        (Method::Get, ["api", "workflows"]) => {
+            let workflows: Vec<Value> = store
+                .list_workflows()
+                .iter()
+                .map(|w| json!({ "id": w.id, "name": w.name, "description": w.description, "commands": w.commands }))
+                .collect();
+            json_response(200, &json!(workflows))
+        }
+        (Method::Get, ["api", "hosts"]) => {
+            let hosts: Vec<Value> = store
+                .list_hosts()
+                .iter()
+                .map(|h| {
+                    let kind = match h.kind {
+                        HostKind::Ssh => "ssh",
+                        HostKind::Container(_) => "container",
+                        HostKind::Kubernetes(_) => "kubernetes",
+                    };
+                    json!({
+                        "id": h.id,
+                        "name": h.name,
+                        "kind": kind,
+                        "hostname": h.hostname,
+                        "port": h.port,
+                        "username": h.username,
+                        "groups": h.groups,
+                        "tags": h.tags,
+                    })
+                })
+                .collect();
+            json_response(200, &json!(hosts))
+        }
+        (Method::Get, ["api", "commands", id, "runs"]) => match id.parse::<Uuid>() {
+            Ok(id) if store.get_command(&id).is_some() => {
+                json_response(200, &json!(store.get_execution_history(&id)))
+            }
+            _ => error(404, "No such command"),
+        },
+        (Method::Post, ["api", "commands", id, "runs"]) => {
+            let Ok(id) = id.parse::<Uuid>() else {
+                let _ = request.respond(error(404, "No such command"));
+                return;
+            };
+            match read_run_request(&mut request) {
+                Err(response) => response,
+                Ok(run_request) => match runs.start(id, run_request) {
+                    Ok(ids) => json_response(202, &json!({ "runs": ids })),
+                    Err(e) => run_error(e),
+                },
+            }
+        }
+        (Method::Get, ["api", "workflows", id, "runs"]) => match id.parse::<Uuid>() {
+            Ok(id) if store.get_workflow(&id).is_some() => {
+                // Ones started here also list their steps' runs
+                let live = runs.workflow_runs(&id);
+                let mut history: Vec<Value> = store
+                    .list_workflow_runs(&id)
+                    .iter()
+                    .filter(|r| !live.iter().any(|w| w.id == r.id))
+                    .map(|r| json!(r))
+                    .collect();
+                history.extend(live.iter().map(|w| json!(w)));
+                json_response(200, &json!(history))
+            }
+            _ => error(404, "No such workflow"),
+        },
+        (Method::Post, ["api", "workflows", id, "runs"]) => {
+            let Ok(id) = id.parse::<Uuid>() else {
+                let _ = request.respond(error(404, "No such workflow"));
+                return;
+            };
+            match read_run_request(&mut request) {
+                Err(response) => response,
+                Ok(run_request) => match runs.start_workflow(id, run_request) {
+                    Ok(run_id) => json_response(202, &json!({ "workflow_run": run_id })),
+                    Err(e) => run_error(e),
+                },
+            }
+        }
+        (Method::Get, ["api", "runs", id]) => {
+            let wait = match param("wait").map(|v| parse_wait(v).ok_or(v)).transpose() {
+                Ok(wait) => wait,
+                Err(v) => {
+                    let _ = request.respond(error(
+                        400,
+                        format!("Invalid wait \"{}\": expected seconds", v),
+                    ));
+                    return;
+                }
+            };
+            let progress = id
+                .parse::<Uuid>()
+                .ok()
+                .and_then(|id| runs.progress(&id, since, wait));
+            match progress {
+                Some(p) => json_response(
+                    200,
+                    &json!({
+                        "run": p.result,
+                        "updates": p.updates.iter().map(|u| {
+                            let (event, mut value) = update_json(u);
+                            value["type"] = json!(event);
+                            value
+                        }).collect::<Vec<_>>(),
+                        "next": p.next,
+                        "finished": p.finished,
+                    }),
+                ),
+                None => error(404, "No such run"),
+            }
+        }
+        (Method::Get, ["api", "runs", id, "events"]) => match id.parse::<Uuid>() {
+            Ok(id) if runs.progress(&id, 0, None).is_some() => {
+                stream_events(request, runs, id, since);
+                return;
+            }
+            _ => error(404, "No such run"),
+        },
+        (Method::Post, ["api", "runs", id, "kill"]) => match id.parse::<Uuid>() {
+            Ok(id) if runs.kill(&id) => json_response(202, &json!({ "killed": id })),
+            _ => error(404, "No such running run"),
+        },
+        (Method::Get, ["api", "runs", id, "log"]) => {
+            match id.parse::<Uuid>().ok().and_then(|id| runs.log(&id)) {
+                Some(log) => Response::from_string(log).with_header(
+                    Header::from_bytes("Content-Type", "text/plain; charset=utf-8").unwrap(),
+                ),
+                None => error(404, "No such run"),
+            }
+        }
+        (_, ["api", ..]) => error(405, "Unknown endpoint or method"),
+        _ => error(404, "Not found"),
+    };
+    let _ = request.respond(response);
+}
+
+/// Follow a run as Server-Sent Events until it finishes or the caller
+/// hangs up. Each event's id is the index to resume from.
+fn stream_events(request: Request, runs: &RunManager, exec_id: Uuid, mut since: usize) {
+    let mut writer = request.into_writer();
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+    if writer
+        .write_all(head.as_bytes())
+        .and_then(|_| writer.flush())
+        .is_err()
+    {
+        return;
+    }
+
+    loop {
+        let Some(progress) = runs.progress(&exec_id, since, Some(KEEPALIVE)) else {
+            return;
+        };
+        let mut chunk = String::new();
+        for (i, update) in progress.updates.iter().enumerate() {
+            let (event, value) = update_json(update);
+            chunk.push_str(&format!(
+                "id: {}\nevent: {}\ndata: {}\n\n",
+                since + i + 1,
+                event,
+                value
+            ));
+        }
+        if chunk.is_empty() {
+            chunk.push_str(": keepalive\n\n");
+        }
+        since = progress.next;
+        if writer
+            .write_all(chunk.as_bytes())
+            .and_then(|_| writer.flush())
+            .is_err()
+            || progress.finished
+        {
+            return;
+        }
+    }
+}
@@ -0,0 +1,381 @@
+#[cfg(test)]
+mod tests {
+    use crate::api::ApiServer;
+    use crate::executor::Executor;
+    use crate::models::{ApiSettings, Command, EnvVar, Workflow};
+    use crate::runs::RunManager;
+    use crate::store::CommandStore;
+    use serde_json::Value;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    const TOKEN: &str = "test+token/a=";
+
+    fn start(store: &CommandStore) -> ApiServer {
+        let settings = ApiSettings {
+            enabled: true,
+            bind: "127.0.0.1:0".to_string(),
+            token: TOKEN.to_string(),
+        };
+        ApiServer::start(
+            &settings,
+            RunManager::new(store.clone(), Arc::new(Executor)),
+        )
+        .unwrap()
+    }
+
+    fn add_command(store: &CommandStore, script: &str, env_vars: Vec<EnvVar>) -> Uuid {
+        store.add_command(Command {
+            env_vars,
+            ..Command::new("API test", script)
+        })
+    }
+
+    fn send(
+        addr: SocketAddr,
+        method: &str,
+        path: &str,
+        token: Option<&str>,
+        body: &str,
+    ) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let auth = token
+            .map(|t| format!("Authorization: Bearer {}\r\n", t))
+            .unwrap_or_default();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            path,
+            auth,
+            body.len(),
+            body
+        )
+        .unwrap();
+        stream
+    }
+
+    /// Make a request and return the status code and body.
+    fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut response = String::new();
+        send(addr, method, path, Some(TOKEN), body)
+            .read_to_string(&mut response)
+            .unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    fn json(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let (status, body) = request(addr, method, path, body);
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    fn start_run(addr: SocketAddr, command_id: Uuid, body: &str) -> Uuid {
+        let (status, response) = json(
+            addr,
+            "POST",
+            &format!("/api/commands/{}/runs", command_id),
+            body,
+        );
+        assert_eq!(status, 202, "{}", response);
+        response["runs"][0].as_str().unwrap().parse().unwrap()
+    }
+
+    /// Poll until the run finishes; returns the run record and every update.
+    fn wait_for(addr: SocketAddr, run_id: Uuid) -> (Value, Vec<Value>) {
+        let mut updates = Vec::new();
+        let mut next = 0;
+        for _ in 0..50 {
+            let (_, progress) = json(
+                addr,
+                "GET",
+                &format!("/api/runs/{}?since={}&wait=1", run_id, next),
+                "",
+            );
+            updates.extend(progress["updates"].as_array().unwrap().iter().cloned());
+            next = progress["next"].as_u64().unwrap();
+            if progress["finished"] == true {
+                return (progress["run"].clone(), updates);
+            }
+        }
+        panic!("run {} did not finish", run_id);
+    }
+
+    #[test]
+    fn test_requires_token() {
+        let store = CommandStore::new_test();
+        let server = start(&store);
+        for token in [None, Some("wrong")] {
+            let mut response = String::new();
+            send(server.addr(), "GET", "/api/commands", token, "")
+                .read_to_string(&mut response)
+                .unwrap();
+            assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+        }
+        let (status, _) = request(
+            server.addr(),
+            "GET",
+            "/api/hosts?token=test%2Btoken%2Fa%3D",
+            "",
+        );
+        assert_eq!(status, 200);
+        // A bare "+" in a query means a space
+        let (status, _) = request(
+            server.addr(),
+            "GET",
+            &format!("/api/hosts?token={}", TOKEN),
+            "",
+        );
+        assert_eq!(status, 200);
+        let mut response = String::new();
+        send(
+            server.addr(),
+            "GET",
+            "/api/hosts?token=test+token%2Fa%3D",
+            None,
+            "",
+        )
+        .read_to_string(&mut response)
+        .unwrap();
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+    }
+
+    #[test]
+    fn test_run_with_parameters() {
+        let store = CommandStore::new_test();
+        let server = start(&store);
+        let vars = vec![
+            EnvVar {
+                key: "GREETING".to_string(),
+                value: "hi".to_string(),
+                ask_user: false,
+            },
+            EnvVar {
+                key: "NAME".to_string(),
+                value: String::new(),
+                ask_user: true,
+            },
+        ];
+        let id = add_command(&store, "echo \"$GREETING $NAME\"", vars);
+
+        let (_, commands) = json(server.addr(), "GET", "/api/commands", "");
+        assert_eq!(commands[0]["id"], id.to_string());
+        assert_eq!(commands[0]["parameters"][1]["required"], true);
+
+        // "Ask" variables must be passed
+        let (status, response) = json(
+            server.addr(),
+            "POST",
+            &format!("/api/commands/{}/runs", id),
+            "{}",
+        );
+        assert_eq!(status, 400);
+        assert!(response["error"].as_str().unwrap().contains("NAME"));
+
+        let run_id = start_run(server.addr(), id, r#"{"env": {"NAME": "api"}}"#);
+        let (run, updates) = wait_for(server.addr(), run_id);
+        assert_eq!(run["exit_code"], 0);
+        assert_eq!(updates.first().unwrap()["type"], "started");
+        assert_eq!(updates.last().unwrap()["type"], "exit");
+
+        let (status, log) = request(
+            server.addr(),
+            "GET",
+            &format!("/api/runs/{}/log", run_id),
+            "",
+        );
+        assert_eq!(status, 200);
+        assert!(log.ends_with("hi api\n"), "{:?}", log);
+
+        // Recorded like any other run
+        assert_eq!(store.get_execution_history(&id).len(), 1);
+        assert!(
+            store
+                .get_execution_log(&run_id)
+                .unwrap()
+                .ends_with("hi api\n")
+        );
+        let (_, history) = json(
+            server.addr(),
+            "GET",
+            &format!("/api/commands/{}/runs", id),
+            "",
+        );
+        assert_eq!(history[0]["id"], run_id.to_string());
+    }
+
+    #[test]
+    fn test_event_stream() {
+        let store = CommandStore::new_test();
+        let server = start(&store);
+        let id = add_command(&store, "echo one; sleep 0.3; echo two; exit 3", Vec::new());
+        let run_id = start_run(server.addr(), id, "");
+
+        let stream = send(
+            server.addr(),
+            "GET",
+            &format!("/api/runs/{}/events", run_id),
+            Some(TOKEN),
+            "",
+        );
+        let lines: Vec<String> = BufReader::new(stream).lines().map(Result::unwrap).collect();
+        assert!(lines.iter().any(|l| l == "Content-Type: text/event-stream"));
+        let events: Vec<&str> = lines
+            .iter()
+            .filter_map(|l| l.strip_prefix("event: "))
+            .collect();
+        assert_eq!(events.first(), Some(&"started"));
+        assert_eq!(events.last(), Some(&"exit"));
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("data: ") && l.contains("two"))
+        );
+        assert_eq!(
+            lines
+                .iter()
+                .rev()
+                .find(|l| l.starts_with("data: "))
+                .unwrap(),
+            "data: {\"code\":3}"
+        );
+    }
+
+    #[test]
+    fn test_kill_run() {
+        let store = CommandStore::new_test();
+        let server = start(&store);
+        let id = add_command(&store, "sleep 30", Vec::new());
+        let run_id = start_run(server.addr(), id, "");
+
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        let (status, _) = json(
+            server.addr(),
+            "POST",
+            &format!("/api/runs/{}/kill", run_id),
+            "",
+        );
+        assert_eq!(status, 202);
+        let (run, _) = wait_for(server.addr(), run_id);
+        assert_ne!(run["exit_code"], 0);
+
+        // Nothing left to kill
+        let (status, _) = json(
+            server.addr(),
+            "POST",
+            &format!("/api/runs/{}/kill", run_id),
+            "",
+        );
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn test_invalid_wait() {
+        let store = CommandStore::new_test();
+        let server = start(&store);
+        let id = add_command(&store, "true", Vec::new());
+        let run_id = start_run(server.addr(), id, "");
+        wait_for(server.addr(), run_id);
+
+        for wait in ["-1", "NaN", "inf", "-inf", "soon"] {
+            let (status, response) = json(
+                server.addr(),
+                "GET",
+                &format!("/api/runs/{}?wait={}", run_id, wait),
+                "",
+            );
+            assert_eq!(status, 400, "wait={}", wait);
+            assert!(response["error"].as_str().unwrap().contains("wait"));
+        }
+        // Too long a wait is cut to the longest allowed
+        let (status, progress) = json(
+            server.addr(),
+            "GET",
+            &format!("/api/runs/{}?wait=1e30", run_id),
+            "",
+        );
+        assert_eq!(status, 200);
+        assert_eq!(progress["finished"], true);
+    }
+
+    #[test]
+    fn test_run_workflow() {
+        let store = CommandStore::new_test();
+        let server = start(&store);
+        let ask = vec![EnvVar {
+            key: "NAME".to_string(),
+            value: String::new(),
+            ask_user: true,
+        }];
+        let steps = vec![
+            add_command(&store, "echo \"first $NAME\"", ask),
+            add_command(&store, "echo second; exit 4", Vec::new()),
+            add_command(&store, "echo never", Vec::new()),
+        ];
+        let workflow = Workflow {
+            id: Uuid::new_v4(),
+            name: "API workflow".to_string(),
+            description: None,
+            commands: steps.clone(),
+            env_vars: Vec::new(),
+            created_at: chrono::Utc::now(),
+            step_run_as: Vec::new(),
+            notify: Default::default(),
+        };
+        store.add_workflow(workflow.clone());
+        let path = format!("/api/workflows/{}/runs", workflow.id);
+
+        let (status, _) = json(server.addr(), "POST", &path, "");
+        assert_eq!(status, 400);
+        let (status, response) = json(server.addr(), "POST", &path, r#"{"env": {"NAME": "api"}}"#);
+        assert_eq!(status, 202, "{}", response);
+        let run_id = response["workflow_run"].clone();
+
+        // Stops at the failing step
+        let mut runs = Value::Null;
+        for _ in 0..50 {
+            let (_, history) = json(server.addr(), "GET", &path, "");
+            runs = history
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|r| r["id"] == run_id)
+                .unwrap()
+                .clone();
+            if !runs["exit_code"].is_null() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        assert_eq!(runs["exit_code"], 4);
+        let step_runs = runs["runs"].as_array().unwrap();
+        assert_eq!(step_runs.len(), 2);
+        let first: Uuid = step_runs[0].as_str().unwrap().parse().unwrap();
+        assert!(
+            store
+                .get_execution_log(&first)
+                .unwrap()
+                .ends_with("first api\n")
+        );
+        assert!(store.get_execution_history(&steps[2]).is_empty());
+        assert_eq!(store.list_workflow_runs(&workflow.id)[0].exit_code, 4);
+    }
+
+    #[test]
+    fn test_unknown_ids() {
+        let store = CommandStore::new_test();
+        let server = start(&store);
+        for (method, path) in [
+            ("GET", format!("/api/runs/{}", Uuid::new_v4())),
+            ("GET", "/api/runs/not-a-uuid/log".to_string()),
+            ("POST", format!("/api/commands/{}/runs", Uuid::new_v4())),
+            ("POST", format!("/api/workflows/{}/runs", Uuid::new_v4())),
+        ] {
+            let (status, _) = json(server.addr(), method, &path, "");
+            assert_eq!(status, 404, "{} {}", method, path);
+        }
+    }
+}
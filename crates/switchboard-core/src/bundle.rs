@@ -0,0 +1,427 @@
+//! Command bundles: commands and workflows as a directory of YAML files and
+//! plain scripts, meant for sharing and for keeping in git.
+//!
+//! ```text
+//! bundle.yaml              format version and the files written
+//! commands/deploy-api.yaml settings; `script: deploy-api.sh`
+//! commands/deploy-api.sh   the script, as is
+//! workflows/release.yaml   steps name command files: `command: deploy-api`
+//! ```
+//!
+//! Files are named after the item and written with fields in a fixed order
+//! and defaults left out, so exporting again only changes what changed.
+//! Explicitly picked target hosts aren't exported (host ids mean nothing
+//! elsewhere; tag selectors are), nor are saved "run as" passwords.
+
+use crate::merge::MergeSource;
+use crate::models::{
+    Command, EnvVar, EscalationPassword, FileTransfer, HostKind, NotifyOn, RunAs, Workflow,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use thiserror::Error;
+use uuid::Uuid;
+
+pub const FORMAT_VERSION: u32 = 1;
+const MANIFEST: &str = "bundle.yaml";
+const COMMANDS_DIR: &str = "commands";
+const WORKFLOWS_DIR: &str = "workflows";
+
+#[derive(Error, Debug)]
+pub enum BundleError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{file}: {source}")]
+    Yaml {
+        file: String,
+        source: serde_yaml::Error,
+    },
+    #[error("{0}")]
+    Invalid(String),
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    /// Every file the export wrote, relative to the bundle, so the next
+    /// export knows what it may remove.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    files: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CommandFile {
+    id: Uuid,
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// The script's file name, next to this file.
+    script: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    working_directory: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    env_vars: Vec<EnvVar>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    run_as: Option<RunAs>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    host_selector: String,
+    #[serde(default, skip_serializing_if = "is_default")]
+    background: bool,
+    #[serde(default, skip_serializing_if = "is_default")]
+    target: HostKind,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    uploads: Vec<FileTransfer>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    artifacts: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    notify: NotifyOn,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StepFile {
+    /// The command file's name, without `.yaml`.
+    command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    run_as: Option<RunAs>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WorkflowFile {
+    id: Uuid,
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    steps: Vec<StepFile>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    env_vars: Vec<EnvVar>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    notify: NotifyOn,
+    created_at: DateTime<Utc>,
+}
+
+/// A saved password is replaced by asking for it.
+fn shareable_run_as(run_as: &Option<RunAs>) -> Option<RunAs> {
+    run_as.clone().map(|mut run_as| {
+        if matches!(run_as.password, EscalationPassword::Saved(_)) {
+            run_as.password = EscalationPassword::Prompt;
+        }
+        run_as
+    })
+}
+
+/// A file name for `name`: "Deploy: API v2" becomes "deploy-api-v2".
+pub fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "untitled".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Unique slugs for `items`, by id: the same name twice gets "-2", "-3"...
+/// in order of creation, so the files keep their names between exports.
+fn slugs<'a>(items: impl Iterator<Item = (Uuid, &'a str, DateTime<Utc>)>) -> HashMap<Uuid, String> {
+    let mut items: Vec<_> = items.collect();
+    items.sort_by_key(|(id, _, created_at)| (*created_at, *id));
+    let mut taken: HashMap<String, usize> = HashMap::new();
+    items
+        .into_iter()
+        .map(|(id, name, _)| {
+            let base = slug(name);
+            let count = taken.entry(base.clone()).or_default();
+            *count += 1;
+            let slug = if *count == 1 {
+                base
+            } else {
+                format!("{}-{}", base, count)
+            };
+            (id, slug)
+        })
+        .collect()
+}
+
+fn to_yaml<T: Serialize>(file: &str, value: &T) -> Result<String, BundleError> {
+    serde_yaml::to_string(value).map_err(|source| BundleError::Yaml {
+        file: file.to_string(),
+        source,
+    })
+}
+
+fn from_yaml<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, BundleError> {
+    let text = std::fs::read_to_string(path)?;
+    serde_yaml::from_str(&text).map_err(|source| BundleError::Yaml {
+        file: path.display().to_string(),
+        source,
+    })
+}
+
+/// Remove the files an earlier export to `dir` listed in its manifest, so
+/// items that were dropped since don't linger. Anything else in `dir` is
+/// left alone, and a directory that holds files but no bundle is refused.
+fn clear_exported(dir: &Path) -> Result<(), BundleError> {
+    let manifest_path = dir.join(MANIFEST);
+    if !manifest_path.exists() {
+        let is_empty = match std::fs::read_dir(dir) {
+            Ok(mut entries) => entries.next().is_none(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => true,
+            Err(e) => return Err(e.into()),
+        };
+        if !is_empty {
+            return Err(BundleError::Invalid(format!(
+                "{} isn't empty and holds no bundle; export to an empty directory",
+                dir.display()
+            )));
+        }
+        return Ok(());
+    }
+
+    let manifest: Manifest = from_yaml(&manifest_path)?;
+    for file in &manifest.files {
+        // Only ever inside the bundle
+        let relative = Path::new(file);
+        if !relative
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+        {
+            continue;
+        }
+        match std::fs::remove_file(dir.join(relative)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Write `commands` and `workflows` to `dir`. Every command a workflow runs
+/// must be among `commands`. Files of an earlier export to `dir` are
+/// replaced.
+pub fn write_bundle(
+    dir: &Path,
+    commands: &[Command],
+    workflows: &[Workflow],
+) -> Result<(), BundleError> {
+    let command_slugs = slugs(
+        commands
+            .iter()
+            .map(|c| (c.id, c.name.as_str(), c.created_at)),
+    );
+    let workflow_slugs = slugs(
+        workflows
+            .iter()
+            .map(|w| (w.id, w.name.as_str(), w.created_at)),
+    );
+
+    clear_exported(dir)?;
+    let commands_dir = dir.join(COMMANDS_DIR);
+    let workflows_dir = dir.join(WORKFLOWS_DIR);
+    std::fs::create_dir_all(&commands_dir)?;
+    std::fs::create_dir_all(&workflows_dir)?;
+
+    let mut files: Vec<String> = command_slugs
+        .values()
+        .flat_map(|slug| {
+            [
+                format!("{}/{}.yaml", COMMANDS_DIR, slug),
+                format!("{}/{}.sh", COMMANDS_DIR, slug),
+            ]
+        })
+        .chain(
+            workflow_slugs
+                .values()
+                .map(|slug| format!("{}/{}.yaml", WORKFLOWS_DIR, slug)),
+        )
+        .collect();
+    files.sort();
+    let manifest = Manifest {
+        version: FORMAT_VERSION,
+        files,
+    };
+    std::fs::write(dir.join(MANIFEST), to_yaml(MANIFEST, &manifest)?)?;
+
+    for command in commands {
+        let slug = &command_slugs[&command.id];
+        let file = CommandFile {
+            id: command.id,
+            name: command.name.clone(),
+            description: command.description.clone().filter(|d| !d.is_empty()),
+            script: format!("{}.sh", slug),
+            working_directory: command.working_directory.clone(),
+            env_vars: command.env_vars.clone(),
+            host: command.host.clone(),
+            user: command.user.clone(),
+            run_as: shareable_run_as(&command.run_as),
+            host_selector: command.host_selector.clone(),
+            background: command.background,
+            target: command.target.clone(),
+            uploads: command.uploads.clone(),
+            artifacts: command.artifacts.clone(),
+            notify: command.notify,
+            created_at: command.created_at,
+        };
+        let yaml_name = format!("{}.yaml", slug);
+        std::fs::write(commands_dir.join(&yaml_name), to_yaml(&yaml_name, &file)?)?;
+
+        let script_path = commands_dir.join(&file.script);
+        std::fs::write(&script_path, &command.script)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&script_path, std::fs::Permissions::from_mode(0o755))?;
+        }
+    }
+
+    for workflow in workflows {
+        let steps = workflow
+            .commands
+            .iter()
+            .enumerate()
+            .map(|(i, id)| {
+                let command = command_slugs.get(id).cloned().ok_or_else(|| {
+                    BundleError::Invalid(format!(
+                        "workflow \"{}\": step {} isn't being exported",
+                        workflow.name,
+                        i + 1
+                    ))
+                })?;
+                let run_as = workflow.step_run_as.get(i).cloned().flatten();
+                Ok(StepFile {
+                    command,
+                    run_as: shareable_run_as(&run_as),
+                })
+            })
+            .collect::<Result<Vec<_>, BundleError>>()?;
+        let file = WorkflowFile {
+            id: workflow.id,
+            name: workflow.name.clone(),
+            description: workflow.description.clone().filter(|d| !d.is_empty()),
+            steps,
+            env_vars: workflow.env_vars.clone(),
+            notify: workflow.notify,
+            created_at: workflow.created_at,
+        };
+        let yaml_name = format!("{}.yaml", workflow_slugs[&workflow.id]);
+        std::fs::write(workflows_dir.join(&yaml_name), to_yaml(&yaml_name, &file)?)?;
+    }
+    Ok(())
+}
+
+/// `.yaml` files in `dir` by name (without the extension), sorted.
+fn yaml_files(dir: &Path) -> Result<BTreeMap<String, std::path::PathBuf>, BundleError> {
+    let mut files = BTreeMap::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if path.extension().is_some_and(|e| e == "yaml" || e == "yml")
+            && let Some(stem) = path.file_stem()
+        {
+            files.insert(stem.to_string_lossy().to_string(), path);
+        }
+    }
+    Ok(files)
+}
+
+/// Read the bundle in `dir`. Commands remember their script file in
+/// `source_path`.
+pub fn read_bundle(dir: &Path) -> Result<MergeSource, BundleError> {
+    let commands_dir = dir.join(COMMANDS_DIR);
+    let workflows_dir = dir.join(WORKFLOWS_DIR);
+    if !commands_dir.is_dir() && !workflows_dir.is_dir() {
+        return Err(BundleError::Invalid(format!(
+            "{} has no commands/ or workflows/ directory",
+            dir.display()
+        )));
+    }
+    if dir.join(MANIFEST).exists() {
+        let manifest: Manifest = from_yaml(&dir.join(MANIFEST))?;
+        if manifest.version > FORMAT_VERSION {
+            return Err(BundleError::Invalid(format!(
+                "bundle format {} is newer than this version of Switchboard reads ({})",
+                manifest.version, FORMAT_VERSION
+            )));
+        }
+    }
+
+    let mut source = MergeSource::default();
+    let mut command_ids = HashMap::new();
+    for (slug, path) in yaml_files(&commands_dir)? {
+        let file: CommandFile = from_yaml(&path)?;
+        let script_path = commands_dir.join(&file.script);
+        if !script_path.starts_with(&commands_dir) || file.script.contains("..") {
+            return Err(BundleError::Invalid(format!(
+                "{}: script must be next to it",
+                path.display()
+            )));
+        }
+        let script = std::fs::read_to_string(&script_path)?;
+        command_ids.insert(slug, file.id);
+        source.commands.push(Command {
+            id: file.id,
+            description: file.description,
+            working_directory: file.working_directory,
+            env_vars: file.env_vars,
+            host: file.host,
+            user: file.user,
+            run_as: file.run_as,
+            host_selector: file.host_selector,
+            created_at: file.created_at,
+            background: file.background,
+            target: file.target,
+            uploads: file.uploads,
+            artifacts: file.artifacts,
+            notify: file.notify,
+            source_path: Some(script_path),
+            ..Command::new(file.name, script)
+        });
+    }
+
+    for (_, path) in yaml_files(&workflows_dir)? {
+        let file: WorkflowFile = from_yaml(&path)?;
+        let mut commands = Vec::new();
+        let mut step_run_as = Vec::new();
+        for step in file.steps {
+            let id = command_ids.get(&step.command).ok_or_else(|| {
+                BundleError::Invalid(format!(
+                    "{}: no command file \"{}.yaml\"",
+                    path.display(),
+                    step.command
+                ))
+            })?;
+            commands.push(*id);
+            step_run_as.push(step.run_as);
+        }
+        source.workflows.push(Workflow {
+            id: file.id,
+            name: file.name,
+            description: file.description,
+            commands,
+            env_vars: file.env_vars,
+            created_at: file.created_at,
+            step_run_as,
+            notify: file.notify,
+        });
+    }
+    Ok(source)
+}
@@ -0,0 +1,180 @@
+#[cfg(test)]
+mod tests {
+    use crate::bundle::{read_bundle, slug};
+    use crate::merge::MergeAction;
+    use crate::models::{Command, EscalationPassword, RunAs, Workflow};
+    use crate::store::CommandStore;
+    use uuid::Uuid;
+
+    fn temp_dir() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("switchboard_bundle_test_{}", Uuid::new_v4()))
+    }
+
+    fn read(path: std::path::PathBuf) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_slugs() {
+        assert_eq!(slug("Deploy: API v2"), "deploy-api-v2");
+        assert_eq!(slug("  --Ünïcode!! "), "n-code");
+        assert_eq!(slug("???"), "untitled");
+    }
+
+    #[test]
+    fn test_export_and_reimport() {
+        let store = CommandStore::new_test();
+        let mut deploy = Command::new("Deploy: API", "#!/bin/bash\nmake deploy\n");
+        let password_id = Uuid::new_v4();
+        crate::secrets::save(&password_id, "hunter2").unwrap();
+        deploy.run_as = Some(RunAs {
+            user: "deploy".into(),
+            password: EscalationPassword::Saved(password_id),
+            ..Default::default()
+        });
+        let mut migrate = Command::new("Migrate", "./migrate.sh");
+        migrate.created_at = deploy.created_at + chrono::Duration::seconds(1);
+        let mut other_migrate = Command::new("Migrate", "echo other");
+        other_migrate.created_at = deploy.created_at + chrono::Duration::seconds(2);
+        let unrelated = Command::new("Unrelated", "true");
+        for command in [&deploy, &migrate, &other_migrate, &unrelated] {
+            store.add_command(command.clone());
+        }
+        let release = Workflow {
+            id: Uuid::new_v4(),
+            name: "Release".into(),
+            description: None,
+            commands: vec![migrate.id, deploy.id],
+            env_vars: vec![],
+            created_at: chrono::Utc::now(),
+            step_run_as: vec![None, None],
+            notify: Default::default(),
+        };
+        store.add_workflow(release.clone());
+
+        // The workflow brings its steps along
+        let dir = temp_dir();
+        store
+            .export_bundle(&dir, &[other_migrate.id], &[release.id])
+            .unwrap();
+        let mut files: Vec<String> = std::fs::read_dir(dir.join("commands"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                "deploy-api.sh",
+                "deploy-api.yaml",
+                "migrate-2.sh",
+                "migrate-2.yaml",
+                "migrate.sh",
+                "migrate.yaml"
+            ]
+        );
+        assert_eq!(read(dir.join("commands/migrate-2.sh")), "echo other");
+        let deploy_yaml = read(dir.join("commands/deploy-api.yaml"));
+        assert!(deploy_yaml.contains("script: deploy-api.sh"));
+        assert!(deploy_yaml.contains("Prompt") && !deploy_yaml.contains(&password_id.to_string()));
+        // Defaults are left out
+        assert!(!deploy_yaml.contains("background"));
+        let workflow_yaml = read(dir.join("workflows/release.yaml"));
+        assert!(
+            workflow_yaml.contains("- command: migrate\n")
+                && workflow_yaml.contains("- command: deploy-api\n")
+        );
+
+        // Exporting again changes nothing
+        let before = read(dir.join("commands/deploy-api.yaml"));
+        store
+            .export_bundle(&dir, &[other_migrate.id], &[release.id])
+            .unwrap();
+        assert_eq!(read(dir.join("commands/deploy-api.yaml")), before);
+
+        // Into a new store
+        let source = read_bundle(&dir).unwrap();
+        assert_eq!(source.workflows[0].commands, vec![migrate.id, deploy.id]);
+        let fresh = CommandStore::new_test();
+        let plan = fresh.preview_bundle(&dir).unwrap();
+        assert_eq!(plan.changes(), 4);
+        fresh.apply_merge(&plan);
+        let imported = fresh.get_command(&migrate.id).unwrap();
+        assert_eq!(imported.script, "./migrate.sh");
+        assert_eq!(imported.source_path, Some(dir.join("commands/migrate.sh")));
+
+        // Back into the one it came from: only the password differs
+        let plan = store.preview_bundle(&dir).unwrap();
+        let actions: Vec<MergeAction> = plan.items.iter().map(|i| i.action).collect();
+        assert_eq!(
+            actions
+                .iter()
+                .filter(|a| **a == MergeAction::Unchanged)
+                .count(),
+            3
+        );
+        assert_eq!(
+            plan.items
+                .iter()
+                .find(|i| i.id == deploy.id)
+                .unwrap()
+                .action,
+            MergeAction::Keep
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_only_replaces_its_own_files() {
+        let store = CommandStore::new_test();
+        let deploy = store.add_command(Command::new("Deploy", "make deploy"));
+        let backup = store.add_command(Command::new("Backup", "tar czf /tmp/b.tgz /srv"));
+
+        // Someone else's files are never touched
+        let dir = temp_dir();
+        std::fs::create_dir_all(dir.join("commands")).unwrap();
+        std::fs::write(dir.join("commands/notes.sh"), "keep me").unwrap();
+        let error = store
+            .export_bundle(&dir, &[deploy], &[])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("isn't empty"), "{}", error);
+        std::fs::remove_dir_all(dir.join("commands")).unwrap();
+
+        store.export_bundle(&dir, &[deploy, backup], &[]).unwrap();
+        std::fs::write(dir.join("commands/notes.sh"), "keep me").unwrap();
+        std::fs::write(dir.join("README.md"), "# Ops").unwrap();
+
+        // Dropped items go, files added by hand stay
+        store.export_bundle(&dir, &[deploy], &[]).unwrap();
+        assert!(
+            !dir.join("commands/backup.yaml").exists() && !dir.join("commands/backup.sh").exists()
+        );
+        assert!(dir.join("commands/deploy.sh").exists());
+        assert_eq!(read(dir.join("commands/notes.sh")), "keep me");
+        assert!(dir.join("README.md").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bad_bundles() {
+        let dir = temp_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(read_bundle(&dir).is_err());
+
+        std::fs::create_dir_all(dir.join("workflows")).unwrap();
+        std::fs::write(
+            dir.join("workflows/release.yaml"),
+            format!("id: {}\nname: Release\nsteps:\n- command: missing\ncreated_at: 2024-01-01T00:00:00Z\n", Uuid::new_v4()),
+        )
+        .unwrap();
+        let error = read_bundle(&dir).unwrap_err().to_string();
+        assert!(
+            error.contains("no command file \"missing.yaml\""),
+            "{}",
+            error
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
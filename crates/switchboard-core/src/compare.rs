@@ -0,0 +1,159 @@
+//! Comparing two runs, e.g. today's failure with last week's success: their
+//! logs line by line (see `diff`), with what changes on every run masked
+//! (see `Normalizer`), and how their details differ. See
+//! `CommandStore::compare_executions`.
+
+use crate::diff::{DiffKind, DiffRow, side_by_side};
+use crate::models::Normalizer;
+use crate::notifications::format_duration;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+
+/// What's compared about each run, besides its log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunSummary {
+    pub command: String,
+    pub host: String,
+    pub started_at: DateTime<Utc>,
+    pub exit_code: Option<i32>,
+    pub duration_ms: Option<u64>,
+    /// The number of the command revision that ran.
+    pub revision: Option<u32>,
+    pub library_commit: Option<String>,
+}
+
+/// One detail of the two runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub name: &'static str,
+    pub old: String,
+    pub new: String,
+    pub differs: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct RunComparison {
+    pub fields: Vec<FieldDiff>,
+    pub rows: Vec<DiffRow>,
+    /// Missing logs and patterns that aren't valid.
+    pub notes: Vec<String>,
+}
+
+impl RunComparison {
+    /// Log lines that differ.
+    pub fn changed_lines(&self) -> usize {
+        self.rows
+            .iter()
+            .filter(|r| r.kind != DiffKind::Same)
+            .count()
+    }
+}
+
+/// `log` with the enabled normalizers applied, in order. Patterns that
+/// don't compile are skipped and described in `notes`.
+pub fn normalize(log: &str, normalizers: &[Normalizer], notes: &mut Vec<String>) -> String {
+    let mut log = log.to_string();
+    for normalizer in normalizers.iter().filter(|n| n.enabled) {
+        match Regex::new(&normalizer.pattern) {
+            Ok(regex) => {
+                log = regex
+                    .replace_all(&log, normalizer.replacement.as_str())
+                    .into_owned()
+            }
+            Err(e) => {
+                let note = format!("Pattern \"{}\" skipped: {}", normalizer.name, e);
+                if !notes.contains(&note) {
+                    notes.push(note);
+                }
+            }
+        }
+    }
+    log
+}
+
+fn field(name: &'static str, old: String, new: String) -> FieldDiff {
+    FieldDiff {
+        differs: old != new,
+        name,
+        old,
+        new,
+    }
+}
+
+fn or_dash<T>(value: Option<T>, show: impl Fn(T) -> String) -> String {
+    value.map(show).unwrap_or_else(|| "—".to_string())
+}
+
+/// How the details of two runs differ. The start times always do and
+/// aren't marked as differing.
+pub fn compare_summaries(old: &RunSummary, new: &RunSummary) -> Vec<FieldDiff> {
+    let time = |t: DateTime<Utc>| {
+        t.with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    };
+    let mut duration = field(
+        "Duration",
+        or_dash(old.duration_ms, format_duration),
+        or_dash(new.duration_ms, format_duration),
+    );
+    if let (Some(old), Some(new)) = (old.duration_ms, new.duration_ms)
+        && old != new
+    {
+        let change = format_duration(old.abs_diff(new));
+        duration.new = format!(
+            "{} ({}{})",
+            duration.new,
+            if new > old { "+" } else { "−" },
+            change
+        );
+    }
+    vec![
+        field("Command", old.command.clone(), new.command.clone()),
+        field("Host", old.host.clone(), new.host.clone()),
+        FieldDiff {
+            differs: false,
+            ..field("Started", time(old.started_at), time(new.started_at))
+        },
+        field(
+            "Exit code",
+            or_dash(old.exit_code, |c| c.to_string()),
+            or_dash(new.exit_code, |c| c.to_string()),
+        ),
+        duration,
+        field(
+            "Revision",
+            or_dash(old.revision, |r| format!("r{}", r)),
+            or_dash(new.revision, |r| format!("r{}", r)),
+        ),
+        field(
+            "Library commit",
+            or_dash(old.library_commit.clone(), |c| c),
+            or_dash(new.library_commit.clone(), |c| c),
+        ),
+    ]
+}
+
+/// Compare two runs and their logs. With `normalizers`, the logs are
+/// normalized first.
+pub fn compare(
+    old: &RunSummary,
+    old_log: &str,
+    new: &RunSummary,
+    new_log: &str,
+    normalizers: Option<&[Normalizer]>,
+) -> RunComparison {
+    let mut notes = Vec::new();
+    let rows = match normalizers {
+        Some(normalizers) => side_by_side(
+            &normalize(old_log, normalizers, &mut notes),
+            &normalize(new_log, normalizers, &mut notes),
+        ),
+        None => side_by_side(old_log, new_log),
+    };
+    RunComparison {
+        fields: compare_summaries(old, new),
+        rows,
+        notes,
+    }
+}
@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests {
+    use crate::compare::{RunSummary, compare_summaries, normalize};
+    use crate::models::{CompareSettings, ExecutionResult, Normalizer};
+    use crate::store::CommandStore;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_normalize() {
+        let normalizers = CompareSettings::default().normalizers;
+        let mut notes = Vec::new();
+        let log = "2026-10-11T04:00:01Z job 3f1c2a9e-5b7d-4c1e-9a2b-0d4e6f8a1b2c started\n[04:00:02] took 3.5s";
+        assert_eq!(
+            normalize(log, &normalizers, &mut notes),
+            "<timestamp> job <uuid> started\n[<time>] took 3.5s"
+        );
+        assert!(notes.is_empty());
+
+        let broken = [Normalizer {
+            name: "Broken".into(),
+            pattern: "(".into(),
+            replacement: String::new(),
+            enabled: true,
+        }];
+        assert_eq!(normalize("a(b", &broken, &mut notes), "a(b");
+        assert_eq!(notes.len(), 1);
+        assert!(notes[0].starts_with("Pattern \"Broken\" skipped"));
+    }
+
+    #[test]
+    fn test_compare_summaries() {
+        let old = RunSummary {
+            command: "Backup".into(),
+            host: "db1".into(),
+            started_at: chrono::Utc::now() - chrono::Duration::days(7),
+            exit_code: Some(0),
+            duration_ms: Some(42_000),
+            revision: Some(3),
+            library_commit: None,
+        };
+        let new = RunSummary {
+            started_at: chrono::Utc::now(),
+            exit_code: Some(2),
+            duration_ms: Some(45_000),
+            revision: Some(4),
+            ..old.clone()
+        };
+        let fields = compare_summaries(&old, &new);
+        let differing: Vec<&str> = fields
+            .iter()
+            .filter(|f| f.differs)
+            .map(|f| f.name)
+            .collect();
+        assert_eq!(differing, vec!["Exit code", "Duration", "Revision"]);
+        let duration = fields.iter().find(|f| f.name == "Duration").unwrap();
+        assert_eq!(
+            (duration.old.as_str(), duration.new.as_str()),
+            ("42s", "45s (+3s)")
+        );
+    }
+
+    #[test]
+    fn test_compare_executions() {
+        let store = CommandStore::new_test();
+        let command_id = Uuid::new_v4();
+        let mut old = ExecutionResult::started(Uuid::new_v4(), command_id, Uuid::nil());
+        old.finish(0);
+        store.add_execution(&old, "backup 2026-10-11 04:00:00\nuploaded\ndone\n");
+        let mut new = ExecutionResult::started(Uuid::new_v4(), command_id, Uuid::nil());
+        new.finish(1);
+        store.add_execution(&new, "backup 2026-10-18 04:00:00\nupload failed\ndone\n");
+
+        let comparison = store.compare_executions(&old.id, &new.id, true).unwrap();
+        assert_eq!(comparison.changed_lines(), 1);
+        assert!(comparison.notes.is_empty());
+        assert_eq!(
+            store
+                .compare_executions(&old.id, &new.id, false)
+                .unwrap()
+                .changed_lines(),
+            2
+        );
+        assert!(
+            store
+                .compare_executions(&old.id, &Uuid::new_v4(), true)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,448 @@
+//! `switchboardd`: a background process that owns the store and the runs,
+//! so runs outlive the GUI and several clients (GUI windows, the CLI)
+//! share one `store.json`. Clients talk to it over a Unix socket: one JSON
+//! request per connection and line, answered by one JSON response per line
+//! (a stream of them for `Attach`).
+
+use crate::api::ApiServer;
+use crate::diagnostics::HostDiagnostics;
+use crate::executor::{CommandExecutor, ExecuteError};
+use crate::models::{
+    BackgroundJob, Command, EscalationPassword, ExecutionResult, ExecutionUpdate, Host,
+};
+use crate::runs::{RunManager, RunRequest};
+use crate::store::{CommandStore, StoreChange, StoreDelta, SyncPoint, default_data_dir};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// How long an `Attach` stream waits for news before checking again.
+const ATTACH_POLL: Duration = Duration::from_secs(15);
+/// How long to wait after a failed `accept` before accepting again.
+const ACCEPT_RETRY: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DaemonRequest {
+    Ping,
+    /// The whole store, as JSON, and where it lives.
+    LoadStore,
+    /// What changed since a connected store last caught up.
+    Changes {
+        since: SyncPoint,
+    },
+    Store(Box<StoreChange>),
+    /// Run a command exactly as given: environment resolved and any
+    /// password filled in by the client. A password typed in for the run
+    /// travels in `password`, as `command` can't carry it.
+    Execute {
+        exec_id: Uuid,
+        command: Box<Command>,
+        host: Box<Host>,
+        env_vars: HashMap<String, String>,
+        password: Option<String>,
+    },
+    /// Run a stored command by id, as the HTTP API does.
+    StartRun {
+        command_id: Uuid,
+        request: RunRequest,
+    },
+    /// Runs still going.
+    ListRuns,
+    /// Stream a run's updates from index `since` on, then `Finished`.
+    Attach {
+        run_id: Uuid,
+        since: usize,
+    },
+    Kill {
+        run_id: Uuid,
+    },
+    Log {
+        run_id: Uuid,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DaemonResponse {
+    Ok,
+    Store {
+        path: PathBuf,
+        json: String,
+        point: SyncPoint,
+    },
+    Changes {
+        point: SyncPoint,
+        delta: Box<StoreDelta>,
+    },
+    Started(Vec<Uuid>),
+    Runs(Vec<ExecutionResult>),
+    Update(ExecutionUpdate),
+    Finished(ExecutionResult),
+    Log(String),
+    Error(String),
+}
+
+#[derive(Error, Debug)]
+pub enum DaemonError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Daemon(String),
+    #[error("Unexpected answer from the daemon: {0:?}")]
+    Unexpected(Box<DaemonResponse>),
+    #[error("The daemon closed the connection")]
+    Closed,
+    #[error("A daemon is already listening on {0}")]
+    AlreadyRunning(PathBuf),
+}
+
+/// Where the daemon listens unless told otherwise.
+pub fn default_socket_path() -> PathBuf {
+    default_data_dir().join("switchboardd.sock")
+}
+
+fn write_line(stream: &mut impl Write, message: &impl Serialize) -> Result<(), DaemonError> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn read_line<T: for<'de> Deserialize<'de>>(reader: &mut impl BufRead) -> Result<T, DaemonError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(DaemonError::Closed);
+    }
+    Ok(serde_json::from_str(&line)?)
+}
+
+// --- Client ---
+
+#[derive(Debug, Clone)]
+pub struct DaemonClient {
+    socket: PathBuf,
+}
+
+impl DaemonClient {
+    /// Connect to the daemon listening on `socket`, checking it answers.
+    pub fn connect(socket: impl Into<PathBuf>) -> Result<Self, DaemonError> {
+        let client = DaemonClient {
+            socket: socket.into(),
+        };
+        client.request(&DaemonRequest::Ping)?;
+        Ok(client)
+    }
+
+    fn send(&self, request: &DaemonRequest) -> Result<BufReader<UnixStream>, DaemonError> {
+        let mut stream = UnixStream::connect(&self.socket)?;
+        write_line(&mut stream, request)?;
+        Ok(BufReader::new(stream))
+    }
+
+    /// Send `request` and wait for its answer; the daemon's `Error`s
+    /// become `Err`.
+    pub fn request(&self, request: &DaemonRequest) -> Result<DaemonResponse, DaemonError> {
+        match read_line(&mut self.send(request)?)? {
+            DaemonResponse::Error(message) => Err(DaemonError::Daemon(message)),
+            response => Ok(response),
+        }
+    }
+
+    /// Follow a run from update `since` on until it finishes.
+    pub fn attach(
+        &self,
+        run_id: Uuid,
+        since: usize,
+        on_update: &dyn Fn(ExecutionUpdate),
+    ) -> Result<ExecutionResult, DaemonError> {
+        let mut reader = self.send(&DaemonRequest::Attach { run_id, since })?;
+        loop {
+            match read_line(&mut reader)? {
+                DaemonResponse::Update(update) => on_update(update),
+                DaemonResponse::Finished(result) => return Ok(result),
+                DaemonResponse::Error(message) => return Err(DaemonError::Daemon(message)),
+                other => return Err(DaemonError::Unexpected(Box::new(other))),
+            }
+        }
+    }
+}
+
+/// Runs commands in the daemon instead of in this process.
+pub struct DaemonExecutor {
+    client: DaemonClient,
+}
+
+impl DaemonExecutor {
+    pub fn new(client: DaemonClient) -> Self {
+        DaemonExecutor { client }
+    }
+
+    pub fn client(&self) -> &DaemonClient {
+        &self.client
+    }
+
+    /// Stream a daemon run's updates (all of them, from the start) to
+    /// `on_update` in the background; a kill request is passed on.
+    pub fn follow(
+        &self,
+        run_id: Uuid,
+        on_update: Box<dyn Fn(ExecutionUpdate) + Send + Sync>,
+        kill_rx: std::sync::mpsc::Receiver<()>,
+    ) {
+        let killer = self.client.clone();
+        std::thread::spawn(move || {
+            // Ends once the sender is dropped
+            if kill_rx.recv().is_ok() {
+                let _ = killer.request(&DaemonRequest::Kill { run_id });
+            }
+        });
+
+        let client = self.client.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = client.attach(run_id, 0, &*on_update) {
+                on_update(ExecutionUpdate::Stderr(format!(
+                    "Lost track of the run in switchboardd: {}\n",
+                    e
+                )));
+                on_update(ExecutionUpdate::Exit(-1));
+            }
+        });
+    }
+}
+
+impl CommandExecutor for DaemonExecutor {
+    fn execute(
+        &self,
+        exec_id: Uuid,
+        command: &Command,
+        host: &Host,
+        env_vars: HashMap<String, String>,
+        on_update: Box<dyn Fn(ExecutionUpdate) + Send + Sync>,
+        kill_rx: std::sync::mpsc::Receiver<()>,
+    ) -> Result<(), ExecuteError> {
+        let password = match command.run_as.as_ref().map(|r| &r.password) {
+            Some(EscalationPassword::Entered(password)) => Some(password.clone()),
+            _ => None,
+        };
+        let request = DaemonRequest::Execute {
+            exec_id,
+            command: Box::new(command.clone()),
+            host: Box::new(host.clone()),
+            env_vars,
+            password,
+        };
+        self.client
+            .request(&request)
+            .map_err(|e| ExecuteError::Daemon(e.to_string()))?;
+        self.follow(exec_id, on_update, kill_rx);
+        Ok(())
+    }
+
+    fn attach_background(
+        &self,
+        _host: &Host,
+        _job: &BackgroundJob,
+        _on_update: Box<dyn Fn(ExecutionUpdate) + Send + Sync>,
+        _kill_rx: std::sync::mpsc::Receiver<()>,
+    ) -> Result<(), ExecuteError> {
+        Err(ExecuteError::Daemon(
+            "switchboardd reattaches background runs itself; follow them by run id".to_string(),
+        ))
+    }
+
+    fn diagnose(&self, host: &Host) -> HostDiagnostics {
+        // Only connects; nothing in the store changes
+        crate::diagnostics::diagnose(host)
+    }
+}
+
+// --- Server ---
+
+fn whole_store(store: &CommandStore) -> DaemonResponse {
+    match store.load_for_client() {
+        Ok((json, point)) => DaemonResponse::Store {
+            path: store.path().to_path_buf(),
+            json,
+            point,
+        },
+        Err(e) => DaemonResponse::Error(e.to_string()),
+    }
+}
+
+/// Listen on `socket`, replacing a stale socket file left by a daemon that
+/// died. Only the owner may connect.
+pub fn bind(socket: &Path) -> Result<UnixListener, DaemonError> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(DaemonError::AlreadyRunning(socket.to_path_buf()));
+        }
+        std::fs::remove_file(socket)?;
+    }
+    // Bind in a directory only we can enter and make the socket private
+    // there, so it's never open to others, then move it into place
+    let private = socket
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(format!(".switchboardd-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&private);
+    std::fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let bound = private.join("socket");
+    let listener = UnixListener::bind(&bound).and_then(|listener| {
+        std::fs::set_permissions(&bound, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::rename(&bound, socket)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_dir_all(&private);
+    Ok(listener?)
+}
+
+/// The daemon's state: the runs (and through them the store) and the
+/// HTTP API, which follows the stored settings.
+pub struct Daemon {
+    runs: RunManager,
+    api: Mutex<Option<ApiServer>>,
+}
+
+impl Daemon {
+    pub fn new(runs: RunManager) -> Self {
+        Daemon {
+            runs,
+            api: Mutex::new(None),
+        }
+    }
+
+    /// (Re)start the HTTP API from the stored settings.
+    pub fn restart_api(&self) {
+        let mut api = self.api.lock().unwrap();
+        // Free the port before binding it again
+        *api = None;
+        let settings = self.runs.store().get_settings().api;
+        if settings.enabled {
+            match ApiServer::start(&settings, self.runs.clone()) {
+                Ok(server) => {
+                    println!("HTTP API listening on http://{}", server.addr());
+                    *api = Some(server);
+                }
+                Err(e) => eprintln!("HTTP API not started: {}", e),
+            }
+        }
+    }
+
+    /// Answer clients. A failed `accept` (e.g. out of file descriptors) is
+    /// logged and the daemon keeps serving, so its runs go on.
+    pub fn serve(self: Arc<Self>, listener: UnixListener) -> Result<(), DaemonError> {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Failed to accept a client: {}", e);
+                    // Give whatever ran out a moment to come back
+                    std::thread::sleep(ACCEPT_RETRY);
+                    continue;
+                }
+            };
+            let daemon = self.clone();
+            std::thread::spawn(move || {
+                // Closed: a connection that only checked we're alive
+                if let Err(e) = daemon.handle(stream)
+                    && !matches!(e, DaemonError::Closed)
+                {
+                    eprintln!("Daemon client error: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn handle(&self, stream: UnixStream) -> Result<(), DaemonError> {
+        let mut writer = stream.try_clone()?;
+        let request: DaemonRequest = read_line(&mut BufReader::new(stream))?;
+        let store = self.runs.store();
+
+        let response = match request {
+            DaemonRequest::Ping => DaemonResponse::Ok,
+            DaemonRequest::LoadStore => whole_store(store),
+            DaemonRequest::Changes { since } => match store.changes_since(since) {
+                (Some(delta), point) => DaemonResponse::Changes {
+                    point,
+                    delta: Box::new(delta),
+                },
+                // Too far behind, or caught up with a daemon that restarted
+                (None, _) => whole_store(store),
+            },
+            DaemonRequest::Store(change) => {
+                let settings_changed = matches!(
+                    *change,
+                    StoreChange::SettingsSaved(_) | StoreChange::Imported(_)
+                );
+                match store.apply(*change) {
+                    Ok(()) => {
+                        if settings_changed {
+                            self.restart_api();
+                        }
+                        DaemonResponse::Ok
+                    }
+                    Err(e) => DaemonResponse::Error(e.to_string()),
+                }
+            }
+            DaemonRequest::Execute {
+                exec_id,
+                mut command,
+                host,
+                env_vars,
+                password,
+            } => {
+                if let Some(run_as) = &mut command.run_as
+                    && let Some(password) = password
+                {
+                    run_as.password = EscalationPassword::Entered(password);
+                }
+                match self.runs.execute(exec_id, &command, &host, env_vars) {
+                    Ok(()) => DaemonResponse::Started(vec![exec_id]),
+                    Err(e) => DaemonResponse::Error(e.to_string()),
+                }
+            }
+            DaemonRequest::StartRun {
+                command_id,
+                request,
+            } => match self.runs.start(command_id, request) {
+                Ok(ids) => DaemonResponse::Started(ids),
+                Err(e) => DaemonResponse::Error(e.to_string()),
+            },
+            DaemonRequest::ListRuns => DaemonResponse::Runs(self.runs.active()),
+            DaemonRequest::Attach { run_id, mut since } => loop {
+                let Some(progress) = self.runs.progress(&run_id, since, Some(ATTACH_POLL)) else {
+                    break DaemonResponse::Error(format!("No run with id {}", run_id));
+                };
+                for update in progress.updates {
+                    write_line(&mut writer, &DaemonResponse::Update(update))?;
+                }
+                since = progress.next;
+                if progress.finished {
+                    break DaemonResponse::Finished(progress.result);
+                }
+            },
+            DaemonRequest::Kill { run_id } => {
+                if self.runs.kill(&run_id) {
+                    DaemonResponse::Ok
+                } else {
+                    DaemonResponse::Error(format!("No running run with id {}", run_id))
+                }
+            }
+            DaemonRequest::Log { run_id } => match self.runs.log(&run_id) {
+                Some(log) => DaemonResponse::Log(log),
+                None => DaemonResponse::Error(format!("No run with id {}", run_id)),
+            },
+        };
+        write_line(&mut writer, &response)
+    }
+}
@@ -0,0 +1,247 @@
+#[cfg(test)]
+mod tests {
+    use crate::daemon::{
+        Daemon, DaemonClient, DaemonError, DaemonExecutor, DaemonRequest, DaemonResponse, bind,
+    };
+    use crate::executor::{CommandExecutor, Executor};
+    use crate::models::{Command, ExecutionStatus, ExecutionUpdate};
+    use crate::runs::{RunManager, RunRequest};
+    use crate::store::{CommandStore, SyncPoint};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use std::sync::mpsc::channel;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    /// Start a daemon on a fresh socket; returns the socket and its store.
+    fn start_daemon() -> (PathBuf, CommandStore) {
+        let socket =
+            std::env::temp_dir().join(format!("switchboardd_test_{}.sock", Uuid::new_v4()));
+        let store = CommandStore::new_test();
+        let listener = bind(&socket).unwrap();
+        let daemon = Arc::new(Daemon::new(RunManager::new(
+            store.clone(),
+            Arc::new(Executor),
+        )));
+        std::thread::spawn(move || daemon.serve(listener));
+        (socket, store)
+    }
+
+    /// Run `command` through the daemon, collecting updates until `Exit`.
+    fn run(executor: &DaemonExecutor, command: &Command) -> (Uuid, Vec<ExecutionUpdate>) {
+        let exec_id = Uuid::new_v4();
+        let (tx, rx) = channel();
+        let (_kill_tx, kill_rx) = channel();
+        let on_update = Box::new(move |update| {
+            let _ = tx.send(update);
+        });
+        executor
+            .execute(
+                exec_id,
+                command,
+                &command.adhoc_host(),
+                Default::default(),
+                on_update,
+                kill_rx,
+            )
+            .unwrap();
+        let mut updates = Vec::new();
+        while let Ok(update) = rx.recv_timeout(Duration::from_secs(10)) {
+            let done = matches!(update, ExecutionUpdate::Exit(_));
+            updates.push(update);
+            if done {
+                break;
+            }
+        }
+        (exec_id, updates)
+    }
+
+    #[test]
+    fn test_connected_store_sends_changes() {
+        let (socket, daemon_store) = start_daemon();
+        let store = CommandStore::connect(DaemonClient::connect(&socket).unwrap()).unwrap();
+        assert!(store.uses_daemon());
+
+        // Changes are sent in the background; refresh waits for them
+        let command = Command::new("Daemon test", "true");
+        store.add_command(command.clone());
+        store.refresh().unwrap();
+        assert_eq!(
+            daemon_store.get_command(&command.id).unwrap().name,
+            "Daemon test"
+        );
+        store.remove_command(&command.id);
+        store.refresh().unwrap();
+        assert!(daemon_store.get_command(&command.id).is_none());
+
+        // Changes by other clients show up after a refresh
+        let other = CommandStore::connect(DaemonClient::connect(&socket).unwrap()).unwrap();
+        other.add_workflow(crate::models::Workflow {
+            id: Uuid::new_v4(),
+            name: "From another client".to_string(),
+            description: None,
+            commands: Vec::new(),
+            env_vars: Vec::new(),
+            created_at: chrono::Utc::now(),
+            step_run_as: Vec::new(),
+            notify: Default::default(),
+        });
+        other.refresh().unwrap();
+        assert!(store.list_workflows().is_empty());
+        store.refresh().unwrap();
+        assert_eq!(store.list_workflows()[0].name, "From another client");
+    }
+
+    #[test]
+    fn test_connected_store_catches_up() {
+        let (socket, daemon_store) = start_daemon();
+        let store = CommandStore::connect(DaemonClient::connect(&socket).unwrap()).unwrap();
+
+        let command = Command::new("Daemon test", "true");
+        daemon_store.add_command(command.clone());
+        store.refresh().unwrap();
+        assert_eq!(store.get_command(&command.id).unwrap().script, "true");
+        assert_eq!(store.list_revisions(&command.id).len(), 1);
+        assert_eq!(store.sync_count(), 1);
+
+        // Nothing new
+        store.refresh().unwrap();
+        assert_eq!(store.sync_count(), 1);
+
+        daemon_store.remove_command(&command.id);
+        store.refresh().unwrap();
+        assert!(store.get_command(&command.id).is_none());
+        assert_eq!(store.sync_count(), 2);
+
+        // A point from another daemon's run can't be caught up from
+        let stale = SyncPoint {
+            generation: Uuid::new_v4(),
+            version: 0,
+        };
+        let (delta, point) = daemon_store.changes_since(stale);
+        assert!(delta.is_none());
+        assert!(daemon_store.changes_since(point).0.is_some());
+
+        // Nor one the journal no longer goes back to
+        for _ in 0..300 {
+            daemon_store.set_settings(daemon_store.get_settings());
+        }
+        assert!(daemon_store.changes_since(point).0.is_none());
+    }
+
+    #[test]
+    fn test_daemon_runs_and_records() {
+        let (socket, daemon_store) = start_daemon();
+        let client = DaemonClient::connect(&socket).unwrap();
+        let executor = DaemonExecutor::new(client.clone());
+        let command = Command::new("Daemon test", "echo from the daemon; exit 4");
+        daemon_store.add_command(command.clone());
+
+        let (exec_id, updates) = run(&executor, &command);
+        assert!(matches!(updates.first(), Some(ExecutionUpdate::Started(_))));
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(4))));
+
+        // The daemon saved the run, whether or not anyone was watching
+        let result = daemon_store.get_execution(&exec_id).unwrap();
+        assert_eq!(result.status, ExecutionStatus::Failed);
+        assert!(
+            daemon_store
+                .get_execution_log(&exec_id)
+                .unwrap()
+                .ends_with("from the daemon\n")
+        );
+        match client
+            .request(&DaemonRequest::Log { run_id: exec_id })
+            .unwrap()
+        {
+            DaemonResponse::Log(log) => assert!(log.ends_with("from the daemon\n")),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reattach_and_kill() {
+        let (socket, daemon_store) = start_daemon();
+        let client = DaemonClient::connect(&socket).unwrap();
+        let command = Command::new("Daemon test", "echo started; sleep 30");
+        daemon_store.add_command(command.clone());
+
+        // Started by one client (the CLI, say)...
+        let request = DaemonRequest::StartRun {
+            command_id: command.id,
+            request: RunRequest::default(),
+        };
+        let Ok(DaemonResponse::Started(ids)) = client.request(&request) else {
+            panic!()
+        };
+        for _ in 0..100 {
+            match client.request(&DaemonRequest::Log { run_id: ids[0] }) {
+                Ok(DaemonResponse::Log(log)) if log.contains("started") => break,
+                _ => std::thread::sleep(Duration::from_millis(50)),
+            }
+        }
+        let Ok(DaemonResponse::Runs(active)) = client.request(&DaemonRequest::ListRuns) else {
+            panic!()
+        };
+        assert_eq!(active.iter().map(|r| r.id).collect::<Vec<_>>(), ids);
+
+        // ...followed and stopped by another, which sees the earlier output
+        let (kill_tx, kill_rx) = channel();
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let sink = updates.clone();
+        DaemonExecutor::new(client.clone()).follow(
+            ids[0],
+            Box::new(move |update| sink.lock().unwrap().push(update)),
+            kill_rx,
+        );
+        kill_tx.send(()).unwrap();
+        for _ in 0..100 {
+            if matches!(
+                updates.lock().unwrap().last(),
+                Some(ExecutionUpdate::Exit(_))
+            ) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let updates = updates.lock().unwrap();
+        assert!(
+            updates
+                .iter()
+                .any(|u| matches!(u, ExecutionUpdate::Stdout(s) if s.contains("started")))
+        );
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(code)) if *code != 0));
+        let Ok(DaemonResponse::Runs(active)) = client.request(&DaemonRequest::ListRuns) else {
+            panic!()
+        };
+        assert!(active.is_empty());
+    }
+
+    #[test]
+    fn test_bind_refuses_second_daemon() {
+        let (socket, _) = start_daemon();
+        assert!(matches!(bind(&socket), Err(DaemonError::AlreadyRunning(_))));
+
+        // A socket file left by a daemon that died is replaced
+        let stale = std::env::temp_dir().join(format!("switchboardd_test_{}.sock", Uuid::new_v4()));
+        drop(std::os::unix::net::UnixListener::bind(&stale).unwrap());
+        assert!(bind(&stale).is_ok());
+        assert_eq!(
+            std::fs::metadata(&stale).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert!(
+            !stale
+                .with_file_name(format!(".switchboardd-{}", std::process::id()))
+                .exists()
+        );
+
+        let unknown = DaemonClient::connect(&socket)
+            .unwrap()
+            .request(&DaemonRequest::Kill {
+                run_id: Uuid::new_v4(),
+            });
+        assert!(matches!(unknown, Err(DaemonError::Daemon(_))));
+    }
+}
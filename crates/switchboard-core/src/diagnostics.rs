@@ -0,0 +1,199 @@
+//! Step-by-step connection diagnostics for a host.
+
+use crate::executor::{is_local_host, open_environment};
+use crate::models::{Host, HostKind};
+use crate::run_environment::{RunEnvironment, ssh};
+use crate::workspace::quoted_base;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    /// Works, but something deserves attention (e.g. an unknown host key).
+    Warning,
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiagnosticCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// The checks run against a host, in order. Checks that depend on a failed
+/// one are left out.
+#[derive(Debug, Clone, Default)]
+pub struct HostDiagnostics {
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+impl HostDiagnostics {
+    pub(crate) fn push(&mut self, name: &str, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(DiagnosticCheck {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Failed)
+    }
+
+    /// The first failure, as "check: detail".
+    pub fn first_failure(&self) -> Option<String> {
+        self.checks
+            .iter()
+            .find(|c| c.status == CheckStatus::Failed)
+            .map(|c| format!("{}: {}", c.name, c.detail))
+    }
+}
+
+/// Unpadded standard base64, as OpenSSH prints key fingerprints.
+pub(crate) fn base64_unpadded(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    out
+}
+
+/// Low free space in /tmp or where runs keep their scripts and logs,
+/// below which we warn.
+const LOW_SPACE_KB: u64 = 100 * 1024;
+
+/// Record the free space on the filesystem holding `dir`, which `quoted`
+/// names for the shell, as the `check` step.
+fn free_space(
+    env: &dyn RunEnvironment,
+    report: &mut HostDiagnostics,
+    check: &str,
+    dir: &str,
+    quoted: &str,
+) {
+    // The directory may not exist yet; then its nearest existing parent
+    // tells which filesystem it will be on.
+    let df = format!(
+        "d={}; while [ ! -d \"$d\" ]; do d=$(dirname \"$d\"); done; df -Pk \"$d\" | awk 'NR == 2 {{ print $4 }}'",
+        quoted
+    );
+    match env.capture(&df) {
+        Ok((0, out)) => match out.trim().parse::<u64>() {
+            Ok(kb) => {
+                let detail = format!("{:.1} GB free for {}", kb as f64 / (1024.0 * 1024.0), dir);
+                let status = if kb < LOW_SPACE_KB {
+                    CheckStatus::Warning
+                } else {
+                    CheckStatus::Ok
+                };
+                report.push(check, status, detail);
+            }
+            Err(_) => report.push(
+                check,
+                CheckStatus::Warning,
+                format!("unexpected df output {:?}", out.trim()),
+            ),
+        },
+        Ok((code, _)) => report.push(
+            check,
+            CheckStatus::Warning,
+            format!("df exited with {}", code),
+        ),
+        Err(e) => report.push(check, CheckStatus::Warning, e.to_string()),
+    }
+}
+
+/// Record basic facts about the host: OS, login shell and free space in
+/// /tmp and on the filesystem holding the run workspace.
+fn remote_facts(env: &dyn RunEnvironment, host: &Host, report: &mut HostDiagnostics) {
+    match env.capture("uname -srm") {
+        Ok((0, out)) => report.push("System", CheckStatus::Ok, out.trim()),
+        Ok((code, _)) => report.push(
+            "System",
+            CheckStatus::Warning,
+            format!("uname exited with {}", code),
+        ),
+        Err(e) => {
+            report.push(
+                "System",
+                CheckStatus::Failed,
+                format!("could not run commands: {}", e),
+            );
+            return;
+        }
+    }
+
+    match env.capture("echo \"${SHELL:-unknown}\"; bash --version 2>/dev/null | head -n 1") {
+        Ok((_, out)) => {
+            let mut lines = out.lines();
+            let shell = lines.next().unwrap_or("unknown").trim().to_string();
+            match lines.next() {
+                Some(bash) => report.push(
+                    "Shell",
+                    CheckStatus::Ok,
+                    format!("{} ({})", shell, bash.trim()),
+                ),
+                None => report.push(
+                    "Shell",
+                    CheckStatus::Warning,
+                    format!("{} (bash not found)", shell),
+                ),
+            }
+        }
+        Err(e) => report.push("Shell", CheckStatus::Warning, e.to_string()),
+    }
+
+    free_space(env, report, "Free space in /tmp", "/tmp", "/tmp");
+    let workspace = match host.workspace.dir.trim() {
+        "" => "~",
+        dir => dir,
+    };
+    free_space(
+        env,
+        report,
+        "Free space in workspace",
+        workspace,
+        &quoted_base(&host.workspace),
+    );
+}
+
+/// Run every check that applies to `host`. SSH hosts get network, protocol
+/// and authentication checks; all hosts that can be reached get the remote
+/// facts.
+pub fn diagnose(host: &Host) -> HostDiagnostics {
+    let mut report = HostDiagnostics::default();
+
+    let env: Option<Box<dyn RunEnvironment>> = if host.kind == HostKind::Ssh && !is_local_host(host)
+    {
+        ssh::diagnose(host, &mut report).map(|env| Box::new(env) as Box<dyn RunEnvironment>)
+    } else {
+        let started = Instant::now();
+        match open_environment(host, None) {
+            Ok(env) => {
+                let detail = match &host.kind {
+                    HostKind::Ssh => "running locally".to_string(),
+                    _ => format!("ready in {} ms", started.elapsed().as_millis()),
+                };
+                report.push("Connect", CheckStatus::Ok, detail);
+                Some(env)
+            }
+            Err(e) => {
+                report.push("Connect", CheckStatus::Failed, e.to_string());
+                None
+            }
+        }
+    };
+
+    if let Some(env) = env {
+        remote_facts(env.as_ref(), host, &mut report);
+    }
+    report
+}
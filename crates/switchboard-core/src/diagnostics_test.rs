@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::{CheckStatus, base64_unpadded, diagnose};
+    use crate::models::{AuthMethod, Host};
+    use std::net::TcpListener;
+    use uuid::Uuid;
+
+    fn make_host(hostname: &str, port: u16) -> Host {
+        Host {
+            id: Uuid::new_v4(),
+            name: "diag".into(),
+            hostname: hostname.into(),
+            port,
+            username: "user".into(),
+            auth: AuthMethod::Agent,
+            kind: Default::default(),
+            groups: vec![],
+            tags: vec![],
+            workspace: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_base64_unpadded() {
+        assert_eq!(base64_unpadded(b""), "");
+        assert_eq!(base64_unpadded(b"f"), "Zg");
+        assert_eq!(base64_unpadded(b"fo"), "Zm8");
+        assert_eq!(base64_unpadded(b"foo"), "Zm9v");
+        assert_eq!(base64_unpadded(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64_unpadded(&[0xff, 0xfe]), "//4");
+    }
+
+    #[test]
+    fn test_local_host_reports_facts() {
+        let report = diagnose(&make_host("localhost", 22));
+        assert!(report.succeeded(), "{:?}", report.first_failure());
+        let names: Vec<&str> = report.checks.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names[..2], ["Connect", "System"]);
+        assert!(names.contains(&"Free space in /tmp"));
+        let space = report
+            .checks
+            .iter()
+            .find(|c| c.name == "Free space in workspace")
+            .unwrap();
+        assert!(
+            space.detail.contains("GB free for ~/.switchboard/runs"),
+            "{}",
+            space.detail
+        );
+    }
+
+    #[test]
+    fn test_stops_at_first_failed_step() {
+        let report = diagnose(&make_host("no-such-host.invalid", 22));
+        assert_eq!(report.checks.len(), 1);
+        assert_eq!(report.checks[0].name, "DNS");
+        assert_eq!(report.checks[0].status, CheckStatus::Failed);
+
+        // A port nothing listens on: DNS works, TCP does not.
+        let port = TcpListener::bind("127.0.0.2:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let report = diagnose(&make_host("127.0.0.2", port));
+        let names: Vec<&str> = report.checks.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["DNS", "TCP"]);
+        assert!(report.first_failure().unwrap().starts_with("TCP: "));
+    }
+
+    #[test]
+    fn test_handshake_failure_is_reported() {
+        let listener = TcpListener::bind("127.0.0.2:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            use std::io::Write;
+            if let Ok((mut stream, _)) = listener.accept() {
+                let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n");
+            }
+        });
+
+        let report = diagnose(&make_host("127.0.0.2", port));
+        assert!(!report.succeeded());
+        assert_eq!(report.checks.last().unwrap().name, "SSH handshake");
+    }
+}
@@ -0,0 +1,113 @@
+//! Line diffs laid out side by side, e.g. for comparing two revisions of a
+//! command.
+
+use crate::models::Command;
+use similar::{Algorithm, DiffOp, capture_diff_slices_deadline};
+use std::time::{Duration, Instant};
+
+/// How long `side_by_side` searches for the smallest diff. Past it, the
+/// rest is diffed coarsely: still correct, but with fewer lines paired up.
+pub const DIFF_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Same,
+    /// The old line was replaced by the new one.
+    Changed,
+    Removed,
+    Added,
+}
+
+/// One row of a side-by-side diff. Lines are (1-based line number, text);
+/// a side is `None` where the other side's line has no counterpart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffRow {
+    pub kind: DiffKind,
+    pub old: Option<(usize, String)>,
+    pub new: Option<(usize, String)>,
+}
+
+/// `old` and `new` line by line, with replaced lines paired up. Gives up
+/// on finding the smallest diff after `DIFF_TIMEOUT`.
+pub fn side_by_side(old: &str, new: &str) -> Vec<DiffRow> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let old_line = |i: usize| Some((i + 1, old_lines[i].to_string()));
+    let new_line = |i: usize| Some((i + 1, new_lines[i].to_string()));
+
+    let mut rows = Vec::new();
+    let deadline = Instant::now() + DIFF_TIMEOUT;
+    for op in capture_diff_slices_deadline(Algorithm::Myers, &old_lines, &new_lines, Some(deadline))
+    {
+        match op {
+            DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } => {
+                rows.extend((0..len).map(|i| DiffRow {
+                    kind: DiffKind::Same,
+                    old: old_line(old_index + i),
+                    new: new_line(new_index + i),
+                }));
+            }
+            DiffOp::Delete {
+                old_index, old_len, ..
+            } => {
+                rows.extend((0..old_len).map(|i| DiffRow {
+                    kind: DiffKind::Removed,
+                    old: old_line(old_index + i),
+                    new: None,
+                }));
+            }
+            DiffOp::Insert {
+                new_index, new_len, ..
+            } => {
+                rows.extend((0..new_len).map(|i| DiffRow {
+                    kind: DiffKind::Added,
+                    old: None,
+                    new: new_line(new_index + i),
+                }));
+            }
+            DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => {
+                rows.extend((0..old_len.max(new_len)).map(|i| DiffRow {
+                    kind: match (i < old_len, i < new_len) {
+                        (true, true) => DiffKind::Changed,
+                        (true, false) => DiffKind::Removed,
+                        _ => DiffKind::Added,
+                    },
+                    old: if i < old_len {
+                        old_line(old_index + i)
+                    } else {
+                        None
+                    },
+                    new: if i < new_len {
+                        new_line(new_index + i)
+                    } else {
+                        None
+                    },
+                }));
+            }
+        }
+    }
+    rows
+}
+
+/// Names of the settings other than the script that differ between two
+/// versions of a command, e.g. "working_directory".
+pub fn changed_settings(old: &Command, new: &Command) -> Vec<String> {
+    let (Ok(serde_json::Value::Object(old)), Ok(new)) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        return Vec::new();
+    };
+    old.iter()
+        .filter(|(key, value)| key.as_str() != "script" && new.get(key.as_str()) != Some(value))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
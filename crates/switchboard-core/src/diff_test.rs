@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use crate::diff::{DIFF_TIMEOUT, DiffKind, DiffRow, changed_settings, side_by_side};
+    use crate::models::Command;
+    use std::time::Instant;
+
+    fn kinds(rows: &[DiffRow]) -> Vec<DiffKind> {
+        rows.iter().map(|r| r.kind).collect()
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        let rows = side_by_side(
+            "set -e\ncd /srv\nmake\nmake install\n",
+            "set -e\ncd /opt\nmake\nmake test\nmake install",
+        );
+        assert_eq!(
+            kinds(&rows),
+            vec![
+                DiffKind::Same,
+                DiffKind::Changed,
+                DiffKind::Same,
+                DiffKind::Added,
+                DiffKind::Same
+            ]
+        );
+        assert_eq!(rows[1].old, Some((2, "cd /srv".to_string())));
+        assert_eq!(rows[1].new, Some((2, "cd /opt".to_string())));
+        assert_eq!(rows[3].old, None);
+        // Line numbers stay with their side
+        assert_eq!(rows[4].old, Some((4, "make install".to_string())));
+        assert_eq!(rows[4].new, Some((5, "make install".to_string())));
+
+        // Uneven replacements are padded on the shorter side
+        let rows = side_by_side("a\nb\nc", "a\nx");
+        assert_eq!(
+            kinds(&rows),
+            vec![DiffKind::Same, DiffKind::Changed, DiffKind::Removed]
+        );
+        assert!(side_by_side("", "").is_empty());
+    }
+
+    #[test]
+    fn test_side_by_side_gives_up_on_huge_diffs() {
+        // Timestamped logs differ on nearly every line
+        let log = |run: usize| {
+            (0..20_000)
+                .map(|i| format!("12:00:{:02}.{:03}{} step {}", i % 60, i % 1000, run, i))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let (old, new) = (log(1), log(2));
+
+        let started = Instant::now();
+        let rows = side_by_side(&old, &new);
+        assert!(started.elapsed() < DIFF_TIMEOUT * 10);
+        assert_eq!(rows.iter().filter(|r| r.old.is_some()).count(), 20_000);
+        assert_eq!(rows.iter().filter(|r| r.new.is_some()).count(), 20_000);
+    }
+
+    #[test]
+    fn test_changed_settings() {
+        let old = Command::new("Deploy", "make");
+        let mut new = old.clone();
+        new.script = "make deploy".into();
+        assert!(changed_settings(&old, &new).is_empty());
+
+        new.working_directory = Some("/srv".into());
+        new.background = true;
+        assert_eq!(
+            changed_settings(&old, &new),
+            vec!["background", "working_directory"]
+        );
+    }
+}
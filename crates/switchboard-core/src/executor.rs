@@ -1,6 +1,10 @@
-use crate::models::{Command, ExecutionUpdate, Host};
-use crate::orchestration::orchestrate_execution;
-use crate::run_environment::{LocalRunEnvironment, RunEnvironment, SshRunEnvironment};
+use crate::diagnostics::HostDiagnostics;
+use crate::models::{BackgroundJob, Command, ContainerMode, ExecutionUpdate, Host, HostKind};
+use crate::orchestration::{orchestrate_execution, watch_background};
+use crate::run_environment::{
+    KillSwitch, LocalRunEnvironment, RunEnvironment, RunEnvironmentError, SshRunEnvironment,
+    container, kubernetes,
+};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -9,6 +13,8 @@ pub enum ExecuteError {
     SshError(String),
     #[error("Connection failed")]
     ConnectionFailed,
+    #[error("Daemon error: {0}")]
+    Daemon(String),
 }
 
 pub trait CommandExecutor: Send + Sync {
@@ -23,10 +29,57 @@ pub trait CommandExecutor: Send + Sync {
         on_update: Box<dyn Fn(ExecutionUpdate) + Send + Sync>,
         kill_rx: std::sync::mpsc::Receiver<()>,
     ) -> Result<(), ExecuteError>;
+
+    /// Resume following a background job started by an earlier `execute`,
+    /// e.g. after the app restarted. Streams the job's log from the start,
+    /// then its exit code; a kill request stops the job.
+    fn attach_background(
+        &self,
+        host: &Host,
+        job: &BackgroundJob,
+        on_update: Box<dyn Fn(ExecutionUpdate) + Send + Sync>,
+        kill_rx: std::sync::mpsc::Receiver<()>,
+    ) -> Result<(), ExecuteError>;
+
+    /// Check step by step whether and how `host` can be reached. Blocks
+    /// until done.
+    fn diagnose(&self, host: &Host) -> HostDiagnostics;
 }
 
 pub struct Executor;
 
+/// Whether `host` means this machine rather than somewhere reached over SSH.
+pub(crate) fn is_local_host(host: &Host) -> bool {
+    host.name.to_lowercase() == "local"
+        || host.hostname.to_lowercase() == "localhost"
+        || host.hostname == "127.0.0.1"
+}
+
+pub(crate) fn open_environment(
+    host: &Host,
+    work_dir: Option<&str>,
+) -> Result<Box<dyn RunEnvironment>, RunEnvironmentError> {
+    match &host.kind {
+        HostKind::Ssh => {}
+        HostKind::Container(target) => {
+            return Ok(Box::new(container::start(
+                target.runtime.binary(),
+                target,
+                work_dir,
+            )?));
+        }
+        HostKind::Kubernetes(target) => {
+            return Ok(Box::new(kubernetes::connect("kubectl", target)?));
+        }
+    }
+
+    if is_local_host(host) {
+        Ok(Box::new(LocalRunEnvironment::new()))
+    } else {
+        Ok(Box::new(SshRunEnvironment::connect(host)?))
+    }
+}
+
 impl CommandExecutor for Executor {
     fn execute(
         &self,
@@ -37,26 +90,18 @@ impl CommandExecutor for Executor {
         on_update: Box<dyn Fn(ExecutionUpdate) + Send + Sync>,
         kill_rx: std::sync::mpsc::Receiver<()>,
     ) -> Result<(), ExecuteError> {
-        let is_local = host.name.to_lowercase() == "local"
-            || host.hostname.to_lowercase() == "localhost"
-            || host.hostname == "127.0.0.1";
-
         let command = command.clone();
         let host = host.clone();
 
         std::thread::spawn(move || {
             on_update(ExecutionUpdate::Started(command.id));
 
-            let env: Box<dyn RunEnvironment> = if is_local {
-                Box::new(LocalRunEnvironment::new())
-            } else {
-                match SshRunEnvironment::connect(&host) {
-                    Ok(e) => Box::new(e),
-                    Err(e) => {
-                        on_update(ExecutionUpdate::Stderr(format!("{}", e)));
-                        on_update(ExecutionUpdate::Exit(-1));
-                        return;
-                    }
+            let env = match open_environment(&host, command.working_directory.as_deref()) {
+                Ok(env) => env,
+                Err(e) => {
+                    on_update(ExecutionUpdate::Stderr(format!("{}", e)));
+                    on_update(ExecutionUpdate::Exit(-1));
+                    return;
                 }
             };
 
@@ -70,4 +115,58 @@ impl CommandExecutor for Executor {
 
         Ok(())
     }
+
+    fn attach_background(
+        &self,
+        host: &Host,
+        job: &BackgroundJob,
+        on_update: Box<dyn Fn(ExecutionUpdate) + Send + Sync>,
+        kill_rx: std::sync::mpsc::Receiver<()>,
+    ) -> Result<(), ExecuteError> {
+        let host = host.clone();
+        let job = job.clone();
+
+        std::thread::spawn(move || {
+            // A throwaway container is removed when its run ends, taking any
+            // background job with it.
+            if let HostKind::Container(target) = &host.kind
+                && matches!(target.mode, ContainerMode::Image { .. })
+            {
+                on_update(ExecutionUpdate::Stderr(
+                    "Background job ended with its throwaway container\n".to_string(),
+                ));
+                on_update(ExecutionUpdate::Exit(-1));
+                return;
+            }
+
+            let env = match open_environment(&host, None) {
+                Ok(env) => env,
+                Err(e) => {
+                    on_update(ExecutionUpdate::Stderr(format!("{}", e)));
+                    on_update(ExecutionUpdate::Exit(-1));
+                    return;
+                }
+            };
+
+            let result = KillSwitch::new(kill_rx)
+                .map_err(RunEnvironmentError::from)
+                .and_then(|kill| watch_background(env.as_ref(), &job, 0, &*on_update, &kill));
+            match result {
+                Ok(code) => {
+                    crate::workspace::finish_run_dir(env.as_ref(), &host.workspace, &job.run_dir);
+                    on_update(ExecutionUpdate::Exit(code))
+                }
+                Err(e) => {
+                    on_update(ExecutionUpdate::Stderr(format!("Execution error: {}", e)));
+                    on_update(ExecutionUpdate::Exit(-1));
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn diagnose(&self, host: &Host) -> HostDiagnostics {
+        crate::diagnostics::diagnose(host)
+    }
 }
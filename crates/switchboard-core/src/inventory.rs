@@ -0,0 +1,463 @@
+//! Import of Ansible inventories (INI or YAML) into `Host` records.
+
+use crate::models::{AuthMethod, Host, HostKind};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum InventoryError {
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+    #[error("group {0:?} is its own ancestor through children")]
+    Cycle(String),
+}
+
+/// Groups every host is implicitly part of; not worth recording.
+const IMPLICIT_GROUPS: [&str; 2] = ["all", "ungrouped"];
+
+#[derive(Default)]
+struct Group {
+    hosts: BTreeSet<String>,
+    children: BTreeSet<String>,
+    vars: BTreeMap<String, String>,
+}
+
+/// An inventory as written: groups with their hosts, child groups and vars,
+/// plus per-host vars.
+#[derive(Default)]
+struct Inventory {
+    groups: BTreeMap<String, Group>,
+    host_vars: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Inventory {
+    fn add_host(&mut self, group: &str, host: &str, vars: BTreeMap<String, String>) {
+        self.groups
+            .entry(group.to_string())
+            .or_default()
+            .hosts
+            .insert(host.to_string());
+        self.host_vars
+            .entry(host.to_string())
+            .or_default()
+            .extend(vars);
+    }
+
+    /// Parent groups of each group, from the `children` relation.
+    fn parents(&self) -> BTreeMap<&str, Vec<&str>> {
+        let mut parents: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (name, group) in &self.groups {
+            for child in &group.children {
+                parents
+                    .entry(child.as_str())
+                    .or_default()
+                    .push(name.as_str());
+            }
+        }
+        parents
+    }
+
+    /// `group` and all its ancestors, ancestors first, each listed once.
+    /// `path` holds the groups being looked up, to catch a cycle.
+    fn lineage<'a>(
+        &'a self,
+        group: &'a str,
+        parents: &BTreeMap<&'a str, Vec<&'a str>>,
+        path: &mut Vec<&'a str>,
+        out: &mut Vec<&'a str>,
+    ) -> Result<(), InventoryError> {
+        if path.contains(&group) {
+            return Err(InventoryError::Cycle(group.to_string()));
+        }
+        if out.contains(&group) {
+            return Ok(());
+        }
+        path.push(group);
+        for parent in parents.get(group).into_iter().flatten() {
+            self.lineage(parent, parents, path, out)?;
+        }
+        path.pop();
+        out.push(group);
+        Ok(())
+    }
+
+    fn into_hosts(self) -> Result<Vec<Host>, InventoryError> {
+        let parents = self.parents();
+        let mut hosts = Vec::new();
+
+        for (name, own_vars) in &self.host_vars {
+            // Vars apply from the widest group to the narrowest, then the
+            // host's own; `all` always comes first.
+            let mut lineage = vec!["all"];
+            for (group_name, group) in &self.groups {
+                if group.hosts.contains(name) {
+                    self.lineage(group_name, &parents, &mut Vec::new(), &mut lineage)?;
+                }
+            }
+
+            let mut vars = BTreeMap::new();
+            for group in &lineage {
+                if let Some(group) = self.groups.get(*group) {
+                    vars.extend(group.vars.clone());
+                }
+            }
+            vars.extend(own_vars.clone());
+
+            let groups: BTreeSet<&str> = lineage
+                .into_iter()
+                .filter(|g| !IMPLICIT_GROUPS.contains(g))
+                .collect();
+            hosts.push(host_from_vars(
+                name,
+                groups.into_iter().map(str::to_string).collect(),
+                vars,
+            ));
+        }
+        Ok(hosts)
+    }
+}
+
+/// Build a host from its inventory vars. Connection vars map onto the host's
+/// fields; other `ansible_*` vars are dropped and the rest become tags.
+fn host_from_vars(name: &str, groups: Vec<String>, mut vars: BTreeMap<String, String>) -> Host {
+    let mut take = |keys: &[&str]| {
+        let mut found = None;
+        for key in keys {
+            if let Some(value) = vars.remove(*key) {
+                found.get_or_insert(value);
+            }
+        }
+        found
+    };
+
+    let hostname = take(&["ansible_host", "ansible_ssh_host"]).unwrap_or_else(|| name.to_string());
+    let port = take(&["ansible_port", "ansible_ssh_port"])
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(22);
+    // Like ssh (and Ansible), connect as the current user by default
+    let username = take(&["ansible_user", "ansible_ssh_user"])
+        .unwrap_or_else(|| std::env::var("USER").unwrap_or_default());
+    let key_file = take(&["ansible_ssh_private_key_file", "ansible_private_key_file"]);
+    let password = take(&["ansible_password", "ansible_ssh_pass"]);
+    let auth = match (key_file, password) {
+        (Some(path), _) => AuthMethod::KeyFile(path),
+        (None, Some(password)) => AuthMethod::Password(password),
+        (None, None) => AuthMethod::Agent,
+    };
+
+    let tags = vars
+        .into_iter()
+        .filter(|(k, _)| !k.starts_with("ansible_"))
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect();
+
+    Host {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        hostname,
+        port,
+        username,
+        auth,
+        kind: HostKind::Ssh,
+        groups,
+        tags,
+        workspace: Default::default(),
+    }
+}
+
+/// Split an INI host line into words, honouring single and double quotes.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote = None;
+    let mut in_word = false;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            None => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Expand an Ansible host range such as `web[01:03]` or `db-[a:c]`.
+fn expand_range(pattern: &str) -> Option<Vec<String>> {
+    let open = pattern.find('[')?;
+    let close = open + pattern[open..].find(']')?;
+    let (prefix, range, suffix) = (
+        &pattern[..open],
+        &pattern[open + 1..close],
+        &pattern[close + 1..],
+    );
+    let (start, end) = range.split_once(':')?;
+
+    let items: Vec<String> = if let (Ok(a), Ok(b)) = (start.parse::<u32>(), end.parse::<u32>()) {
+        let width = if start.starts_with('0') {
+            start.len()
+        } else {
+            0
+        };
+        (a..=b)
+            .map(|n| format!("{:0width$}", n, width = width))
+            .collect()
+    } else {
+        let (a, b) = (start.chars().next()?, end.chars().next()?);
+        if start.len() != 1 || end.len() != 1 {
+            return None;
+        }
+        (a..=b).map(|c| c.to_string()).collect()
+    };
+
+    let mut out = Vec::new();
+    for item in items {
+        let name = format!("{}{}{}", prefix, item, suffix);
+        match expand_range(&name) {
+            Some(more) => out.extend(more),
+            None => out.push(name),
+        }
+    }
+    Some(out)
+}
+
+fn parse_ini(text: &str) -> Result<Inventory, InventoryError> {
+    enum Section {
+        Hosts(String),
+        Vars(String),
+        Children(String),
+    }
+
+    let mut inv = Inventory::default();
+    let mut section = Section::Hosts("ungrouped".to_string());
+
+    for (i, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let error = |message: String| InventoryError::Parse {
+            line: i + 1,
+            message,
+        };
+
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header
+                .strip_suffix(']')
+                .ok_or_else(|| error(format!("unterminated section header {:?}", line)))?;
+            section = match header.split_once(':') {
+                Some((group, "vars")) => Section::Vars(group.to_string()),
+                Some((group, "children")) => Section::Children(group.to_string()),
+                Some((_, kind)) => return Err(error(format!("unknown section type {:?}", kind))),
+                None => Section::Hosts(header.to_string()),
+            };
+            let (Section::Hosts(g) | Section::Vars(g) | Section::Children(g)) = &section;
+            inv.groups.entry(g.clone()).or_default();
+            continue;
+        }
+
+        match &section {
+            Section::Hosts(group) => {
+                let mut words = split_words(line).into_iter();
+                let Some(pattern) = words.next() else {
+                    continue;
+                };
+                let mut vars = BTreeMap::new();
+                for word in words {
+                    let (key, value) = word
+                        .split_once('=')
+                        .ok_or_else(|| error(format!("expected key=value, got {:?}", word)))?;
+                    vars.insert(key.to_string(), value.to_string());
+                }
+                for host in expand_range(&pattern).unwrap_or_else(|| vec![pattern.clone()]) {
+                    inv.add_host(group, &host, vars.clone());
+                }
+            }
+            Section::Vars(group) => {
+                let (key, value) = line
+                    .split_once('=')
+                    .ok_or_else(|| error(format!("expected key=value, got {:?}", line)))?;
+                let value = split_words(value).join(" ");
+                inv.groups
+                    .entry(group.clone())
+                    .or_default()
+                    .vars
+                    .insert(key.trim().to_string(), value);
+            }
+            Section::Children(group) => {
+                inv.groups.entry(line.to_string()).or_default();
+                inv.groups
+                    .entry(group.clone())
+                    .or_default()
+                    .children
+                    .insert(line.to_string());
+            }
+        }
+    }
+    Ok(inv)
+}
+
+fn yaml_scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn yaml_vars(value: Option<&serde_yaml::Value>) -> BTreeMap<String, String> {
+    let mut vars = BTreeMap::new();
+    if let Some(serde_yaml::Value::Mapping(map)) = value {
+        for (k, v) in map {
+            if let (Some(k), Some(v)) = (yaml_scalar(k), yaml_scalar(v)) {
+                vars.insert(k, v);
+            }
+        }
+    }
+    vars
+}
+
+fn parse_yaml_group(inv: &mut Inventory, name: &str, value: &serde_yaml::Value) {
+    inv.groups.entry(name.to_string()).or_default();
+    let vars = yaml_vars(value.get("vars"));
+    inv.groups
+        .entry(name.to_string())
+        .or_default()
+        .vars
+        .extend(vars);
+
+    if let Some(serde_yaml::Value::Mapping(hosts)) = value.get("hosts") {
+        for (host, host_vars) in hosts {
+            let Some(host) = yaml_scalar(host) else {
+                continue;
+            };
+            let vars = yaml_vars(Some(host_vars));
+            for host in expand_range(&host).unwrap_or_else(|| vec![host.clone()]) {
+                inv.add_host(name, &host, vars.clone());
+            }
+        }
+    }
+
+    if let Some(serde_yaml::Value::Mapping(children)) = value.get("children") {
+        for (child, child_value) in children {
+            let Some(child) = yaml_scalar(child) else {
+                continue;
+            };
+            inv.groups
+                .entry(name.to_string())
+                .or_default()
+                .children
+                .insert(child.clone());
+            parse_yaml_group(inv, &child, child_value);
+        }
+    }
+}
+
+fn parse_yaml(value: &serde_yaml::Mapping) -> Inventory {
+    let mut inv = Inventory::default();
+    for (name, group) in value {
+        if let Some(name) = yaml_scalar(name) {
+            parse_yaml_group(&mut inv, &name, group);
+        }
+    }
+    inv
+}
+
+/// Parse an Ansible inventory in INI or YAML form into hosts, sorted by
+/// name. Group membership includes parent groups reached through
+/// `children`; group vars apply to member hosts with host vars winning.
+/// Hosts without `ansible_user` connect as the current user.
+pub fn parse_inventory(text: &str) -> Result<Vec<Host>, InventoryError> {
+    let looks_ini = text
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with(';'))
+        .is_some_and(|l| l.starts_with('['));
+
+    let inv = if looks_ini {
+        parse_ini(text)?
+    } else {
+        match serde_yaml::from_str::<serde_yaml::Value>(text) {
+            Ok(serde_yaml::Value::Mapping(map)) => parse_yaml(&map),
+            // A bare list of host lines is valid INI but not a YAML mapping.
+            Ok(_) => parse_ini(text)?,
+            Err(e) if text.contains(": ") || text.trim_start().starts_with("---") => {
+                return Err(e.into());
+            }
+            Err(_) => parse_ini(text)?,
+        }
+    };
+    inv.into_hosts()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HostImportAction {
+    Create,
+    /// Replaces the existing host of the same name, keeping its id.
+    Update,
+    Unchanged,
+    /// A host of this name exists and re-sync is off.
+    Skip,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostImportItem {
+    pub host: Host,
+    pub action: HostImportAction,
+}
+
+/// Decide what importing `imported` would do given the `existing` hosts.
+/// With `resync` hosts are matched by name and updated in place; without
+/// it, names that already exist are skipped.
+pub fn plan_host_import(
+    existing: &[Host],
+    imported: Vec<Host>,
+    resync: bool,
+) -> Vec<HostImportItem> {
+    imported
+        .into_iter()
+        .map(
+            |mut host| match existing.iter().find(|h| h.name == host.name) {
+                None => HostImportItem {
+                    host,
+                    action: HostImportAction::Create,
+                },
+                Some(_) if !resync => HostImportItem {
+                    host,
+                    action: HostImportAction::Skip,
+                },
+                Some(current) => {
+                    // The inventory doesn't describe the workspace; keep ours.
+                    host.id = current.id;
+                    host.workspace = current.workspace.clone();
+                    let action = if *current == host {
+                        HostImportAction::Unchanged
+                    } else {
+                        HostImportAction::Update
+                    };
+                    HostImportItem { host, action }
+                }
+            },
+        )
+        .collect()
+}
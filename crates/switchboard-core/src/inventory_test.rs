@@ -0,0 +1,173 @@
+#[cfg(test)]
+mod tests {
+    use crate::inventory::{HostImportAction, InventoryError, parse_inventory, plan_host_import};
+    use crate::models::{AuthMethod, Host};
+
+    fn find<'a>(hosts: &'a [Host], name: &str) -> &'a Host {
+        hosts.iter().find(|h| h.name == name).expect("host missing")
+    }
+
+    #[test]
+    fn test_parse_ini_inventory() {
+        let hosts = parse_inventory(
+            r#"
+# staging
+bastion ansible_host=203.0.113.5
+
+[web]
+web[01:02].example.com ansible_user=deploy
+web-canary ansible_host=10.0.0.9 ansible_port=2222 role="web canary"
+
+[db]
+db1 ansible_host=10.0.1.1 ansible_ssh_private_key_file=~/.ssh/db.pem
+
+[web:vars]
+role=web
+ansible_user=www
+
+[staging:children]
+web
+db
+
+[staging:vars]
+env=staging
+"#,
+        )
+        .unwrap();
+
+        let names: Vec<&str> = hosts.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "bastion",
+                "db1",
+                "web-canary",
+                "web01.example.com",
+                "web02.example.com"
+            ]
+        );
+
+        let bastion = find(&hosts, "bastion");
+        assert_eq!(bastion.hostname, "203.0.113.5");
+        assert!(bastion.groups.is_empty());
+
+        let web1 = find(&hosts, "web01.example.com");
+        assert_eq!(web1.hostname, "web01.example.com");
+        assert_eq!(web1.username, "deploy");
+        assert_eq!(web1.groups, vec!["staging", "web"]);
+        assert_eq!(web1.tags, vec!["env=staging", "role=web"]);
+        assert!(web1.matches_selector("role=web,env=staging"));
+
+        let canary = find(&hosts, "web-canary");
+        assert_eq!((canary.hostname.as_str(), canary.port), ("10.0.0.9", 2222));
+        assert_eq!(canary.username, "www");
+        assert_eq!(canary.tags, vec!["env=staging", "role=web canary"]);
+
+        let db1 = find(&hosts, "db1");
+        assert_eq!(db1.auth, AuthMethod::KeyFile("~/.ssh/db.pem".into()));
+        assert_eq!(db1.groups, vec!["db", "staging"]);
+    }
+
+    #[test]
+    fn test_parse_yaml_inventory() {
+        let hosts = parse_inventory(
+            r#"
+all:
+  vars:
+    ansible_user: admin
+  hosts:
+    bastion:
+      ansible_host: 203.0.113.5
+  children:
+    web:
+      vars:
+        role: web
+      hosts:
+        web1:
+          ansible_port: 2200
+        web2:
+          ansible_user: deploy
+    prod:
+      children:
+        web:
+      vars:
+        env: prod
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(hosts.len(), 3);
+        let bastion = find(&hosts, "bastion");
+        assert_eq!(bastion.username, "admin");
+        assert!(bastion.groups.is_empty());
+
+        let web1 = find(&hosts, "web1");
+        assert_eq!(web1.port, 2200);
+        assert_eq!(web1.username, "admin");
+        assert_eq!(web1.groups, vec!["prod", "web"]);
+        assert_eq!(web1.tags, vec!["env=prod", "role=web"]);
+
+        assert_eq!(find(&hosts, "web2").username, "deploy");
+    }
+
+    #[test]
+    fn test_parse_errors_report_line() {
+        let err = parse_inventory("[web]\nweb1 not-a-var\n").unwrap_err();
+        assert!(err.to_string().starts_with("line 2:"), "{}", err);
+    }
+
+    #[test]
+    fn test_cyclic_children_are_an_error() {
+        let err = parse_inventory("[a]\nweb1\n\n[a:children]\nb\n\n[b:children]\na\n").unwrap_err();
+        assert!(matches!(err, InventoryError::Cycle(_)), "{}", err);
+
+        let yaml = "a:\n  hosts:\n    web1:\n  children:\n    b:\n      children:\n        a:\n";
+        assert!(matches!(
+            parse_inventory(yaml),
+            Err(InventoryError::Cycle(_))
+        ));
+
+        // Reaching a group twice without a cycle is fine
+        let hosts =
+            parse_inventory("[web]\nweb1\n\n[prod:children]\nweb\n\n[all:children]\nprod\nweb\n")
+                .unwrap();
+        assert_eq!(hosts[0].groups, vec!["prod", "web"]);
+    }
+
+    #[test]
+    fn test_missing_user_is_the_current_user() {
+        let hosts = parse_inventory("web1 ansible_host=10.0.0.1\n").unwrap();
+        assert_eq!(hosts[0].username, std::env::var("USER").unwrap_or_default());
+    }
+
+    #[test]
+    fn test_plan_import_resync_by_name() {
+        let existing = parse_inventory("[web]\nweb1 ansible_host=10.0.0.1\nweb2\n").unwrap();
+        let imported = parse_inventory("[web]\nweb1 ansible_host=10.0.0.99\nweb2\nweb3\n").unwrap();
+
+        let plan = plan_host_import(&existing, imported.clone(), false);
+        let actions: Vec<_> = plan.iter().map(|i| i.action).collect();
+        assert_eq!(
+            actions,
+            vec![
+                HostImportAction::Skip,
+                HostImportAction::Skip,
+                HostImportAction::Create
+            ]
+        );
+
+        let plan = plan_host_import(&existing, imported, true);
+        let actions: Vec<_> = plan.iter().map(|i| i.action).collect();
+        assert_eq!(
+            actions,
+            vec![
+                HostImportAction::Update,
+                HostImportAction::Unchanged,
+                HostImportAction::Create
+            ]
+        );
+        // Updates keep the existing record's id
+        assert_eq!(plan[0].host.id, existing[0].id);
+        assert_eq!(plan[0].host.hostname, "10.0.0.99");
+    }
+}
@@ -1,14 +1,67 @@
+pub mod api;
+pub mod bundle;
+pub mod compare;
+pub mod daemon;
+pub mod diagnostics;
+pub mod diff;
 pub mod executor;
+pub mod inventory;
+pub mod library;
+pub mod merge;
 pub mod models;
+pub mod notifications;
 pub mod persistence;
+pub mod runs;
+pub mod script_import;
+pub mod search;
+pub mod secrets;
+pub mod shell;
+pub mod snapshots;
+pub mod stats;
 pub mod store;
 pub(crate) mod orchestration;
 pub(crate) mod run_environment;
+pub(crate) mod transfer;
+pub(crate) mod workspace;
 
 pub use executor::*;
 pub use models::*;
 pub use persistence::*;
 pub use store::CommandStore;
 
+#[cfg(test)]
+mod api_test;
+#[cfg(test)]
+mod bundle_test;
+#[cfg(test)]
+mod compare_test;
+#[cfg(test)]
+mod daemon_test;
+#[cfg(test)]
+mod diagnostics_test;
+#[cfg(test)]
+mod diff_test;
+#[cfg(test)]
+mod inventory_test;
+#[cfg(test)]
+mod library_test;
+#[cfg(test)]
+mod merge_test;
+#[cfg(test)]
+mod notifications_test;
+#[cfg(test)]
+mod orchestration_test;
+#[cfg(test)]
+mod script_import_test;
+#[cfg(test)]
+mod search_test;
+#[cfg(test)]
+mod shell_test;
+#[cfg(test)]
+mod snapshots_test;
+#[cfg(test)]
+mod stats_test;
 #[cfg(test)]
 mod store_test;
+#[cfg(test)]
+mod transfer_test;
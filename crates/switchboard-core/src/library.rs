@@ -0,0 +1,134 @@
+//! Libraries: checkouts of shared repositories of commands and workflows,
+//! kept in the bundle layout (see `bundle`). Their items are listed next to
+//! the store's own but can't be changed in Switchboard; they're reloaded
+//! when the files change, and a command is forked into the store to make
+//! it one's own (see `CommandStore::fork_command`).
+
+use crate::bundle;
+use crate::models::{Command, LibrarySource, Workflow};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime};
+
+/// How often libraries are checked for changes (see
+/// `CommandStore::reload_libraries`).
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The files whose changes reload a library. `.git/logs/HEAD` changes
+/// with every commit, pull and checkout, even ones that leave the library's
+/// files as they were.
+const WATCHED: [&str; 5] = [
+    "bundle.yaml",
+    "commands",
+    "workflows",
+    ".git/HEAD",
+    ".git/logs/HEAD",
+];
+
+type Fingerprint = Vec<(PathBuf, u64, Option<SystemTime>)>;
+
+#[derive(Debug, Clone)]
+pub struct Library {
+    pub source: LibrarySource,
+    /// The commit the checkout is at, with "-dirty" appended when the
+    /// library's files have uncommitted changes. `None` outside git.
+    pub commit: Option<String>,
+    pub commands: Vec<Command>,
+    pub workflows: Vec<Workflow>,
+    /// Why the library couldn't be read; it has no items then.
+    pub error: Option<String>,
+    fingerprint: Fingerprint,
+}
+
+impl Library {
+    pub fn load(source: &LibrarySource) -> Self {
+        let fingerprint = fingerprint(&source.path);
+        let (commands, workflows, error) = match bundle::read_bundle(&source.path) {
+            Ok(bundle) => (bundle.commands, bundle.workflows, None),
+            Err(e) => (Vec::new(), Vec::new(), Some(e.to_string())),
+        };
+        Library {
+            source: source.clone(),
+            commit: head_commit(&source.path),
+            commands,
+            workflows,
+            error,
+            fingerprint,
+        }
+    }
+
+    /// Whether this is `source` as it is on disk now.
+    pub(crate) fn is_current(&self, source: &LibrarySource) -> bool {
+        self.source == *source && self.fingerprint == fingerprint(&source.path)
+    }
+
+    pub fn contains(&self, id: &uuid::Uuid) -> bool {
+        self.commands.iter().any(|c| c.id == *id) || self.workflows.iter().any(|w| w.id == *id)
+    }
+}
+
+/// A commit hash as it's usually shown, e.g. "3f2a9c1".
+pub fn short_commit(commit: &str) -> String {
+    match commit.strip_suffix("-dirty") {
+        Some(hash) => format!("{}-dirty", &hash[..hash.len().min(7)]),
+        None => commit[..commit.len().min(7)].to_string(),
+    }
+}
+
+fn fingerprint(dir: &Path) -> Fingerprint {
+    let mut files = Vec::new();
+    for name in WATCHED {
+        let path = dir.join(name);
+        let entries: Vec<PathBuf> = if path.is_dir() {
+            std::fs::read_dir(&path)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|e| e.path())
+                .collect()
+        } else {
+            vec![path]
+        };
+        for path in entries {
+            if let Ok(meta) = std::fs::metadata(&path) {
+                files.push((path, meta.len(), meta.modified().ok()));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn head_commit(dir: &Path) -> Option<String> {
+    let commit = git(dir, &["rev-parse", "HEAD"])?;
+    let changes = git(
+        dir,
+        &[
+            "status",
+            "--porcelain",
+            "--",
+            "bundle.yaml",
+            "commands",
+            "workflows",
+        ],
+    );
+    Some(match changes {
+        Some(changes) if !changes.is_empty() => format!("{}-dirty", commit),
+        _ => commit,
+    })
+}
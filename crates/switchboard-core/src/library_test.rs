@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests {
+    use crate::library::short_commit;
+    use crate::models::{Command, LibrarySource};
+    use crate::store::CommandStore;
+    use std::path::Path;
+    use uuid::Uuid;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    fn use_library(store: &CommandStore, dir: &Path) {
+        let mut settings = store.get_settings();
+        settings.libraries.push(LibrarySource {
+            name: "Ops".into(),
+            path: dir.to_path_buf(),
+            enabled: true,
+        });
+        store.set_settings(settings);
+    }
+
+    #[test]
+    fn test_library_commands() {
+        let dir = std::env::temp_dir().join(format!("switchboard_library_test_{}", Uuid::new_v4()));
+        let team = CommandStore::new_test();
+        let deploy = Command::new("Deploy", "make deploy\n");
+        team.add_command(deploy.clone());
+        team.export_bundle(&dir, &[deploy.id], &[]).unwrap();
+        git(&dir, &["init", "-q"]);
+        git(&dir, &["add", "-A"]);
+        git(&dir, &["commit", "-q", "-m", "Add deploy"]);
+
+        let store = CommandStore::new_test();
+        use_library(&store, &dir);
+        assert!(store.list_commands().is_empty());
+        assert_eq!(
+            store.get_command(&deploy.id).unwrap().script,
+            "make deploy\n"
+        );
+        let library = store.library_of(&deploy.id).unwrap();
+        let commit = library.commit.clone().unwrap();
+        assert_eq!(commit.len(), 40);
+        assert_eq!(short_commit(&commit), commit[..7]);
+        assert!(!store.reload_libraries());
+
+        // Changing a file reloads it, and marks the checkout dirty
+        std::fs::write(dir.join("commands/deploy.sh"), "make deploy-all\n").unwrap();
+        assert!(store.reload_libraries());
+        assert_eq!(
+            store.get_command(&deploy.id).unwrap().script,
+            "make deploy-all\n"
+        );
+        assert_eq!(
+            store.library_of(&deploy.id).unwrap().commit,
+            Some(format!("{}-dirty", commit))
+        );
+
+        let fork = store.fork_command(&deploy.id).unwrap();
+        assert_ne!(fork.id, deploy.id);
+        assert_eq!(store.list_commands().len(), 1);
+        assert!(store.library_of(&fork.id).is_none());
+        assert!(store.fork_command(&fork.id).is_none());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_unreadable_library() {
+        let store = CommandStore::new_test();
+        use_library(&store, Path::new("/nonexistent/switchboard-library"));
+        let libraries = store.list_libraries();
+        assert_eq!(libraries.len(), 1);
+        assert!(libraries[0].error.is_some());
+        assert!(libraries[0].commit.is_none());
+    }
+}
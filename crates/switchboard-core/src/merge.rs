@@ -0,0 +1,328 @@
+//! Merging another store's export into this one item by item, rather than
+//! replacing everything like `CommandStore::import_json`. `plan` decides
+//! what each command, workflow and host in the file would do, the user
+//! settles the conflicts, then `CommandStore::apply_merge` writes it.
+
+use crate::models::{Command, Host, RunAs, RunAsSettings, Workflow};
+use crate::snapshots::{ItemKind, comparable};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MergeConflict {
+    None,
+    /// This store has an item with the same id and different contents.
+    SameId,
+    /// This store has a different item (this id) with the same name.
+    SameName(Uuid),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MergeAction {
+    Create,
+    /// Already here as it is in the file.
+    Unchanged,
+    /// Keep this store's item and leave the file's out.
+    Keep,
+    /// Replace this store's item with the file's, keeping its id.
+    Overwrite,
+    /// Add the file's item as a new one next to this store's.
+    Duplicate,
+}
+
+impl MergeAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MergeAction::Create => "New",
+            MergeAction::Unchanged => "Unchanged",
+            MergeAction::Keep => "Keep mine",
+            MergeAction::Overwrite => "Overwrite",
+            MergeAction::Duplicate => "Duplicate",
+        }
+    }
+}
+
+/// A command, workflow or host from the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeItem {
+    pub kind: ItemKind,
+    /// Its id in the file.
+    pub id: Uuid,
+    pub name: String,
+    pub conflict: MergeConflict,
+    /// Starts as `Keep` for conflicts; see `choices`.
+    pub action: MergeAction,
+    /// The id it gets with `MergeAction::Duplicate`.
+    duplicate_id: Uuid,
+}
+
+impl MergeItem {
+    /// The actions the user may pick.
+    pub fn choices(&self) -> &'static [MergeAction] {
+        match self.conflict {
+            MergeConflict::None => &[],
+            _ => &[
+                MergeAction::Keep,
+                MergeAction::Overwrite,
+                MergeAction::Duplicate,
+            ],
+        }
+    }
+
+    /// The id the item has in this store once merged.
+    fn merged_id(&self) -> Uuid {
+        match (self.action, self.conflict) {
+            (MergeAction::Duplicate, _) => self.duplicate_id,
+            (_, MergeConflict::SameName(existing)) => existing,
+            _ => self.id,
+        }
+    }
+
+    fn writes(&self) -> bool {
+        matches!(
+            self.action,
+            MergeAction::Create | MergeAction::Overwrite | MergeAction::Duplicate
+        )
+    }
+}
+
+/// The parts of an export that are merged.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MergeSource {
+    #[serde(default)]
+    pub commands: Vec<Command>,
+    #[serde(default)]
+    pub workflows: Vec<Workflow>,
+    #[serde(default)]
+    pub hosts: Vec<Host>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergePlan {
+    pub items: Vec<MergeItem>,
+    /// References in the file to commands or hosts that are neither in it
+    /// nor in this store. They're dropped when the plan is applied.
+    pub problems: Vec<String>,
+    source: MergeSource,
+}
+
+impl MergePlan {
+    /// Items that would be written.
+    pub fn changes(&self) -> usize {
+        self.items.iter().filter(|i| i.writes()).count()
+    }
+
+    /// The run-as settings of the commands and workflows it writes.
+    pub(crate) fn written_run_as(&mut self) -> impl Iterator<Item = &mut Option<RunAs>> {
+        let items = &self.items;
+        let writes = move |kind: ItemKind, id: Uuid| {
+            items
+                .iter()
+                .any(|i| i.kind == kind && i.id == id && i.writes())
+        };
+        let commands = self
+            .source
+            .commands
+            .iter_mut()
+            .filter(move |c| writes(ItemKind::Command, c.id))
+            .map(|c| &mut c.run_as);
+        let steps = self
+            .source
+            .workflows
+            .iter_mut()
+            .filter(move |w| writes(ItemKind::Workflow, w.id))
+            .flat_map(|w| w.step_run_as.iter_mut());
+        commands.chain(steps)
+    }
+}
+
+fn plan_items<T: Clone + Serialize + RunAsSettings>(
+    kind: ItemKind,
+    existing: &[T],
+    incoming: &[T],
+    id: impl Fn(&T) -> Uuid,
+    name: impl Fn(&T) -> &str,
+) -> Vec<MergeItem> {
+    incoming
+        .iter()
+        .map(|item| {
+            let conflict = match existing.iter().find(|e| id(e) == id(item)) {
+                Some(current) if comparable(current) == comparable(item) => None,
+                Some(_) => Some(MergeConflict::SameId),
+                None => Some(match existing.iter().find(|e| name(e) == name(item)) {
+                    Some(other) => MergeConflict::SameName(id(other)),
+                    None => MergeConflict::None,
+                }),
+            };
+            let (conflict, action) = match conflict {
+                None => (MergeConflict::None, MergeAction::Unchanged),
+                Some(MergeConflict::None) => (MergeConflict::None, MergeAction::Create),
+                Some(conflict) => (conflict, MergeAction::Keep),
+            };
+            MergeItem {
+                kind,
+                id: id(item),
+                name: name(item).to_string(),
+                conflict,
+                action,
+                duplicate_id: Uuid::new_v4(),
+            }
+        })
+        .collect()
+}
+
+/// Decide what merging `source` into the given items would do.
+pub fn plan(
+    commands: &[Command],
+    workflows: &[Workflow],
+    hosts: &[Host],
+    source: MergeSource,
+) -> MergePlan {
+    let mut items = plan_items(
+        ItemKind::Command,
+        commands,
+        &source.commands,
+        |c| c.id,
+        |c| &c.name,
+    );
+    items.extend(plan_items(
+        ItemKind::Workflow,
+        workflows,
+        &source.workflows,
+        |w| w.id,
+        |w| &w.name,
+    ));
+    items.extend(plan_items(
+        ItemKind::Host,
+        hosts,
+        &source.hosts,
+        |h| h.id,
+        |h| &h.name,
+    ));
+
+    let command_ids: HashSet<Uuid> = commands
+        .iter()
+        .chain(&source.commands)
+        .map(|c| c.id)
+        .collect();
+    let host_ids: HashSet<Uuid> = hosts.iter().chain(&source.hosts).map(|h| h.id).collect();
+    let mut problems = Vec::new();
+    for workflow in &source.workflows {
+        for (i, id) in workflow.commands.iter().enumerate() {
+            if !command_ids.contains(id) {
+                problems.push(format!(
+                    "Workflow \"{}\": step {} runs a command that's missing; the step is dropped",
+                    workflow.name,
+                    i + 1
+                ));
+            }
+        }
+    }
+    for command in &source.commands {
+        let missing = command
+            .target_hosts
+            .iter()
+            .filter(|id| !host_ids.contains(id))
+            .count();
+        if missing > 0 {
+            problems.push(format!(
+                "Command \"{}\": {} target host(s) missing; they're dropped",
+                command.name, missing
+            ));
+        }
+    }
+
+    MergePlan {
+        items,
+        problems,
+        source,
+    }
+}
+
+/// What applying a plan writes.
+#[derive(Debug, Default)]
+pub(crate) struct MergeWrites {
+    pub commands: Vec<Command>,
+    pub workflows: Vec<Workflow>,
+    pub hosts: Vec<Host>,
+}
+
+/// The items `plan` writes into a store holding `commands` and `hosts`,
+/// with their ids settled and references to the file's items pointed at
+/// where those end up. References to nothing are dropped.
+pub(crate) fn resolve(plan: &MergePlan, commands: &[Command], hosts: &[Host]) -> MergeWrites {
+    let ids: HashMap<Uuid, Uuid> = plan.items.iter().map(|i| (i.id, i.merged_id())).collect();
+    let merged = |id: &Uuid| ids.get(id).copied().unwrap_or(*id);
+    let written = |kind: ItemKind, id: Uuid| {
+        plan.items
+            .iter()
+            .find(|i| i.kind == kind && i.id == id && i.writes())
+            .map(|i| (i.merged_id(), i.action == MergeAction::Duplicate))
+    };
+
+    let mut writes = MergeWrites::default();
+    for host in &plan.source.hosts {
+        if let Some((id, duplicate)) = written(ItemKind::Host, host.id) {
+            let mut host = host.clone();
+            host.id = id;
+            if duplicate {
+                host.name = format!("{} (imported)", host.name);
+            }
+            writes.hosts.push(host);
+        }
+    }
+
+    let host_ids: HashSet<Uuid> = hosts
+        .iter()
+        .map(|h| h.id)
+        .chain(writes.hosts.iter().map(|h| h.id))
+        .collect();
+    for command in &plan.source.commands {
+        if let Some((id, duplicate)) = written(ItemKind::Command, command.id) {
+            let mut command = command.clone();
+            command.id = id;
+            if duplicate {
+                command.name = format!("{} (imported)", command.name);
+            }
+            command.target_hosts = command
+                .target_hosts
+                .iter()
+                .map(merged)
+                .filter(|id| host_ids.contains(id))
+                .collect();
+            writes.commands.push(command);
+        }
+    }
+
+    let command_ids: HashSet<Uuid> = commands
+        .iter()
+        .map(|c| c.id)
+        .chain(writes.commands.iter().map(|c| c.id))
+        .collect();
+    for workflow in &plan.source.workflows {
+        if let Some((id, duplicate)) = written(ItemKind::Workflow, workflow.id) {
+            let mut workflow = workflow.clone();
+            workflow.id = id;
+            if duplicate {
+                workflow.name = format!("{} (imported)", workflow.name);
+            }
+            let steps: Vec<(Uuid, Option<_>)> = workflow
+                .commands
+                .iter()
+                .enumerate()
+                .map(|(i, command)| {
+                    (
+                        merged(command),
+                        workflow.step_run_as.get(i).cloned().flatten(),
+                    )
+                })
+                .filter(|(command, _)| command_ids.contains(command))
+                .collect();
+            workflow.commands = steps.iter().map(|(command, _)| *command).collect();
+            workflow.step_run_as = steps.into_iter().map(|(_, run_as)| run_as).collect();
+            writes.workflows.push(workflow);
+        }
+    }
+    writes
+}
@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod tests {
+    use crate::merge::{MergeAction, MergeConflict};
+    use crate::models::{Command, Workflow};
+    use crate::snapshots::ItemKind;
+    use crate::store::CommandStore;
+    use uuid::Uuid;
+
+    fn make_workflow(name: &str, commands: Vec<Uuid>) -> Workflow {
+        Workflow {
+            id: Uuid::new_v4(),
+            name: name.into(),
+            description: None,
+            commands,
+            env_vars: vec![],
+            created_at: chrono::Utc::now(),
+            step_run_as: Vec::new(),
+            notify: Default::default(),
+        }
+    }
+
+    /// A store with "Build", "Test" and "Lint", and an export that has
+    /// "Build" unchanged, "Test" edited, its own "Lint", a new "Deploy"
+    /// and a workflow running all of them plus a command it doesn't have.
+    fn setup() -> (CommandStore, String, [Command; 4], Workflow) {
+        let store = CommandStore::new_test();
+        let build = Command::new("Build", "make");
+        let test = Command::new("Test", "make test");
+        let lint = Command::new("Lint", "make lint");
+        for command in [&build, &test, &lint] {
+            store.add_command(command.clone());
+        }
+
+        let mut edited_test = test.clone();
+        edited_test.script = "make check".into();
+        let their_lint = Command::new("Lint", "cargo clippy");
+        let deploy = Command::new("Deploy", "make deploy");
+        let workflow = make_workflow(
+            "Release",
+            vec![
+                build.id,
+                edited_test.id,
+                their_lint.id,
+                Uuid::new_v4(),
+                deploy.id,
+            ],
+        );
+        let export = serde_json::json!({
+            "commands": [build, edited_test, their_lint, deploy],
+            "workflows": [workflow],
+            "hosts": [],
+        });
+        (
+            store,
+            export.to_string(),
+            [build, test, their_lint, deploy],
+            workflow,
+        )
+    }
+
+    #[test]
+    fn test_preview_finds_conflicts() {
+        let (store, export, [build, test, their_lint, deploy], workflow) = setup();
+        let lint = store
+            .list_commands()
+            .into_iter()
+            .find(|c| c.name == "Lint")
+            .unwrap();
+
+        let plan = store.preview_merge(&export).unwrap();
+        let summary: Vec<(Uuid, MergeConflict, MergeAction)> = plan
+            .items
+            .iter()
+            .map(|i| (i.id, i.conflict, i.action))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (build.id, MergeConflict::None, MergeAction::Unchanged),
+                (test.id, MergeConflict::SameId, MergeAction::Keep),
+                (
+                    their_lint.id,
+                    MergeConflict::SameName(lint.id),
+                    MergeAction::Keep
+                ),
+                (deploy.id, MergeConflict::None, MergeAction::Create),
+                (workflow.id, MergeConflict::None, MergeAction::Create),
+            ]
+        );
+        assert_eq!(plan.items[4].kind, ItemKind::Workflow);
+        assert_eq!(plan.problems.len(), 1);
+        assert!(plan.problems[0].contains("step 4"));
+        assert_eq!(plan.changes(), 2);
+    }
+
+    #[test]
+    fn test_apply_merge() {
+        let (store, export, [build, test, their_lint, deploy], workflow) = setup();
+        let lint = store
+            .list_commands()
+            .into_iter()
+            .find(|c| c.name == "Lint")
+            .unwrap();
+
+        // Take their "Test", keep my "Lint"
+        let mut plan = store.preview_merge(&export).unwrap();
+        plan.items[1].action = MergeAction::Overwrite;
+        assert_eq!(store.apply_merge(&plan), 3);
+
+        assert_eq!(store.list_commands().len(), 4);
+        assert_eq!(store.get_command(&test.id).unwrap().script, "make check");
+        assert_eq!(store.get_command(&lint.id).unwrap().script, "make lint");
+        assert!(store.get_command(&their_lint.id).is_none());
+        // Steps follow where their commands ended up; the missing one is dropped
+        let merged = store.get_workflow(&workflow.id).unwrap();
+        assert_eq!(merged.commands, vec![build.id, test.id, lint.id, deploy.id]);
+
+        // Duplicates get new ids, and steps follow them
+        let mut plan = store.preview_merge(&export).unwrap();
+        for item in &mut plan.items {
+            if item.id == their_lint.id {
+                item.action = MergeAction::Duplicate;
+            } else if item.id == workflow.id {
+                // Differs from the file now that its steps were fixed up
+                assert_eq!(item.conflict, MergeConflict::SameId);
+                item.action = MergeAction::Overwrite;
+            }
+        }
+        store.apply_merge(&plan);
+        let copy = store
+            .list_commands()
+            .into_iter()
+            .find(|c| c.name == "Lint (imported)")
+            .unwrap();
+        assert_ne!(copy.id, their_lint.id);
+        assert_eq!(copy.script, "cargo clippy");
+        assert!(
+            store
+                .get_workflow(&workflow.id)
+                .unwrap()
+                .commands
+                .contains(&copy.id)
+        );
+    }
+
+    #[test]
+    fn test_merge_plain_text_passwords() {
+        use crate::models::{EscalationPassword, RunAs};
+
+        // An older export keeps a saved "run as" password as is
+        let store = CommandStore::new_test();
+        let mut cmd = Command::new("Reboot", "reboot");
+        cmd.run_as = Some(RunAs::default());
+        let mut json = serde_json::json!({ "commands": [cmd], "workflows": [], "hosts": [] });
+        json["commands"][0]["run_as"]["password"] = serde_json::json!({ "Saved": "hunter2" });
+        let export = json.to_string();
+        store.import_json(&export).unwrap();
+
+        // Previewing leaves it alone, and it matches the one now in the keychain
+        let plan = store.preview_merge(&export).unwrap();
+        assert_eq!(plan.items[0].action, MergeAction::Unchanged);
+
+        // Applying it moves it there
+        let other = CommandStore::new_test();
+        other.apply_merge(&other.preview_merge(&export).unwrap());
+        let password = other.get_command(&cmd.id).unwrap().run_as.unwrap().password;
+        let EscalationPassword::Saved(id) = password else {
+            panic!("not moved: {:?}", password)
+        };
+        assert_eq!(crate::secrets::load(&id).unwrap(), "hunter2");
+    }
+}
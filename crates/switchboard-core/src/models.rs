@@ -37,14 +37,128 @@ pub struct WorkflowV0 {
 }
 // ----------------------------------
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum AuthMethod {
     Password(String),
     KeyFile(String),
     Agent,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ContainerRuntime {
+    #[default]
+    Docker,
+    Podman,
+}
+
+impl ContainerRuntime {
+    /// The CLI binary used to drive this runtime.
+    pub fn binary(&self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "docker",
+            ContainerRuntime::Podman => "podman",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ContainerMode {
+    /// Run inside an already running container.
+    Exec { container: String },
+    /// Start a fresh container from an image for each run, with the working
+    /// directory mounted at the same path.
+    Image { image: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContainerTarget {
+    pub runtime: ContainerRuntime,
+    pub mode: ContainerMode,
+}
+
+impl Default for ContainerTarget {
+    fn default() -> Self {
+        ContainerTarget {
+            runtime: ContainerRuntime::Docker,
+            mode: ContainerMode::Exec {
+                container: String::new(),
+            },
+        }
+    }
+}
+
+/// A pod reached through the local `kubectl`. Empty fields fall back to
+/// kubectl's defaults (current context, namespace, default container).
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KubernetesTarget {
+    #[serde(default)]
+    pub context: String,
+    #[serde(default)]
+    pub namespace: String,
+    /// Label selector used to pick a running pod, e.g. `app=web`.
+    #[serde(default)]
+    pub selector: String,
+    /// A specific pod; takes precedence over `selector`.
+    #[serde(default)]
+    pub pod: String,
+    #[serde(default)]
+    pub container: String,
+}
+
+/// What a `Host` record points at and how commands reach it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HostKind {
+    /// A machine reached over SSH (or this machine, for "local" hosts).
+    #[default]
+    Ssh,
+    Container(ContainerTarget),
+    Kubernetes(KubernetesTarget),
+}
+
+/// What happens to the directories old runs leave on a host.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LogCleanup {
+    KeepAll,
+    /// Remove a run's directory as soon as the run has finished.
+    DeleteAfterRun,
+    /// Before each run, remove run directories whose log has not been
+    /// written to for this many days.
+    OlderThanDays(u32),
+}
+
+impl Default for LogCleanup {
+    fn default() -> Self {
+        LogCleanup::OlderThanDays(7)
+    }
+}
+
+/// Where a host keeps the scripts, logs and exit codes of runs. Each run
+/// gets a private (mode 700) subdirectory named after its id.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RemoteWorkspace {
+    /// Base directory; a leading `~` is the remote user's home.
+    #[serde(default = "RemoteWorkspace::default_dir")]
+    pub dir: String,
+    #[serde(default)]
+    pub log_cleanup: LogCleanup,
+}
+
+impl RemoteWorkspace {
+    pub fn default_dir() -> String {
+        "~/.switchboard/runs".to_string()
+    }
+}
+
+impl Default for RemoteWorkspace {
+    fn default() -> Self {
+        RemoteWorkspace {
+            dir: RemoteWorkspace::default_dir(),
+            log_cleanup: LogCleanup::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Host {
     pub id: Uuid,
     pub name: String,
@@ -52,10 +166,156 @@ pub struct Host {
     pub port: u16,
     pub username: String,
     pub auth: AuthMethod,
+    #[serde(default)]
+    pub kind: HostKind,
+    /// Inventory groups this host belongs to, e.g. `webservers`.
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Free-form labels, either `key=value` (e.g. `role=web`) or bare words.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub workspace: RemoteWorkspace,
+}
+
+impl Host {
+    /// Whether the host matches every term of a comma-separated selector
+    /// such as `role=web,env=staging`. A `key=value` term matches a tag; a
+    /// bare term matches a tag, a group or the host's name. An empty
+    /// selector matches nothing.
+    pub fn matches_selector(&self, selector: &str) -> bool {
+        let mut terms = selector
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .peekable();
+        if terms.peek().is_none() {
+            return false;
+        }
+        terms.all(|term| match term.split_once('=') {
+            Some((key, value)) => self
+                .tags
+                .iter()
+                .filter_map(|t| t.split_once('='))
+                .any(|(k, v)| k.trim() == key.trim() && v.trim() == value.trim()),
+            None => {
+                self.name == term
+                    || self.groups.iter().any(|g| g == term)
+                    || self.tags.iter().any(|t| t == term)
+            }
+        })
+    }
 }
 
 use std::path::PathBuf;
 
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EscalationMethod {
+    #[default]
+    Sudo,
+    Su,
+}
+
+/// Where the password for `sudo`/`su` comes from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(from = "StoredPassword", into = "StoredPassword")]
+pub enum EscalationPassword {
+    /// None needed: passwordless (`NOPASSWD`) sudo, or su as root.
+    #[default]
+    None,
+    /// Asked for each time the command runs.
+    Prompt,
+    /// Kept in the system keychain under this id; see `secrets`.
+    Saved(Uuid),
+    /// Typed in for one run. Never written out: it serializes as `Prompt`.
+    Entered(String),
+}
+
+/// `EscalationPassword` as stored. Stores from before the keychain kept
+/// the password itself in `Saved`; that reads back as `Entered` until
+/// `StoreData::secure_passwords` moves it to the keychain.
+#[derive(Serialize, Deserialize)]
+enum StoredPassword {
+    None,
+    Prompt,
+    Saved(String),
+}
+
+impl From<StoredPassword> for EscalationPassword {
+    fn from(stored: StoredPassword) -> Self {
+        match stored {
+            StoredPassword::None => EscalationPassword::None,
+            StoredPassword::Prompt => EscalationPassword::Prompt,
+            StoredPassword::Saved(value) => match Uuid::parse_str(&value) {
+                Ok(id) => EscalationPassword::Saved(id),
+                Err(_) => EscalationPassword::Entered(value),
+            },
+        }
+    }
+}
+
+impl From<EscalationPassword> for StoredPassword {
+    fn from(password: EscalationPassword) -> Self {
+        match password {
+            EscalationPassword::None => StoredPassword::None,
+            EscalationPassword::Prompt | EscalationPassword::Entered(_) => StoredPassword::Prompt,
+            EscalationPassword::Saved(id) => StoredPassword::Saved(id.to_string()),
+        }
+    }
+}
+
+/// Run a command's script as another user on the host.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RunAs {
+    pub user: String,
+    #[serde(default)]
+    pub method: EscalationMethod,
+    #[serde(default)]
+    pub password: EscalationPassword,
+}
+
+impl Default for RunAs {
+    fn default() -> Self {
+        RunAs {
+            user: "root".to_string(),
+            method: EscalationMethod::Sudo,
+            password: EscalationPassword::None,
+        }
+    }
+}
+
+/// The "run as" settings an item holds.
+pub(crate) trait RunAsSettings {
+    fn run_as_mut(&mut self) -> Vec<&mut Option<RunAs>>;
+}
+
+impl RunAsSettings for Command {
+    fn run_as_mut(&mut self) -> Vec<&mut Option<RunAs>> {
+        vec![&mut self.run_as]
+    }
+}
+
+impl RunAsSettings for Workflow {
+    fn run_as_mut(&mut self) -> Vec<&mut Option<RunAs>> {
+        self.step_run_as.iter_mut().collect()
+    }
+}
+
+impl RunAsSettings for Host {
+    fn run_as_mut(&mut self) -> Vec<&mut Option<RunAs>> {
+        Vec::new()
+    }
+}
+
+/// A local file or directory copied to the host before a command runs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileTransfer {
+    pub local_path: String,
+    /// Destination on the host; relative paths are taken from the working
+    /// directory.
+    pub remote_path: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Command {
     pub id: Uuid,
@@ -66,14 +326,58 @@ pub struct Command {
     pub env_vars: Vec<EnvVar>,
     pub host: Option<String>,
     pub user: Option<String>,
+    /// Run the script as this user (via sudo or su) rather than the login
+    /// user.
+    #[serde(default)]
+    pub run_as: Option<RunAs>,
     pub target_hosts: Vec<Uuid>,
+    /// Inventory hosts to run on, chosen by tag when the command runs (see
+    /// `Host::matches_selector`). Used together with `target_hosts`.
+    #[serde(default)]
+    pub host_selector: String,
     pub created_at: DateTime<Utc>,
     #[serde(default)]
     pub background: bool,
+    /// Where to run when not on `host`: a container or a pod.
+    #[serde(default)]
+    pub target: HostKind,
+    #[serde(default)]
+    pub uploads: Vec<FileTransfer>,
+    /// Host paths (files or directories) fetched into the execution's
+    /// artifacts directory after the run, under their path relative to the
+    /// working directory.
+    #[serde(default)]
+    pub artifacts: Vec<String>,
+    /// When to send notifications about a finished run.
+    #[serde(default)]
+    pub notify: NotifyOn,
     #[serde(skip)]
     pub source_path: Option<PathBuf>,
 }
 
+/// A saved version of a command. Edits made within a short while of each
+/// other share a revision; see `CommandStore::add_command`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandRevision {
+    pub id: Uuid,
+    pub command_id: Uuid,
+    /// Counts up from 1 for each command.
+    pub number: u32,
+    pub saved_at: DateTime<Utc>,
+    /// The command as it was saved.
+    pub command: Command,
+    /// A run used this revision, so later edits start a new one.
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+impl CommandRevision {
+    /// Whether `command` has the same contents as this revision.
+    pub fn matches(&self, command: &Command) -> bool {
+        serde_json::to_value(&self.command).ok() == serde_json::to_value(command).ok()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Workflow {
     pub id: Uuid,
@@ -82,6 +386,77 @@ pub struct Workflow {
     pub commands: Vec<Uuid>,
     pub env_vars: Vec<EnvVar>,
     pub created_at: DateTime<Utc>,
+    /// Per-step "run as" overrides, indexed like `commands`; `None` (or a
+    /// missing entry) uses the command's own setting.
+    #[serde(default)]
+    pub step_run_as: Vec<Option<RunAs>>,
+    /// When to send notifications about a finished workflow; its steps'
+    /// own settings still apply to them.
+    #[serde(default)]
+    pub notify: NotifyOn,
+}
+
+impl Workflow {
+    /// The "run as" setting for step `index`, which runs `command`.
+    pub fn run_as_for_step(&self, index: usize, command: &Command) -> Option<RunAs> {
+        match self.step_run_as.get(index) {
+            Some(Some(run_as)) => Some(run_as.clone()),
+            _ => command.run_as.clone(),
+        }
+    }
+}
+
+impl Command {
+    /// A new command running `script` on this machine, with everything
+    /// else left at its default.
+    pub fn new(name: impl Into<String>, script: impl Into<String>) -> Self {
+        Command {
+            id: Uuid::new_v4(),
+            name: name.into(),
+            description: None,
+            script: script.into(),
+            working_directory: None,
+            env_vars: Vec::new(),
+            host: None,
+            user: None,
+            run_as: None,
+            target_hosts: Vec::new(),
+            host_selector: String::new(),
+            created_at: Utc::now(),
+            background: false,
+            target: HostKind::Ssh,
+            uploads: Vec::new(),
+            artifacts: Vec::new(),
+            notify: NotifyOn::Never,
+            source_path: None,
+        }
+    }
+
+    /// The host a command targets when no inventory host is picked, built
+    /// from its own host/user fields: this machine, an SSH host, or the
+    /// container or pod in `target`.
+    pub fn adhoc_host(&self) -> Host {
+        let default_user = std::env::var("USER").unwrap_or_else(|_| "root".into());
+        let name = match &self.target {
+            HostKind::Container(_) => "Container",
+            HostKind::Kubernetes(_) => "Kubernetes",
+            HostKind::Ssh if self.host.is_some() => "Remote",
+            HostKind::Ssh => "local",
+        };
+
+        Host {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            hostname: self.host.clone().unwrap_or_else(|| "localhost".to_string()),
+            port: 22,
+            username: self.user.clone().unwrap_or(default_user),
+            auth: AuthMethod::Agent,
+            kind: self.target.clone(),
+            groups: Vec::new(),
+            tags: Vec::new(),
+            workspace: Default::default(),
+        }
+    }
 }
 
 impl From<CommandV0> for Command {
@@ -98,17 +473,15 @@ impl From<CommandV0> for Command {
 
         Command {
             id: old.id,
-            name: old.name,
             description: old.description,
-            script: old.script,
             working_directory: old.working_directory,
             env_vars,
             host: old.host,
             user: old.user,
             target_hosts: old.target_hosts,
             created_at: old.created_at,
-            background: false,
             source_path: old.source_path,
+            ..Command::new(old.name, old.script)
         }
     }
 }
@@ -122,6 +495,8 @@ impl From<WorkflowV0> for Workflow {
             commands: old.commands,
             env_vars: Vec::new(),
             created_at: old.created_at,
+            step_run_as: Vec::new(),
+            notify: NotifyOn::Never,
         }
     }
 }
@@ -134,11 +509,33 @@ pub enum ExecutionStatus {
     Failed,
 }
 
+/// A `nohup` process left running on a host by a background command.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BackgroundJob {
+    pub pid: u32,
+    pub log_path: String,
+    /// File the wrapper writes the script's exit code to once it finishes.
+    pub exit_path: String,
+    /// The run's directory in the host's workspace; empty for jobs started
+    /// before workspaces existed.
+    #[serde(default)]
+    pub run_dir: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExecutionUpdate {
     Started(Uuid),
     Stdout(String),
     Stderr(String),
+    /// The command detached into the background; output keeps streaming
+    /// from its log until `Exit`.
+    Detached(BackgroundJob),
+    /// A file fetched from the host after the run; `name` is a relative path
+    /// within the execution's artifacts directory.
+    Artifact {
+        name: String,
+        data: Vec<u8>,
+    },
     Exit(i32),
 }
 
@@ -153,4 +550,256 @@ pub struct ExecutionResult {
     pub duration_ms: Option<u64>,
     pub status: ExecutionStatus,
     pub log_file: String, // e.g. "550e8400-e29b-41d4-a716-446655440000.log.gz"
+    #[serde(default)]
+    pub background: Option<BackgroundJob>,
+    /// The command revision that ran; see `CommandRevision`.
+    #[serde(default)]
+    pub revision: Option<Uuid>,
+    /// For a library command, the commit its checkout was at (see
+    /// `library::Library::commit`).
+    #[serde(default)]
+    pub library_commit: Option<String>,
+}
+
+impl ExecutionResult {
+    /// A run that started just now; `finish` records how it ended.
+    pub fn started(id: Uuid, command_id: Uuid, host_id: Uuid) -> Self {
+        ExecutionResult {
+            id,
+            command_id,
+            host_id,
+            started_at: Utc::now(),
+            finished_at: None,
+            exit_code: None,
+            duration_ms: None,
+            status: ExecutionStatus::Running,
+            log_file: format!("{}.log.gz", id),
+            background: None,
+            revision: None,
+            library_commit: None,
+        }
+    }
+
+    pub fn finish(&mut self, exit_code: i32) {
+        let finished_at = Utc::now();
+        self.duration_ms = Some(
+            finished_at
+                .signed_duration_since(self.started_at)
+                .num_milliseconds()
+                .max(0) as u64,
+        );
+        self.finished_at = Some(finished_at);
+        self.exit_code = Some(exit_code);
+        self.status = if exit_code == 0 {
+            ExecutionStatus::Completed
+        } else {
+            ExecutionStatus::Failed
+        };
+    }
+}
+
+/// A finished run of a workflow. Its steps are recorded as
+/// `ExecutionResult`s of their own.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WorkflowRun {
+    pub id: Uuid,
+    pub workflow_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    /// The first failing step's exit code, or 0.
+    pub exit_code: i32,
+}
+
+/// Which finished runs (or workflows) to send notifications about.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum NotifyOn {
+    #[default]
+    Never,
+    Failure,
+    Success,
+    Always,
+}
+
+impl NotifyOn {
+    pub fn matches(self, succeeded: bool) -> bool {
+        match self {
+            NotifyOn::Never => false,
+            NotifyOn::Failure => !succeeded,
+            NotifyOn::Success => succeeded,
+            NotifyOn::Always => true,
+        }
+    }
+}
+
+/// Where a notification goes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum NotificationChannelKind {
+    /// POSTs the run as JSON, with the rendered message.
+    Webhook { url: String },
+    /// POSTs `{"text": message}` to a Slack (or compatible) incoming webhook.
+    Slack { url: String },
+    /// A notification on this machine, via `notify-send` or `osascript`.
+    Desktop,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NotificationChannel {
+    pub name: String,
+    pub kind: NotificationChannelKind,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Notification channels and message format (see `notifications`). Which
+/// runs notify is set per command and workflow.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NotificationSettings {
+    #[serde(default)]
+    pub channels: Vec<NotificationChannel>,
+    /// Message text; `{name}`, `{status}`, `{exit_code}`, `{duration}`,
+    /// `{host}` and `{log_tail}` are filled in.
+    #[serde(default = "NotificationSettings::default_template")]
+    pub template: String,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings {
+            channels: Vec::new(),
+            template: Self::default_template(),
+        }
+    }
+}
+
+impl NotificationSettings {
+    pub fn default_template() -> String {
+        "{name} {status} (exit code {exit_code}) after {duration} on {host}\n{log_tail}".to_string()
+    }
+}
+
+/// The embedded HTTP API (see `api`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ApiSettings {
+    pub enabled: bool,
+    /// Address to listen on; loopback unless deliberately changed.
+    pub bind: String,
+    /// Callers send this as `Authorization: Bearer <token>`.
+    pub token: String,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        ApiSettings {
+            enabled: false,
+            bind: "127.0.0.1:7323".to_string(),
+            token: String::new(),
+        }
+    }
+}
+
+impl ApiSettings {
+    /// A fresh random token.
+    pub fn generate_token() -> String {
+        Uuid::new_v4().simple().to_string()
+    }
+}
+
+/// App-wide settings, kept in the store.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Settings {
+    #[serde(default)]
+    pub api: ApiSettings,
+    #[serde(default)]
+    pub notifications: NotificationSettings,
+    #[serde(default)]
+    pub snapshots: SnapshotSettings,
+    #[serde(default)]
+    pub libraries: Vec<LibrarySource>,
+    #[serde(default)]
+    pub compare: CompareSettings,
+}
+
+/// A pattern masked in logs before two runs are compared, so that what
+/// changes on every run (times, ids) doesn't show up as a difference.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Normalizer {
+    pub name: String,
+    /// A regular expression (see the `regex` crate).
+    pub pattern: String,
+    /// What matches are replaced with; `$1` etc. refer to groups.
+    pub replacement: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CompareSettings {
+    pub normalizers: Vec<Normalizer>,
+}
+
+impl Default for CompareSettings {
+    fn default() -> Self {
+        let normalizer = |name: &str, pattern: &str, replacement: &str, enabled| Normalizer {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            enabled,
+        };
+        CompareSettings {
+            normalizers: vec![
+                normalizer(
+                    "Timestamps",
+                    r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:?\d{2})?",
+                    "<timestamp>",
+                    true,
+                ),
+                normalizer("Times", r"\b\d{2}:\d{2}:\d{2}(\.\d+)?\b", "<time>", true),
+                normalizer(
+                    "UUIDs",
+                    r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b",
+                    "<uuid>",
+                    true,
+                ),
+                normalizer(
+                    "Durations",
+                    r"\b\d+(\.\d+)?\s?(ms|s)\b",
+                    "<duration>",
+                    false,
+                ),
+            ],
+        }
+    }
+}
+
+/// A checkout of a shared repository of commands and workflows, kept in
+/// the bundle layout (see `library`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LibrarySource {
+    pub name: String,
+    pub path: PathBuf,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+/// When to take snapshots of the store by themselves (see `snapshots`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SnapshotSettings {
+    /// Hours between scheduled snapshots; 0 turns them off.
+    pub interval_hours: u32,
+    /// Automatic snapshots kept; older ones are pruned. Manual snapshots
+    /// are kept until deleted.
+    pub keep: usize,
+}
+
+impl Default for SnapshotSettings {
+    fn default() -> Self {
+        SnapshotSettings {
+            interval_hours: 24,
+            keep: 30,
+        }
+    }
 }
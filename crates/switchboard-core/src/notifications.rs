@@ -0,0 +1,265 @@
+//! Notifications about finished runs and workflows, sent to the channels in
+//! the settings: JSON webhooks, Slack incoming webhooks and desktop
+//! notifications. Whether a run notifies is up to its command's (or
+//! workflow's) `notify` setting.
+
+use crate::models::{
+    ExecutionResult, NotificationChannel, NotificationChannelKind, NotificationSettings, Workflow,
+};
+use crate::store::CommandStore;
+use serde::Serialize;
+use serde_json::json;
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Lines of output included as `{log_tail}`.
+const LOG_TAIL_LINES: usize = 20;
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum NotifyError {
+    #[error("HTTP request failed: {0}")]
+    Http(String),
+    #[error("Could not show a desktop notification: {0}")]
+    Desktop(String),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationSource {
+    Command,
+    Workflow,
+}
+
+/// A finished run or workflow, as sent to the channels.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub source: NotificationSource,
+    /// The command's or workflow's id.
+    pub id: Uuid,
+    /// Set for command runs.
+    pub run_id: Option<Uuid>,
+    pub name: String,
+    pub host: String,
+    pub exit_code: i32,
+    pub duration_ms: u64,
+    pub log_tail: String,
+}
+
+impl Notification {
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == 0
+    }
+
+    /// `template` with the placeholders filled in, in one pass so that
+    /// placeholders inside the values (say, in the log tail) are kept.
+    pub fn render(&self, template: &str) -> String {
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest
+                .find('}')
+                .and_then(|end| Some((end, self.placeholder(&rest[1..end])?)));
+            match value {
+                Some((end, value)) => {
+                    rendered.push_str(&value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+
+    /// The value of the `{name}` placeholder, if there is one.
+    fn placeholder(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "name" => self.name.clone(),
+            "status" if self.succeeded() => "succeeded".to_string(),
+            "status" => "failed".to_string(),
+            "exit_code" => self.exit_code.to_string(),
+            "duration" => format_duration(self.duration_ms),
+            "host" => self.host.clone(),
+            "log_tail" => self.log_tail.clone(),
+            _ => return None,
+        })
+    }
+}
+
+/// "850ms", "42s", "3m 05s", "2h 10m".
+pub fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    match secs {
+        0 => format!("{}ms", ms),
+        1..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// The last `LOG_TAIL_LINES` lines of `output`.
+pub fn log_tail(output: &str) -> String {
+    let lines: Vec<&str> = output.trim_end().lines().collect();
+    lines[lines.len().saturating_sub(LOG_TAIL_LINES)..].join("\n")
+}
+
+/// Send `notification` to one channel, waiting for it to be delivered.
+pub fn send(
+    channel: &NotificationChannel,
+    notification: &Notification,
+    template: &str,
+) -> Result<(), NotifyError> {
+    let message = notification.render(template);
+    match &channel.kind {
+        NotificationChannelKind::Webhook { url } => {
+            let mut body = serde_json::to_value(notification).unwrap_or_default();
+            body["status"] = json!(if notification.succeeded() {
+                "succeeded"
+            } else {
+                "failed"
+            });
+            body["message"] = json!(message);
+            post_json(url, &body)
+        }
+        NotificationChannelKind::Slack { url } => post_json(url, &json!({ "text": message })),
+        NotificationChannelKind::Desktop => {
+            let title = format!(
+                "{} {}",
+                notification.name,
+                if notification.succeeded() {
+                    "succeeded"
+                } else {
+                    "failed"
+                }
+            );
+            show_desktop(&title, &message)
+        }
+    }
+}
+
+fn post_json(url: &str, body: &serde_json::Value) -> Result<(), NotifyError> {
+    ureq::post(url)
+        .timeout(SEND_TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(&body.to_string())
+        .map(|_| ())
+        .map_err(|e| NotifyError::Http(e.to_string()))
+}
+
+fn show_desktop(title: &str, message: &str) -> Result<(), NotifyError> {
+    let mut process = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification {} with title {}",
+            applescript_string(message),
+            applescript_string(title)
+        );
+        let mut process = std::process::Command::new("osascript");
+        process.arg("-e").arg(script);
+        process
+    } else {
+        let mut process = std::process::Command::new("notify-send");
+        process
+            .arg("--app-name=Switchboard")
+            // The title is a command name, which may start with "-"
+            .arg("--")
+            .arg(title)
+            .arg(message);
+        process
+    };
+    let output = process
+        .output()
+        .map_err(|e| NotifyError::Desktop(e.to_string()))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(NotifyError::Desktop(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Send `notification` to every enabled channel in the background; failures
+/// are logged.
+pub fn dispatch(settings: &NotificationSettings, notification: Notification) {
+    let channels: Vec<NotificationChannel> = settings
+        .channels
+        .iter()
+        .filter(|c| c.enabled)
+        .cloned()
+        .collect();
+    if channels.is_empty() {
+        return;
+    }
+    let template = settings.template.clone();
+    std::thread::spawn(move || {
+        for channel in channels {
+            if let Err(e) = send(&channel, &notification, &template) {
+                eprintln!("Notification to {} failed: {}", channel.name, e);
+            }
+        }
+    });
+}
+
+/// Notify about a finished command run if its command asks for it.
+pub fn run_finished(store: &CommandStore, result: &ExecutionResult, output: &str) {
+    let (Some(command), Some(exit_code)) =
+        (store.get_command(&result.command_id), result.exit_code)
+    else {
+        return;
+    };
+    if !command.notify.matches(exit_code == 0) {
+        return;
+    }
+    let host = match store.get_host(&result.host_id) {
+        Some(host) => host.name,
+        None => command.adhoc_host().hostname,
+    };
+    let notification = Notification {
+        source: NotificationSource::Command,
+        id: command.id,
+        run_id: Some(result.id),
+        name: command.name,
+        host,
+        exit_code,
+        duration_ms: result.duration_ms.unwrap_or_default(),
+        log_tail: log_tail(output),
+    };
+    dispatch(&store.get_settings().notifications, notification);
+}
+
+/// Notify about a finished workflow if it asks for it. `exit_code` is the
+/// failing step's, or 0; `host` and `output` are the last step's.
+pub fn workflow_finished(
+    store: &CommandStore,
+    workflow: &Workflow,
+    exit_code: i32,
+    duration_ms: u64,
+    host: String,
+    output: &str,
+) {
+    if !workflow.notify.matches(exit_code == 0) {
+        return;
+    }
+    let notification = Notification {
+        source: NotificationSource::Workflow,
+        id: workflow.id,
+        run_id: None,
+        name: workflow.name.clone(),
+        host,
+        exit_code,
+        duration_ms,
+        log_tail: log_tail(output),
+    };
+    dispatch(&store.get_settings().notifications, notification);
+}
@@ -0,0 +1,149 @@
+#[cfg(test)]
+mod tests {
+    use crate::executor::Executor;
+    use crate::models::{Command, NotificationChannel, NotificationChannelKind, NotifyOn};
+    use crate::notifications::{Notification, NotificationSource, format_duration, log_tail, send};
+    use crate::runs::{RunManager, RunRequest};
+    use crate::store::CommandStore;
+    use serde_json::Value;
+    use std::sync::Arc;
+    use std::sync::mpsc::{Receiver, channel};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    /// A local HTTP server answering every request with `status`; returns
+    /// its URL and the request bodies it gets.
+    fn stub(status: u16) -> (String, Receiver<String>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr().to_ip().unwrap());
+        let (tx, rx) = channel();
+        std::thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let _ = request.respond(tiny_http::Response::empty(status));
+                if tx.send(body).is_err() {
+                    return;
+                }
+            }
+        });
+        (url, rx)
+    }
+
+    fn notification(exit_code: i32) -> Notification {
+        Notification {
+            source: NotificationSource::Command,
+            id: Uuid::new_v4(),
+            run_id: Some(Uuid::new_v4()),
+            name: "Deploy".to_string(),
+            host: "web-1".to_string(),
+            exit_code,
+            duration_ms: 125_000,
+            log_tail: "done".to_string(),
+        }
+    }
+
+    fn channel_to(kind: NotificationChannelKind) -> NotificationChannel {
+        NotificationChannel {
+            name: "test".to_string(),
+            kind,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_render_template() {
+        let message = notification(2)
+            .render("{name} {status} ({exit_code}) after {duration} on {host}: {log_tail}");
+        assert_eq!(message, "Deploy failed (2) after 2m 05s on web-1: done");
+        assert!(notification(0).render("{status}").contains("succeeded"));
+
+        // Values are never expanded again, and unknown braces are kept
+        let mut odd = notification(0);
+        odd.name = "{log_tail}".to_string();
+        odd.log_tail = "{host} {".to_string();
+        assert_eq!(
+            odd.render("{name}: {log_tail} {unknown} {"),
+            "{log_tail}: {host} { {unknown} {"
+        );
+
+        assert_eq!(format_duration(850), "850ms");
+        assert_eq!(format_duration(42_000), "42s");
+        assert_eq!(format_duration(7_800_000), "2h 10m");
+
+        let output: String = (1..=30).map(|i| format!("line {}\n", i)).collect();
+        let tail = log_tail(&output);
+        assert!(tail.starts_with("line 11\n") && tail.ends_with("line 30"));
+    }
+
+    #[test]
+    fn test_webhook_and_slack() {
+        let (url, bodies) = stub(200);
+
+        send(
+            &channel_to(NotificationChannelKind::Webhook { url: url.clone() }),
+            &notification(1),
+            "{name} {status}",
+        )
+        .unwrap();
+        let body: Value = serde_json::from_str(&bodies.recv().unwrap()).unwrap();
+        assert_eq!(body["source"], "command");
+        assert_eq!(body["status"], "failed");
+        assert_eq!(body["exit_code"], 1);
+        assert_eq!(body["duration_ms"], 125_000);
+        assert_eq!(body["message"], "Deploy failed");
+
+        send(
+            &channel_to(NotificationChannelKind::Slack { url }),
+            &notification(0),
+            "{name} {status}",
+        )
+        .unwrap();
+        let body: Value = serde_json::from_str(&bodies.recv().unwrap()).unwrap();
+        assert_eq!(body, serde_json::json!({ "text": "Deploy succeeded" }));
+
+        // Errors from the receiver are reported
+        let (url, _bodies) = stub(500);
+        assert!(
+            send(
+                &channel_to(NotificationChannelKind::Slack { url }),
+                &notification(0),
+                ""
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_finished_runs_notify_per_rule() {
+        let store = CommandStore::new_test();
+        let (url, bodies) = stub(200);
+        let mut settings = store.get_settings();
+        settings
+            .notifications
+            .channels
+            .push(channel_to(NotificationChannelKind::Webhook { url }));
+        store.set_settings(settings);
+
+        let command = |script: &str| {
+            store.add_command(Command {
+                notify: NotifyOn::Failure,
+                ..Command::new(script, script)
+            })
+        };
+        let runs = RunManager::new(store.clone(), Arc::new(Executor));
+        runs.start(command("echo fine"), RunRequest::default())
+            .unwrap();
+        let run_id = runs
+            .start(command("echo broken; exit 3"), RunRequest::default())
+            .unwrap()[0];
+
+        // Only the failure is reported
+        let body: Value =
+            serde_json::from_str(&bodies.recv_timeout(Duration::from_secs(10)).unwrap()).unwrap();
+        assert_eq!(body["run_id"], run_id.to_string());
+        assert_eq!(body["exit_code"], 3);
+        assert!(body["log_tail"].as_str().unwrap().ends_with("broken"));
+        assert!(bodies.recv_timeout(Duration::from_millis(500)).is_err());
+    }
+}
@@ -1,35 +1,146 @@
-use crate::models::{Command, ExecutionUpdate, Host};
-use crate::run_environment::{OutputChunk, RunEnvironment, RunEnvironmentError};
+use crate::models::{
+    BackgroundJob, Command, EscalationMethod, EscalationPassword, ExecutionUpdate, Host,
+};
+use crate::run_environment::{KillSwitch, OutputChunk, RunEnvironment, RunEnvironmentError};
+use crate::secrets;
+use crate::shell::{bash_c, elevate, exports, is_password_prompt, quote};
+use crate::transfer::{fetch_artifacts, upload};
+use crate::workspace::{finish_run_dir, prepare_run_dir, remove_run_dir};
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+const BACKGROUND_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What sudo (`-n`) and su print when they would need a password they
+/// can't ask for.
+const PASSWORD_NEEDED_MARKERS: [&str; 2] =
+    ["a password is required", "must be run from a terminal"];
 
 pub(crate) fn orchestrate_execution(
     exec_id: uuid::Uuid,
     env: &dyn RunEnvironment,
     command: &Command,
-    _host: &Host,
+    host: &Host,
     mut env_vars: HashMap<String, String>,
     on_update: &dyn Fn(ExecutionUpdate),
-    kill_rx: std::sync::mpsc::Receiver<()>,
+    kill_rx: Receiver<()>,
 ) -> Result<(), RunEnvironmentError> {
-    let log_file = format!("/tmp/switchboard_{}.log", exec_id);
-    let script_path = format!("/tmp/switchboard_{}.sh", exec_id);
+    let kill = KillSwitch::new(kill_rx)?;
+    let run_as = command.run_as.as_ref();
+    let password = match run_as.map(|r| &r.password) {
+        Some(EscalationPassword::Saved(id)) => Some(secrets::load(id).map_err(|e| {
+            RunEnvironmentError::Unsupported(format!(
+                "the saved run-as password is unavailable: {}",
+                e
+            ))
+        })?),
+        Some(EscalationPassword::Entered(password)) => Some(password.clone()),
+        Some(EscalationPassword::Prompt) => {
+            return Err(RunEnvironmentError::Unsupported(
+                "no password was entered to run as another user".to_string(),
+            ));
+        }
+        _ => None,
+    };
+    if password.is_some() && command.background {
+        return Err(RunEnvironmentError::BackgroundFailed(
+            "a background run can't answer a password prompt; use passwordless sudo instead"
+                .to_string(),
+        ));
+    }
+
+    let run_dir = prepare_run_dir(env, &host.workspace, exec_id)?;
+    env.use_run_dir(&run_dir.path);
+    // Until the script starts, a failure leaves nothing worth keeping
+    let abandon = |e: RunEnvironmentError| {
+        remove_run_dir(env, &run_dir.path);
+        e
+    };
+    let log_file = run_dir.log();
+    let script_path = run_dir.script();
 
     env_vars.insert("SWITCHBOARD_RUN".to_string(), exec_id.to_string());
+    env_vars.insert("SWITCHBOARD_RUN_DIR".to_string(), run_dir.path.clone());
     env_vars.insert("SWITCHBOARD_LOG".to_string(), log_file.clone());
 
-    env.write_file(&script_path, command.script.as_bytes())?;
+    let env_exports = exports(&env_vars).map_err(|e| abandon(e.into()))?;
 
-    let mut env_exports = String::new();
-    for (key, val) in &env_vars {
-        let escaped_val = val.replace('\'', "'\\''");
-        env_exports.push_str(&format!("export {}='{}'; ", key, escaped_val));
+    // Without SFTP the script goes in on stdin: a plain foreground run pipes
+    // it straight into `bash -s`; a background run, which has to outlive the
+    // connection, or a run in a terminal (to type a password) has `cat`
+    // write it to the run directory first.
+    let via_stdin = !env.has_file_transfer();
+    let stream_script = via_stdin && !command.background && password.is_none();
+    if !via_stdin {
+        env.write_file(&script_path, command.script.as_bytes())
+            .map_err(abandon)?;
+    } else if !stream_script {
+        let code = env
+            .run_with_input(
+                &format!("umask 077 && cat > {}", quote(&script_path)),
+                command.script.as_bytes(),
+                &|_| {},
+                &KillSwitch::never(),
+            )
+            .map_err(abandon)?;
+        if code != 0 {
+            return Err(abandon(RunEnvironmentError::UploadFailed(format!(
+                "could not write {} (exit code {})",
+                script_path, code
+            ))));
+        }
     }
+    let interpreter = if stream_script || run_as.is_some() {
+        "bash -l -s".to_string()
+    } else {
+        format!("bash -l {}", quote(&script_path))
+    };
 
     let work_dir = command.working_directory.as_deref().unwrap_or("/");
-    let inner_cmd = format!(
-        "{}chmod +x {} && cd {} && bash -l {}; rm -f {}",
-        env_exports, script_path, work_dir, script_path, script_path
-    );
+    let body = format!("{}cd {} && {}", env_exports, quote(work_dir), interpreter);
+    let body = match run_as {
+        // The other user can't read our private run directory, so the
+        // script is redirected in before switching users.
+        Some(run_as) if !stream_script => {
+            let elevated = elevate(run_as, &body).map_err(|e| abandon(e.into()))?;
+            format!("{} < {}", elevated, quote(&script_path))
+        }
+        Some(run_as) => elevate(run_as, &body).map_err(|e| abandon(e.into()))?,
+        None => body,
+    };
+    // Runs the script and removes it, keeping the script's exit code in $rc.
+    let run_script = format!("{}; rc=$?; rm -f {}", body, quote(&script_path));
+
+    // Spot sudo/su refusing to run without a password, to explain it after
+    // the run.
+    let password_needed = Cell::new(false);
+    let watched = |update: ExecutionUpdate| {
+        if let ExecutionUpdate::Stdout(s) | ExecutionUpdate::Stderr(s) = &update
+            && run_as.is_some()
+            && PASSWORD_NEEDED_MARKERS.iter().any(|m| s.contains(m))
+        {
+            password_needed.set(true);
+        }
+        on_update(update)
+    };
+    let on_update: &dyn Fn(ExecutionUpdate) = &watched;
+    let explain_password_needed = || {
+        if let Some(run_as) = run_as
+            && password_needed.get()
+        {
+            let method = match run_as.method {
+                EscalationMethod::Sudo => "sudo",
+                EscalationMethod::Su => "su",
+            };
+            on_update(ExecutionUpdate::Stderr(format!(
+                "\n[Run as {}: {} needs a password. Set the run-as password to \"Ask\" or \"Saved\", \
+                 or allow passwordless sudo (NOPASSWD) for this user.]\n",
+                run_as.user, method
+            )));
+        }
+    };
 
     let map_chunk = |chunk: OutputChunk| match chunk {
         OutputChunk::Stdout(s) => on_update(ExecutionUpdate::Stdout(s)),
@@ -37,21 +148,167 @@ pub(crate) fn orchestrate_execution(
     };
 
     env.emit_preamble(&map_chunk, &log_file);
+    if via_stdin {
+        on_update(ExecutionUpdate::Stdout(
+            "SFTP is not available on this host; sending the script over stdin\n".to_string(),
+        ));
+    }
+    upload(env, &command.uploads, work_dir, on_update).map_err(abandon)?;
 
     if command.background {
-        let exec_cmd = format!("nohup bash -c '{}' > {} 2>&1 &", inner_cmd, log_file);
-        let handle = env.run_background(&exec_cmd)?;
+        let exit_file = run_dir.exit();
+        // The wrapper records the script's own exit code (not `rm`'s) so the
+        // poller can report it after the process is gone. `setsid` makes the
+        // job a process group leader so stopping it takes its children too.
+        let wrapped = format!("{}; echo $rc > {}", run_script, quote(&exit_file));
+        let exec_cmd = format!(
+            "if command -v setsid >/dev/null 2>&1; then launcher=setsid; else launcher=; fi; \
+             $launcher nohup {} > {} 2>&1 < /dev/null & echo $!",
+            bash_c(&wrapped),
+            quote(&log_file)
+        );
+        let handle = env.run_background(&exec_cmd).map_err(abandon)?;
         on_update(ExecutionUpdate::Stdout(format!(
-            "Background process started: {}\n",
-            handle.pid_or_hint
+            "Background process started: PID {}\n",
+            handle.pid
         )));
-        on_update(ExecutionUpdate::Exit(0));
+        let job = BackgroundJob {
+            pid: handle.pid,
+            log_path: log_file,
+            exit_path: exit_file,
+            run_dir: run_dir.path.clone(),
+        };
+        on_update(ExecutionUpdate::Detached(job.clone()));
+        let code = watch_background(env, &job, 0, on_update, &kill)?;
+        fetch_artifacts(env, &command.artifacts, work_dir, on_update);
+        finish_run_dir(env, &host.workspace, &run_dir.path);
+        explain_password_needed();
+        on_update(ExecutionUpdate::Exit(code));
     } else {
-        let escaped_inner = inner_cmd.replace('\'', "'\\''");
-        let exec_cmd = format!("/bin/bash -c '{}' | tee {}", escaped_inner, log_file);
-        let code = env.run(&exec_cmd, &map_chunk, &kill_rx)?;
+        // pipefail makes the pipeline report the script's exit code, not tee's.
+        let exec_cmd = bash_c(&format!(
+            "set -o pipefail; {} | tee {}",
+            bash_c(&format!("{}; exit $rc", run_script)),
+            quote(&log_file)
+        ));
+        let code = match (run_as, password) {
+            (Some(run_as), Some(password)) => {
+                let method = run_as.method;
+                env.run_in_pty(
+                    &exec_cmd,
+                    &|tail| is_password_prompt(method, tail),
+                    &password,
+                    &map_chunk,
+                    &kill,
+                )?
+            }
+            _ if stream_script => {
+                env.run_with_input(&exec_cmd, command.script.as_bytes(), &map_chunk, &kill)?
+            }
+            _ => env.run(&exec_cmd, &map_chunk, &kill)?,
+        };
+        fetch_artifacts(env, &command.artifacts, work_dir, on_update);
+        finish_run_dir(env, &host.workspace, &run_dir.path);
+        explain_password_needed();
         on_update(ExecutionUpdate::Exit(code));
     }
 
     Ok(())
 }
+
+enum BackgroundState {
+    Running,
+    Exited(i32),
+    Lost,
+}
+
+/// Tails a background job's log from byte `offset` until the job exits or a
+/// kill request arrives, forwarding new output. Returns the job's exit code
+/// (-1 if it was stopped or lost).
+pub(crate) fn watch_background(
+    env: &dyn RunEnvironment,
+    job: &BackgroundJob,
+    mut offset: u64,
+    on_update: &dyn Fn(ExecutionUpdate),
+    kill: &KillSwitch,
+) -> Result<i32, RunEnvironmentError> {
+    loop {
+        let (state, next, output) = poll_background(env, job, offset)?;
+        offset = next;
+        if !output.is_empty() {
+            on_update(ExecutionUpdate::Stdout(output));
+        }
+
+        match state {
+            BackgroundState::Exited(code) => {
+                let _ = env.capture(&format!("rm -f {}", quote(&job.exit_path)));
+                return Ok(code);
+            }
+            BackgroundState::Lost => {
+                on_update(ExecutionUpdate::Stderr(format!(
+                    "\n[Background process {} is gone without recording an exit code]\n",
+                    job.pid
+                )));
+                return Ok(-1);
+            }
+            BackgroundState::Running => {}
+        }
+
+        if kill.wait(BACKGROUND_POLL_INTERVAL) {
+            on_update(ExecutionUpdate::Stderr(format!(
+                "\n[Stopping background process {}...]\n",
+                job.pid
+            )));
+            stop_background(env, job)?;
+            return Ok(-1);
+        }
+    }
+}
+
+/// Reports whether the job is still alive and returns its log past `offset`
+/// along with the offset to continue from. The log is read by byte count,
+/// so output that isn't valid UTF-8 doesn't throw the offset off.
+fn poll_background(
+    env: &dyn RunEnvironment,
+    job: &BackgroundJob,
+    offset: u64,
+) -> Result<(BackgroundState, u64, String), RunEnvironmentError> {
+    // Liveness is checked before the exit file (the wrapper writes it just
+    // before exiting) and before tailing, so an "exit" answer is always
+    // followed by the complete log. Unreaped zombies count as exited.
+    let probe = format!(
+        "if ps -o stat= -p {pid} 2>/dev/null | grep -qv Z; then echo running; \
+         elif [ -s {exit} ]; then echo \"exit:$(cat {exit})\"; else echo lost; fi; \
+         size=$(( $(wc -c < {log} 2>/dev/null) + 0 )); echo $size; \
+         if [ $size -gt {offset} ]; then tail -c +{start} {log} | head -c $((size - {offset})); fi; true",
+        exit = quote(&job.exit_path),
+        pid = job.pid,
+        offset = offset,
+        start = offset + 1,
+        log = quote(&job.log_path),
+    );
+    let (_, stdout) = env.capture(&probe)?;
+    let (status, rest) = stdout.split_once('\n').unwrap_or((stdout.as_str(), ""));
+    let (size, output) = rest.split_once('\n').unwrap_or((rest, ""));
+
+    let state = match status.strip_prefix("exit:") {
+        Some(code) => BackgroundState::Exited(code.trim().parse().unwrap_or(-1)),
+        None if status == "running" => BackgroundState::Running,
+        None => BackgroundState::Lost,
+    };
+    let offset = size.trim().parse().unwrap_or(offset).max(offset);
+    Ok((state, offset, output.to_string()))
+}
+
+/// Terminates a background job along with the processes it spawned.
+pub(crate) fn stop_background(
+    env: &dyn RunEnvironment,
+    job: &BackgroundJob,
+) -> Result<(), RunEnvironmentError> {
+    env.capture(&format!(
+        "kill -TERM -- -{pid} 2>/dev/null || {{ pkill -TERM -P {pid}; kill -TERM {pid}; }}; rm -f {exit}",
+        pid = job.pid,
+        exit = quote(&job.exit_path),
+    ))?;
+    Ok(())
+}
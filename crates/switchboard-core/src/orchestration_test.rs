@@ -0,0 +1,567 @@
+#[cfg(test)]
+mod tests {
+    use crate::models::{
+        AuthMethod, Command, EscalationMethod, EscalationPassword, ExecutionUpdate, FileTransfer,
+        Host, LogCleanup, RemoteWorkspace, RunAs,
+    };
+    use crate::orchestration::orchestrate_execution;
+    use crate::run_environment::{
+        BackgroundHandle, DirEntry, KillSwitch, LocalRunEnvironment, OutputChunk, RunEnvironment,
+        RunEnvironmentError,
+    };
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use uuid::Uuid;
+
+    fn make_command(script: &str, background: bool) -> Command {
+        Command {
+            background,
+            ..Command::new("Test Command", script)
+        }
+    }
+
+    fn local_host() -> Host {
+        Host {
+            id: Uuid::new_v4(),
+            name: "local".into(),
+            hostname: "localhost".into(),
+            port: 22,
+            username: "user".into(),
+            auth: AuthMethod::Agent,
+            kind: Default::default(),
+            groups: vec![],
+            tags: vec![],
+            workspace: RemoteWorkspace {
+                dir: std::env::temp_dir()
+                    .join(format!("switchboard_ws_{}", Uuid::new_v4()))
+                    .to_string_lossy()
+                    .to_string(),
+                log_cleanup: LogCleanup::KeepAll,
+            },
+        }
+    }
+
+    /// This machine, as seen through an SSH server without SFTP.
+    struct NoSftp(LocalRunEnvironment);
+
+    fn no_sftp() -> RunEnvironmentError {
+        RunEnvironmentError::UploadFailed("sftp subsystem unavailable".into())
+    }
+
+    impl RunEnvironment for NoSftp {
+        fn write_file(&self, _: &str, _: &[u8]) -> Result<(), RunEnvironmentError> {
+            Err(no_sftp())
+        }
+        fn read_file(&self, _: &str) -> Result<Vec<u8>, RunEnvironmentError> {
+            Err(no_sftp())
+        }
+        fn list_dir(&self, _: &str) -> Result<Vec<DirEntry>, RunEnvironmentError> {
+            Err(no_sftp())
+        }
+        fn is_dir(&self, _: &str) -> Result<bool, RunEnvironmentError> {
+            Err(no_sftp())
+        }
+        fn create_dir(&self, _: &str) -> Result<(), RunEnvironmentError> {
+            Err(no_sftp())
+        }
+        fn run(
+            &self,
+            command: &str,
+            on_output: &dyn Fn(OutputChunk),
+            kill: &KillSwitch,
+        ) -> Result<i32, RunEnvironmentError> {
+            self.0.run(command, on_output, kill)
+        }
+        fn run_with_input(
+            &self,
+            command: &str,
+            input: &[u8],
+            on_output: &dyn Fn(OutputChunk),
+            kill: &KillSwitch,
+        ) -> Result<i32, RunEnvironmentError> {
+            self.0.run_with_input(command, input, on_output, kill)
+        }
+        fn has_file_transfer(&self) -> bool {
+            false
+        }
+        fn emit_preamble(&self, _: &dyn Fn(OutputChunk), _: &str) {}
+    }
+
+    /// This machine, failing to launch background jobs.
+    struct NoBackground(LocalRunEnvironment);
+
+    impl RunEnvironment for NoBackground {
+        fn write_file(&self, path: &str, contents: &[u8]) -> Result<(), RunEnvironmentError> {
+            self.0.write_file(path, contents)
+        }
+        fn read_file(&self, path: &str) -> Result<Vec<u8>, RunEnvironmentError> {
+            self.0.read_file(path)
+        }
+        fn list_dir(&self, path: &str) -> Result<Vec<DirEntry>, RunEnvironmentError> {
+            self.0.list_dir(path)
+        }
+        fn is_dir(&self, path: &str) -> Result<bool, RunEnvironmentError> {
+            self.0.is_dir(path)
+        }
+        fn create_dir(&self, path: &str) -> Result<(), RunEnvironmentError> {
+            self.0.create_dir(path)
+        }
+        fn run(
+            &self,
+            command: &str,
+            on_output: &dyn Fn(OutputChunk),
+            kill: &KillSwitch,
+        ) -> Result<i32, RunEnvironmentError> {
+            self.0.run(command, on_output, kill)
+        }
+        fn run_with_input(
+            &self,
+            command: &str,
+            input: &[u8],
+            on_output: &dyn Fn(OutputChunk),
+            kill: &KillSwitch,
+        ) -> Result<i32, RunEnvironmentError> {
+            self.0.run_with_input(command, input, on_output, kill)
+        }
+        fn run_background(&self, _: &str) -> Result<BackgroundHandle, RunEnvironmentError> {
+            Err(RunEnvironmentError::BackgroundFailed("no PID".into()))
+        }
+        fn emit_preamble(&self, _: &dyn Fn(OutputChunk), _: &str) {}
+    }
+
+    fn run_without_sftp(command: &Command) -> Vec<ExecutionUpdate> {
+        let updates = Mutex::new(Vec::new());
+        let (_kill_tx, kill_rx) = std::sync::mpsc::channel();
+        orchestrate_execution(
+            Uuid::new_v4(),
+            &NoSftp(LocalRunEnvironment::new()),
+            command,
+            &local_host(),
+            HashMap::from([("GREETING".to_string(), "hello there".to_string())]),
+            &|u| updates.lock().unwrap().push(u),
+            kill_rx,
+        )
+        .expect("orchestration failed");
+        updates.into_inner().unwrap()
+    }
+
+    fn run(command: &Command) -> Vec<ExecutionUpdate> {
+        run_on(&local_host(), command)
+    }
+
+    fn run_on(host: &Host, command: &Command) -> Vec<ExecutionUpdate> {
+        let updates = Mutex::new(Vec::new());
+        let (_kill_tx, kill_rx) = std::sync::mpsc::channel();
+        orchestrate_execution(
+            Uuid::new_v4(),
+            &LocalRunEnvironment::new(),
+            command,
+            host,
+            HashMap::new(),
+            &|u| updates.lock().unwrap().push(u),
+            kill_rx,
+        )
+        .expect("orchestration failed");
+        updates.into_inner().unwrap()
+    }
+
+    fn stdout(updates: &[ExecutionUpdate]) -> String {
+        updates
+            .iter()
+            .filter_map(|u| match u {
+                ExecutionUpdate::Stdout(s) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_background_run_reports_pid_output_and_exit_code() {
+        let updates = run(&make_command("echo from-background; exit 3", true));
+
+        let job = updates
+            .iter()
+            .find_map(|u| match u {
+                ExecutionUpdate::Detached(job) => Some(job.clone()),
+                _ => None,
+            })
+            .expect("no Detached update");
+        assert!(job.pid > 0);
+        assert!(stdout(&updates).contains("from-background"));
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(3))));
+        assert!(!std::path::Path::new(&job.exit_path).exists());
+        assert!(job.log_path.starts_with(&job.run_dir));
+    }
+
+    #[test]
+    fn test_background_log_offset_counts_bytes() {
+        // The invalid byte turns into a three-byte replacement character
+        // that must not push the next read past "c"
+        let updates = run(&make_command(
+            "printf 'a\\377b\\n'; sleep 2; printf 'c\\n'",
+            true,
+        ));
+        let out = stdout(&updates);
+        assert!(out.ends_with("a\u{FFFD}b\nc\n"), "{:?}", out);
+    }
+
+    #[test]
+    fn test_run_dir_is_private_and_removed_after_run() {
+        let mut host = local_host();
+        host.workspace.log_cleanup = LogCleanup::DeleteAfterRun;
+        let updates = run_on(
+            &host,
+            &make_command(
+                "echo \"$SWITCHBOARD_RUN_DIR\"; stat -c %a \"$SWITCHBOARD_RUN_DIR\"",
+                false,
+            ),
+        );
+
+        let out = stdout(&updates);
+        let mut lines = out.lines();
+        let run_dir = lines.next().unwrap();
+        assert!(run_dir.starts_with(&host.workspace.dir));
+        assert_eq!(lines.next(), Some("700"));
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(0))));
+        assert!(!std::path::Path::new(run_dir).exists());
+        assert!(std::path::Path::new(&host.workspace.dir).is_dir());
+    }
+
+    #[test]
+    fn test_run_dir_is_removed_when_the_run_cannot_start() {
+        let host = local_host();
+        let try_run = |command: &Command| {
+            let (_kill_tx, kill_rx) = std::sync::mpsc::channel();
+            orchestrate_execution(
+                Uuid::new_v4(),
+                &LocalRunEnvironment::new(),
+                command,
+                &host,
+                HashMap::new(),
+                &|_| {},
+                kill_rx,
+            )
+        };
+
+        // Refused before anything is created
+        let mut command = make_command("true", false);
+        command.run_as = Some(RunAs {
+            password: EscalationPassword::Prompt,
+            ..Default::default()
+        });
+        assert!(try_run(&command).is_err());
+        assert!(!std::path::Path::new(&host.workspace.dir).exists());
+
+        // Failing once its directory exists
+        let mut command = make_command("true", false);
+        command.uploads = vec![FileTransfer {
+            local_path: "/nonexistent/switchboard/file".into(),
+            remote_path: "file".into(),
+        }];
+        assert!(try_run(&command).is_err());
+        let left = std::fs::read_dir(&host.workspace.dir).unwrap().count();
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    fn test_run_dir_is_removed_when_the_background_launch_fails() {
+        let host = local_host();
+        let (_kill_tx, kill_rx) = std::sync::mpsc::channel();
+        let result = orchestrate_execution(
+            Uuid::new_v4(),
+            &NoBackground(LocalRunEnvironment::new()),
+            &make_command("sleep 1", true),
+            &host,
+            HashMap::new(),
+            &|_| {},
+            kill_rx,
+        );
+
+        assert!(matches!(
+            result,
+            Err(RunEnvironmentError::BackgroundFailed(_))
+        ));
+        let left = std::fs::read_dir(&host.workspace.dir).unwrap().count();
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    fn test_old_run_dirs_are_pruned() {
+        let mut host = local_host();
+        host.workspace.log_cleanup = LogCleanup::OlderThanDays(7);
+        let base = std::path::PathBuf::from(&host.workspace.dir);
+        let old = base.join(Uuid::new_v4().to_string());
+        let recent = base.join(Uuid::new_v4().to_string());
+        let unrelated = base.join("notes");
+        for dir in [&old, &recent, &unrelated] {
+            std::fs::create_dir_all(dir).unwrap();
+            std::fs::write(dir.join("output.log"), "log").unwrap();
+        }
+        for dir in [&old, &unrelated] {
+            let status = std::process::Command::new("touch")
+                .args(["-d", "10 days ago"])
+                .arg(dir.join("output.log"))
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+
+        let updates = run_on(&host, &make_command("echo hi", false));
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(0))));
+        assert!(!old.exists());
+        assert!(recent.exists());
+        assert!(unrelated.exists());
+        // The new run's directory (with its log) is kept.
+        assert_eq!(std::fs::read_dir(&base).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn test_background_run_can_be_stopped() {
+        let command = make_command("sleep 30", true);
+        let updates = Mutex::new(Vec::new());
+        let (kill_tx, kill_rx) = std::sync::mpsc::channel();
+        kill_tx.send(()).unwrap();
+
+        orchestrate_execution(
+            Uuid::new_v4(),
+            &LocalRunEnvironment::new(),
+            &command,
+            &local_host(),
+            HashMap::new(),
+            &|u| updates.lock().unwrap().push(u),
+            kill_rx,
+        )
+        .expect("orchestration failed");
+
+        let updates = updates.into_inner().unwrap();
+        let job = updates
+            .iter()
+            .find_map(|u| match u {
+                ExecutionUpdate::Detached(job) => Some(job.clone()),
+                _ => None,
+            })
+            .expect("no Detached update");
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(-1))));
+
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        let ps = std::process::Command::new("ps")
+            .args(["-o", "stat=", "-p", &job.pid.to_string()])
+            .output()
+            .unwrap();
+        let stat = String::from_utf8_lossy(&ps.stdout);
+        assert!(
+            stat.trim().is_empty() || stat.contains('Z'),
+            "background process survived stop"
+        );
+    }
+
+    #[test]
+    fn test_awkward_paths_and_values_are_quoted() {
+        let dir =
+            std::env::temp_dir().join(format!("switchboard dir 'quoted' $HOME {}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut command = make_command("pwd; printf '%s\\n' \"$GREETING\"; exit 5", false);
+        command.working_directory = Some(dir.to_string_lossy().to_string());
+
+        let updates = Mutex::new(Vec::new());
+        let (_kill_tx, kill_rx) = std::sync::mpsc::channel();
+        let env_vars =
+            HashMap::from([("GREETING".to_string(), "it's \"$(date)\" `x`".to_string())]);
+        orchestrate_execution(
+            Uuid::new_v4(),
+            &LocalRunEnvironment::new(),
+            &command,
+            &local_host(),
+            env_vars,
+            &|u| updates.lock().unwrap().push(u),
+            kill_rx,
+        )
+        .expect("orchestration failed");
+
+        let updates = updates.into_inner().unwrap();
+        assert_eq!(
+            stdout(&updates),
+            format!("{}\nit's \"$(date)\" `x`\n", dir.display())
+        );
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(5))));
+    }
+
+    #[test]
+    fn test_script_goes_over_stdin_without_sftp() {
+        let script = "echo \"$GREETING\"\nif [ -e \"$SWITCHBOARD_RUN_DIR/script.sh\" ]; then echo uploaded; fi\nexit 7";
+        let updates = run_without_sftp(&make_command(script, false));
+        let out = stdout(&updates);
+        assert!(out.contains("sending the script over stdin"));
+        assert!(out.contains("hello there\n"));
+        assert!(!out.contains("uploaded"));
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(7))));
+
+        let updates = run_without_sftp(&make_command("echo \"bg $GREETING\"; exit 2", true));
+        assert!(stdout(&updates).contains("bg hello there"));
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(2))));
+    }
+
+    #[test]
+    fn test_uploads_go_over_stdin_without_sftp() {
+        let local = std::env::temp_dir().join(format!("switchboard_upload_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(local.join("conf")).unwrap();
+        std::fs::write(local.join("conf/app.ini"), "port = 80\n").unwrap();
+        let work_dir =
+            std::env::temp_dir().join(format!("switchboard_upload_dest_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&work_dir).unwrap();
+
+        let mut command = make_command("cat deploy/conf/app.ini", false);
+        command.working_directory = Some(work_dir.to_string_lossy().to_string());
+        command.uploads = vec![FileTransfer {
+            local_path: local.to_string_lossy().to_string(),
+            remote_path: "deploy".into(),
+        }];
+        let updates = run_without_sftp(&command);
+        let out = stdout(&updates);
+        assert!(out.contains("[upload] 1/1"), "{}", out);
+        assert!(out.contains("port = 80\n"), "{}", out);
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(0))));
+    }
+
+    #[test]
+    fn test_artifacts_come_back_without_sftp() {
+        let work_dir =
+            std::env::temp_dir().join(format!("switchboard_artifacts_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&work_dir).unwrap();
+
+        let mut command = make_command(
+            "mkdir -p out/sub && printf 'a\\n' > out/a.txt && printf '\\000\\377\\n' > out/sub/b.bin",
+            false,
+        );
+        command.working_directory = Some(work_dir.to_string_lossy().to_string());
+        command.artifacts = vec!["out".into(), "missing.log".into()];
+        let updates = run_without_sftp(&command);
+
+        let artifacts: Vec<(String, Vec<u8>)> = updates
+            .iter()
+            .filter_map(|u| match u {
+                ExecutionUpdate::Artifact { name, data } => Some((name.clone(), data.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            artifacts,
+            vec![
+                ("out/a.txt".to_string(), b"a\n".to_vec()),
+                ("out/sub/b.bin".to_string(), vec![0, 255, b'\n']),
+            ]
+        );
+        assert!(
+            updates
+                .iter()
+                .any(|u| matches!(u, ExecutionUpdate::Stderr(s) if s.contains("missing.log")))
+        );
+        std::fs::remove_dir_all(&work_dir).unwrap();
+    }
+
+    fn run_as_nobody() -> Option<RunAs> {
+        Some(RunAs {
+            user: "nobody".to_string(),
+            method: EscalationMethod::Su,
+            password: EscalationPassword::None,
+        })
+    }
+
+    #[test]
+    fn test_run_as_switches_user() {
+        // The tests run as root, so su needs no password.
+        let mut command = make_command("id -un; echo \"$GREETING\"", false);
+        command.run_as = run_as_nobody();
+        let updates = run_without_sftp(&command);
+        assert!(
+            stdout(&updates).ends_with("nobody\nhello there\n"),
+            "{:?}",
+            stdout(&updates)
+        );
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(0))));
+
+        let updates = run(&command);
+        assert!(stdout(&updates).contains("nobody\n"));
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(0))));
+    }
+
+    #[test]
+    fn test_missing_run_as_password_is_explained() {
+        let mut command = make_command("echo 'sudo: a password is required' >&2; exit 1", false);
+        command.run_as = run_as_nobody();
+        let updates = run(&command);
+        let stderr: String = updates
+            .iter()
+            .filter_map(|u| match u {
+                ExecutionUpdate::Stderr(s) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert!(
+            stderr.contains("[Run as nobody: su needs a password."),
+            "{:?}",
+            stderr
+        );
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(1))));
+    }
+
+    #[test]
+    fn test_invalid_env_key_is_rejected() {
+        let (_kill_tx, kill_rx) = std::sync::mpsc::channel();
+        let env_vars = HashMap::from([("X; touch /tmp/pwned".to_string(), "1".to_string())]);
+        let result = orchestrate_execution(
+            Uuid::new_v4(),
+            &LocalRunEnvironment::new(),
+            &make_command("echo should-not-run", false),
+            &local_host(),
+            env_vars,
+            &|u| assert!(!matches!(u, ExecutionUpdate::Stdout(s) if s.contains("should-not-run"))),
+            kill_rx,
+        );
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("Invalid environment variable name"), "{}", err);
+    }
+
+    #[test]
+    fn test_foreground_run_streams_stdout_and_stderr() {
+        let updates = run(&make_command("echo to-stdout; echo to-stderr >&2", false));
+
+        let stderr: String = updates
+            .iter()
+            .filter_map(|u| match u {
+                ExecutionUpdate::Stderr(s) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert!(stdout(&updates).contains("to-stdout"));
+        assert!(stderr.contains("to-stderr"));
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(0))));
+    }
+
+    #[test]
+    fn test_foreground_kill_is_prompt() {
+        let command = make_command("sleep 30", false);
+        let updates = Mutex::new(Vec::new());
+        let (kill_tx, kill_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            let _ = kill_tx.send(());
+        });
+
+        let started = std::time::Instant::now();
+        orchestrate_execution(
+            Uuid::new_v4(),
+            &LocalRunEnvironment::new(),
+            &command,
+            &local_host(),
+            HashMap::new(),
+            &|u| updates.lock().unwrap().push(u),
+            kill_rx,
+        )
+        .expect("orchestration failed");
+
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+        let updates = updates.into_inner().unwrap();
+        assert!(matches!(updates.last(), Some(ExecutionUpdate::Exit(-1))));
+    }
+}
@@ -0,0 +1,236 @@
+use super::local::run_process;
+use super::poll::KillSwitch;
+use super::{DirEntry, OutputChunk, RunEnvironment, RunEnvironmentError};
+use crate::shell::quote;
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+
+/// Runs commands through a local CLI that execs into somewhere else, such
+/// as `docker exec -i <container>` or `kubectl exec -i <pod> --`.
+///
+/// Everything, file operations included, is done by running small shell
+/// commands through `binary` followed by `prefix`, so the target only needs
+/// `sh`, `bash` and coreutils.
+pub struct CliRunEnvironment {
+    binary: PathBuf,
+    prefix: Vec<String>,
+    /// Shown in the preamble and in error messages, e.g. "container web".
+    label: String,
+    /// Arguments to `binary` run when the environment is dropped.
+    cleanup: Option<Vec<String>>,
+    /// The run's private directory on the target, once there is one.
+    run_dir: RefCell<Option<String>>,
+}
+
+impl CliRunEnvironment {
+    pub(super) fn new(binary: PathBuf, prefix: Vec<String>, label: String) -> Self {
+        CliRunEnvironment {
+            binary,
+            prefix,
+            label,
+            cleanup: None,
+            run_dir: RefCell::new(None),
+        }
+    }
+
+    pub(super) fn with_cleanup(mut self, args: Vec<String>) -> Self {
+        self.cleanup = Some(args);
+        self
+    }
+
+    /// Check the target is reachable, reporting a missing binary or a
+    /// stopped target as a connection failure.
+    pub(super) fn check(self) -> Result<Self, RunEnvironmentError> {
+        match self.exec(&["true"], None) {
+            Ok(_) => Ok(self),
+            Err(RunEnvironmentError::Io(e)) => Err(RunEnvironmentError::ConnectionFailed(format!(
+                "could not run {}: {}",
+                self.binary.display(),
+                e
+            ))),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Run `args` on the target, optionally feeding `input` on stdin, and
+    /// fail unless it exits successfully.
+    fn exec(&self, args: &[&str], input: Option<&[u8]>) -> Result<Output, RunEnvironmentError> {
+        let mut child = std::process::Command::new(&self.binary)
+            .args(&self.prefix)
+            .args(args)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(input) = input {
+            let mut stdin = child.stdin.take().expect("Failed to open stdin");
+            stdin.write_all(input)?;
+        }
+        let output = child.wait_with_output()?;
+        if output.status.success() {
+            Ok(output)
+        } else {
+            Err(RunEnvironmentError::ConnectionFailed(format!(
+                "{}: {}",
+                self.label,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )))
+        }
+    }
+
+    /// Run a file operation on the target, reporting failures as I/O errors
+    /// against `path`.
+    fn file_op(
+        &self,
+        args: &[&str],
+        input: Option<&[u8]>,
+        path: &str,
+    ) -> Result<Output, RunEnvironmentError> {
+        self.exec(args, input)
+            .map_err(|e| RunEnvironmentError::Io(std::io::Error::other(format!("{}: {}", path, e))))
+    }
+}
+
+impl Drop for CliRunEnvironment {
+    fn drop(&mut self) {
+        if let Some(args) = &self.cleanup {
+            let _ = std::process::Command::new(&self.binary)
+                .args(args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+}
+
+impl RunEnvironment for CliRunEnvironment {
+    fn write_file(&self, path: &str, contents: &[u8]) -> Result<(), RunEnvironmentError> {
+        self.file_op(
+            &["sh", "-c", "cat > \"$1\" && chmod 755 \"$1\"", "sh", path],
+            Some(contents),
+            path,
+        )?;
+        Ok(())
+    }
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, RunEnvironmentError> {
+        Ok(self.file_op(&["cat", path], None, path)?.stdout)
+    }
+
+    fn list_dir(&self, path: &str) -> Result<Vec<DirEntry>, RunEnvironmentError> {
+        let output = self.file_op(&["ls", "-1Ap", path], None, path)?;
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| match l.strip_suffix('/') {
+                Some(name) => DirEntry {
+                    name: name.to_string(),
+                    is_dir: true,
+                },
+                None => DirEntry {
+                    name: l.to_string(),
+                    is_dir: false,
+                },
+            })
+            .collect())
+    }
+
+    fn is_dir(&self, path: &str) -> Result<bool, RunEnvironmentError> {
+        let output = self.file_op(
+            &[
+                "sh",
+                "-c",
+                "test -e \"$1\" || exit 1; test -d \"$1\" && echo dir || echo file",
+                "sh",
+                path,
+            ],
+            None,
+            path,
+        )?;
+        Ok(String::from_utf8_lossy(&output.stdout).trim() == "dir")
+    }
+
+    fn create_dir(&self, path: &str) -> Result<(), RunEnvironmentError> {
+        self.file_op(&["mkdir", "-p", path], None, path)?;
+        Ok(())
+    }
+
+    fn run(
+        &self,
+        command: &str,
+        on_output: &dyn Fn(OutputChunk),
+        kill: &KillSwitch,
+    ) -> Result<i32, RunEnvironmentError> {
+        self.run_with_input(command, &[], on_output, kill)
+    }
+
+    fn run_with_input(
+        &self,
+        command: &str,
+        input: &[u8],
+        on_output: &dyn Fn(OutputChunk),
+        kill: &KillSwitch,
+    ) -> Result<i32, RunEnvironmentError> {
+        // The command goes in on stdin so it never has to survive another
+        // round of argument quoting; `input` follows it there (bash reads a
+        // piped script line by line, leaving the rest to the command).
+        // Killing the local CLI doesn't stop what it started on the target,
+        // so the shell records its PID in the run directory and a kill
+        // signals it directly. Before there is a run directory (only short
+        // setup commands run then), just the local CLI is killed.
+        let pid_file = self
+            .run_dir
+            .borrow()
+            .as_ref()
+            .map(|dir| format!("{}/cli.pid", dir));
+        let mut process = std::process::Command::new(&self.binary);
+        process.args(&self.prefix);
+        match &pid_file {
+            Some(pid_file) => process
+                .args(["sh", "-c"])
+                .arg(format!("echo $$ > {}; exec /bin/bash -s", quote(pid_file))),
+            None => process.args(["/bin/bash", "-s"]),
+        };
+
+        let stop = || {
+            let Some(pid_file) = &pid_file else { return };
+            let _ = self.exec(
+                &[
+                    "sh",
+                    "-c",
+                    "pid=$(cat \"$1\") || exit 0; pkill -TERM -P \"$pid\" 2>/dev/null; kill -TERM \"$pid\"",
+                    "sh",
+                    pid_file,
+                ],
+                None,
+            );
+        };
+        let input = [format!("{}\n", command).as_bytes(), input].concat();
+        let code = run_process(process, Some(input), on_output, kill, &stop);
+        if let Some(pid_file) = &pid_file {
+            let _ = self.exec(&["rm", "-f", pid_file], None);
+        }
+        code
+    }
+
+    fn use_run_dir(&self, path: &str) {
+        *self.run_dir.borrow_mut() = Some(path.to_string());
+    }
+
+    fn emit_preamble(&self, on_output: &dyn Fn(OutputChunk), _log_file: &str) {
+        let binary = Path::new(&self.binary)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        on_output(OutputChunk::Stdout(format!(
+            "Running in {} via {}\n",
+            self.label, binary
+        )));
+    }
+}
@@ -0,0 +1,162 @@
+use super::RunEnvironmentError;
+use super::cli::CliRunEnvironment;
+use crate::models::{ContainerMode, ContainerTarget};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Labels on throwaway containers naming the machine and process that
+/// started them, so ones a crashed process left behind can be found.
+const HOST_LABEL: &str = "switchboard.host";
+const PID_LABEL: &str = "switchboard.pid";
+
+/// Open an environment that runs commands inside a container via the
+/// docker/podman CLI.
+///
+/// In `Exec` mode commands go to an existing container. In `Image` mode a
+/// throwaway container is started from the image (with the working
+/// directory bind-mounted at the same path) and removed again when the
+/// environment is dropped. Throwaway containers left over by a process on
+/// this machine that is gone are removed before the first one is started.
+pub fn start(
+    binary: impl Into<PathBuf>,
+    target: &ContainerTarget,
+    work_dir: Option<&str>,
+) -> Result<CliRunEnvironment, RunEnvironmentError> {
+    let binary = binary.into();
+    match &target.mode {
+        ContainerMode::Exec { container } => {
+            let container = container.trim();
+            if container.is_empty() {
+                return Err(RunEnvironmentError::ConnectionFailed(
+                    "no container name given".to_string(),
+                ));
+            }
+            CliRunEnvironment::new(
+                binary,
+                exec_prefix(container),
+                format!("container {}", container),
+            )
+            .check()
+        }
+        ContainerMode::Image { image } => {
+            reap_once(&binary);
+            let mut cmd = std::process::Command::new(&binary);
+            cmd.args(["run", "-d", "--rm"]);
+            cmd.arg("--label")
+                .arg(format!("{}={}", HOST_LABEL, hostname()));
+            cmd.arg("--label")
+                .arg(format!("{}={}", PID_LABEL, std::process::id()));
+            if let Some(dir) = work_dir.filter(|d| *d != "/") {
+                cmd.arg("-v")
+                    .arg(format!("{0}:{0}", dir))
+                    .arg("-w")
+                    .arg(dir);
+            }
+            cmd.args([
+                "--entrypoint",
+                "sh",
+                image.trim(),
+                "-c",
+                "while :; do sleep 3600; done",
+            ]);
+            let output = cmd.output().map_err(|e| {
+                RunEnvironmentError::ConnectionFailed(format!(
+                    "could not run {}: {}",
+                    binary.display(),
+                    e
+                ))
+            })?;
+            if !output.status.success() {
+                return Err(RunEnvironmentError::ConnectionFailed(format!(
+                    "could not start {}: {}",
+                    image,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )));
+            }
+
+            let id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            let short_id: String = id.chars().take(12).collect();
+            Ok(CliRunEnvironment::new(
+                binary,
+                exec_prefix(&id),
+                format!("throwaway container {} ({})", short_id, image.trim()),
+            )
+            .with_cleanup(vec!["rm".to_string(), "-f".to_string(), id]))
+        }
+    }
+}
+
+fn exec_prefix(container: &str) -> Vec<String> {
+    vec!["exec".to_string(), "-i".to_string(), container.to_string()]
+}
+
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    // SAFETY: gethostname writes at most `buffer.len()` bytes into it.
+    if unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } != 0 {
+        return "localhost".to_string();
+    }
+    let end = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..end]).to_string()
+}
+
+fn process_is_alive(pid: i32) -> bool {
+    // SAFETY: signal 0 only checks that the process exists.
+    let rc = unsafe { libc::kill(pid, 0) };
+    rc == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Remove stale throwaway containers once per runtime binary and process.
+fn reap_once(binary: &Path) {
+    static REAPED: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+    if REAPED
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .insert(binary.to_path_buf())
+        && let Err(e) = reap_stale(binary)
+    {
+        log::warn!("Could not remove leftover containers: {}", e);
+    }
+}
+
+/// Remove throwaway containers started on this machine by processes that
+/// no longer run; they're only removed on drop, which a crash skips.
+fn reap_stale(binary: &Path) -> Result<(), String> {
+    let run = |args: &[&str]| -> Result<String, String> {
+        let output = std::process::Command::new(binary)
+            .args(args)
+            .output()
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    };
+
+    let filter = format!("label={}={}", HOST_LABEL, hostname());
+    let listed = run(&["ps", "-aq", "--filter", &filter])?;
+    let ids: Vec<&str> = listed.split_whitespace().collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let format = format!("{{{{index .Config.Labels \"{}\"}}}}", PID_LABEL);
+    let mut args = vec!["inspect", "--format", &format];
+    args.extend(&ids);
+    let pids = run(&args)?;
+
+    let stale: Vec<&str> = ids
+        .iter()
+        .zip(pids.lines())
+        .filter(|(_, pid)| pid.trim().parse().is_ok_and(|pid| !process_is_alive(pid)))
+        .map(|(id, _)| *id)
+        .collect();
+    if !stale.is_empty() {
+        log::info!("Removing {} leftover container(s)", stale.len());
+        let mut args = vec!["rm", "-f"];
+        args.extend(&stale);
+        run(&args)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,182 @@
+#[cfg(test)]
+mod tests {
+    use crate::models::{ContainerMode, ContainerRuntime, ContainerTarget};
+    use crate::run_environment::container::start;
+    use crate::run_environment::{KillSwitch, OutputChunk, RunEnvironment};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use std::sync::mpsc::channel;
+    use std::time::{Duration, Instant};
+    use uuid::Uuid;
+
+    /// A stand-in for the docker CLI: `exec` runs the command on this
+    /// machine, `run` prints a container id, `rm` is logged and `ps` and
+    /// `inspect` print `ps.out` and `inspect.out` from its directory.
+    fn fake_runtime() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("switchboard_container_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let calls = dir.join("calls.log");
+        let binary = dir.join("docker");
+        std::fs::write(
+            &binary,
+            format!(
+                "#!/bin/sh\necho \"$*\" >> {}\n\
+                 case \"$1\" in\n\
+                 exec) shift 3; exec \"$@\" ;;\n\
+                 run) echo fake123 ;;\n\
+                 rm) ;;\n\
+                 ps) cat {dir}/ps.out 2>/dev/null ;;\n\
+                 inspect) cat {dir}/inspect.out ;;\n\
+                 *) exit 1 ;;\n\
+                 esac\n",
+                calls.display(),
+                dir = dir.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+        (binary, calls)
+    }
+
+    fn exec_target(container: &str) -> ContainerTarget {
+        ContainerTarget {
+            runtime: ContainerRuntime::Docker,
+            mode: ContainerMode::Exec {
+                container: container.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_exec_mode_runs_and_transfers_files() {
+        let (binary, calls) = fake_runtime();
+        let env = start(&binary, &exec_target("web"), None).unwrap();
+
+        let out = std::cell::RefCell::new(String::new());
+        let code = env
+            .run(
+                "echo hello; exit 4",
+                &|chunk| {
+                    if let OutputChunk::Stdout(s) = chunk {
+                        out.borrow_mut().push_str(&s);
+                    }
+                },
+                &KillSwitch::never(),
+            )
+            .unwrap();
+        assert_eq!(code, 4);
+        assert_eq!(out.into_inner(), "hello\n");
+
+        let dir = binary.parent().unwrap().join("data");
+        let file = dir.join("a.txt");
+        env.create_dir(&dir.to_string_lossy()).unwrap();
+        env.write_file(&file.to_string_lossy(), b"contents")
+            .unwrap();
+        assert_eq!(env.read_file(&file.to_string_lossy()).unwrap(), b"contents");
+        assert!(env.is_dir(&dir.to_string_lossy()).unwrap());
+        assert!(!env.is_dir(&file.to_string_lossy()).unwrap());
+        assert!(env.is_dir(&dir.join("missing").to_string_lossy()).is_err());
+
+        let entries = env.list_dir(&dir.to_string_lossy()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "a.txt");
+        assert!(!entries[0].is_dir);
+
+        let log = std::fs::read_to_string(calls).unwrap();
+        assert!(log.lines().all(|l| l.starts_with("exec -i web ")));
+    }
+
+    #[test]
+    fn test_image_mode_starts_and_removes_container() {
+        let (binary, calls) = fake_runtime();
+        let target = ContainerTarget {
+            runtime: ContainerRuntime::Podman,
+            mode: ContainerMode::Image {
+                image: "alpine:3".to_string(),
+            },
+        };
+        let env = start(&binary, &target, Some("/srv/app")).unwrap();
+        assert_eq!(env.capture("echo ok").unwrap(), (0, "ok\n".to_string()));
+        drop(env);
+
+        let log = std::fs::read_to_string(calls).unwrap();
+        let lines: Vec<&str> = log.lines().skip_while(|l| !l.starts_with("run ")).collect();
+        assert!(lines[0].starts_with("run -d --rm --label switchboard.host="));
+        assert!(lines[0].contains(&format!(
+            "--label switchboard.pid={} -v /srv/app:/srv/app -w /srv/app",
+            std::process::id()
+        )));
+        assert!(lines[0].contains("alpine:3"));
+        assert!(lines[1].starts_with("exec -i fake123 "));
+        assert_eq!(*lines.last().unwrap(), "rm -f fake123");
+    }
+
+    #[test]
+    fn test_image_mode_removes_leftover_containers() {
+        let (binary, calls) = fake_runtime();
+        let dir = binary.parent().unwrap();
+        // One from a process that's gone, one from this one
+        std::fs::write(dir.join("ps.out"), "gone1\nmine1\n").unwrap();
+        std::fs::write(
+            dir.join("inspect.out"),
+            format!("{}\n{}\n", i32::MAX, std::process::id()),
+        )
+        .unwrap();
+        let target = ContainerTarget {
+            runtime: ContainerRuntime::Docker,
+            mode: ContainerMode::Image {
+                image: "alpine:3".to_string(),
+            },
+        };
+        drop(start(&binary, &target, None).unwrap());
+        drop(start(&binary, &target, None).unwrap());
+
+        let log = std::fs::read_to_string(calls).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(lines[0].starts_with("ps -aq --filter label=switchboard.host="));
+        assert!(lines[1].starts_with("inspect --format") && lines[1].ends_with("gone1 mine1"));
+        assert_eq!(lines[2], "rm -f gone1");
+        // Only looked for once
+        assert_eq!(lines.iter().filter(|l| l.starts_with("ps ")).count(), 1);
+    }
+
+    #[test]
+    fn test_exec_mode_missing_container_name() {
+        let (binary, _) = fake_runtime();
+        assert!(start(&binary, &exec_target(" "), None).is_err());
+    }
+
+    #[test]
+    fn test_kill_stops_process_in_container() {
+        let (binary, calls) = fake_runtime();
+        let env = start(&binary, &exec_target("web"), None).unwrap();
+        let run_dir = binary.parent().unwrap().join("run");
+        std::fs::create_dir(&run_dir).unwrap();
+        env.use_run_dir(&run_dir.to_string_lossy());
+        let (kill_tx, kill_rx) = channel();
+        let kill = KillSwitch::new(kill_rx).unwrap();
+
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            let _ = kill_tx.send(());
+        });
+        let started = Instant::now();
+        let code = env.run("sleep 30", &|_| {}, &kill).unwrap();
+        assert_eq!(code, -1);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // The shell's PID went to the run directory, and is gone again
+        let pid_file = run_dir.join("cli.pid");
+        let log = std::fs::read_to_string(calls).unwrap();
+        assert!(
+            log.lines()
+                .any(|l| l.starts_with("exec -i web sh -c echo $$ >")
+                    && l.contains(&*pid_file.to_string_lossy()))
+        );
+        assert!(
+            log.lines()
+                .any(|l| l.starts_with("exec -i web sh -c pid=$(cat"))
+        );
+        assert!(!pid_file.exists());
+    }
+}
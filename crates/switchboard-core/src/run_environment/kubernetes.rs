@@ -0,0 +1,88 @@
+use super::RunEnvironmentError;
+use super::cli::CliRunEnvironment;
+use crate::models::KubernetesTarget;
+use std::path::PathBuf;
+
+/// kubectl flags selecting the target's context and namespace.
+fn scope_args(target: &KubernetesTarget) -> Vec<String> {
+    let mut args = Vec::new();
+    if !target.context.trim().is_empty() {
+        args.push("--context".to_string());
+        args.push(target.context.trim().to_string());
+    }
+    if !target.namespace.trim().is_empty() {
+        args.push("--namespace".to_string());
+        args.push(target.namespace.trim().to_string());
+    }
+    args
+}
+
+/// The named pod, or the first running pod matching the label selector.
+fn resolve_pod(binary: &PathBuf, target: &KubernetesTarget) -> Result<String, RunEnvironmentError> {
+    if !target.pod.trim().is_empty() {
+        return Ok(target.pod.trim().to_string());
+    }
+    if target.selector.trim().is_empty() {
+        return Err(RunEnvironmentError::ConnectionFailed(
+            "no pod or label selector given".to_string(),
+        ));
+    }
+
+    let output = std::process::Command::new(binary)
+        .args(scope_args(target))
+        .args(["get", "pods", "--selector", target.selector.trim()])
+        .args([
+            "--field-selector=status.phase=Running",
+            "-o",
+            "jsonpath={.items[*].metadata.name}",
+        ])
+        .output()
+        .map_err(|e| {
+            RunEnvironmentError::ConnectionFailed(format!(
+                "could not run {}: {}",
+                binary.display(),
+                e
+            ))
+        })?;
+    if !output.status.success() {
+        return Err(RunEnvironmentError::ConnectionFailed(format!(
+            "could not list pods: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let mut pods: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    pods.sort();
+    pods.into_iter().next().ok_or_else(|| {
+        RunEnvironmentError::ConnectionFailed(format!(
+            "no running pod matches {}",
+            target.selector.trim()
+        ))
+    })
+}
+
+/// Open an environment that runs commands in a pod via `kubectl exec`.
+pub fn connect(
+    binary: impl Into<PathBuf>,
+    target: &KubernetesTarget,
+) -> Result<CliRunEnvironment, RunEnvironmentError> {
+    let binary = binary.into();
+    let pod = resolve_pod(&binary, target)?;
+
+    let mut prefix = scope_args(target);
+    prefix.extend(["exec".to_string(), "-i".to_string(), pod.clone()]);
+    if !target.container.trim().is_empty() {
+        prefix.push("--container".to_string());
+        prefix.push(target.container.trim().to_string());
+    }
+    prefix.push("--".to_string());
+
+    let namespace = match target.namespace.trim() {
+        "" => String::new(),
+        ns => format!("{}/", ns),
+    };
+    CliRunEnvironment::new(binary, prefix, format!("pod {}{}", namespace, pod)).check()
+}
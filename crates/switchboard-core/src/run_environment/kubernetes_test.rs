@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod tests {
+    use crate::models::KubernetesTarget;
+    use crate::run_environment::kubernetes::connect;
+    use crate::run_environment::{KillSwitch, OutputChunk, RunEnvironment};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use std::sync::mpsc::channel;
+    use std::time::{Duration, Instant};
+    use uuid::Uuid;
+
+    /// A stand-in for kubectl: `get pods` lists two pods and `exec` runs
+    /// whatever follows `--` on this machine. Every call is logged.
+    fn fake_kubectl() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("switchboard_kubectl_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let calls = dir.join("calls.log");
+        let binary = dir.join("kubectl");
+        std::fs::write(
+            &binary,
+            format!(
+                "#!/bin/sh\necho \"$*\" >> {}\n\
+                 for arg in \"$@\"; do\n\
+                 case \"$arg\" in get) echo 'web-b web-a'; exit 0 ;; esac\n\
+                 done\n\
+                 while [ \"$1\" != -- ]; do [ $# -eq 0 ] && exit 1; shift; done\n\
+                 shift\n\
+                 exec \"$@\"\n",
+                calls.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+        (binary, calls)
+    }
+
+    fn target() -> KubernetesTarget {
+        KubernetesTarget {
+            context: "staging".to_string(),
+            namespace: "shop".to_string(),
+            selector: "app=web".to_string(),
+            pod: String::new(),
+            container: "app".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_selector_picks_pod_and_runs_script() {
+        let (binary, calls) = fake_kubectl();
+        let env = connect(&binary, &target()).unwrap();
+
+        let stdout = std::cell::RefCell::new(String::new());
+        let stderr = std::cell::RefCell::new(String::new());
+        let code = env
+            .run(
+                "echo out; echo err >&2; exit 7",
+                &|chunk| match chunk {
+                    OutputChunk::Stdout(s) => stdout.borrow_mut().push_str(&s),
+                    OutputChunk::Stderr(s) => stderr.borrow_mut().push_str(&s),
+                },
+                &KillSwitch::never(),
+            )
+            .unwrap();
+        assert_eq!(code, 7);
+        assert_eq!(stdout.into_inner(), "out\n");
+        assert_eq!(stderr.into_inner(), "err\n");
+
+        let log = std::fs::read_to_string(calls).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(
+            lines[0].starts_with("--context staging --namespace shop get pods --selector app=web")
+        );
+        assert!(
+            lines[1].starts_with(
+                "--context staging --namespace shop exec -i web-a --container app -- "
+            )
+        );
+    }
+
+    #[test]
+    fn test_named_pod_skips_lookup() {
+        let (binary, calls) = fake_kubectl();
+        let target = KubernetesTarget {
+            pod: "db-0".to_string(),
+            ..Default::default()
+        };
+        let env = connect(&binary, &target).unwrap();
+        assert_eq!(env.capture("echo ok").unwrap(), (0, "ok\n".to_string()));
+
+        let log = std::fs::read_to_string(calls).unwrap();
+        assert!(log.lines().all(|l| l.starts_with("exec -i db-0 -- ")));
+    }
+
+    #[test]
+    fn test_missing_pod_and_selector() {
+        let (binary, _) = fake_kubectl();
+        assert!(connect(&binary, &KubernetesTarget::default()).is_err());
+    }
+
+    #[test]
+    fn test_kill_stops_process_in_pod() {
+        let (binary, _) = fake_kubectl();
+        let env = connect(&binary, &target()).unwrap();
+        let (kill_tx, kill_rx) = channel();
+        let kill = KillSwitch::new(kill_rx).unwrap();
+
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            let _ = kill_tx.send(());
+        });
+        let started = Instant::now();
+        let code = env.run("sleep 30", &|_| {}, &kill).unwrap();
+        assert_eq!(code, -1);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
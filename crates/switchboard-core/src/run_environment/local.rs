@@ -1,7 +1,183 @@
-use super::{BackgroundHandle, OutputChunk, RunEnvironment, RunEnvironmentError};
-use std::io::Read;
+use super::poll::{KillSwitch, poll, pollfd, set_nonblocking};
+use super::terminal::{PromptResponder, normalize_newlines};
+use super::{DirEntry, OutputChunk, RunEnvironment, RunEnvironmentError};
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
 use std::os::unix::fs::PermissionsExt;
-use std::process::Stdio;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Stdio};
+use std::time::Duration;
+
+/// Upper bound on how long a finished script can go unnoticed while a
+/// process it spawned keeps its output pipes open.
+const CHILD_EXIT_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Forward everything currently readable from a non-blocking pipe. Returns
+/// whether the pipe is still open.
+fn drain(
+    pipe: &mut impl Read,
+    buffer: &mut [u8],
+    wrap: fn(String) -> OutputChunk,
+    on_output: &dyn Fn(OutputChunk),
+) -> std::io::Result<bool> {
+    loop {
+        match pipe.read(buffer) {
+            Ok(0) => return Ok(false),
+            Ok(n) => on_output(wrap(String::from_utf8_lossy(&buffer[..n]).to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Run a local process to completion, streaming its stdout and stderr.
+/// `input`, if any, is fed to its stdin from a helper thread. On a kill
+/// request `on_kill` runs first (to stop anything the process merely
+/// proxies for), then the process itself is killed.
+pub(super) fn run_process(
+    mut process: std::process::Command,
+    input: Option<Vec<u8>>,
+    on_output: &dyn Fn(OutputChunk),
+    kill: &KillSwitch,
+    on_kill: &dyn Fn(),
+) -> Result<i32, RunEnvironmentError> {
+    let mut child = process
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let Some(input) = input {
+        let mut stdin = child.stdin.take().expect("Failed to open stdin");
+        // The process may exit without reading everything; that's its call.
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+    }
+
+    let mut stdout = child.stdout.take().expect("Failed to open stdout");
+    let mut stderr = child.stderr.take().expect("Failed to open stderr");
+    set_nonblocking(stdout.as_raw_fd())?;
+    set_nonblocking(stderr.as_raw_fd())?;
+
+    let mut stdout_open = true;
+    let mut stderr_open = true;
+    let mut buffer = [0u8; 4096];
+
+    loop {
+        if kill.is_fired() {
+            on_output(OutputChunk::Stderr(
+                "\n[Killing execution...]\n".to_string(),
+            ));
+            on_kill();
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(-1);
+        }
+
+        if stdout_open {
+            stdout_open = drain(&mut stdout, &mut buffer, OutputChunk::Stdout, on_output)?;
+        }
+        if stderr_open {
+            stderr_open = drain(&mut stderr, &mut buffer, OutputChunk::Stderr, on_output)?;
+        }
+
+        if !stdout_open && !stderr_open {
+            let status = child.wait()?;
+            return Ok(status.code().unwrap_or(-1));
+        }
+
+        // The script may have exited while something it spawned still
+        // holds the pipes open; don't wait for that to finish.
+        if let Some(status) = child.try_wait()? {
+            // Drain any remaining output
+            if stdout_open {
+                drain(&mut stdout, &mut buffer, OutputChunk::Stdout, on_output)?;
+            }
+            if stderr_open {
+                drain(&mut stderr, &mut buffer, OutputChunk::Stderr, on_output)?;
+            }
+            return Ok(status.code().unwrap_or(-1));
+        }
+
+        let mut fds = Vec::with_capacity(3);
+        if stdout_open {
+            fds.push(pollfd(stdout.as_raw_fd(), libc::POLLIN));
+        }
+        if stderr_open {
+            fds.push(pollfd(stderr.as_raw_fd(), libc::POLLIN));
+        }
+        if let Some(fd) = kill.wake_fd() {
+            fds.push(pollfd(fd, libc::POLLIN));
+        }
+        poll(&mut fds, Some(CHILD_EXIT_CHECK_INTERVAL))?;
+    }
+}
+
+/// Open a pseudo-terminal; returns its (controller, child side) ends.
+fn open_pty() -> std::io::Result<(OwnedFd, OwnedFd)> {
+    let (mut controller, mut child_side) = (0, 0);
+    // SAFETY: openpty only writes the two descriptors it returns.
+    let rc = unsafe {
+        libc::openpty(
+            &mut controller,
+            &mut child_side,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: both descriptors are freshly opened and owned by nobody else.
+    Ok(unsafe {
+        (
+            OwnedFd::from_raw_fd(controller),
+            OwnedFd::from_raw_fd(child_side),
+        )
+    })
+}
+
+/// Terminate a process started as a session leader, with its children.
+fn kill_session(child: &mut Child) {
+    // SAFETY: signalling a process group has no memory-safety concerns.
+    unsafe { libc::kill(-(child.id() as i32), libc::SIGTERM) };
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Forward everything currently readable from a terminal, answering
+/// password prompts. Returns whether the terminal is still open.
+fn drain_terminal(
+    controller: &mut std::fs::File,
+    buffer: &mut [u8],
+    responder: &mut PromptResponder,
+    on_output: &dyn Fn(OutputChunk),
+) -> Result<bool, RunEnvironmentError> {
+    loop {
+        match controller.read(buffer) {
+            Ok(0) => return Ok(false),
+            Ok(n) => {
+                let text = String::from_utf8_lossy(&buffer[..n]).to_string();
+                on_output(OutputChunk::Stdout(normalize_newlines(&text)));
+                if let Some(reply) = responder.feed(&text)? {
+                    controller.write_all(&reply)?;
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            // Linux reports EIO once every child-side descriptor is closed.
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
 
 pub struct LocalRunEnvironment;
 
@@ -20,92 +196,123 @@ impl RunEnvironment for LocalRunEnvironment {
         Ok(())
     }
 
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, RunEnvironmentError> {
+        Ok(std::fs::read(path)?)
+    }
+
+    fn list_dir(&self, path: &str) -> Result<Vec<DirEntry>, RunEnvironmentError> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            entries.push(DirEntry {
+                name: entry.file_name().to_string_lossy().to_string(),
+                is_dir: entry.metadata()?.is_dir(),
+            });
+        }
+        Ok(entries)
+    }
+
+    fn is_dir(&self, path: &str) -> Result<bool, RunEnvironmentError> {
+        Ok(std::fs::metadata(path)?.is_dir())
+    }
+
+    fn create_dir(&self, path: &str) -> Result<(), RunEnvironmentError> {
+        std::fs::create_dir_all(path)?;
+        Ok(())
+    }
+
     fn run(
         &self,
         command: &str,
         on_output: &dyn Fn(OutputChunk),
-        kill_rx: &std::sync::mpsc::Receiver<()>,
+        kill: &KillSwitch,
     ) -> Result<i32, RunEnvironmentError> {
-        let mut child = std::process::Command::new("/bin/bash")
-            .arg("-c")
-            .arg(command)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-
-        let mut stdout = child.stdout.take().expect("Failed to open stdout");
-        let mut stderr = child.stderr.take().expect("Failed to open stderr");
+        let mut process = std::process::Command::new("/bin/bash");
+        process.arg("-c").arg(command);
+        run_process(process, None, on_output, kill, &|| {})
+    }
 
-        let (out_tx, out_rx) = std::sync::mpsc::channel::<OutputChunk>();
-        let out_tx_stderr = out_tx.clone();
+    fn run_with_input(
+        &self,
+        command: &str,
+        input: &[u8],
+        on_output: &dyn Fn(OutputChunk),
+        kill: &KillSwitch,
+    ) -> Result<i32, RunEnvironmentError> {
+        let mut process = std::process::Command::new("/bin/bash");
+        process.arg("-c").arg(command);
+        run_process(process, Some(input.to_vec()), on_output, kill, &|| {})
+    }
 
-        std::thread::spawn(move || {
-            let mut buffer = [0u8; 1024];
-            loop {
-                match stdout.read(&mut buffer) {
-                    Ok(n) if n > 0 => {
-                        let s = String::from_utf8_lossy(&buffer[0..n]).to_string();
-                        let _ = out_tx.send(OutputChunk::Stdout(s));
-                    }
-                    _ => break,
+    fn run_in_pty(
+        &self,
+        command: &str,
+        is_prompt: &dyn Fn(&str) -> bool,
+        answer: &str,
+        on_output: &dyn Fn(OutputChunk),
+        kill: &KillSwitch,
+    ) -> Result<i32, RunEnvironmentError> {
+        let (controller, child_side) = open_pty()?;
+        let mut process = std::process::Command::new("/bin/bash");
+        process
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::from(child_side.try_clone()?))
+            .stdout(Stdio::from(child_side.try_clone()?))
+            .stderr(Stdio::from(child_side));
+        // The child becomes a session leader with the terminal as its
+        // controlling tty, which is where sudo and su ask for passwords.
+        // SAFETY: only async-signal-safe calls between fork and exec.
+        unsafe {
+            process.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
                 }
-            }
-        });
+                Ok(())
+            });
+        }
+        let mut child = process.spawn()?;
+        // Close our copies of the child side so reads see the end.
+        drop(process);
 
-        std::thread::spawn(move || {
-            let mut buffer = [0u8; 1024];
-            loop {
-                match stderr.read(&mut buffer) {
-                    Ok(n) if n > 0 => {
-                        let s = String::from_utf8_lossy(&buffer[0..n]).to_string();
-                        let _ = out_tx_stderr.send(OutputChunk::Stderr(s));
-                    }
-                    _ => break,
-                }
-            }
-        });
+        let mut controller = std::fs::File::from(controller);
+        set_nonblocking(controller.as_raw_fd())?;
+        let mut responder = PromptResponder::new(is_prompt, answer);
+        let mut buffer = [0u8; 4096];
 
         loop {
-            if kill_rx.try_recv().is_ok() {
-                on_output(OutputChunk::Stderr("\n[Killing execution...]\n".to_string()));
-                let _ = child.kill();
-                let _ = child.wait();
+            if kill.is_fired() {
+                on_output(OutputChunk::Stderr(
+                    "\n[Killing execution...]\n".to_string(),
+                ));
+                kill_session(&mut child);
                 return Ok(-1);
             }
 
-            while let Ok(chunk) = out_rx.try_recv() {
-                on_output(chunk);
+            let open = match drain_terminal(&mut controller, &mut buffer, &mut responder, on_output)
+            {
+                Ok(open) => open,
+                Err(e) => {
+                    kill_session(&mut child);
+                    return Err(e);
+                }
+            };
+            if !open {
+                return Ok(child.wait()?.code().unwrap_or(-1));
+            }
+            // Something the command started may still hold the terminal.
+            if let Some(status) = child.try_wait()? {
+                let _ = drain_terminal(&mut controller, &mut buffer, &mut responder, on_output);
+                return Ok(status.code().unwrap_or(-1));
             }
 
-            match child.try_wait() {
-                Ok(Some(status)) => {
-                    // Drain any remaining output
-                    while let Ok(chunk) = out_rx.try_recv() {
-                        on_output(chunk);
-                    }
-                    return Ok(status.code().unwrap_or(-1));
-                }
-                Ok(None) => {
-                    std::thread::sleep(std::time::Duration::from_millis(10));
-                }
-                Err(_) => return Ok(-1),
+            let mut fds = vec![pollfd(controller.as_raw_fd(), libc::POLLIN)];
+            if let Some(fd) = kill.wake_fd() {
+                fds.push(pollfd(fd, libc::POLLIN));
             }
+            poll(&mut fds, Some(CHILD_EXIT_CHECK_INTERVAL))?;
         }
     }
 
-    fn run_background(&self, command: &str) -> Result<BackgroundHandle, RunEnvironmentError> {
-        let child = std::process::Command::new("nohup")
-            .arg("/bin/bash")
-            .arg("-c")
-            .arg(command)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .stdin(Stdio::null())
-            .spawn()?;
-        Ok(BackgroundHandle {
-            pid_or_hint: child.id().to_string(),
-        })
-    }
-
     fn emit_preamble(&self, _on_output: &dyn Fn(OutputChunk), _log_file: &str) {}
 }
@@ -1,9 +1,26 @@
+pub mod cli;
+pub mod container;
+pub mod kubernetes;
 pub mod local;
+pub mod poll;
+pub mod pool;
 pub mod ssh;
+pub mod terminal;
 
 pub use local::LocalRunEnvironment;
+pub use poll::KillSwitch;
 pub use ssh::SshRunEnvironment;
 
+#[cfg(test)]
+mod container_test;
+#[cfg(test)]
+mod kubernetes_test;
+#[cfg(test)]
+mod pool_test;
+#[cfg(test)]
+mod terminal_test;
+
+use std::cell::RefCell;
 use thiserror::Error;
 
 pub enum OutputChunk {
@@ -11,8 +28,14 @@ pub enum OutputChunk {
     Stderr(String),
 }
 
+/// An entry returned by `RunEnvironment::list_dir`.
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
 pub struct BackgroundHandle {
-    pub pid_or_hint: String,
+    pub pid: u32,
 }
 
 #[derive(Error, Debug)]
@@ -27,19 +50,105 @@ pub enum RunEnvironmentError {
     AuthFailed(String),
     #[error("Upload failed: {0}")]
     UploadFailed(String),
+    #[error("Download failed: {0}")]
+    DownloadFailed(String),
+    #[error("Background launch failed: {0}")]
+    BackgroundFailed(String),
+    #[error("Remote workspace unavailable: {0}")]
+    Workspace(String),
+    #[error("{0}")]
+    Shell(#[from] crate::shell::ShellError),
+    #[error("The password was rejected")]
+    PasswordRejected,
+    #[error("Not supported: {0}")]
+    Unsupported(String),
 }
 
 pub trait RunEnvironment: Send {
     fn write_file(&self, path: &str, contents: &[u8]) -> Result<(), RunEnvironmentError>;
 
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, RunEnvironmentError>;
+
+    /// Lists the entries of a directory, excluding `.` and `..`.
+    fn list_dir(&self, path: &str) -> Result<Vec<DirEntry>, RunEnvironmentError>;
+
+    /// Whether `path` is a directory; errors if it doesn't exist.
+    fn is_dir(&self, path: &str) -> Result<bool, RunEnvironmentError>;
+
+    /// Creates a directory and any missing parents.
+    fn create_dir(&self, path: &str) -> Result<(), RunEnvironmentError>;
+
     fn run(
         &self,
         command: &str,
         on_output: &dyn Fn(OutputChunk),
-        kill_rx: &std::sync::mpsc::Receiver<()>,
+        kill: &KillSwitch,
     ) -> Result<i32, RunEnvironmentError>;
 
-    fn run_background(&self, command: &str) -> Result<BackgroundHandle, RunEnvironmentError>;
+    /// Like `run`, but feeds `input` to the command's stdin and then closes
+    /// it.
+    fn run_with_input(
+        &self,
+        command: &str,
+        input: &[u8],
+        on_output: &dyn Fn(OutputChunk),
+        kill: &KillSwitch,
+    ) -> Result<i32, RunEnvironmentError>;
+
+    /// Like `run`, but in a pseudo-terminal, typing `answer` the first time
+    /// the output shows a prompt matching `is_prompt`. A terminal has one
+    /// output stream, so everything arrives as stdout.
+    fn run_in_pty(
+        &self,
+        _command: &str,
+        _is_prompt: &dyn Fn(&str) -> bool,
+        _answer: &str,
+        _on_output: &dyn Fn(OutputChunk),
+        _kill: &KillSwitch,
+    ) -> Result<i32, RunEnvironmentError> {
+        Err(RunEnvironmentError::Unsupported(
+            "this environment can't run commands in a terminal".to_string(),
+        ))
+    }
+
+    /// Hands over the run's private directory (mode 700), for files the
+    /// environment keeps for itself while commands run.
+    fn use_run_dir(&self, _path: &str) {}
+
+    /// Whether the file operations above work. An SSH host without the
+    /// SFTP subsystem can still run commands.
+    fn has_file_transfer(&self) -> bool {
+        true
+    }
+
+    /// Runs `command` to completion and returns its exit code and stdout.
+    fn capture(&self, command: &str) -> Result<(i32, String), RunEnvironmentError> {
+        let stdout = RefCell::new(String::new());
+        let code = self.run(
+            command,
+            &|chunk| {
+                if let OutputChunk::Stdout(s) = chunk {
+                    stdout.borrow_mut().push_str(&s);
+                }
+            },
+            &KillSwitch::never(),
+        )?;
+        Ok((code, stdout.into_inner()))
+    }
+
+    /// Launches `command`, which must background its payload and print the
+    /// payload's PID (e.g. `nohup ... & echo $!`), and returns that PID.
+    fn run_background(&self, command: &str) -> Result<BackgroundHandle, RunEnvironmentError> {
+        let (code, stdout) = self.capture(command)?;
+        let pid = stdout.trim().lines().last().unwrap_or_default().trim();
+        match pid.parse() {
+            Ok(pid) if code == 0 => Ok(BackgroundHandle { pid }),
+            _ => Err(RunEnvironmentError::BackgroundFailed(format!(
+                "expected a PID, got exit code {} and output {:?}",
+                code, stdout
+            ))),
+        }
+    }
 
     fn emit_preamble(&self, on_output: &dyn Fn(OutputChunk), log_file: &str);
 }
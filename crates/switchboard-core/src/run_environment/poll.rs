@@ -0,0 +1,103 @@
+use std::io::Write;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+/// A kill request that I/O loops can wait on alongside their other fds.
+///
+/// A helper thread blocks on the execution's kill channel and, when a kill
+/// arrives, raises a flag and writes to a socket pair so any `poll` that
+/// includes `wake_fd` returns at once. If every sender is dropped the
+/// switch simply never fires.
+pub struct KillSwitch {
+    fired: Arc<AtomicBool>,
+    wake: Option<UnixStream>,
+}
+
+impl KillSwitch {
+    pub fn new(kill_rx: Receiver<()>) -> std::io::Result<Self> {
+        let (wake, mut notify) = UnixStream::pair()?;
+        let fired = Arc::new(AtomicBool::new(false));
+        let flag = fired.clone();
+
+        std::thread::spawn(move || {
+            if kill_rx.recv().is_ok() {
+                flag.store(true, Ordering::SeqCst);
+                let _ = notify.write_all(&[1]);
+            }
+        });
+
+        Ok(KillSwitch {
+            fired,
+            wake: Some(wake),
+        })
+    }
+
+    /// A switch that is never fired, for internal commands nobody can kill.
+    pub fn never() -> Self {
+        KillSwitch {
+            fired: Arc::new(AtomicBool::new(false)),
+            wake: None,
+        }
+    }
+
+    pub fn is_fired(&self) -> bool {
+        self.fired.load(Ordering::SeqCst)
+    }
+
+    /// An fd that becomes readable once the switch fires.
+    pub fn wake_fd(&self) -> Option<RawFd> {
+        self.wake.as_ref().map(|w| w.as_raw_fd())
+    }
+
+    /// Sleep until the switch fires or `timeout` passes; returns whether it
+    /// fired.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        match self.wake_fd() {
+            Some(fd) => {
+                let _ = poll(&mut [pollfd(fd, libc::POLLIN)], Some(timeout));
+            }
+            None => std::thread::sleep(timeout),
+        }
+        self.is_fired()
+    }
+}
+
+pub fn pollfd(fd: RawFd, events: libc::c_short) -> libc::pollfd {
+    libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    }
+}
+
+/// Block until one of `fds` is ready or `timeout` passes (`None` waits
+/// forever). Returns the number of ready fds; `revents` is filled in.
+pub fn poll(fds: &mut [libc::pollfd], timeout: Option<Duration>) -> std::io::Result<usize> {
+    let timeout_ms = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+    loop {
+        // SAFETY: `fds` is a live, correctly sized `pollfd` slice; poll only writes `revents`.
+        let n = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms) };
+        if n >= 0 {
+            return Ok(n as usize);
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Put `fd` into non-blocking mode so reads return `WouldBlock` when empty.
+pub fn set_nonblocking(fd: RawFd) -> std::io::Result<()> {
+    // SAFETY: fcntl with F_GETFL on a caller-owned fd touches no memory.
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    // SAFETY: fcntl with F_SETFL on a caller-owned fd touches no memory.
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long an idle connection may sit in the pool before it is closed.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// How often idle connections are pinged to keep NAT/firewall state alive.
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+struct Idle<T> {
+    conn: T,
+    since: Instant,
+}
+
+/// A pool of idle, already-authenticated connections keyed by destination.
+///
+/// Connections are leased exclusively: `checkout` removes one from the pool
+/// and the caller hands it back with `checkin` when done, so concurrent runs
+/// against the same host each get their own connection.
+pub struct ConnectionPool<T> {
+    idle: Mutex<HashMap<String, Vec<Idle<T>>>>,
+    idle_timeout: Duration,
+}
+
+impl<T> ConnectionPool<T> {
+    pub fn new(idle_timeout: Duration) -> Self {
+        ConnectionPool {
+            idle: Mutex::new(HashMap::new()),
+            idle_timeout,
+        }
+    }
+
+    /// Take the most recently used live connection for `key`, discarding
+    /// expired ones and any that `is_alive` rejects.
+    pub fn checkout(&self, key: &str, is_alive: impl Fn(&T) -> bool) -> Option<T> {
+        let mut candidates = {
+            let mut idle = self.idle.lock().unwrap();
+            let entries = idle.get_mut(key)?;
+            entries.retain(|e| e.since.elapsed() < self.idle_timeout);
+            std::mem::take(entries)
+        };
+
+        // Liveness checks may hit the network, so run them outside the lock.
+        let mut found = None;
+        while let Some(entry) = candidates.pop() {
+            if is_alive(&entry.conn) {
+                found = Some(entry.conn);
+                break;
+            }
+        }
+
+        if !candidates.is_empty() {
+            let mut idle = self.idle.lock().unwrap();
+            let entries = idle.entry(key.to_string()).or_default();
+            candidates.append(entries);
+            *entries = candidates;
+        }
+        found
+    }
+
+    /// Return a connection to the pool for reuse.
+    pub fn checkin(&self, key: &str, conn: T) {
+        let mut idle = self.idle.lock().unwrap();
+        idle.entry(key.to_string()).or_default().push(Idle {
+            conn,
+            since: Instant::now(),
+        });
+    }
+
+    /// Drop expired connections and any that fail `is_alive`. Used by the
+    /// keepalive loop, so `is_alive` is expected to ping the connection.
+    pub fn sweep(&self, is_alive: impl Fn(&T) -> bool) {
+        let all = std::mem::take(&mut *self.idle.lock().unwrap());
+
+        let mut kept: HashMap<String, Vec<Idle<T>>> = HashMap::new();
+        for (key, entries) in all {
+            for entry in entries {
+                if entry.since.elapsed() < self.idle_timeout && is_alive(&entry.conn) {
+                    kept.entry(key.clone()).or_default().push(entry);
+                }
+            }
+        }
+
+        let mut idle = self.idle.lock().unwrap();
+        for (key, mut entries) in kept {
+            // Keep anything checked in while we were sweeping.
+            let slot = idle.entry(key).or_default();
+            entries.append(slot);
+            *slot = entries;
+        }
+    }
+
+    #[cfg(test)]
+    pub fn idle_count(&self, key: &str) -> usize {
+        let idle = self.idle.lock().unwrap();
+        idle.get(key).map_or(0, |e| e.len())
+    }
+}
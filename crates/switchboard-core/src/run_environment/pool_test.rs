@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod tests {
+    use crate::models::{AuthMethod, Command};
+    use crate::run_environment::RunEnvironmentError;
+    use crate::run_environment::pool::ConnectionPool;
+    use crate::run_environment::ssh::{pool_key, retry_if_stale};
+    use ssh2::{Error, ErrorCode};
+    use std::cell::{Cell, RefCell};
+    use std::time::Duration;
+
+    /// A stand-in for a pooled SSH session.
+    struct FakeSession {
+        alive: bool,
+        has_sftp: bool,
+    }
+
+    fn open_sftp(session: &FakeSession) -> Result<(), Error> {
+        if !session.alive {
+            Err(Error::new(ErrorCode::Session(-43), "Failure while reading"))
+        } else if !session.has_sftp {
+            // What libssh2 reports when the subsystem request is refused
+            Err(Error::new(
+                ErrorCode::Session(-21),
+                "Unable to request SFTP subsystem",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_checkout_reuses_checked_in_connection() {
+        let pool = ConnectionPool::new(Duration::from_secs(60));
+        assert_eq!(pool.checkout("a", |_| true), None::<u32>);
+
+        pool.checkin("a", 1);
+        pool.checkin("a", 2);
+        assert_eq!(pool.checkout("b", |_| true), None);
+        assert_eq!(pool.checkout("a", |_| true), Some(2));
+        assert_eq!(pool.checkout("a", |_| true), Some(1));
+        assert_eq!(pool.checkout("a", |_| true), None);
+    }
+
+    #[test]
+    fn test_checkout_skips_dead_connections() {
+        let pool = ConnectionPool::new(Duration::from_secs(60));
+        pool.checkin("a", 1);
+        pool.checkin("a", 2);
+        pool.checkin("a", 3);
+
+        // 3 is dead and gets discarded; 1 stays pooled for the next caller.
+        assert_eq!(pool.checkout("a", |c| *c != 3), Some(2));
+        assert_eq!(pool.idle_count("a"), 1);
+        assert_eq!(pool.checkout("a", |_| true), Some(1));
+    }
+
+    #[test]
+    fn test_idle_connections_expire() {
+        let pool = ConnectionPool::new(Duration::from_millis(20));
+        pool.checkin("a", 1);
+        std::thread::sleep(Duration::from_millis(40));
+        pool.checkin("a", 2);
+
+        pool.sweep(|_| true);
+        assert_eq!(pool.idle_count("a"), 1);
+        assert_eq!(pool.checkout("a", |_| true), Some(2));
+    }
+
+    #[test]
+    fn test_sweep_drops_dead_connections() {
+        let pool = ConnectionPool::new(Duration::from_secs(60));
+        pool.checkin("a", 1);
+        pool.checkin("b", 2);
+
+        pool.sweep(|c| *c != 1);
+        assert_eq!(pool.idle_count("a"), 0);
+        assert_eq!(pool.idle_count("b"), 1);
+    }
+
+    #[test]
+    fn test_sessions_are_pooled_per_login() {
+        let mut host = Command::new("x", "true").adhoc_host();
+        let agent = pool_key(&host);
+        host.auth = AuthMethod::KeyFile("~/.ssh/deploy".into());
+        let key = pool_key(&host);
+        host.auth = AuthMethod::Password("hunter2".into());
+        let password = pool_key(&host);
+        host.auth = AuthMethod::Password("hunter3".into());
+
+        assert_ne!(agent, key);
+        assert_ne!(key, password);
+        assert_ne!(password, pool_key(&host));
+        assert!(!password.contains("hunter2"));
+    }
+
+    #[test]
+    fn test_reused_session_without_sftp_is_kept() {
+        let conn = RefCell::new(FakeSession {
+            alive: true,
+            has_sftp: false,
+        });
+        let reused = Cell::new(true);
+        let reopened = Cell::new(0);
+
+        let result = retry_if_stale(
+            &conn,
+            &reused,
+            open_sftp,
+            |s| s.alive,
+            || {
+                reopened.set(reopened.get() + 1);
+                Ok(FakeSession {
+                    alive: true,
+                    has_sftp: false,
+                })
+            },
+            RunEnvironmentError::Ssh,
+        );
+
+        assert!(matches!(result, Err(RunEnvironmentError::Ssh(_))));
+        assert_eq!(reopened.get(), 0);
+        assert!(reused.get());
+    }
+
+    #[test]
+    fn test_dead_reused_session_is_reopened() {
+        let conn = RefCell::new(FakeSession {
+            alive: false,
+            has_sftp: true,
+        });
+        let reused = Cell::new(true);
+
+        let result = retry_if_stale(
+            &conn,
+            &reused,
+            open_sftp,
+            |s| s.alive,
+            || {
+                Ok(FakeSession {
+                    alive: true,
+                    has_sftp: true,
+                })
+            },
+            RunEnvironmentError::Ssh,
+        );
+
+        assert!(result.is_ok());
+        assert!(conn.borrow().alive);
+        assert!(!reused.get());
+    }
+
+    #[test]
+    fn test_refused_request_on_dead_reused_session_is_reopened() {
+        // Channel failures look the same whether the server refused the
+        // request or the socket died, so the keepalive decides.
+        let conn = RefCell::new(FakeSession {
+            alive: true,
+            has_sftp: false,
+        });
+        let reused = Cell::new(true);
+
+        let result = retry_if_stale(
+            &conn,
+            &reused,
+            open_sftp,
+            |_| false,
+            || {
+                Ok(FakeSession {
+                    alive: true,
+                    has_sftp: true,
+                })
+            },
+            RunEnvironmentError::Ssh,
+        );
+
+        assert!(result.is_ok());
+        assert!(!reused.get());
+    }
+
+    #[test]
+    fn test_fresh_session_errors_are_not_retried() {
+        let conn = RefCell::new(FakeSession {
+            alive: false,
+            has_sftp: true,
+        });
+        let reused = Cell::new(false);
+
+        let result = retry_if_stale(
+            &conn,
+            &reused,
+            open_sftp,
+            |s| s.alive,
+            || -> Result<FakeSession, RunEnvironmentError> { panic!("reconnected") },
+            RunEnvironmentError::Ssh,
+        );
+
+        assert!(result.is_err());
+    }
+}
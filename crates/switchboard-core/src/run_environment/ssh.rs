@@ -1,166 +1,768 @@
-use super::{BackgroundHandle, OutputChunk, RunEnvironment, RunEnvironmentError};
-use crate::models::Host;
-use ssh2::Session;
+use super::poll::{KillSwitch, poll, pollfd};
+use super::pool::{ConnectionPool, IDLE_TIMEOUT, KEEPALIVE_INTERVAL};
+use super::terminal::{PromptResponder, normalize_newlines};
+use super::{DirEntry, OutputChunk, RunEnvironment, RunEnvironmentError};
+use crate::diagnostics::{CheckStatus, HostDiagnostics, base64_unpadded};
+use crate::models::{AuthMethod, Host};
+use ssh2::{
+    BlockDirections, Channel, CheckResult, ErrorCode, HashType, HostKeyType, KnownHostFileKind,
+    MethodType, Session,
+};
+use std::cell::{Cell, RefCell};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io::{Read, Write};
-use std::net::TcpStream;
-use std::path::Path;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
-pub struct SshRunEnvironment {
+/// Safety net for waits on the socket: re-check the channel at least this
+/// often even if `poll` reports nothing.
+const MAX_SOCKET_WAIT: Duration = Duration::from_secs(1);
+
+/// An authenticated session together with its socket, which is needed to
+/// wait for readiness while the session is non-blocking.
+#[derive(Clone)]
+struct Connection {
     sess: Session,
+    fd: RawFd,
+}
+
+/// Authenticated sessions shared across executions and workflow steps.
+fn pool() -> &'static ConnectionPool<Connection> {
+    static POOL: OnceLock<ConnectionPool<Connection>> = OnceLock::new();
+    POOL.get_or_init(|| {
+        std::thread::spawn(|| {
+            loop {
+                std::thread::sleep(KEEPALIVE_INTERVAL);
+                pool().sweep(session_is_alive);
+            }
+        });
+        ConnectionPool::new(IDLE_TIMEOUT)
+    })
+}
+
+/// Sessions are only shared between hosts that log in the same way, so a
+/// host with another key file or password never gets someone else's.
+pub(super) fn pool_key(host: &Host) -> String {
+    let identity = match &host.auth {
+        AuthMethod::Agent => "agent".to_string(),
+        AuthMethod::KeyFile(path) => format!("key:{}", path),
+        // Keep the password itself out of the key
+        AuthMethod::Password(password) => {
+            let mut hasher = DefaultHasher::new();
+            password.hash(&mut hasher);
+            format!("password:{:x}", hasher.finish())
+        }
+    };
+    format!(
+        "{}@{}:{}/{}",
+        host.username, host.hostname, host.port, identity
+    )
+}
+
+fn session_is_alive(conn: &Connection) -> bool {
+    conn.sess.keepalive_send().is_ok()
+}
+
+/// libssh2 error codes meaning the connection underneath the session is
+/// gone (socket send/recv failures, disconnects and timeouts).
+const TRANSPORT_ERRORS: [i32; 6] = [-7, -9, -13, -30, -43, -45];
+
+/// Run `op` on `conn`, and if it fails on a `reused` connection that died
+/// while pooled, replace it with `reopen` and retry once. A connection is
+/// only treated as dead on a transport error or if `is_alive` fails
+/// afterwards; the server refusing a request (such as the SFTP subsystem)
+/// on a healthy connection is returned as is.
+pub(super) fn retry_if_stale<C, R>(
+    conn: &RefCell<C>,
+    reused: &Cell<bool>,
+    op: impl Fn(&C) -> Result<R, ssh2::Error>,
+    is_alive: impl Fn(&C) -> bool,
+    reopen: impl FnOnce() -> Result<C, RunEnvironmentError>,
+    map_err: fn(String) -> RunEnvironmentError,
+) -> Result<R, RunEnvironmentError> {
+    let e = match op(&conn.borrow()) {
+        Ok(r) => return Ok(r),
+        Err(e) => e,
+    };
+    let transport =
+        matches!(e.code(), ErrorCode::Session(code) if TRANSPORT_ERRORS.contains(&code));
+    if !reused.get() || (!transport && is_alive(&conn.borrow())) {
+        return Err(map_err(e.to_string()));
+    }
+    *conn.borrow_mut() = reopen()?;
+    reused.set(false);
+    op(&conn.borrow()).map_err(|e| map_err(e.to_string()))
+}
+
+/// Expand a leading `~/` to the user's home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Authenticate with the host's configured method first, then the SSH
+/// agent, then the default keys in `~/.ssh`. Returns a description of the
+/// method that worked, or why each attempt failed.
+fn authenticate(sess: &Session, host: &Host) -> Result<String, Vec<String>> {
+    let user = host.username.as_str();
+    let mut failures = Vec::new();
+    let mut attempt = |desc: String, result: Result<(), ssh2::Error>| match result {
+        Ok(()) if sess.authenticated() => Some(desc),
+        Ok(()) => {
+            failures.push(format!("{}: not accepted", desc));
+            None
+        }
+        Err(e) => {
+            failures.push(format!("{}: {}", desc, e));
+            None
+        }
+    };
+
+    let configured = match &host.auth {
+        AuthMethod::KeyFile(path) => attempt(
+            format!("key file {}", path),
+            sess.userauth_pubkey_file(user, None, &expand_home(path), None),
+        ),
+        AuthMethod::Password(password) => attempt(
+            "password".to_string(),
+            sess.userauth_password(user, password),
+        ),
+        AuthMethod::Agent => None,
+    };
+    if let Some(desc) = configured {
+        return Ok(desc);
+    }
+
+    if let Some(desc) = attempt("ssh agent".to_string(), sess.userauth_agent(user)) {
+        return Ok(desc);
+    }
+
+    if let Ok(home) = std::env::var("HOME") {
+        for key_name in ["id_ed25519", "id_ecdsa", "id_rsa", "id_dsa"] {
+            let key_path = Path::new(&home).join(".ssh").join(key_name);
+            if key_path.exists()
+                && let Some(desc) = attempt(
+                    format!("key {}", key_path.display()),
+                    sess.userauth_pubkey_file(user, None, &key_path, None),
+                )
+            {
+                return Ok(desc);
+            }
+        }
+    }
+    Err(failures)
+}
+
+/// How long each network step of a diagnosis may take.
+const DIAGNOSTIC_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn host_key_type_name(kind: HostKeyType) -> &'static str {
+    match kind {
+        HostKeyType::Rsa => "RSA",
+        HostKeyType::Dss => "DSA",
+        HostKeyType::Ecdsa256 => "ECDSA-256",
+        HostKeyType::Ecdsa384 => "ECDSA-384",
+        HostKeyType::Ecdsa521 => "ECDSA-521",
+        HostKeyType::Ed25519 => "ED25519",
+        HostKeyType::Unknown => "unknown",
+    }
+}
+
+/// Compare the server's key with `~/.ssh/known_hosts`.
+fn known_host_status(sess: &Session, host: &Host, key: &[u8]) -> (CheckStatus, &'static str) {
+    let Ok(mut known) = sess.known_hosts() else {
+        return (CheckStatus::Warning, "known_hosts unavailable");
+    };
+    let path = expand_home("~/.ssh/known_hosts");
+    if known.read_file(&path, KnownHostFileKind::OpenSSH).is_err() {
+        return (CheckStatus::Warning, "no ~/.ssh/known_hosts");
+    }
+    match known.check_port(&host.hostname, host.port, key) {
+        CheckResult::Match => (CheckStatus::Ok, "matches known_hosts"),
+        CheckResult::Mismatch => (CheckStatus::Failed, "DOES NOT MATCH known_hosts"),
+        CheckResult::NotFound => (CheckStatus::Warning, "not in known_hosts"),
+        CheckResult::Failure => (CheckStatus::Warning, "known_hosts check failed"),
+    }
+}
+
+/// Walk through connecting to `host` step by step, recording each step in
+/// `report`. Stops at the first step that fails; on success returns an
+/// environment on the new session so the caller can look around the host.
+pub(crate) fn diagnose(host: &Host, report: &mut HostDiagnostics) -> Option<SshRunEnvironment> {
+    let started = Instant::now();
+    let addrs: Vec<SocketAddr> = match (host.hostname.as_str(), host.port).to_socket_addrs() {
+        Ok(addrs) => addrs.collect(),
+        Err(e) => {
+            report.push(
+                "DNS",
+                CheckStatus::Failed,
+                format!("{}: {}", host.hostname, e),
+            );
+            return None;
+        }
+    };
+    let ips: Vec<String> = addrs.iter().map(|a| a.ip().to_string()).collect();
+    report.push(
+        "DNS",
+        CheckStatus::Ok,
+        format!(
+            "{} -> {} ({} ms)",
+            host.hostname,
+            ips.join(", "),
+            started.elapsed().as_millis()
+        ),
+    );
+
+    let mut tcp = None;
+    let mut errors = Vec::new();
+    for addr in &addrs {
+        let started = Instant::now();
+        match TcpStream::connect_timeout(addr, DIAGNOSTIC_TIMEOUT) {
+            Ok(stream) => {
+                report.push(
+                    "TCP",
+                    CheckStatus::Ok,
+                    format!(
+                        "connected to {} in {} ms",
+                        addr,
+                        started.elapsed().as_millis()
+                    ),
+                );
+                tcp = Some(stream);
+                break;
+            }
+            Err(e) => errors.push(format!("{}: {}", addr, e)),
+        }
+    }
+    let Some(tcp) = tcp else {
+        report.push("TCP", CheckStatus::Failed, errors.join("; "));
+        return None;
+    };
+    let fd = tcp.as_raw_fd();
+
+    let mut sess = match Session::new() {
+        Ok(sess) => sess,
+        Err(e) => {
+            report.push("SSH handshake", CheckStatus::Failed, e.to_string());
+            return None;
+        }
+    };
+    sess.set_tcp_stream(tcp);
+    sess.set_timeout(DIAGNOSTIC_TIMEOUT.as_millis() as u32);
+    if let Err(e) = sess.handshake() {
+        report.push("SSH handshake", CheckStatus::Failed, e.to_string());
+        return None;
+    }
+    report.push(
+        "SSH banner",
+        CheckStatus::Ok,
+        sess.banner().unwrap_or("(none)"),
+    );
+
+    let algorithms: Vec<String> = [
+        ("kex", MethodType::Kex),
+        ("host key", MethodType::HostKey),
+        ("cipher", MethodType::CryptCs),
+        ("mac", MethodType::MacCs),
+        ("compression", MethodType::CompCs),
+    ]
+    .into_iter()
+    .filter_map(|(label, kind)| sess.methods(kind).map(|m| format!("{} {}", label, m)))
+    .collect();
+    report.push("Algorithms", CheckStatus::Ok, algorithms.join(", "));
+
+    match sess.host_key() {
+        Some((key, kind)) => {
+            let fingerprint = sess
+                .host_key_hash(HashType::Sha256)
+                .map(|hash| format!("SHA256:{}", base64_unpadded(hash)))
+                .unwrap_or_else(|| "no fingerprint".to_string());
+            let (status, known) = known_host_status(&sess, host, key);
+            report.push(
+                "Host key",
+                status,
+                format!("{} {} ({})", host_key_type_name(kind), fingerprint, known),
+            );
+        }
+        None => report.push("Host key", CheckStatus::Warning, "server sent no host key"),
+    }
+
+    match sess.auth_methods(&host.username) {
+        Ok(methods) => report.push("Auth methods offered", CheckStatus::Ok, methods),
+        Err(e) => report.push("Auth methods offered", CheckStatus::Warning, e.to_string()),
+    }
+
+    if !sess.authenticated() {
+        match authenticate(&sess, host) {
+            Ok(desc) => report.push(
+                "Authentication",
+                CheckStatus::Ok,
+                format!("{} as {}", desc, host.username),
+            ),
+            Err(failures) => {
+                report.push("Authentication", CheckStatus::Failed, failures.join("; "));
+                return None;
+            }
+        }
+    } else {
+        report.push(
+            "Authentication",
+            CheckStatus::Ok,
+            "server accepted \"none\"",
+        );
+    }
+
+    sess.set_timeout(0);
+    sess.set_keepalive(true, KEEPALIVE_INTERVAL.as_secs() as u32);
+    Some(SshRunEnvironment {
+        conn: RefCell::new(Connection { sess, fd }),
+        reused: Cell::new(false),
+        broken: Cell::new(false),
+        sftp_available: Cell::new(None),
+        host: host.clone(),
+    })
+}
+
+/// Runs commands over SSH. The session is leased from a per-host pool on
+/// `connect` and returned to it on drop, unless a run left it broken.
+pub struct SshRunEnvironment {
+    conn: RefCell<Connection>,
+    /// Whether `conn` came from the pool (and so may have died while idle).
+    reused: Cell<bool>,
+    /// Set once a run was killed or failed mid-stream, leaving the session
+    /// in an unknown state; it's then closed rather than pooled.
+    broken: Cell<bool>,
+    /// Whether the host offers SFTP, once we've asked.
+    sftp_available: Cell<Option<bool>>,
     host: Host,
 }
 
 impl SshRunEnvironment {
     pub fn connect(host: &Host) -> Result<Self, RunEnvironmentError> {
+        let (conn, reused) = match pool().checkout(&pool_key(host), session_is_alive) {
+            Some(conn) => (conn, true),
+            None => (Self::open_session(host)?, false),
+        };
+
+        Ok(SshRunEnvironment {
+            conn: RefCell::new(conn),
+            reused: Cell::new(reused),
+            broken: Cell::new(false),
+            sftp_available: Cell::new(None),
+            host: host.clone(),
+        })
+    }
+
+    fn open_session(host: &Host) -> Result<Connection, RunEnvironmentError> {
         let tcp = TcpStream::connect(format!("{}:{}", host.hostname, host.port))
             .map_err(|e| RunEnvironmentError::ConnectionFailed(e.to_string()))?;
+        let fd = tcp.as_raw_fd();
 
         let mut sess = Session::new().map_err(|e| RunEnvironmentError::Ssh(e.to_string()))?;
         sess.set_tcp_stream(tcp);
         sess.handshake()
             .map_err(|e| RunEnvironmentError::ConnectionFailed(e.to_string()))?;
 
-        let mut auth_success = false;
-
-        if sess.userauth_agent(&host.username).is_ok() && sess.authenticated() {
-            auth_success = true;
-        }
-
-        if !auth_success {
-            let key_types = ["id_ed25519", "id_ecdsa", "id_rsa", "id_dsa"];
-            if let Ok(home) = std::env::var("HOME") {
-                for key_name in key_types {
-                    let key_path = Path::new(&home).join(".ssh").join(key_name);
-                    if key_path.exists() {
-                        if sess
-                            .userauth_pubkey_file(&host.username, None, &key_path, None)
-                            .is_ok()
-                            && sess.authenticated()
-                        {
-                            auth_success = true;
-                            break;
-                        }
-                    }
-                }
-            }
-        }
-
-        if !auth_success {
+        if let Err(failures) = authenticate(&sess, host) {
             return Err(RunEnvironmentError::AuthFailed(format!(
-                "Authentication failed for user '{}' on {}\n\nTroubleshooting:\n\
+                "Authentication failed for user '{}' on {}\n\nTried:\n  {}\n\nTroubleshooting:\n\
                 1. Verify your SSH keys are set up for {}\n\
                 2. Run 'ssh-add -l' to check if your key is loaded in the agent\n\
                 3. Try 'ssh {}@{}' manually to test the connection\n\
                 4. Check that your public key is in ~/.ssh/authorized_keys on the remote host",
-                host.username, host.hostname, host.hostname, host.username, host.hostname
+                host.username,
+                host.hostname,
+                failures.join("\n  "),
+                host.hostname,
+                host.username,
+                host.hostname
             )));
         }
 
-        Ok(SshRunEnvironment {
-            sess,
-            host: host.clone(),
-        })
+        sess.set_keepalive(true, KEEPALIVE_INTERVAL.as_secs() as u32);
+        Ok(Connection { sess, fd })
     }
-}
-
-impl RunEnvironment for SshRunEnvironment {
-    fn write_file(&self, path: &str, contents: &[u8]) -> Result<(), RunEnvironmentError> {
-        let sftp = self
-            .sess
-            .sftp()
-            .map_err(|e| RunEnvironmentError::UploadFailed(e.to_string()))?;
-
-        let mut remote_file = sftp
-            .create(Path::new(path))
-            .map_err(|e| RunEnvironmentError::UploadFailed(e.to_string()))?;
 
-        remote_file
-            .write_all(contents)
-            .map_err(|e| RunEnvironmentError::UploadFailed(e.to_string()))?;
+    /// Run `op` against the session. If a pooled session turns out to be
+    /// dead, reconnect once and retry on the fresh one.
+    fn with_session<R>(
+        &self,
+        op: impl Fn(&Session) -> Result<R, ssh2::Error>,
+        map_err: fn(String) -> RunEnvironmentError,
+    ) -> Result<R, RunEnvironmentError> {
+        retry_if_stale(
+            &self.conn,
+            &self.reused,
+            |conn| op(&conn.sess),
+            session_is_alive,
+            || {
+                log::info!(
+                    "Pooled session to {} is stale, reconnecting",
+                    self.host.hostname
+                );
+                Self::open_session(&self.host)
+            },
+            map_err,
+        )
+    }
 
-        Ok(())
+    /// Execute `command` on a new channel, optionally writing `input` to
+    /// its stdin first or giving it a terminal whose password prompt gets
+    /// answered, and stream its output until it exits or is killed.
+    fn run_channel(
+        &self,
+        command: &str,
+        input: Option<&[u8]>,
+        responder: Option<PromptResponder>,
+        on_output: &dyn Fn(OutputChunk),
+        kill: &KillSwitch,
+    ) -> Result<i32, RunEnvironmentError> {
+        let result = self.stream_channel(command, input, responder, on_output, kill);
+        if result.is_err() {
+            self.broken.set(true);
+        }
+        result
     }
 
-    fn run(
+    fn stream_channel(
         &self,
         command: &str,
+        input: Option<&[u8]>,
+        responder: Option<PromptResponder>,
         on_output: &dyn Fn(OutputChunk),
-        kill_rx: &std::sync::mpsc::Receiver<()>,
+        kill: &KillSwitch,
     ) -> Result<i32, RunEnvironmentError> {
-        let mut channel = self
-            .sess
-            .channel_session()
-            .map_err(|e| RunEnvironmentError::Ssh(e.to_string()))?;
+        let mut channel = self.with_session(|s| s.channel_session(), RunEnvironmentError::Ssh)?;
+
+        let terminal = TerminalInput::default();
+        let responder = responder.map(RefCell::new);
+        if responder.is_some() {
+            channel
+                .request_pty("xterm", None, None)
+                .map_err(|e| RunEnvironmentError::Ssh(e.to_string()))?;
+        }
+        let watch = |chunk: OutputChunk| {
+            let Some(responder) = &responder else {
+                return on_output(chunk);
+            };
+            let OutputChunk::Stdout(text) = chunk else {
+                return on_output(chunk);
+            };
+            match responder.borrow_mut().feed(&text) {
+                Ok(Some(reply)) => terminal.pending.borrow_mut().extend(reply),
+                Ok(None) => {}
+                Err(_) => terminal.failed.set(true),
+            }
+            on_output(OutputChunk::Stdout(normalize_newlines(&text)));
+        };
 
         channel
             .exec(command)
             .map_err(|e| RunEnvironmentError::Ssh(e.to_string()))?;
 
-        let mut stdout_buffer = [0u8; 1024];
-        let mut stderr_buffer = [0u8; 1024];
-
-        loop {
-            if kill_rx.try_recv().is_ok() {
-                on_output(OutputChunk::Stderr(
-                    "\n[Killing execution...]\n".to_string(),
-                ));
-                let _ = channel.write_all(&[0x03]);
-                let _ = channel.flush();
-                std::thread::sleep(std::time::Duration::from_millis(200));
-                let _ = channel.send_eof();
-                let _ = channel.close();
-                on_output(OutputChunk::Stderr("[Execution terminated]\n".to_string()));
-                return Ok(-1);
+        let conn = self.conn.borrow().clone();
+        conn.sess.set_blocking(false);
+        let finished = pump(
+            &conn,
+            &mut channel,
+            input,
+            &watch,
+            kill,
+            responder.as_ref().map(|_| &terminal),
+        );
+        conn.sess.set_blocking(true);
+
+        if terminal.failed.get() {
+            let _ = channel.close();
+            return Err(RunEnvironmentError::PasswordRejected);
+        }
+        if !finished? {
+            self.broken.set(true);
+            on_output(OutputChunk::Stderr(
+                "\n[Killing execution...]\n".to_string(),
+            ));
+            let _ = channel.write_all(&[0x03]);
+            let _ = channel.flush();
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            let _ = channel.send_eof();
+            let _ = channel.close();
+            on_output(OutputChunk::Stderr("[Execution terminated]\n".to_string()));
+            return Ok(-1);
+        }
+
+        let _ = channel.wait_close();
+        Ok(channel.exit_status().unwrap_or(-1))
+    }
+}
+
+impl Drop for SshRunEnvironment {
+    fn drop(&mut self) {
+        if self.broken.get() {
+            return;
+        }
+        pool().checkin(&pool_key(&self.host), self.conn.get_mut().clone());
+    }
+}
+
+/// Forward everything currently buffered on one stream of a non-blocking
+/// channel. Returns whether anything was read.
+fn drain(
+    stream: &mut impl Read,
+    buffer: &mut [u8],
+    wrap: fn(String) -> OutputChunk,
+    on_output: &dyn Fn(OutputChunk),
+) -> Result<bool, RunEnvironmentError> {
+    let mut progressed = false;
+    loop {
+        match stream.read(buffer) {
+            Ok(0) => return Ok(progressed),
+            Ok(n) => {
+                progressed = true;
+                on_output(wrap(String::from_utf8_lossy(&buffer[..n]).to_string()));
             }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(progressed),
+            Err(e) => return Err(RunEnvironmentError::Ssh(e.to_string())),
+        }
+    }
+}
 
-            match channel.read(&mut stdout_buffer) {
-                Ok(n) if n > 0 => {
-                    let s = String::from_utf8_lossy(&stdout_buffer[0..n]).to_string();
-                    on_output(OutputChunk::Stdout(s));
-                }
-                _ => {}
+/// Keystrokes for a channel's terminal, queued by the output callback and
+/// typed by `pump`.
+#[derive(Default)]
+struct TerminalInput {
+    pending: RefCell<Vec<u8>>,
+    /// Set when the password was rejected; the run is then abandoned.
+    failed: Cell<bool>,
+}
+
+/// Write as much of `input` to a non-blocking channel's stdin as it takes
+/// without blocking, closing stdin once it's all written; `input` is set to
+/// `None` then. Returns whether anything was written.
+fn feed_stdin(
+    channel: &mut Channel,
+    input: &mut Option<&[u8]>,
+) -> Result<bool, RunEnvironmentError> {
+    let Some(remaining) = input else {
+        return Ok(false);
+    };
+    let would_block = |e: &std::io::Error| e.kind() == std::io::ErrorKind::WouldBlock;
+    let mut progressed = false;
+    while !remaining.is_empty() {
+        match channel.write(remaining) {
+            Ok(n) => {
+                *remaining = &remaining[n..];
+                progressed = true;
             }
+            Err(e) if would_block(&e) => return Ok(progressed),
+            Err(e) => return Err(RunEnvironmentError::Ssh(e.to_string())),
+        }
+    }
+    match channel.send_eof().map_err(std::io::Error::from) {
+        Ok(()) => {
+            *input = None;
+            Ok(true)
+        }
+        Err(e) if would_block(&e) => Ok(progressed),
+        Err(e) => Err(RunEnvironmentError::Ssh(e.to_string())),
+    }
+}
 
-            match channel.stderr().read(&mut stderr_buffer) {
-                Ok(n) if n > 0 => {
-                    let s = String::from_utf8_lossy(&stderr_buffer[0..n]).to_string();
-                    on_output(OutputChunk::Stderr(s));
+/// Stream a non-blocking channel's output until EOF, writing `input` to
+/// its stdin as the channel takes it and sleeping in `poll` on the socket
+/// and the kill switch in between. Returns false if killed (or if
+/// `terminal` input failed).
+fn pump(
+    conn: &Connection,
+    channel: &mut Channel,
+    mut input: Option<&[u8]>,
+    on_output: &dyn Fn(OutputChunk),
+    kill: &KillSwitch,
+    terminal: Option<&TerminalInput>,
+) -> Result<bool, RunEnvironmentError> {
+    let mut buffer = [0u8; 4096];
+    loop {
+        if kill.is_fired() {
+            return Ok(false);
+        }
+        // Interleaved with reading, so a script bigger than the channel
+        // window can't deadlock with a remote side that prints early
+        let wrote = feed_stdin(channel, &mut input)?;
+        if let Some(terminal) = terminal {
+            if terminal.failed.get() {
+                return Ok(false);
+            }
+            let mut pending = terminal.pending.borrow_mut();
+            if !pending.is_empty() {
+                match channel.write(&pending) {
+                    Ok(n) => {
+                        pending.drain(..n);
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(RunEnvironmentError::Ssh(e.to_string())),
                 }
-                _ => {}
             }
+        }
 
-            if channel.eof() {
-                break;
-            }
+        // Reading one stream can pull the other's packets into libssh2's
+        // buffers, so only sleep after a pass that found nothing on either.
+        let out = drain(channel, &mut buffer, OutputChunk::Stdout, on_output)?;
+        let err = drain(
+            &mut channel.stderr(),
+            &mut buffer,
+            OutputChunk::Stderr,
+            on_output,
+        )?;
+        if channel.eof() {
+            return Ok(true);
+        }
+        if out || err || wrote {
+            continue;
+        }
 
-            std::thread::sleep(std::time::Duration::from_millis(10));
+        let events = match conn.sess.block_directions() {
+            BlockDirections::Outbound => libc::POLLOUT,
+            BlockDirections::Both => libc::POLLIN | libc::POLLOUT,
+            BlockDirections::Inbound | BlockDirections::None => libc::POLLIN,
+        };
+        let mut fds = vec![pollfd(conn.fd, events)];
+        if let Some(fd) = kill.wake_fd() {
+            fds.push(pollfd(fd, libc::POLLIN));
         }
+        poll(&mut fds, Some(MAX_SOCKET_WAIT))?;
+    }
+}
 
-        let _ = channel.wait_close();
-        Ok(channel.exit_status().unwrap_or(-1))
+impl RunEnvironment for SshRunEnvironment {
+    fn write_file(&self, path: &str, contents: &[u8]) -> Result<(), RunEnvironmentError> {
+        let sftp = self.with_session(|s| s.sftp(), RunEnvironmentError::UploadFailed)?;
+
+        let mut remote_file = sftp
+            .create(Path::new(path))
+            .map_err(|e| RunEnvironmentError::UploadFailed(e.to_string()))?;
+
+        remote_file
+            .write_all(contents)
+            .map_err(|e| RunEnvironmentError::UploadFailed(e.to_string()))?;
+
+        Ok(())
     }
 
-    fn run_background(&self, command: &str) -> Result<BackgroundHandle, RunEnvironmentError> {
-        let mut channel = self
-            .sess
-            .channel_session()
-            .map_err(|e| RunEnvironmentError::Ssh(e.to_string()))?;
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, RunEnvironmentError> {
+        let sftp = self.with_session(|s| s.sftp(), RunEnvironmentError::DownloadFailed)?;
 
-        channel
-            .exec(command)
-            .map_err(|e| RunEnvironmentError::Ssh(e.to_string()))?;
+        let mut remote_file = sftp
+            .open(Path::new(path))
+            .map_err(|e| RunEnvironmentError::DownloadFailed(format!("{}: {}", path, e)))?;
 
-        let _ = channel.send_eof();
-        let _ = channel.close();
+        let mut contents = Vec::new();
+        remote_file
+            .read_to_end(&mut contents)
+            .map_err(|e| RunEnvironmentError::DownloadFailed(format!("{}: {}", path, e)))?;
 
-        Ok(BackgroundHandle {
-            pid_or_hint: "remote background process".to_string(),
-        })
+        Ok(contents)
+    }
+
+    fn list_dir(&self, path: &str) -> Result<Vec<DirEntry>, RunEnvironmentError> {
+        let sftp = self.with_session(|s| s.sftp(), RunEnvironmentError::DownloadFailed)?;
+
+        let entries = sftp
+            .readdir(Path::new(path))
+            .map_err(|e| RunEnvironmentError::DownloadFailed(format!("{}: {}", path, e)))?;
+
+        Ok(entries
+            .into_iter()
+            .map(|(p, stat)| DirEntry {
+                name: p
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                is_dir: stat.is_dir(),
+            })
+            .collect())
+    }
+
+    fn is_dir(&self, path: &str) -> Result<bool, RunEnvironmentError> {
+        let sftp = self.with_session(|s| s.sftp(), RunEnvironmentError::DownloadFailed)?;
+
+        sftp.stat(Path::new(path))
+            .map(|stat| stat.is_dir())
+            .map_err(|e| RunEnvironmentError::DownloadFailed(format!("{}: {}", path, e)))
+    }
+
+    fn create_dir(&self, path: &str) -> Result<(), RunEnvironmentError> {
+        let sftp = self.with_session(|s| s.sftp(), RunEnvironmentError::UploadFailed)?;
+
+        let mut current = std::path::PathBuf::new();
+        for component in Path::new(path).components() {
+            current.push(component);
+            if sftp.stat(&current).is_ok_and(|stat| stat.is_dir()) {
+                continue;
+            }
+            sftp.mkdir(&current, 0o755).map_err(|e| {
+                RunEnvironmentError::UploadFailed(format!("{}: {}", current.display(), e))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn run(
+        &self,
+        command: &str,
+        on_output: &dyn Fn(OutputChunk),
+        kill: &KillSwitch,
+    ) -> Result<i32, RunEnvironmentError> {
+        self.run_channel(command, None, None, on_output, kill)
+    }
+
+    fn run_with_input(
+        &self,
+        command: &str,
+        input: &[u8],
+        on_output: &dyn Fn(OutputChunk),
+        kill: &KillSwitch,
+    ) -> Result<i32, RunEnvironmentError> {
+        self.run_channel(command, Some(input), None, on_output, kill)
+    }
+
+    fn run_in_pty(
+        &self,
+        command: &str,
+        is_prompt: &dyn Fn(&str) -> bool,
+        answer: &str,
+        on_output: &dyn Fn(OutputChunk),
+        kill: &KillSwitch,
+    ) -> Result<i32, RunEnvironmentError> {
+        self.run_channel(
+            command,
+            None,
+            Some(PromptResponder::new(is_prompt, answer)),
+            on_output,
+            kill,
+        )
+    }
+
+    fn has_file_transfer(&self) -> bool {
+        if let Some(available) = self.sftp_available.get() {
+            return available;
+        }
+        let available = self
+            .with_session(|s| s.sftp(), RunEnvironmentError::Ssh)
+            .is_ok();
+        if !available {
+            log::info!("{} offers no SFTP subsystem", self.host.hostname);
+        }
+        self.sftp_available.set(Some(available));
+        available
     }
 
     fn emit_preamble(&self, on_output: &dyn Fn(OutputChunk), log_file: &str) {
         on_output(OutputChunk::Stdout(
-            "Logging to /tmp. Tail it with the following command:\n".to_string(),
+            "Logging on the host. Tail it with the following command:\n".to_string(),
         ));
         on_output(OutputChunk::Stdout(format!(
             "\n\nssh {}@{} -- tail -f {}\n\n----------------\n",
@@ -0,0 +1,54 @@
+use super::RunEnvironmentError;
+
+/// Only the end of the current output line is kept for prompt matching.
+const MAX_TAIL: usize = 512;
+
+/// Watches a terminal's output for a password prompt and types the answer.
+/// The prompt is answered once; if it comes back, the password was wrong.
+pub struct PromptResponder<'a> {
+    is_prompt: &'a dyn Fn(&str) -> bool,
+    answer: &'a str,
+    tail: String,
+    answered: bool,
+}
+
+impl<'a> PromptResponder<'a> {
+    pub fn new(is_prompt: &'a dyn Fn(&str) -> bool, answer: &'a str) -> Self {
+        PromptResponder {
+            is_prompt,
+            answer,
+            tail: String::new(),
+            answered: false,
+        }
+    }
+
+    /// Look at newly arrived output; returns what to type, if anything.
+    pub fn feed(&mut self, text: &str) -> Result<Option<Vec<u8>>, RunEnvironmentError> {
+        self.tail.push_str(text);
+        if let Some(newline) = self.tail.rfind('\n') {
+            self.tail.drain(..=newline);
+        }
+        if self.tail.len() > MAX_TAIL {
+            let mut cut = self.tail.len() - MAX_TAIL;
+            while !self.tail.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.tail.drain(..cut);
+        }
+
+        if !(self.is_prompt)(&self.tail) {
+            return Ok(None);
+        }
+        self.tail.clear();
+        if self.answered {
+            return Err(RunEnvironmentError::PasswordRejected);
+        }
+        self.answered = true;
+        Ok(Some(format!("{}\n", self.answer).into_bytes()))
+    }
+}
+
+/// Terminal output uses CRLF line endings; the rest of the app expects LF.
+pub fn normalize_newlines(text: &str) -> String {
+    text.replace("\r\n", "\n")
+}
@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod tests {
+    use crate::models::EscalationMethod;
+    use crate::run_environment::terminal::PromptResponder;
+    use crate::run_environment::{
+        KillSwitch, LocalRunEnvironment, OutputChunk, RunEnvironment, RunEnvironmentError,
+    };
+    use crate::shell::{SUDO_PROMPT, is_password_prompt};
+    use std::cell::RefCell;
+
+    fn su_prompt(tail: &str) -> bool {
+        is_password_prompt(EscalationMethod::Su, tail)
+    }
+
+    #[test]
+    fn test_responder_answers_once() {
+        let sudo_prompt = |tail: &str| is_password_prompt(EscalationMethod::Sudo, tail);
+        let mut responder = PromptResponder::new(&sudo_prompt, "pw");
+        assert_eq!(
+            responder.feed("building...\n[switchboard] sudo").unwrap(),
+            None
+        );
+        assert_eq!(
+            responder.feed(" password: ").unwrap(),
+            Some(b"pw\n".to_vec())
+        );
+        assert_eq!(responder.feed("\nok\n").unwrap(), None);
+        assert!(matches!(
+            responder.feed(SUDO_PROMPT),
+            Err(RunEnvironmentError::PasswordRejected)
+        ));
+    }
+
+    #[test]
+    fn test_pty_types_password() {
+        let out = RefCell::new(String::new());
+        let code = LocalRunEnvironment::new()
+            .run_in_pty(
+                "[ -t 0 ] && echo on-a-tty; read -s -p 'Password: ' pw; echo; echo \"got $pw\"; exit 3",
+                &su_prompt,
+                "s3cret",
+                &|chunk| {
+                    if let OutputChunk::Stdout(s) = chunk {
+                        out.borrow_mut().push_str(&s);
+                    }
+                },
+                &KillSwitch::never(),
+            )
+            .unwrap();
+        assert_eq!(code, 3);
+        let out = out.into_inner();
+        assert!(out.contains("on-a-tty\n"), "{:?}", out);
+        assert!(out.contains("got s3cret\n"), "{:?}", out);
+        assert!(!out.contains('\r'));
+    }
+
+    #[test]
+    fn test_pty_reports_rejected_password() {
+        let result = LocalRunEnvironment::new().run_in_pty(
+            "while :; do read -s -p 'Password: ' pw; echo; echo 'Sorry, try again.'; done",
+            &su_prompt,
+            "wrong",
+            &|_| {},
+            &KillSwitch::never(),
+        );
+        assert!(matches!(result, Err(RunEnvironmentError::PasswordRejected)));
+    }
+}
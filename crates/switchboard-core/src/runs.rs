@@ -0,0 +1,574 @@
+//! Runs owned by something other than the GUI window: the HTTP API or the
+//! daemon. Their updates are kept in memory so callers can poll or follow
+//! them, and they are recorded in the store just like the UI's own runs.
+
+use crate::executor::{CommandExecutor, ExecuteError};
+use crate::models::{
+    Command, EscalationPassword, ExecutionResult, ExecutionUpdate, Host, Workflow, WorkflowRun,
+};
+use crate::notifications;
+use crate::store::CommandStore;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::mpsc::{Sender, channel};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Finished runs kept in memory for late pollers; older ones are only in
+/// the store.
+const MAX_FINISHED_RUNS: usize = 100;
+
+#[derive(Error, Debug)]
+pub enum RunError {
+    #[error("No command with id {0}")]
+    UnknownCommand(Uuid),
+    #[error("No workflow with id {0}")]
+    UnknownWorkflow(Uuid),
+    #[error("Missing values for {}", .0.join(", "))]
+    MissingParameters(Vec<String>),
+    #[error("The command runs as another user and needs a password")]
+    PasswordRequired,
+    #[error(transparent)]
+    Execute(#[from] ExecuteError),
+}
+
+/// What to run a command with.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunRequest {
+    /// Environment overrides; required for variables marked "ask".
+    pub env: HashMap<String, String>,
+    /// Answer for a "Run as" password that is asked each run.
+    pub password: Option<String>,
+}
+
+/// A run's record plus the updates a caller hasn't seen yet.
+#[derive(Debug, Clone)]
+pub struct RunProgress {
+    pub result: ExecutionResult,
+    pub updates: Vec<ExecutionUpdate>,
+    /// Index to ask for next time.
+    pub next: usize,
+    /// No more updates will come: the run ended, or it is only known from
+    /// history.
+    pub finished: bool,
+}
+
+/// A workflow run started here and the runs of its steps so far.
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkflowProgress {
+    pub id: Uuid,
+    pub workflow_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    /// Runs of the steps so far, in order.
+    pub runs: Vec<Uuid>,
+    /// Set once it ended: the first failing step's exit code, or 0.
+    pub exit_code: Option<i32>,
+}
+
+struct LiveRun {
+    result: ExecutionResult,
+    updates: Vec<ExecutionUpdate>,
+    output: String,
+    kill_tx: Option<Sender<()>>,
+}
+
+#[derive(Default)]
+struct LiveRuns {
+    runs: HashMap<Uuid, LiveRun>,
+    /// Finished runs, oldest first.
+    finished: Vec<Uuid>,
+    /// Workflow runs, oldest first.
+    workflows: Vec<WorkflowProgress>,
+}
+
+#[derive(Clone)]
+pub struct RunManager {
+    store: CommandStore,
+    executor: Arc<dyn CommandExecutor>,
+    live: Arc<(Mutex<LiveRuns>, Condvar)>,
+}
+
+impl RunManager {
+    pub fn new(store: CommandStore, executor: Arc<dyn CommandExecutor>) -> Self {
+        RunManager {
+            store,
+            executor,
+            live: Arc::new((Mutex::new(LiveRuns::default()), Condvar::new())),
+        }
+    }
+
+    pub fn store(&self) -> &CommandStore {
+        &self.store
+    }
+
+    /// Start `command_id` on each of its targets; returns one run id per
+    /// target.
+    pub fn start(&self, command_id: Uuid, request: RunRequest) -> Result<Vec<Uuid>, RunError> {
+        let mut command = self
+            .store
+            .get_command(&command_id)
+            .ok_or(RunError::UnknownCommand(command_id))?;
+
+        let mut env_vars = HashMap::new();
+        let mut missing = Vec::new();
+        for var in &command.env_vars {
+            match request.env.get(&var.key) {
+                Some(value) => env_vars.insert(var.key.clone(), value.clone()),
+                None if var.ask_user => {
+                    missing.push(var.key.clone());
+                    None
+                }
+                None => env_vars.insert(var.key.clone(), var.value.clone()),
+            };
+        }
+        if !missing.is_empty() {
+            return Err(RunError::MissingParameters(missing));
+        }
+        env_vars.extend(request.env);
+
+        if let Some(run_as) = &mut command.run_as
+            && run_as.password == EscalationPassword::Prompt
+        {
+            let password = request.password.ok_or(RunError::PasswordRequired)?;
+            run_as.password = EscalationPassword::Entered(password);
+        }
+
+        let inventory = self.store.resolve_targets(&command);
+        let targets = if inventory.is_empty() {
+            vec![command.adhoc_host()]
+        } else {
+            inventory
+        };
+
+        let mut ids = Vec::new();
+        for host in targets {
+            let exec_id = Uuid::new_v4();
+            self.execute(exec_id, &command, &host, env_vars.clone())?;
+            ids.push(exec_id);
+        }
+        Ok(ids)
+    }
+
+    /// Start `workflow_id`'s steps one after another in the background,
+    /// each on all of its targets, stopping after the first step that
+    /// fails. Returns the workflow run's id.
+    pub fn start_workflow(&self, workflow_id: Uuid, request: RunRequest) -> Result<Uuid, RunError> {
+        let workflow = self
+            .store
+            .get_workflow(&workflow_id)
+            .ok_or(RunError::UnknownWorkflow(workflow_id))?;
+        let mut steps = Vec::new();
+        for (i, command_id) in workflow.commands.iter().enumerate() {
+            let mut command = self
+                .store
+                .get_command(command_id)
+                .ok_or(RunError::UnknownCommand(*command_id))?;
+            command.run_as = workflow.run_as_for_step(i, &command);
+            steps.push(command);
+        }
+
+        // The workflow's variables override its commands'
+        let mut missing = Vec::new();
+        let vars = workflow
+            .env_vars
+            .iter()
+            .chain(steps.iter().flat_map(|c| &c.env_vars));
+        for var in vars {
+            let effective = workflow
+                .env_vars
+                .iter()
+                .find(|v| v.key == var.key)
+                .unwrap_or(var);
+            if effective.ask_user
+                && !request.env.contains_key(&var.key)
+                && !missing.contains(&var.key)
+            {
+                missing.push(var.key.clone());
+            }
+        }
+        if !missing.is_empty() {
+            return Err(RunError::MissingParameters(missing));
+        }
+
+        for command in &mut steps {
+            if let Some(run_as) = &mut command.run_as
+                && run_as.password == EscalationPassword::Prompt
+            {
+                let password = request.password.clone().ok_or(RunError::PasswordRequired)?;
+                run_as.password = EscalationPassword::Entered(password);
+            }
+        }
+
+        let progress = WorkflowProgress {
+            id: Uuid::new_v4(),
+            workflow_id,
+            started_at: Utc::now(),
+            runs: Vec::new(),
+            exit_code: None,
+        };
+        let id = progress.id;
+        {
+            let mut live = self.live.0.lock().unwrap();
+            live.workflows.push(progress);
+            let finished = live
+                .workflows
+                .iter()
+                .filter(|w| w.exit_code.is_some())
+                .count();
+            if finished > MAX_FINISHED_RUNS
+                && let Some(oldest) = live.workflows.iter().position(|w| w.exit_code.is_some())
+            {
+                live.workflows.remove(oldest);
+            }
+        }
+
+        let manager = self.clone();
+        std::thread::spawn(move || manager.run_workflow(id, &workflow, &steps, &request.env));
+        Ok(id)
+    }
+
+    fn run_workflow(
+        &self,
+        id: Uuid,
+        workflow: &Workflow,
+        steps: &[Command],
+        overrides: &HashMap<String, String>,
+    ) {
+        let started = Instant::now();
+        let mut exit_code = 0;
+        let mut last = None;
+        for command in steps {
+            let mut env_vars: HashMap<String, String> = command
+                .env_vars
+                .iter()
+                .map(|v| (v.key.clone(), v.value.clone()))
+                .collect();
+            env_vars.extend(
+                workflow
+                    .env_vars
+                    .iter()
+                    .map(|v| (v.key.clone(), v.value.clone())),
+            );
+            env_vars.extend(overrides.clone());
+
+            let inventory = self.store.resolve_targets(command);
+            let targets = if inventory.is_empty() {
+                vec![command.adhoc_host()]
+            } else {
+                inventory
+            };
+            let mut ids = Vec::new();
+            for host in &targets {
+                let exec_id = Uuid::new_v4();
+                if let Err(e) = self.execute(exec_id, command, host, env_vars.clone()) {
+                    log::warn!(
+                        "Workflow {} could not start {}: {}",
+                        workflow.name,
+                        command.name,
+                        e
+                    );
+                    exit_code = -1;
+                    break;
+                }
+                ids.push(exec_id);
+                last = Some((exec_id, host.name.clone()));
+            }
+            self.workflow_progress(&id, |w| w.runs.extend(&ids));
+
+            for exec_id in ids {
+                let code = self.wait(&exec_id);
+                if exit_code == 0 {
+                    exit_code = code;
+                }
+            }
+            if exit_code != 0 {
+                break;
+            }
+        }
+
+        let duration_ms = started.elapsed().as_millis() as u64;
+        let mut started_at = Utc::now();
+        self.workflow_progress(&id, |w| {
+            w.exit_code = Some(exit_code);
+            started_at = w.started_at;
+        });
+        self.store.add_workflow_run(WorkflowRun {
+            id,
+            workflow_id: workflow.id,
+            started_at,
+            duration_ms,
+            exit_code,
+        });
+        let (host, output) = match last {
+            Some((exec_id, host)) => (host, self.log(&exec_id).unwrap_or_default()),
+            None => (String::new(), String::new()),
+        };
+        notifications::workflow_finished(
+            &self.store,
+            workflow,
+            exit_code,
+            duration_ms,
+            host,
+            &output,
+        );
+    }
+
+    fn workflow_progress(&self, id: &Uuid, change: impl FnOnce(&mut WorkflowProgress)) {
+        let mut live = self.live.0.lock().unwrap();
+        if let Some(progress) = live.workflows.iter_mut().find(|w| w.id == *id) {
+            change(progress);
+        }
+    }
+
+    /// Block until a run finishes; returns its exit code.
+    fn wait(&self, exec_id: &Uuid) -> i32 {
+        let mut since = 0;
+        loop {
+            let Some(progress) = self.progress(exec_id, since, Some(Duration::from_secs(60)))
+            else {
+                return -1;
+            };
+            if progress.finished {
+                return progress.result.exit_code.unwrap_or(-1);
+            }
+            since = progress.next;
+        }
+    }
+
+    /// Workflow runs of `workflow_id` started here that are still in
+    /// memory, oldest first.
+    pub fn workflow_runs(&self, workflow_id: &Uuid) -> Vec<WorkflowProgress> {
+        let live = self.live.0.lock().unwrap();
+        live.workflows
+            .iter()
+            .filter(|w| w.workflow_id == *workflow_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Run `command` on `host` as given: environment resolved and any
+    /// password filled in.
+    pub fn execute(
+        &self,
+        exec_id: Uuid,
+        command: &Command,
+        host: &Host,
+        env_vars: HashMap<String, String>,
+    ) -> Result<(), ExecuteError> {
+        // Ad hoc hosts aren't in the inventory and are recorded as nil
+        let host_id = if self.store.get_host(&host.id).is_some() {
+            host.id
+        } else {
+            Uuid::nil()
+        };
+        let mut result = ExecutionResult::started(exec_id, command.id, host_id);
+        result.revision = self.store.pin_revision(&command.id);
+        result.library_commit = self.store.library_of(&command.id).and_then(|l| l.commit);
+        let (kill_tx, kill_rx) = channel();
+        self.track(result, kill_tx);
+
+        let manager = self.clone();
+        let on_update = Box::new(move |update| manager.record(exec_id, update));
+        let started = self
+            .executor
+            .execute(exec_id, command, host, env_vars, on_update, kill_rx);
+        if started.is_err() {
+            self.live.0.lock().unwrap().runs.remove(&exec_id);
+        }
+        started
+    }
+
+    /// Follow background runs that were still going when the store was
+    /// last saved, e.g. before the daemon restarted.
+    pub fn reattach_background(&self) {
+        for command in self.store.list_commands() {
+            for result in self.store.get_execution_history(&command.id) {
+                let Some(job) = result.background.clone() else {
+                    continue;
+                };
+                if result.exit_code.is_some() {
+                    continue;
+                }
+
+                let exec_id = result.id;
+                let host = self
+                    .store
+                    .get_host(&result.host_id)
+                    .unwrap_or_else(|| command.adhoc_host());
+                let (kill_tx, kill_rx) = channel();
+                self.track(result, kill_tx);
+
+                // The job's log is streamed again from the start
+                let manager = self.clone();
+                let on_update = Box::new(move |update| manager.record(exec_id, update));
+                if let Err(e) = self
+                    .executor
+                    .attach_background(&host, &job, on_update, kill_rx)
+                {
+                    eprintln!("Failed to reattach to background run {}: {}", exec_id, e);
+                    self.live.0.lock().unwrap().runs.remove(&exec_id);
+                }
+            }
+        }
+    }
+
+    fn track(&self, result: ExecutionResult, kill_tx: Sender<()>) {
+        self.live.0.lock().unwrap().runs.insert(
+            result.id,
+            LiveRun {
+                result,
+                updates: Vec::new(),
+                output: String::new(),
+                kill_tx: Some(kill_tx),
+            },
+        );
+    }
+
+    /// Runs still going, oldest first.
+    pub fn active(&self) -> Vec<ExecutionResult> {
+        let live = self.live.0.lock().unwrap();
+        let mut active: Vec<ExecutionResult> = live
+            .runs
+            .values()
+            .filter(|r| r.result.exit_code.is_none())
+            .map(|r| r.result.clone())
+            .collect();
+        active.sort_by_key(|r| r.started_at);
+        active
+    }
+
+    /// For an update that changes a run's record, save the new record with
+    /// the output so far. That happens before the update is published, so a
+    /// run that shows as finished is in the history too, and without holding
+    /// the lock pollers wait on.
+    fn save_record(&self, exec_id: &Uuid, update: &ExecutionUpdate) -> Option<ExecutionResult> {
+        let (job, code) = match update {
+            ExecutionUpdate::Detached(job) => (Some(job), None),
+            ExecutionUpdate::Exit(code) => (None, Some(*code)),
+            _ => return None,
+        };
+        let (mut result, output) = {
+            let live = self.live.0.lock().unwrap();
+            let run = live.runs.get(exec_id)?;
+            (run.result.clone(), run.output.clone())
+        };
+        if let Some(job) = job {
+            // Persisted now so the job can be reattached later
+            result.background = Some(job.clone());
+        }
+        if let Some(code) = code {
+            result.finish(code);
+        }
+        self.store.add_execution(&result, &output);
+        if code.is_some() {
+            notifications::run_finished(&self.store, &result, &output);
+        }
+        Some(result)
+    }
+
+    fn record(&self, exec_id: Uuid, update: ExecutionUpdate) {
+        let mut artifact_error = None;
+        let update = match update {
+            ExecutionUpdate::Artifact { name, data } => {
+                if let Err(e) = self.store.add_artifact(&exec_id, &name, &data) {
+                    artifact_error = Some(format!("[artifacts] failed to save {}: {}\n", name, e));
+                }
+                // Callers fetch artifacts from disk; don't keep them twice
+                ExecutionUpdate::Artifact {
+                    name,
+                    data: Vec::new(),
+                }
+            }
+            update => update,
+        };
+        let saved = self.save_record(&exec_id, &update);
+
+        let (lock, changed) = &*self.live;
+        let mut live = lock.lock().unwrap();
+        let Some(run) = live.runs.get_mut(&exec_id) else {
+            return;
+        };
+        if let ExecutionUpdate::Stdout(text) | ExecutionUpdate::Stderr(text) = &update {
+            run.output.push_str(text);
+        }
+        run.output.extend(artifact_error);
+        if let Some(result) = saved {
+            run.result = result;
+        }
+        let exited = matches!(update, ExecutionUpdate::Exit(_));
+        if exited {
+            run.kill_tx = None;
+        }
+        run.updates.push(update);
+
+        if exited {
+            live.finished.push(exec_id);
+            if live.finished.len() > MAX_FINISHED_RUNS {
+                let oldest = live.finished.remove(0);
+                live.runs.remove(&oldest);
+            }
+        }
+        changed.notify_all();
+    }
+
+    /// A run's record and its updates from index `since` on. With `wait`,
+    /// blocks up to that long for something new while the run is going.
+    /// Runs no longer in memory come from the store's history, without
+    /// updates.
+    pub fn progress(
+        &self,
+        exec_id: &Uuid,
+        since: usize,
+        wait: Option<Duration>,
+    ) -> Option<RunProgress> {
+        let (lock, changed) = &*self.live;
+        let deadline = wait.map(|w| Instant::now() + w);
+        let mut live = lock.lock().unwrap();
+        while let Some(run) = live.runs.get(exec_id) {
+            let finished = run.result.exit_code.is_some();
+            let timed_out = deadline.is_none_or(|d| Instant::now() >= d);
+            if run.updates.len() > since || finished || timed_out {
+                return Some(RunProgress {
+                    result: run.result.clone(),
+                    updates: run.updates.get(since..).unwrap_or_default().to_vec(),
+                    next: run.updates.len().max(since),
+                    finished,
+                });
+            }
+            let remaining = deadline.map_or(Duration::ZERO, |d| {
+                d.saturating_duration_since(Instant::now())
+            });
+            live = changed.wait_timeout(live, remaining).unwrap().0;
+        }
+        drop(live);
+
+        let result = self.store.get_execution(exec_id)?;
+        Some(RunProgress {
+            finished: true,
+            result,
+            updates: Vec::new(),
+            next: since,
+        })
+    }
+
+    /// Ask a running run to stop. Returns false if it isn't running here.
+    pub fn kill(&self, exec_id: &Uuid) -> bool {
+        let live = self.live.0.lock().unwrap();
+        match live.runs.get(exec_id).and_then(|r| r.kill_tx.as_ref()) {
+            Some(kill_tx) => kill_tx.send(()).is_ok(),
+            None => false,
+        }
+    }
+
+    /// A run's output so far, or its saved log once it's only in history.
+    pub fn log(&self, exec_id: &Uuid) -> Option<String> {
+        if let Some(run) = self.live.0.lock().unwrap().runs.get(exec_id) {
+            return Some(run.output.clone());
+        }
+        self.store.get_execution_log(exec_id)
+    }
+}
@@ -0,0 +1,256 @@
+//! Commands from what people already have: a folder of shell scripts, or
+//! lines of their bash or zsh history. The importers return candidates to
+//! preview and pick from; the picked ones are saved like any new command.
+//!
+//! Commands always run in bash, so a script whose shebang names another
+//! interpreter (python3, perl, ...) is wrapped to be handed to it.
+
+use crate::models::Command;
+use std::path::{Path, PathBuf};
+
+/// History entries offered, newest first.
+pub const MAX_HISTORY_ENTRIES: usize = 1000;
+const HEREDOC_DELIMITER: &str = "SWITCHBOARD_SCRIPT";
+const NAME_LENGTH: usize = 40;
+
+/// A command that would be created.
+#[derive(Debug, Clone)]
+pub struct ImportCandidate {
+    pub command: Command,
+    /// What runs the script, e.g. "bash" or "python3".
+    pub interpreter: String,
+    /// Where it comes from: a file name, or "history".
+    pub origin: String,
+    /// The name of a command that already has this script.
+    pub existing: Option<String>,
+    pub selected: bool,
+}
+
+fn new_command(
+    name: String,
+    description: Option<String>,
+    script: String,
+    source_path: Option<PathBuf>,
+) -> Command {
+    Command {
+        description,
+        source_path,
+        ..Command::new(name, script)
+    }
+}
+
+fn existing_name(script: &str, existing: &[Command]) -> Option<String> {
+    existing
+        .iter()
+        .find(|c| c.script.trim() == script.trim())
+        .map(|c| c.name.clone())
+}
+
+/// The interpreter a script's `#!` line names, with `env` left out:
+/// "python3 -u" for `#!/usr/bin/env -S python3 -u`, "/bin/sh" for
+/// `#!/bin/sh`.
+pub fn shebang_interpreter(script: &str) -> Option<String> {
+    let line = script.lines().next()?.strip_prefix("#!")?;
+    let mut words: Vec<&str> = line.split_whitespace().collect();
+    if words
+        .first()
+        .is_some_and(|w| Path::new(w).file_name().is_some_and(|n| n == "env"))
+    {
+        words.remove(0);
+        if words.first() == Some(&"-S") {
+            words.remove(0);
+        }
+    }
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+/// Whether bash can run a script meant for `interpreter` as it is.
+fn runs_in_bash(interpreter: &str) -> bool {
+    let program = interpreter.split_whitespace().next().unwrap_or_default();
+    matches!(
+        Path::new(program).file_name().and_then(|n| n.to_str()),
+        Some("bash" | "sh")
+    )
+}
+
+/// `script` as a command: as it is when bash runs it, otherwise fed to
+/// its interpreter from a here-document.
+pub fn wrap_script(script: &str) -> String {
+    let Some(interpreter) = shebang_interpreter(script).filter(|i| !runs_in_bash(i)) else {
+        return script.to_string();
+    };
+    let mut delimiter = HEREDOC_DELIMITER.to_string();
+    while script.lines().any(|l| l == delimiter) {
+        delimiter.push('_');
+    }
+    let newline = if script.ends_with('\n') { "" } else { "\n" };
+    format!(
+        "exec {} - \"$@\" <<'{}'\n{}{}{}\n",
+        interpreter, delimiter, script, newline, delimiter
+    )
+}
+
+/// The comment block at the top of a script, after the `#!` line, as one
+/// line. Lint directives, editor settings and rules of `#`s are skipped.
+pub fn leading_comment(script: &str) -> Option<String> {
+    let mut lines = script.lines().peekable();
+    if lines.peek().is_some_and(|l| l.starts_with("#!")) {
+        lines.next();
+    }
+    let text: Vec<&str> = lines
+        .skip_while(|l| l.trim().is_empty())
+        .map_while(|l| l.trim().strip_prefix('#'))
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.chars().all(|c| "#-=*".contains(c)))
+        .filter(|l| !l.starts_with("shellcheck") && !l.starts_with("vim:") && !l.starts_with("-*-"))
+        .collect();
+    (!text.is_empty()).then(|| text.join(" "))
+}
+
+/// The `.sh` files directly in `dir`, by file name.
+pub fn from_directory(dir: &Path, existing: &[Command]) -> std::io::Result<Vec<ImportCandidate>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|e| e == "sh"))
+        .collect();
+    paths.sort();
+
+    let mut candidates = Vec::new();
+    for path in paths {
+        let script = std::fs::read_to_string(&path)?;
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let wrapped = wrap_script(&script);
+        let existing = existing_name(&wrapped, existing);
+        candidates.push(ImportCandidate {
+            interpreter: shebang_interpreter(&script).unwrap_or_else(|| "bash".to_string()),
+            origin: path
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            selected: existing.is_none(),
+            existing,
+            command: new_command(name, leading_comment(&script), wrapped, Some(path)),
+        });
+    }
+    Ok(candidates)
+}
+
+/// A name for a history entry: its first line, shortened.
+fn history_name(entry: &str) -> String {
+    let line = entry.lines().next().unwrap_or_default().trim();
+    if line.chars().count() <= NAME_LENGTH {
+        line.to_string()
+    } else {
+        format!(
+            "{}…",
+            line.chars().take(NAME_LENGTH - 1).collect::<String>()
+        )
+    }
+}
+
+/// History entries as commands, none picked yet.
+pub fn from_history(entries: &[String], existing: &[Command]) -> Vec<ImportCandidate> {
+    entries
+        .iter()
+        .map(|entry| ImportCandidate {
+            command: new_command(history_name(entry), None, entry.clone(), None),
+            interpreter: "bash".to_string(),
+            origin: "history".to_string(),
+            existing: existing_name(entry, existing),
+            selected: false,
+        })
+        .collect()
+}
+
+/// The entries of a bash or zsh history file, newest first, each once.
+/// Understands bash's `#<time>` lines and zsh's extended format
+/// (`: <time>:<duration>;command`), including zsh's multi-line entries.
+pub fn parse_history(text: &str) -> Vec<String> {
+    let mut entries: Vec<String> = Vec::new();
+    let mut continued: Option<String> = None;
+    for line in text.lines() {
+        let line = match continued.take() {
+            Some(mut entry) => {
+                entry.push('\n');
+                entry.push_str(line);
+                entry
+            }
+            None => {
+                if line.len() > 1
+                    && line.starts_with('#')
+                    && line[1..].chars().all(|c| c.is_ascii_digit())
+                {
+                    continue;
+                }
+                match line.strip_prefix(": ").and_then(|l| l.split_once(';')) {
+                    Some((time, command))
+                        if time.chars().all(|c| c.is_ascii_digit() || c == ':') =>
+                    {
+                        command.to_string()
+                    }
+                    _ => line.to_string(),
+                }
+            }
+        };
+        if let Some(entry) = line.strip_suffix('\\') {
+            continued = Some(entry.to_string());
+        } else if !line.trim().is_empty() {
+            entries.push(line);
+        }
+    }
+    entries.extend(continued);
+
+    let mut seen = std::collections::HashSet::new();
+    entries
+        .into_iter()
+        .rev()
+        .filter(|e| seen.insert(e.clone()))
+        .take(MAX_HISTORY_ENTRIES)
+        .collect()
+}
+
+/// zsh writes some bytes as 0x83 and the byte XOR 32.
+fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter();
+    while let Some(&b) = bytes.next() {
+        match (b, bytes.as_slice().first()) {
+            (0x83, Some(&next)) => {
+                out.push(next ^ 32);
+                bytes.next();
+            }
+            _ => out.push(b),
+        }
+    }
+    out
+}
+
+/// The entries of the history file at `path`; see `parse_history`.
+pub fn read_history(path: &Path) -> std::io::Result<Vec<String>> {
+    let mut bytes = std::fs::read(path)?;
+    if path
+        .file_name()
+        .is_some_and(|n| n.to_string_lossy().contains("zsh"))
+    {
+        bytes = unmetafy(&bytes);
+    }
+    Ok(parse_history(&String::from_utf8_lossy(&bytes)))
+}
+
+/// `$HISTFILE`, `~/.bash_history` and `~/.zsh_history`, those that exist.
+pub fn history_files() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::env::var_os("HISTFILE")
+        .map(PathBuf::from)
+        .into_iter()
+        .collect();
+    if let Some(dirs) = directories::BaseDirs::new() {
+        files.push(dirs.home_dir().join(".bash_history"));
+        files.push(dirs.home_dir().join(".zsh_history"));
+    }
+    let mut seen = std::collections::HashSet::new();
+    files.retain(|f| f.is_file() && seen.insert(f.clone()));
+    files
+}
@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod tests {
+    use crate::models::Command;
+    use crate::script_import::{
+        from_directory, from_history, leading_comment, parse_history, shebang_interpreter,
+        wrap_script,
+    };
+    use uuid::Uuid;
+
+    #[test]
+    fn test_script_headers() {
+        assert_eq!(
+            shebang_interpreter("#!/bin/bash -e\necho"),
+            Some("/bin/bash -e".into())
+        );
+        assert_eq!(
+            shebang_interpreter("#!/usr/bin/env python3\n"),
+            Some("python3".into())
+        );
+        assert_eq!(
+            shebang_interpreter("#!/usr/bin/env -S python3 -u\n"),
+            Some("python3 -u".into())
+        );
+        assert_eq!(shebang_interpreter("echo hi"), None);
+
+        let script = "#!/bin/bash\n\n##########\n# Rotate the logs on the web hosts.\n# shellcheck disable=SC2086\n#\n# Keeps a week.\nset -e\n# not this\n";
+        assert_eq!(
+            leading_comment(script),
+            Some("Rotate the logs on the web hosts. Keeps a week.".into())
+        );
+        assert_eq!(leading_comment("#!/bin/sh\necho"), None);
+
+        assert_eq!(wrap_script("#!/bin/sh\necho hi\n"), "#!/bin/sh\necho hi\n");
+        assert_eq!(
+            wrap_script("#!/usr/bin/env python3\nprint('hi')"),
+            "exec python3 - \"$@\" <<'SWITCHBOARD_SCRIPT'\n#!/usr/bin/env python3\nprint('hi')\nSWITCHBOARD_SCRIPT\n"
+        );
+        assert!(
+            wrap_script("#!/usr/bin/perl\nprint <<SWITCHBOARD_SCRIPT;\nhi\nSWITCHBOARD_SCRIPT\n")
+                .contains("<<'SWITCHBOARD_SCRIPT_'\n")
+        );
+    }
+
+    #[test]
+    fn test_import_directory() {
+        let dir =
+            std::env::temp_dir().join(format!("switchboard_script_import_test_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("rotate-logs.sh"),
+            "#!/bin/bash\n# Rotate logs\nlogrotate -f /etc/logrotate.conf\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("report.sh"),
+            "#!/usr/bin/env python3\n# Weekly report\nprint('report')\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a script").unwrap();
+
+        let existing = [Command::new(
+            "Rotate",
+            "#!/bin/bash\n# Rotate logs\nlogrotate -f /etc/logrotate.conf",
+        )];
+        let candidates = from_directory(&dir, &existing).unwrap();
+        assert_eq!(candidates.len(), 2);
+
+        let report = &candidates[0];
+        assert_eq!(report.command.name, "report");
+        assert_eq!(report.command.description.as_deref(), Some("Weekly report"));
+        assert_eq!(report.interpreter, "python3");
+        assert!(report.command.script.starts_with("exec python3 - "));
+        assert!(report.selected);
+
+        let rotate = &candidates[1];
+        assert_eq!(rotate.command.name, "rotate-logs");
+        assert_eq!(rotate.interpreter, "/bin/bash");
+        assert_eq!(rotate.existing.as_deref(), Some("Rotate"));
+        assert!(!rotate.selected);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_history() {
+        let bash = "#1700000000\nls -la\n#1700000001\nkubectl get pods -n production --selector app=api,tier=backend\nls -la\n\n";
+        let entries = parse_history(bash);
+        assert_eq!(
+            entries,
+            vec![
+                "ls -la",
+                "kubectl get pods -n production --selector app=api,tier=backend"
+            ]
+        );
+
+        let zsh = ": 1700000000:0;git status\n: 1700000005:2;for f in *.log; do\\\n  gzip $f\\\ndone\n: 1700000009:0;git status\n";
+        let entries = parse_history(zsh);
+        assert_eq!(
+            entries,
+            vec!["git status", "for f in *.log; do\n  gzip $f\ndone"]
+        );
+
+        let existing = [Command::new("Status", "git status")];
+        let candidates = from_history(&entries, &existing);
+        assert_eq!(candidates[0].existing.as_deref(), Some("Status"));
+        assert_eq!(candidates[1].command.name, "for f in *.log; do");
+        assert!(candidates.iter().all(|c| !c.selected));
+
+        let long = from_history(
+            &["kubectl get pods -n production --selector app=api,tier=backend".to_string()],
+            &[],
+        );
+        assert_eq!(long[0].command.name.chars().count(), 40);
+        assert!(long[0].command.name.ends_with('…'));
+    }
+}
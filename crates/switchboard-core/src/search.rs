@@ -0,0 +1,315 @@
+//! Full-text search over commands and run logs. A query's words must each
+//! start a word in the text, in any order and case: "conn refused" finds
+//! "Connection refused". Logs are found through `LogIndex`, an inverted
+//! index kept by the store (see `CommandStore::search`), and the matching
+//! lines are then read back from the logs themselves.
+
+use crate::models::{Command, ExecutionResult};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Lines shown before and after a matching log line.
+pub const CONTEXT_LINES: usize = 2;
+/// Most log lines returned by one search.
+pub const MAX_LOG_MATCHES: usize = 200;
+
+#[derive(Debug, Clone)]
+pub struct CommandMatch {
+    pub command_id: Uuid,
+    pub name: String,
+    pub in_name: bool,
+    pub in_description: bool,
+    /// Matching script lines as (1-based line number, line).
+    pub script_lines: Vec<(usize, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogMatch {
+    pub exec_id: Uuid,
+    pub command_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    /// 1-based.
+    pub line_number: usize,
+    pub before: Vec<String>,
+    pub line: String,
+    pub after: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchResults {
+    pub commands: Vec<CommandMatch>,
+    /// Newest run first, then in log order.
+    pub logs: Vec<LogMatch>,
+    /// More log lines matched than `MAX_LOG_MATCHES`.
+    pub truncated: bool,
+}
+
+impl SearchResults {
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty() && self.logs.is_empty()
+    }
+}
+
+/// Lowercased words (letters, digits and `_`) of `text`.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// A query's distinct words.
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = words(query).collect();
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+/// Whether every term starts some word of `text`.
+pub fn text_matches(text: &str, terms: &[String]) -> bool {
+    let text_words: Vec<String> = words(text).collect();
+    !terms.is_empty()
+        && terms
+            .iter()
+            .all(|t| text_words.iter().any(|w| w.starts_with(t.as_str())))
+}
+
+pub fn match_command(command: &Command, terms: &[String]) -> Option<CommandMatch> {
+    let in_name = text_matches(&command.name, terms);
+    let in_description = command
+        .description
+        .as_deref()
+        .is_some_and(|d| text_matches(d, terms));
+    let script_lines: Vec<(usize, String)> = command
+        .script
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| text_matches(line, terms))
+        .map(|(i, line)| (i + 1, line.to_string()))
+        .collect();
+    (in_name || in_description || !script_lines.is_empty()).then(|| CommandMatch {
+        command_id: command.id,
+        name: command.name.clone(),
+        in_name,
+        in_description,
+        script_lines,
+    })
+}
+
+/// Append `log`'s matching lines, with context, to `out`, up to `limit`
+/// entries in total. Returns false if some were left out.
+pub fn match_log(
+    result: &ExecutionResult,
+    log: &str,
+    terms: &[String],
+    out: &mut Vec<LogMatch>,
+    limit: usize,
+) -> bool {
+    let lines: Vec<&str> = log.lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        if !text_matches(line, terms) {
+            continue;
+        }
+        if out.len() >= limit {
+            return false;
+        }
+        let owned = |range: &[&str]| range.iter().map(|l| l.to_string()).collect();
+        out.push(LogMatch {
+            exec_id: result.id,
+            command_id: result.command_id,
+            started_at: result.started_at,
+            line_number: i + 1,
+            before: owned(&lines[i.saturating_sub(CONTEXT_LINES)..i]),
+            line: line.to_string(),
+            after: owned(&lines[i + 1..(i + 1 + CONTEXT_LINES).min(lines.len())]),
+        });
+    }
+    true
+}
+
+/// Which runs' logs contain which words.
+#[derive(Debug, Default)]
+pub struct LogIndex {
+    words: BTreeMap<String, HashSet<Uuid>>,
+    /// Indexed runs, and whether they had finished when indexed.
+    runs: HashMap<Uuid, bool>,
+}
+
+impl LogIndex {
+    /// Index (or re-index) a run's log.
+    pub fn add(&mut self, result: &ExecutionResult, log: &str) {
+        self.add_words(result, words(log).collect());
+    }
+
+    fn add_words(&mut self, result: &ExecutionResult, log_words: HashSet<String>) {
+        self.remove(&result.id);
+        for word in log_words {
+            self.words.entry(word).or_default().insert(result.id);
+        }
+        self.runs.insert(result.id, result.exit_code.is_some());
+    }
+
+    pub fn remove(&mut self, exec_id: &Uuid) {
+        if self.runs.remove(exec_id).is_some() {
+            self.words.retain(|_, runs| {
+                runs.remove(exec_id);
+                !runs.is_empty()
+            });
+        }
+    }
+
+    /// Whether `result`'s log is missing, or was indexed before it finished.
+    pub fn is_stale(&self, result: &ExecutionResult) -> bool {
+        match self.runs.get(&result.id) {
+            None => true,
+            Some(finished) => !finished && result.exit_code.is_some(),
+        }
+    }
+
+    /// Runs whose log has, for every term, a word starting with it.
+    pub fn candidates(&self, terms: &[String]) -> HashSet<Uuid> {
+        let mut candidates: Option<HashSet<Uuid>> = None;
+        for term in terms {
+            let with_term: HashSet<Uuid> = self
+                .words
+                .range(term.clone()..)
+                .take_while(|(word, _)| word.starts_with(term.as_str()))
+                .flat_map(|(_, runs)| runs.iter().copied())
+                .collect();
+            candidates = Some(match candidates {
+                None => with_term,
+                Some(previous) => previous.intersection(&with_term).copied().collect(),
+            });
+        }
+        candidates.unwrap_or_default()
+    }
+}
+
+/// The log matches of the last search, kept so repeating it once more runs
+/// have finished only reads their logs.
+#[derive(Debug)]
+struct LastSearch {
+    terms: Vec<String>,
+    /// Runs looked at, and whether they had finished then.
+    runs: HashMap<Uuid, bool>,
+    logs: Vec<LogMatch>,
+    truncated: bool,
+}
+
+/// What `CommandStore::search` keeps between searches.
+#[derive(Debug, Default)]
+pub struct SearchState {
+    pub index: LogIndex,
+    last: Option<LastSearch>,
+}
+
+/// Log lines matching `terms` in the logs of `executions`, newest run
+/// first, and whether more matched than `MAX_LOG_MATCHES`. Only logs of
+/// runs that are new, or have finished, since the last search with the
+/// same terms are read with `read_log`.
+///
+/// Logs are read and indexed without holding `state`'s lock, which
+/// `CommandStore::add_execution` takes from the UI thread when a run ends.
+pub fn search_logs(
+    state: &Mutex<Option<SearchState>>,
+    executions: &[ExecutionResult],
+    terms: &[String],
+    read_log: impl Fn(&Uuid) -> Option<String>,
+) -> (Vec<LogMatch>, bool) {
+    let mut executions: Vec<&ExecutionResult> = executions.iter().collect();
+    executions.sort_by_key(|e| std::cmp::Reverse(e.started_at));
+
+    let stale: Vec<&ExecutionResult> = {
+        let mut state = state.lock().unwrap();
+        let index = &state.get_or_insert_with(SearchState::default).index;
+        executions
+            .iter()
+            .copied()
+            .filter(|result| index.is_stale(result))
+            .collect()
+    };
+    let read: Vec<(&ExecutionResult, HashSet<String>)> = stale
+        .into_iter()
+        .map(|result| {
+            let log = read_log(&result.id).unwrap_or_default();
+            (result, words(&log).collect())
+        })
+        .collect();
+
+    let (candidates, last) = {
+        let mut state = state.lock().unwrap();
+        let state = state.get_or_insert_with(SearchState::default);
+        for (result, log_words) in read {
+            // Unless `add_execution` indexed it in the meantime
+            if state.index.is_stale(result) {
+                state.index.add_words(result, log_words);
+            }
+        }
+        (state.index.candidates(terms), state.last.take())
+    };
+
+    // Started over for other terms, or once a run it looked at is gone
+    let ids: HashSet<Uuid> = executions.iter().map(|e| e.id).collect();
+    let mut last = match last {
+        Some(last) if last.terms == terms && last.runs.keys().all(|id| ids.contains(id)) => last,
+        _ => LastSearch {
+            terms: terms.to_vec(),
+            runs: HashMap::new(),
+            logs: Vec::new(),
+            truncated: false,
+        },
+    };
+    for result in &executions {
+        let finished = result.exit_code.is_some();
+        if last
+            .runs
+            .insert(result.id, finished)
+            .is_some_and(|was_finished| was_finished || !finished)
+        {
+            continue;
+        }
+        last.logs.retain(|m| m.exec_id != result.id);
+        if !candidates.contains(&result.id) {
+            continue;
+        }
+
+        // Older than every match kept, with no room left: only whether
+        // it has any matters
+        let full = last.logs.len() >= MAX_LOG_MATCHES
+            && last
+                .logs
+                .last()
+                .is_some_and(|m| m.started_at > result.started_at);
+        if full && last.truncated {
+            continue;
+        }
+        let Some(log) = read_log(&result.id) else {
+            continue;
+        };
+        let mut matches = Vec::new();
+        let limit = if full { 0 } else { MAX_LOG_MATCHES };
+        if !match_log(result, &log, terms, &mut matches, limit) {
+            last.truncated = true;
+        }
+        let at = last
+            .logs
+            .iter()
+            .position(|m| m.started_at < result.started_at)
+            .unwrap_or(last.logs.len());
+        last.logs.splice(at..at, matches);
+        if last.logs.len() > MAX_LOG_MATCHES {
+            last.logs.truncate(MAX_LOG_MATCHES);
+            last.truncated = true;
+        }
+    }
+
+    let found = (last.logs.clone(), last.truncated);
+    state
+        .lock()
+        .unwrap()
+        .get_or_insert_with(SearchState::default)
+        .last = Some(last);
+    found
+}
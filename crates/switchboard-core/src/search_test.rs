@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod tests {
+    use crate::models::{Command, ExecutionResult};
+    use crate::search::{LogIndex, LogMatch, match_log, query_terms, search_logs, text_matches};
+    use crate::store::CommandStore;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use uuid::Uuid;
+
+    fn make_command(name: &str, script: &str) -> Command {
+        Command {
+            description: Some("Restarts the web tier".to_string()),
+            ..Command::new(name, script)
+        }
+    }
+
+    fn finished_run(command_id: Uuid) -> ExecutionResult {
+        let mut result = ExecutionResult::started(Uuid::new_v4(), command_id, Uuid::nil());
+        result.finish(1);
+        result
+    }
+
+    #[test]
+    fn test_terms_match_word_prefixes() {
+        let terms = query_terms("Conn REFUSED conn");
+        assert_eq!(terms, vec!["conn", "refused"]);
+        assert!(text_matches("ssh: Connection refused (port 22)", &terms));
+        assert!(!text_matches("Connection reset", &terms));
+        // Prefixes of words, not arbitrary substrings
+        assert!(!text_matches("reconnect refused", &terms));
+        assert!(!text_matches("anything", &query_terms("  ::  ")));
+    }
+
+    #[test]
+    fn test_log_matches_have_context() {
+        let result = finished_run(Uuid::new_v4());
+        let log = "one\ntwo\nERROR: disk full\nthree\nfour\nfive\nerror again";
+        let mut matches = Vec::new();
+        assert!(match_log(
+            &result,
+            log,
+            &query_terms("error"),
+            &mut matches,
+            10
+        ));
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].line_number, 3);
+        assert_eq!(matches[0].before, vec!["one", "two"]);
+        assert_eq!(matches[0].after, vec!["three", "four"]);
+        assert_eq!(matches[1].after, Vec::<String>::new());
+
+        // Stops at the limit
+        let mut matches = Vec::new();
+        assert!(!match_log(
+            &result,
+            log,
+            &query_terms("error"),
+            &mut matches,
+            1
+        ));
+        assert_eq!(matches.len(), 1);
+    }
+
+    #[test]
+    fn test_index_refreshes_finished_runs() {
+        let mut index = LogIndex::default();
+        let mut result = ExecutionResult::started(Uuid::new_v4(), Uuid::new_v4(), Uuid::nil());
+        index.add(&result, "starting up");
+        assert!(!index.is_stale(&result));
+        assert!(index.candidates(&query_terms("done")).is_empty());
+
+        // A background run recorded again once it exits
+        result.finish(0);
+        assert!(index.is_stale(&result));
+        index.add(&result, "starting up\ndone");
+        assert_eq!(index.candidates(&query_terms("start done")).len(), 1);
+        assert!(index.candidates(&query_terms("start missing")).is_empty());
+
+        index.remove(&result.id);
+        assert!(index.candidates(&query_terms("start")).is_empty());
+    }
+
+    #[test]
+    fn test_repeated_search_reads_only_new_logs() {
+        let mut first = finished_run(Uuid::new_v4());
+        first.started_at -= chrono::Duration::seconds(1);
+        let mut running = ExecutionResult::started(Uuid::new_v4(), Uuid::nil(), Uuid::nil());
+        let logs: HashMap<Uuid, &str> =
+            HashMap::from([(first.id, "error: one"), (running.id, "error: two")]);
+        let reads = RefCell::new(Vec::new());
+        let read_log = |id: &Uuid| {
+            reads.borrow_mut().push(*id);
+            logs.get(id).map(|log| log.to_string())
+        };
+        let terms = query_terms("error");
+        let exec_ids = |found: Vec<LogMatch>| found.iter().map(|m| m.exec_id).collect::<Vec<_>>();
+
+        let state = Mutex::new(None);
+        let mut runs = vec![first.clone(), finished_run(Uuid::new_v4())];
+        let (found, truncated) = search_logs(&state, &runs, &terms, read_log);
+        assert_eq!(exec_ids(found), vec![first.id]);
+        assert!(!truncated);
+        reads.borrow_mut().clear();
+        search_logs(&state, &runs, &terms, read_log);
+        assert!(reads.borrow().is_empty());
+
+        // A run still going when searched is read again once it finishes
+        runs.push(running.clone());
+        search_logs(&state, &runs, &terms, read_log);
+        reads.borrow_mut().clear();
+        running.finish(0);
+        runs[2] = running.clone();
+        let found = search_logs(&state, &runs, &terms, read_log).0;
+        assert!(reads.borrow().iter().all(|id| *id == running.id));
+        assert_eq!(exec_ids(found), vec![running.id, first.id]);
+
+        // Removed runs' matches go
+        runs.remove(0);
+        let found = search_logs(&state, &runs, &terms, read_log).0;
+        assert_eq!(exec_ids(found), vec![running.id]);
+    }
+
+    #[test]
+    fn test_logs_are_read_without_the_lock() {
+        let runs = vec![finished_run(Uuid::new_v4()), finished_run(Uuid::new_v4())];
+        let state = Mutex::new(None);
+        let read_log = |_: &Uuid| {
+            // `add_execution` must be able to take it meanwhile
+            assert!(state.try_lock().is_ok());
+            Some("error: disk full".to_string())
+        };
+
+        let (found, _) = search_logs(&state, &runs, &query_terms("disk"), read_log);
+        assert_eq!(found.len(), 2);
+    }
+
+    #[test]
+    fn test_store_search() {
+        let store = CommandStore::new_test();
+        let command = make_command(
+            "Restart nginx",
+            "sudo systemctl restart nginx\nsystemctl status nginx",
+        );
+        store.add_command(command.clone());
+        store.add_command(make_command("Backup", "tar czf /tmp/backup.tgz /srv"));
+
+        let old = finished_run(command.id);
+        store.add_execution(&old, "Job for nginx.service failed\nsee journalctl");
+
+        let results = store.search("nginx");
+        assert_eq!(results.commands.len(), 1);
+        assert!(results.commands[0].in_name && !results.commands[0].in_description);
+        assert_eq!(results.commands[0].script_lines.len(), 2);
+        assert_eq!(results.logs.len(), 1);
+        assert_eq!(results.logs[0].exec_id, old.id);
+        assert_eq!(results.logs[0].after, vec!["see journalctl"]);
+
+        // Runs recorded after the index was built are found too, newest first
+        let mut new = finished_run(command.id);
+        new.started_at += chrono::Duration::seconds(1);
+        store.add_execution(&new, "nginx: [emerg] bind() to 0.0.0.0:80 failed");
+        let results = store.search("nginx failed");
+        assert_eq!(
+            results.logs.iter().map(|m| m.exec_id).collect::<Vec<_>>(),
+            vec![new.id, old.id]
+        );
+
+        assert_eq!(store.search("web tier").commands.len(), 2);
+        assert!(store.search("nothing like this").is_empty());
+    }
+}
@@ -0,0 +1,152 @@
+//! Passwords kept in the system keychain rather than in `store.json`: the
+//! macOS keychain through `security`, or the Secret Service (GNOME Keyring,
+//! KWallet) through `secret-tool` elsewhere. The store only holds the id an
+//! entry is filed under, so exports, snapshots and the daemon's messages
+//! never carry the secret itself.
+
+use thiserror::Error;
+use uuid::Uuid;
+
+#[cfg(not(test))]
+pub use system::{load, save};
+
+// Tests mustn't touch the keychain of whoever runs them
+#[cfg(test)]
+pub use memory::{load, save};
+
+#[derive(Error, Debug)]
+pub enum SecretError {
+    #[error("Could not run {0}: {1}")]
+    Tool(&'static str, String),
+    #[error("The keychain has no saved password {0}")]
+    NotFound(Uuid),
+    #[error("The keychain refused: {0}")]
+    Failed(String),
+}
+
+#[cfg(not(test))]
+mod system {
+    use super::SecretError;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    use uuid::Uuid;
+
+    /// The service name entries are filed under.
+    const SERVICE: &str = "switchboard";
+    /// `security`'s exit code for a missing entry.
+    const NOT_FOUND: i32 = 44;
+
+    /// Run `tool` with `args`, feeding `input` on stdin, and return its
+    /// stdout, or `None` if the entry doesn't exist. A secret only ever
+    /// goes in on stdin, never as an argument.
+    fn run(
+        tool: &'static str,
+        args: &[&str],
+        input: Option<&str>,
+    ) -> Result<Option<String>, SecretError> {
+        let mut child = Command::new(tool)
+            .args(args)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| SecretError::Tool(tool, e.to_string()))?;
+        if let Some(input) = input
+            && let Some(mut stdin) = child.stdin.take()
+        {
+            stdin
+                .write_all(input.as_bytes())
+                .map_err(|e| SecretError::Tool(tool, e.to_string()))?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|e| SecretError::Tool(tool, e.to_string()))?;
+        if output.status.success() {
+            Ok(Some(String::from_utf8_lossy(&output.stdout).to_string()))
+        } else if output.stderr.is_empty() || output.status.code() == Some(NOT_FOUND) {
+            // secret-tool fails without a word when there's no such entry
+            Ok(None)
+        } else {
+            Err(SecretError::Failed(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ))
+        }
+    }
+
+    fn hex(text: &str) -> String {
+        text.bytes().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Save `secret` as entry `id`, replacing any earlier one.
+    pub fn save(id: &Uuid, secret: &str) -> Result<(), SecretError> {
+        let account = id.to_string();
+        let saved = if cfg!(target_os = "macos") {
+            // `security -i` reads the command from stdin; hex needs no quoting
+            let line = format!(
+                "add-generic-password -U -s {} -a {} -X {}\n",
+                SERVICE,
+                account,
+                hex(secret)
+            );
+            run("security", &["-i"], Some(&line))?
+        } else {
+            let label = format!("Switchboard password {}", id);
+            let args = [
+                "store", "--label", &label, "service", SERVICE, "account", &account,
+            ];
+            run("secret-tool", &args, Some(secret))?
+        };
+        saved
+            .map(|_| ())
+            .ok_or_else(|| SecretError::Failed(format!("could not save password {}", id)))
+    }
+
+    /// The secret saved as entry `id`.
+    pub fn load(id: &Uuid) -> Result<String, SecretError> {
+        let account = id.to_string();
+        let found = if cfg!(target_os = "macos") {
+            run(
+                "security",
+                &["find-generic-password", "-s", SERVICE, "-a", &account, "-w"],
+                None,
+            )?
+            .map(|out| out.strip_suffix('\n').unwrap_or(&out).to_string())
+        } else {
+            run(
+                "secret-tool",
+                &["lookup", "service", SERVICE, "account", &account],
+                None,
+            )?
+        };
+        found.ok_or(SecretError::NotFound(*id))
+    }
+}
+
+#[cfg(test)]
+mod memory {
+    use super::SecretError;
+    use std::collections::HashMap;
+    use std::sync::{Mutex, MutexGuard, OnceLock};
+    use uuid::Uuid;
+
+    fn keychain() -> MutexGuard<'static, HashMap<Uuid, String>> {
+        static KEYCHAIN: OnceLock<Mutex<HashMap<Uuid, String>>> = OnceLock::new();
+        KEYCHAIN.get_or_init(Default::default).lock().unwrap()
+    }
+
+    pub fn save(id: &Uuid, secret: &str) -> Result<(), SecretError> {
+        keychain().insert(*id, secret.to_string());
+        Ok(())
+    }
+
+    pub fn load(id: &Uuid) -> Result<String, SecretError> {
+        keychain()
+            .get(id)
+            .cloned()
+            .ok_or(SecretError::NotFound(*id))
+    }
+}
@@ -0,0 +1,110 @@
+//! Building shell command lines. Every word that comes from a command,
+//! host or path goes through `quote`, so spaces and quotes survive however
+//! many `bash -c` layers the line is wrapped in.
+
+use crate::models::{EscalationMethod, EscalationPassword, RunAs};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ShellError {
+    #[error(
+        "Invalid environment variable name {0:?}: use letters, digits and underscores, not starting with a digit"
+    )]
+    InvalidEnvKey(String),
+    #[error("Invalid user name {0:?} to run as")]
+    InvalidUser(String),
+}
+
+/// The prompt sudo is told to print, so its password request can't be
+/// confused with anything the script prints.
+pub const SUDO_PROMPT: &str = "[switchboard] sudo password: ";
+
+/// Quote `word` so a POSIX shell reads it back as exactly one word with
+/// exactly these bytes. Words made only of characters that are never
+/// special are left bare for readability.
+pub fn quote(word: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(is_plain) {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+/// Like `quote`, but a leading `~` stays outside the quotes as `"$HOME"`
+/// so it expands to the home directory of whoever runs the line.
+pub fn quote_home_path(path: &str) -> String {
+    match path.strip_prefix('~') {
+        Some("") => "\"$HOME\"".to_string(),
+        Some(rest) if rest.starts_with('/') => format!("\"$HOME\"{}", quote(rest)),
+        _ => quote(path),
+    }
+}
+
+/// Whether `key` can be used as an environment variable name in a shell.
+pub fn is_env_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `export` statements for `vars`, sorted by name, each followed by `; `.
+pub fn exports(vars: &HashMap<String, String>) -> Result<String, ShellError> {
+    let mut keys: Vec<&String> = vars.keys().collect();
+    keys.sort();
+    let mut out = String::new();
+    for key in keys {
+        if !is_env_key(key) {
+            return Err(ShellError::InvalidEnvKey(key.clone()));
+        }
+        out.push_str(&format!("export {}={}; ", key, quote(&vars[key])));
+    }
+    Ok(out)
+}
+
+/// A command line running `script` in a fresh bash.
+pub fn bash_c(script: &str) -> String {
+    format!("/bin/bash -c {}", quote(script))
+}
+
+/// Wrap `command` so it runs in bash as `run_as.user`, via sudo or su.
+/// Without a password, sudo is told not to prompt (`-n`) so a missing
+/// `NOPASSWD` rule fails fast instead of hanging.
+pub fn elevate(run_as: &RunAs, command: &str) -> Result<String, ShellError> {
+    let user = run_as.user.trim();
+    let valid = !user.is_empty()
+        && !user.starts_with('-')
+        && user
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-$".contains(c));
+    if !valid {
+        return Err(ShellError::InvalidUser(run_as.user.clone()));
+    }
+
+    Ok(match (run_as.method, &run_as.password) {
+        (EscalationMethod::Sudo, EscalationPassword::None) => {
+            format!("sudo -n -u {} -- {}", quote(user), bash_c(command))
+        }
+        (EscalationMethod::Sudo, _) => format!(
+            "sudo -p {} -u {} -- {}",
+            quote(SUDO_PROMPT),
+            quote(user),
+            bash_c(command)
+        ),
+        (EscalationMethod::Su, _) => {
+            format!("su -s /bin/bash -c {} {}", quote(command), quote(user))
+        }
+    })
+}
+
+/// Whether terminal output ending in `tail` is `method` asking for a
+/// password. su's prompt is localized, so anything like "Password:" counts.
+pub fn is_password_prompt(method: EscalationMethod, tail: &str) -> bool {
+    let tail = tail.trim_end();
+    match method {
+        EscalationMethod::Sudo => tail.ends_with(SUDO_PROMPT.trim_end()),
+        EscalationMethod::Su => tail.ends_with(':') && tail.to_lowercase().contains("passw"),
+    }
+}
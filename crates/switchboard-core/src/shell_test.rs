@@ -0,0 +1,118 @@
+#[cfg(test)]
+mod tests {
+    use crate::models::{EscalationMethod, EscalationPassword, RunAs};
+    use crate::shell::{ShellError, bash_c, elevate, exports, is_env_key, quote, quote_home_path};
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+
+    /// Run `line` with bash and return its stdout.
+    fn bash(line: &str) -> String {
+        let output = std::process::Command::new("/bin/bash")
+            .arg("-c")
+            .arg(line)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_quote_examples() {
+        assert_eq!(quote("plain/path-1.txt"), "plain/path-1.txt");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("has space"), "'has space'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert_eq!(quote("$(rm -rf /)"), "'$(rm -rf /)'");
+        assert_eq!(quote_home_path("~"), "\"$HOME\"");
+        assert_eq!(quote_home_path("~/my runs"), "\"$HOME\"'/my runs'");
+        assert_eq!(quote_home_path("~other/x"), "'~other/x'");
+    }
+
+    #[test]
+    fn test_exports_reject_invalid_keys() {
+        for key in ["", "1ABC", "A-B", "A B", "X;rm -rf /", "ÄB"] {
+            let vars = HashMap::from([(key.to_string(), "v".to_string())]);
+            assert_eq!(
+                exports(&vars),
+                Err(ShellError::InvalidEnvKey(key.to_string()))
+            );
+        }
+        let vars = HashMap::from([
+            ("B".to_string(), "two words".to_string()),
+            ("_a1".to_string(), "x".to_string()),
+        ]);
+        assert_eq!(
+            exports(&vars).unwrap(),
+            "export B='two words'; export _a1=x; "
+        );
+    }
+
+    #[test]
+    fn test_elevate() {
+        let mut run_as = RunAs {
+            user: "deploy".to_string(),
+            method: EscalationMethod::Sudo,
+            password: EscalationPassword::None,
+        };
+        assert_eq!(
+            elevate(&run_as, "id -un").unwrap(),
+            "sudo -n -u deploy -- /bin/bash -c 'id -un'"
+        );
+
+        run_as.password = EscalationPassword::Entered("pw".to_string());
+        assert_eq!(
+            elevate(&run_as, "id -un").unwrap(),
+            "sudo -p '[switchboard] sudo password: ' -u deploy -- /bin/bash -c 'id -un'"
+        );
+
+        run_as.method = EscalationMethod::Su;
+        assert_eq!(
+            elevate(&run_as, "id -un").unwrap(),
+            "su -s /bin/bash -c 'id -un' deploy"
+        );
+
+        for user in ["", "-root", "root; reboot", "a b"] {
+            run_as.user = user.to_string();
+            assert_eq!(
+                elevate(&run_as, "true"),
+                Err(ShellError::InvalidUser(user.to_string()))
+            );
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_quote_round_trips(word in "[^\u{0}]*") {
+            prop_assert_eq!(bash(&format!("printf %s {}", quote(&word))), word);
+        }
+
+        #[test]
+        fn prop_quote_survives_nested_bash(word in "[^\u{0}]*") {
+            let inner = format!("printf %s {}", quote(&word));
+            prop_assert_eq!(bash(&bash_c(&bash_c(&inner))), word);
+        }
+
+        // A lone `_` is a valid name, but bash keeps resetting `$_` itself.
+        #[test]
+        fn prop_exports_set_values(key in "[A-Za-z][A-Za-z0-9_]{0,10}|_[A-Za-z0-9_]{1,10}", value in "[^\u{0}]*") {
+            let vars = HashMap::from([(key.clone(), value.clone())]);
+            let line = format!("{}printf %s \"${}\"", exports(&vars).unwrap(), key);
+            prop_assert_eq!(bash(&line), value);
+        }
+
+        #[test]
+        fn prop_env_key_validation(key in "\\PC{0,8}") {
+            let expected = key.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            prop_assert_eq!(is_env_key(&key), expected);
+            let vars = HashMap::from([(key.clone(), String::new())]);
+            prop_assert_eq!(exports(&vars).is_ok(), expected);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Store snapshots: gzipped copies of `store.json` in `snapshots/`, named
+//! by the SHA-256 of their contents, with timestamps and labels kept in
+//! `snapshots/index.json`. See `CommandStore::create_snapshot` and
+//! `CommandStore::restore_snapshot`.
+
+use crate::models::{EscalationPassword, RunAsSettings};
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const INDEX_FILE: &str = "index.json";
+/// How often the store's owner checks whether a scheduled snapshot is due
+/// (see `CommandStore::snapshot_if_due`).
+pub const SCHEDULE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SnapshotKind {
+    /// Taken by hand; never pruned.
+    #[default]
+    Manual,
+    BeforeImport,
+    BeforeRestore,
+    Scheduled,
+}
+
+impl SnapshotKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SnapshotKind::Manual => "Manual",
+            SnapshotKind::BeforeImport => "Before import",
+            SnapshotKind::BeforeRestore => "Before restore",
+            SnapshotKind::Scheduled => "Scheduled",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    /// SHA-256 of the snapshot, in hex; also its file name.
+    pub hash: String,
+    /// When the store was last in this state.
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub kind: SnapshotKind,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ItemKind {
+    Command,
+    Workflow,
+    Host,
+}
+
+/// A command, workflow or host, by id.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct SnapshotItem {
+    pub kind: ItemKind,
+    pub id: Uuid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemChange {
+    /// Created since the snapshot; restoring deletes it.
+    Added,
+    /// Deleted since the snapshot; restoring brings it back.
+    Removed,
+    Changed,
+}
+
+/// How an item differs between a snapshot and the current state.
+#[derive(Debug, Clone)]
+pub struct ItemDiff {
+    pub item: SnapshotItem,
+    /// The current name, or the snapshot's for deleted items.
+    pub name: String,
+    pub change: ItemChange,
+}
+
+/// `item` as compared between two versions of it. Passwords kept for
+/// "run as" all compare the same: an older store's plain-text ones only
+/// move to the keychain when it's written, each to a new entry.
+pub(crate) fn comparable<T: Clone + Serialize + RunAsSettings>(
+    item: &T,
+) -> Option<serde_json::Value> {
+    let mut item = item.clone();
+    for run_as in item.run_as_mut().into_iter().flatten() {
+        if let EscalationPassword::Saved(_) | EscalationPassword::Entered(_) = run_as.password {
+            run_as.password = EscalationPassword::Saved(Uuid::nil());
+        }
+    }
+    serde_json::to_value(item).ok()
+}
+
+/// Append to `out` how the items in `snapshot` and `current` differ.
+pub(crate) fn diff_items<T: Clone + Serialize + RunAsSettings>(
+    kind: ItemKind,
+    snapshot: &[T],
+    current: &[T],
+    id: impl Fn(&T) -> Uuid,
+    name: impl Fn(&T) -> &str,
+    out: &mut Vec<ItemDiff>,
+) {
+    let mut diffs = Vec::new();
+    for item in current {
+        let change = match snapshot.iter().find(|s| id(s) == id(item)) {
+            None => ItemChange::Added,
+            Some(old) if comparable(old) != comparable(item) => ItemChange::Changed,
+            Some(_) => continue,
+        };
+        diffs.push(ItemDiff {
+            item: SnapshotItem { kind, id: id(item) },
+            name: name(item).to_string(),
+            change,
+        });
+    }
+    for old in snapshot
+        .iter()
+        .filter(|s| !current.iter().any(|c| id(c) == id(s)))
+    {
+        diffs.push(ItemDiff {
+            item: SnapshotItem { kind, id: id(old) },
+            name: name(old).to_string(),
+            change: ItemChange::Removed,
+        });
+    }
+    diffs.sort_by(|a, b| a.name.cmp(&b.name));
+    out.extend(diffs);
+}
+
+/// Where the snapshot `hash` is kept; refuses anything but a SHA-256 hash
+/// so a name from a client can't point outside `dir`.
+pub(crate) fn snapshot_path(dir: &Path, hash: &str) -> anyhow::Result<PathBuf> {
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("not a snapshot: {:?}", hash);
+    }
+    Ok(dir.join(format!("{}.json.gz", hash)))
+}
+
+pub(crate) fn write_snapshot(dir: &Path, hash: &str, json: &str) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let file = std::fs::File::create(snapshot_path(dir, hash)?)?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder.write_all(json.as_bytes())?;
+    encoder.finish()?;
+    Ok(())
+}
+
+pub(crate) fn read_snapshot(dir: &Path, hash: &str) -> anyhow::Result<String> {
+    let file = std::fs::File::open(snapshot_path(dir, hash)?)?;
+    let mut json = String::new();
+    GzDecoder::new(file).read_to_string(&mut json)?;
+    Ok(json)
+}
+
+/// The snapshots in `dir`, newest first. Snapshots taken before the index
+/// existed are listed with their file's modification time.
+pub(crate) fn read_index(dir: &Path) -> Vec<SnapshotInfo> {
+    let mut index: Vec<SnapshotInfo> = std::fs::read_to_string(dir.join(INDEX_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    index.retain(|s| snapshot_path(dir, &s.hash).is_ok_and(|p| p.exists()));
+
+    for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some(hash) = file_name.strip_suffix(".json.gz") else {
+            continue;
+        };
+        if snapshot_path(dir, hash).is_err() || index.iter().any(|s| s.hash == hash) {
+            continue;
+        }
+        let modified = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .map(DateTime::<Utc>::from);
+        index.push(SnapshotInfo {
+            hash: hash.to_string(),
+            created_at: modified.unwrap_or_else(|_| Utc::now()),
+            label: String::new(),
+            kind: SnapshotKind::Manual,
+        });
+    }
+    index.sort_by_key(|s| std::cmp::Reverse(s.created_at));
+    index
+}
+
+pub(crate) fn write_index(dir: &Path, index: &[SnapshotInfo]) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(dir.join(INDEX_FILE), serde_json::to_string_pretty(index)?)?;
+    Ok(())
+}
@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod tests {
+    use crate::models::{Command, Host};
+    use crate::snapshots::{ItemChange, ItemKind, SnapshotItem, SnapshotKind};
+    use crate::store::CommandStore;
+    use uuid::Uuid;
+
+    fn make_host(name: &str) -> Host {
+        Host {
+            id: Uuid::new_v4(),
+            name: name.into(),
+            hostname: format!("{}.example.com", name),
+            port: 22,
+            username: "deploy".into(),
+            auth: crate::models::AuthMethod::Agent,
+            kind: Default::default(),
+            groups: vec![],
+            tags: vec![],
+            workspace: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_list_and_prune() {
+        let store = CommandStore::new_test();
+        let first = store
+            .create_snapshot("before upgrade", SnapshotKind::Manual)
+            .unwrap();
+        // The same state again is the same snapshot, and keeps its label
+        let again = store.create_snapshot("", SnapshotKind::Scheduled).unwrap();
+        assert_eq!(again.hash, first.hash);
+        assert_eq!(again.label, "before upgrade");
+        assert_eq!(again.kind, SnapshotKind::Manual);
+
+        for name in ["a", "b", "c"] {
+            store.add_command(Command::new(name, "true"));
+            store.create_snapshot("", SnapshotKind::Scheduled).unwrap();
+        }
+        let snapshots = store.list_snapshots();
+        assert_eq!(snapshots.len(), 4);
+        assert_eq!(snapshots[3].hash, first.hash);
+
+        // Manual snapshots aren't pruned
+        assert_eq!(store.prune_snapshots(1).unwrap(), 2);
+        let kinds: Vec<SnapshotKind> = store.list_snapshots().iter().map(|s| s.kind).collect();
+        assert_eq!(kinds, vec![SnapshotKind::Scheduled, SnapshotKind::Manual]);
+
+        store.delete_snapshot(&first.hash).unwrap();
+        assert_eq!(store.list_snapshots().len(), 1);
+        assert!(store.delete_snapshot("../store").is_err());
+    }
+
+    #[test]
+    fn test_diff_and_restore() {
+        let store = CommandStore::new_test();
+        let mut deploy = Command::new("Deploy", "true");
+        let backup = Command::new("Backup", "true");
+        let web = make_host("web");
+        store.add_command(deploy.clone());
+        store.add_command(backup.clone());
+        store.add_host(web.clone());
+        let snapshot = store.snapshot_state().unwrap();
+
+        deploy.script = "make deploy".into();
+        store.add_command(deploy.clone());
+        store.remove_command(&backup.id);
+        let db = make_host("db");
+        store.add_host(db.clone());
+
+        let diffs = store.diff_snapshot(&snapshot).unwrap();
+        let changes: Vec<(ItemKind, &str, ItemChange)> = diffs
+            .iter()
+            .map(|d| (d.item.kind, d.name.as_str(), d.change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (ItemKind::Command, "Backup", ItemChange::Removed),
+                (ItemKind::Command, "Deploy", ItemChange::Changed),
+                (ItemKind::Host, "db", ItemChange::Added),
+            ]
+        );
+
+        // Only the deleted command
+        let item = SnapshotItem {
+            kind: ItemKind::Command,
+            id: backup.id,
+        };
+        store.restore_snapshot(&snapshot, Some(vec![item])).unwrap();
+        assert!(store.get_command(&backup.id).is_some());
+        assert_eq!(store.get_command(&deploy.id).unwrap().script, "make deploy");
+        assert_eq!(store.list_snapshots()[0].kind, SnapshotKind::BeforeRestore);
+
+        // Everything, keeping the replaced version as a revision
+        store.restore_snapshot(&snapshot, None).unwrap();
+        assert!(store.diff_snapshot(&snapshot).unwrap().is_empty());
+        assert!(store.get_host(&db.id).is_none());
+        assert_eq!(
+            store.list_revisions(&deploy.id)[1].command.script,
+            "make deploy"
+        );
+    }
+
+    #[test]
+    fn test_automatic_snapshots() {
+        let store = CommandStore::new_test();
+        let json = store.export_json().unwrap();
+        store.add_command(Command::new("Deploy", "true"));
+        store.import_json(&json).unwrap();
+        let snapshots = store.list_snapshots();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].kind, SnapshotKind::BeforeImport);
+        assert_eq!(
+            store.diff_snapshot(&snapshots[0].hash).unwrap()[0].change,
+            ItemChange::Removed
+        );
+
+        // Not due while the newest snapshot is recent
+        assert!(store.snapshot_if_due().unwrap().is_none());
+        let mut settings = store.get_settings();
+        settings.snapshots.interval_hours = 0;
+        store.set_settings(settings);
+        assert!(store.snapshot_if_due().unwrap().is_none());
+
+        let fresh = CommandStore::new_test();
+        let taken = fresh.snapshot_if_due().unwrap().unwrap();
+        assert_eq!(taken.kind, SnapshotKind::Scheduled);
+    }
+}
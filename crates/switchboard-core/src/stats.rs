@@ -0,0 +1,282 @@
+//! Figures over the recorded runs: for each command, workflow and host,
+//! how often it ran and failed, how long it took, and how that changed day
+//! by day. See `CommandStore::stats`.
+
+use crate::snapshots::{ItemKind, SnapshotItem};
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use std::collections::BTreeMap;
+
+/// One finished run, as counted.
+#[derive(Debug, Clone)]
+pub(crate) struct RunRecord {
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: Option<u64>,
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DayStats {
+    pub day: NaiveDate,
+    pub runs: usize,
+    pub failures: usize,
+    pub median_duration_ms: Option<u64>,
+}
+
+impl DayStats {
+    /// The share of runs that succeeded, from 0 to 1; `None` without runs.
+    pub fn success_rate(&self) -> Option<f64> {
+        success_rate(self.runs, self.failures)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ItemStats {
+    pub item: SnapshotItem,
+    pub name: String,
+    pub runs: usize,
+    pub failures: usize,
+    pub p50_ms: Option<u64>,
+    pub p90_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+    /// How the median duration in the later half of the period compares
+    /// with the earlier half: 0.25 is a quarter slower, -0.1 a tenth
+    /// faster. `None` unless both halves have runs.
+    pub duration_trend: Option<f64>,
+    pub last_run: Option<DateTime<Utc>>,
+    /// Every day of the period, oldest first.
+    pub days: Vec<DayStats>,
+}
+
+impl ItemStats {
+    pub fn success_rate(&self) -> Option<f64> {
+        success_rate(self.runs, self.failures)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StatsReport {
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    /// Runs of commands on every day of the period, oldest first.
+    /// Workflow runs aren't counted again here.
+    pub days: Vec<DayStats>,
+    /// Commands, workflows and hosts that ran in the period, by name.
+    pub items: Vec<ItemStats>,
+}
+
+impl StatsReport {
+    pub fn items_of(&self, kind: ItemKind) -> impl Iterator<Item = &ItemStats> {
+        self.items.iter().filter(move |i| i.item.kind == kind)
+    }
+
+    /// Those of `kind` that failed, most failures first.
+    pub fn most_failing(&self, kind: ItemKind) -> Vec<&ItemStats> {
+        let mut failing: Vec<&ItemStats> = self.items_of(kind).filter(|i| i.failures > 0).collect();
+        failing.sort_by(|a, b| {
+            b.failures
+                .cmp(&a.failures)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        failing
+    }
+
+    /// One row per command, workflow and host.
+    pub fn summary_csv(&self) -> String {
+        let mut csv = String::from(
+            "kind,name,runs,failures,success_rate,p50_ms,p90_ms,p99_ms,duration_trend,last_run\n",
+        );
+        for item in &self.items {
+            let row = [
+                kind_name(item.item.kind).to_string(),
+                item.name.clone(),
+                item.runs.to_string(),
+                item.failures.to_string(),
+                optional(item.success_rate().map(|r| format!("{:.3}", r))),
+                optional(item.p50_ms),
+                optional(item.p90_ms),
+                optional(item.p99_ms),
+                optional(item.duration_trend.map(|t| format!("{:.3}", t))),
+                optional(item.last_run.map(|t| t.to_rfc3339())),
+            ];
+            csv.push_str(&csv_row(&row));
+        }
+        csv
+    }
+
+    /// One row per command, workflow and host and day.
+    pub fn daily_csv(&self) -> String {
+        let mut csv = String::from("kind,name,date,runs,failures,median_duration_ms\n");
+        for item in &self.items {
+            for day in &item.days {
+                let row = [
+                    kind_name(item.item.kind).to_string(),
+                    item.name.clone(),
+                    day.day.to_string(),
+                    day.runs.to_string(),
+                    day.failures.to_string(),
+                    optional(day.median_duration_ms),
+                ];
+                csv.push_str(&csv_row(&row));
+            }
+        }
+        csv
+    }
+}
+
+fn success_rate(runs: usize, failures: usize) -> Option<f64> {
+    (runs > 0).then(|| (runs - failures) as f64 / runs as f64)
+}
+
+fn kind_name(kind: ItemKind) -> &'static str {
+    match kind {
+        ItemKind::Command => "command",
+        ItemKind::Workflow => "workflow",
+        ItemKind::Host => "host",
+    }
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn csv_row(fields: &[String]) -> String {
+    let quoted: Vec<String> = fields
+        .iter()
+        .map(|f| {
+            if f.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", f.replace('"', "\"\""))
+            } else {
+                f.clone()
+            }
+        })
+        .collect();
+    format!("{}\n", quoted.join(","))
+}
+
+/// The `p`th percentile (0 to 100) of `sorted`, by nearest rank.
+pub fn percentile(sorted: &[u64], p: f64) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+fn median(runs: &[&RunRecord]) -> Option<u64> {
+    let mut durations: Vec<u64> = runs.iter().filter_map(|r| r.duration_ms).collect();
+    durations.sort_unstable();
+    percentile(&durations, 50.0)
+}
+
+/// The days from `since` to `until` in the time zone `tz`.
+fn days<Tz: TimeZone>(since: DateTime<Utc>, until: DateTime<Utc>, tz: &Tz) -> Vec<NaiveDate> {
+    let last = until.with_timezone(tz).date_naive();
+    let mut day = since.with_timezone(tz).date_naive();
+    let mut days = Vec::new();
+    while day <= last {
+        days.push(day);
+        day += Duration::days(1);
+    }
+    days
+}
+
+fn day_stats<Tz: TimeZone>(runs: &[&RunRecord], days: &[NaiveDate], tz: &Tz) -> Vec<DayStats> {
+    let mut by_day: BTreeMap<NaiveDate, Vec<&RunRecord>> =
+        days.iter().map(|d| (*d, Vec::new())).collect();
+    for run in runs {
+        if let Some(day) = by_day.get_mut(&run.started_at.with_timezone(tz).date_naive()) {
+            day.push(run);
+        }
+    }
+    by_day
+        .into_iter()
+        .map(|(day, runs)| DayStats {
+            day,
+            runs: runs.len(),
+            failures: runs.iter().filter(|r| !r.success).count(),
+            median_duration_ms: median(&runs),
+        })
+        .collect()
+}
+
+fn item_stats<Tz: TimeZone>(
+    item: SnapshotItem,
+    name: String,
+    runs: &[&RunRecord],
+    report: &StatsReport,
+    tz: &Tz,
+) -> ItemStats {
+    let mut durations: Vec<u64> = runs.iter().filter_map(|r| r.duration_ms).collect();
+    durations.sort_unstable();
+    let middle = report.since + (report.until - report.since) / 2;
+    let (earlier, later): (Vec<&RunRecord>, Vec<&RunRecord>) =
+        runs.iter().partition(|r| r.started_at < middle);
+    let duration_trend = match (median(&earlier), median(&later)) {
+        (Some(before), Some(after)) if before > 0 => Some(after as f64 / before as f64 - 1.0),
+        _ => None,
+    };
+    let days: Vec<NaiveDate> = report.days.iter().map(|d| d.day).collect();
+    ItemStats {
+        item,
+        name,
+        runs: runs.len(),
+        failures: runs.iter().filter(|r| !r.success).count(),
+        p50_ms: percentile(&durations, 50.0),
+        p90_ms: percentile(&durations, 90.0),
+        p99_ms: percentile(&durations, 99.0),
+        duration_trend,
+        last_run: runs.iter().map(|r| r.started_at).max(),
+        days: day_stats(runs, &days, tz),
+    }
+}
+
+/// Stats over `runs` started from `since` to `until`, with days in the
+/// time zone `tz`. Runs are given with what they count towards: their
+/// command and host, or their workflow.
+pub(crate) fn build<Tz: TimeZone>(
+    runs: &[(Vec<(SnapshotItem, String)>, RunRecord)],
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    tz: &Tz,
+) -> StatsReport {
+    let runs: Vec<&(Vec<(SnapshotItem, String)>, RunRecord)> = runs
+        .iter()
+        .filter(|(_, r)| r.started_at >= since && r.started_at <= until)
+        .collect();
+    let command_runs: Vec<&RunRecord> = runs
+        .iter()
+        .filter(|(items, _)| items.iter().any(|(item, _)| item.kind == ItemKind::Command))
+        .map(|(_, run)| run)
+        .collect();
+    let mut report = StatsReport {
+        since,
+        until,
+        days: day_stats(&command_runs, &days(since, until, tz), tz),
+        items: Vec::new(),
+    };
+
+    let mut by_item: BTreeMap<(ItemKind, uuid::Uuid), (String, Vec<&RunRecord>)> = BTreeMap::new();
+    for (items, run) in &runs {
+        for (item, name) in items {
+            by_item
+                .entry((item.kind, item.id))
+                .or_insert_with(|| (name.clone(), Vec::new()))
+                .1
+                .push(run);
+        }
+    }
+    let mut items: Vec<ItemStats> = by_item
+        .into_iter()
+        .map(|((kind, id), (name, runs))| {
+            item_stats(SnapshotItem { kind, id }, name, &runs, &report, tz)
+        })
+        .collect();
+    items.sort_by(|a, b| {
+        a.item
+            .kind
+            .cmp(&b.item.kind)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    report.items = items;
+    report
+}
@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod tests {
+    use crate::models::{ExecutionResult, WorkflowRun};
+    use crate::snapshots::{ItemKind, SnapshotItem};
+    use crate::stats::{RunRecord, build, percentile};
+    use crate::store::CommandStore;
+    use chrono::{Duration, FixedOffset, TimeZone, Utc};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_percentiles() {
+        let durations: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&durations, 50.0), Some(50));
+        assert_eq!(percentile(&durations, 90.0), Some(90));
+        assert_eq!(percentile(&durations, 99.0), Some(99));
+        assert_eq!(percentile(&[7], 99.0), Some(7));
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+
+    #[test]
+    fn test_build() {
+        let since = Utc.with_ymd_and_hms(2026, 10, 1, 0, 0, 0).unwrap();
+        let until = since + Duration::days(4) - Duration::seconds(1);
+        let backup = (
+            SnapshotItem {
+                kind: ItemKind::Command,
+                id: Uuid::new_v4(),
+            },
+            "Backup, nightly".to_string(),
+        );
+        let deploy = (
+            SnapshotItem {
+                kind: ItemKind::Command,
+                id: Uuid::new_v4(),
+            },
+            "Deploy".to_string(),
+        );
+        let run = |item: &(SnapshotItem, String), day: i64, duration_ms: u64, success: bool| {
+            let started_at = since + Duration::days(day) + Duration::hours(12);
+            (
+                vec![item.clone()],
+                RunRecord {
+                    started_at,
+                    duration_ms: Some(duration_ms),
+                    success,
+                },
+            )
+        };
+        let runs = vec![
+            run(&backup, 0, 1000, true),
+            run(&backup, 1, 1000, false),
+            run(&backup, 2, 1500, true),
+            run(&backup, 3, 1500, false),
+            run(&deploy, 3, 200, true),
+            // Outside the period
+            run(&deploy, 9, 200, false),
+        ];
+        let report = build(&runs, since, until, &FixedOffset::east_opt(0).unwrap());
+
+        assert_eq!(report.days.len(), 4);
+        assert_eq!(
+            report.days.iter().map(|d| d.runs).collect::<Vec<_>>(),
+            vec![1, 1, 1, 2]
+        );
+        assert_eq!(report.days[3].failures, 1);
+
+        let stats = &report.items[0];
+        assert_eq!(stats.name, "Backup, nightly");
+        assert_eq!((stats.runs, stats.failures), (4, 2));
+        assert_eq!(stats.success_rate(), Some(0.5));
+        assert_eq!((stats.p50_ms, stats.p99_ms), (Some(1000), Some(1500)));
+        assert_eq!(stats.duration_trend, Some(0.5));
+        assert_eq!(report.most_failing(ItemKind::Command).len(), 1);
+
+        let csv = report.summary_csv();
+        assert!(csv.starts_with("kind,name,runs,failures,success_rate,"));
+        assert!(csv.contains("\ncommand,\"Backup, nightly\",4,2,0.500,1000,1500,1500,0.500,"));
+        assert_eq!(report.daily_csv().lines().count(), 1 + 2 * 4);
+    }
+
+    #[test]
+    fn test_store_stats() {
+        let store = CommandStore::new_test();
+        let command_id = Uuid::new_v4();
+        for exit_code in [0, 0, 3] {
+            let mut result = ExecutionResult::started(Uuid::new_v4(), command_id, Uuid::nil());
+            result.finish(exit_code);
+            store.add_execution(&result, "");
+        }
+        // Still running: not counted
+        store.add_execution(
+            &ExecutionResult::started(Uuid::new_v4(), command_id, Uuid::nil()),
+            "",
+        );
+        let workflow_id = Uuid::new_v4();
+        store.add_workflow_run(WorkflowRun {
+            id: Uuid::new_v4(),
+            workflow_id,
+            started_at: Utc::now(),
+            duration_ms: 5000,
+            exit_code: 3,
+        });
+
+        let report = store.stats(Utc::now() - Duration::days(7));
+        let command = report.items_of(ItemKind::Command).next().unwrap();
+        assert_eq!((command.runs, command.failures), (3, 1));
+        let host = report.items_of(ItemKind::Host).next().unwrap();
+        assert_eq!(host.name, "(local or ad hoc)");
+        let workflow = report.items_of(ItemKind::Workflow).next().unwrap();
+        assert_eq!(
+            (workflow.runs, workflow.failures, workflow.p50_ms),
+            (1, 1, Some(5000))
+        );
+        assert_eq!(report.days.iter().map(|d| d.runs).sum::<usize>(), 3);
+        assert_eq!(store.list_workflow_runs(&workflow_id).len(), 1);
+    }
+}
@@ -1,12 +1,29 @@
-use crate::models::{Command, ExecutionResult, Host, Workflow};
+use crate::bundle::{self, BundleError};
+use crate::compare::{self, RunComparison, RunSummary};
+use crate::daemon::{DaemonClient, DaemonError, DaemonRequest, DaemonResponse};
+use crate::inventory::{HostImportAction, HostImportItem, plan_host_import};
+use crate::library::Library;
+use crate::merge::{self, MergePlan, MergeSource};
+use crate::models::{
+    Command, CommandRevision, EscalationPassword, ExecutionResult, Host, LibrarySource, RunAs,
+    Settings, Workflow, WorkflowRun,
+};
+use crate::search::{self, SearchResults, SearchState};
+use crate::secrets;
+use crate::snapshots::{self, ItemDiff, ItemKind, SnapshotInfo, SnapshotItem, SnapshotKind};
+use crate::stats::{self, RunRecord, StatsReport};
 use flate2::Compression;
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{Read, Write};
 use std::path::PathBuf;
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
 #[derive(Default, Serialize, Deserialize, Clone)]
@@ -16,55 +33,774 @@ struct StoreData {
     hosts: Vec<Host>,
     #[serde(default)]
     executions: Vec<ExecutionResult>,
+    #[serde(default)]
+    settings: Settings,
+    #[serde(default)]
+    revisions: Vec<CommandRevision>,
+    #[serde(default)]
+    workflow_runs: Vec<WorkflowRun>,
+}
+
+/// Saves of a command this close together update its latest revision
+/// instead of starting a new one.
+pub const REVISION_DEBOUNCE: chrono::Duration = chrono::Duration::seconds(60);
+/// Revisions kept per command; the oldest are dropped first.
+pub const MAX_REVISIONS: usize = 100;
+
+/// Move "run as" passwords that older stores kept in plain text into the
+/// keychain, leaving only a reference. One the keychain won't take is asked
+/// for at run time instead. Returns whether anything changed.
+fn secure_passwords<'a>(run_as: impl Iterator<Item = &'a mut Option<RunAs>>) -> bool {
+    // A command and its revisions share one entry
+    let mut saved: HashMap<String, Uuid> = HashMap::new();
+    let mut changed = false;
+    for run_as in run_as.flatten() {
+        let EscalationPassword::Entered(password) = &run_as.password else {
+            continue;
+        };
+        let id = match saved.get(password) {
+            Some(id) => Ok(*id),
+            None => {
+                let id = Uuid::new_v4();
+                secrets::save(&id, password).map(|_| id)
+            }
+        };
+        run_as.password = match id {
+            Ok(id) => {
+                saved.insert(password.clone(), id);
+                EscalationPassword::Saved(id)
+            }
+            Err(e) => {
+                eprintln!("Could not move a saved password to the keychain: {}", e);
+                EscalationPassword::Prompt
+            }
+        };
+        changed = true;
+    }
+    changed
+}
+
+impl StoreData {
+    fn latest_revision(&mut self, command_id: &Uuid) -> Option<&mut CommandRevision> {
+        self.revisions
+            .iter_mut()
+            .filter(|r| r.command_id == *command_id)
+            .max_by_key(|r| r.number)
+    }
+
+    /// Record `command` as saved just now, unless its latest revision
+    /// already has the same contents. `previous` is the version it
+    /// replaces, kept as the first revision of commands saved before
+    /// revisions were recorded. With `force`, a new revision is started
+    /// even within `REVISION_DEBOUNCE`.
+    fn record_revision(&mut self, previous: Option<&Command>, command: &Command, force: bool) {
+        let now = chrono::Utc::now();
+        if let Some(previous) = previous
+            && self.latest_revision(&command.id).is_none()
+        {
+            self.revisions.push(CommandRevision {
+                id: Uuid::new_v4(),
+                command_id: command.id,
+                number: 1,
+                saved_at: previous.created_at,
+                command: previous.clone(),
+                pinned: false,
+            });
+        }
+
+        let number = match self.latest_revision(&command.id) {
+            Some(latest) if latest.matches(command) => return,
+            Some(latest)
+                if !force && !latest.pinned && now - latest.saved_at < REVISION_DEBOUNCE =>
+            {
+                latest.command = command.clone();
+                latest.saved_at = now;
+                return;
+            }
+            Some(latest) => latest.number + 1,
+            None => 1,
+        };
+        self.revisions.push(CommandRevision {
+            id: Uuid::new_v4(),
+            command_id: command.id,
+            number,
+            saved_at: now,
+            command: command.clone(),
+            pinned: false,
+        });
+
+        // Revisions a run used stay, so its result can still show what ran
+        if let Some(oldest_kept) = number.checked_sub(MAX_REVISIONS as u32) {
+            self.revisions
+                .retain(|r| r.command_id != command.id || r.number > oldest_kept || r.pinned);
+        }
+    }
+
+    /// See `secure_passwords`.
+    fn secure_passwords(&mut self) -> bool {
+        let commands = self.commands.iter_mut().map(|c| &mut c.run_as);
+        let revisions = self.revisions.iter_mut().map(|r| &mut r.command.run_as);
+        let steps = self
+            .workflows
+            .iter_mut()
+            .flat_map(|w| w.step_run_as.iter_mut());
+        secure_passwords(commands.chain(revisions).chain(steps))
+    }
+
+    /// How `snapshot`'s commands, workflows and hosts differ from these.
+    fn diff(&self, snapshot: &StoreData) -> Vec<ItemDiff> {
+        let mut diffs = Vec::new();
+        snapshots::diff_items(
+            ItemKind::Command,
+            &snapshot.commands,
+            &self.commands,
+            |c| c.id,
+            |c| &c.name,
+            &mut diffs,
+        );
+        snapshots::diff_items(
+            ItemKind::Workflow,
+            &snapshot.workflows,
+            &self.workflows,
+            |w| w.id,
+            |w| &w.name,
+            &mut diffs,
+        );
+        snapshots::diff_items(
+            ItemKind::Host,
+            &snapshot.hosts,
+            &self.hosts,
+            |h| h.id,
+            |h| &h.name,
+            &mut diffs,
+        );
+        diffs
+    }
+
+    /// Put `items` back the way they are in `snapshot`: deleting the ones
+    /// it doesn't have. Restored commands get a revision if `revisions`.
+    fn restore(&mut self, snapshot: &StoreData, items: &[SnapshotItem], revisions: bool) {
+        for item in items {
+            let id = item.id;
+            match item.kind {
+                ItemKind::Command => {
+                    let previous = self
+                        .commands
+                        .iter()
+                        .position(|c| c.id == id)
+                        .map(|i| self.commands.remove(i));
+                    match snapshot.commands.iter().find(|c| c.id == id) {
+                        Some(cmd) => {
+                            if revisions {
+                                self.record_revision(previous.as_ref(), cmd, true);
+                            }
+                            self.commands.push(cmd.clone());
+                        }
+                        None => self.revisions.retain(|r| r.command_id != id),
+                    }
+                }
+                ItemKind::Workflow => {
+                    self.workflows.retain(|w| w.id != id);
+                    self.workflows
+                        .extend(snapshot.workflows.iter().find(|w| w.id == id).cloned());
+                }
+                ItemKind::Host => {
+                    self.hosts.retain(|h| h.id != id);
+                    match snapshot.hosts.iter().find(|h| h.id == id) {
+                        Some(host) => self.hosts.push(host.clone()),
+                        None => {
+                            for cmd in &mut self.commands {
+                                cmd.target_hosts.retain(|h| *h != id);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A change to the store. A store connected to the daemon sends these
+/// instead of writing `store.json` itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum StoreChange {
+    CommandSaved(Command),
+    CommandRemoved(Uuid),
+    HostSaved(Host),
+    HostRemoved(Uuid),
+    HostsImported(Vec<HostImportItem>),
+    WorkflowSaved(Workflow),
+    WorkflowRemoved(Uuid),
+    RevisionRestored {
+        command_id: Uuid,
+        revision_id: Uuid,
+    },
+    RevisionPinned(Uuid),
+    /// The log itself is already in the shared executions directory.
+    ExecutionRecorded(ExecutionResult),
+    WorkflowRunRecorded(WorkflowRun),
+    /// A whole store, as exported by `export_json`.
+    Imported(String),
+    Merged(Box<MergePlan>),
+    /// `None` restores everything.
+    SnapshotRestored {
+        hash: String,
+        items: Option<Vec<SnapshotItem>>,
+    },
+    SettingsSaved(Settings),
+}
+
+/// How often a store connected to the daemon picks up changes made by
+/// other clients (see `CommandStore::refresh`).
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(2);
+/// Changes an owned store remembers for connected stores to catch up with;
+/// one further behind is sent the whole store.
+const MAX_JOURNAL: usize = 256;
+
+/// How far a connected store has caught up with the store that owns it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncPoint {
+    /// New each time the owner starts, as its versions start over.
+    pub generation: Uuid,
+    pub version: u64,
+}
+
+/// What a change is looked up by when catching up: its item's id.
+trait Keyed {
+    fn key(&self) -> Uuid;
+}
+
+macro_rules! keyed {
+    ($($item:ty),*) => {
+        $(impl Keyed for $item {
+            fn key(&self) -> Uuid {
+                self.id
+            }
+        })*
+    };
+}
+
+keyed!(Command, Workflow, Host, ExecutionResult, WorkflowRun);
+
+/// Revisions go by command: a change replaces all of a command's revisions.
+impl Keyed for CommandRevision {
+    fn key(&self) -> Uuid {
+        self.command_id
+    }
+}
+
+/// Which items of one collection changed.
+#[derive(Debug, Clone)]
+enum Keys {
+    All,
+    Some(HashSet<Uuid>),
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Keys::Some(HashSet::new())
+    }
+}
+
+impl Keys {
+    fn one(key: Uuid) -> Self {
+        Keys::Some(HashSet::from([key]))
+    }
+
+    fn add(&mut self, other: &Keys) {
+        match (&mut *self, other) {
+            (Keys::All, _) => {}
+            (_, Keys::All) => *self = Keys::All,
+            (Keys::Some(keys), Keys::Some(more)) => keys.extend(more),
+        }
+    }
+}
+
+/// The parts of the store one change touched.
+#[derive(Debug, Clone, Default)]
+struct Touched {
+    commands: Keys,
+    workflows: Keys,
+    hosts: Keys,
+    executions: Keys,
+    revisions: Keys,
+    workflow_runs: Keys,
+    settings: bool,
+}
+
+impl Touched {
+    fn everything() -> Self {
+        Touched {
+            commands: Keys::All,
+            workflows: Keys::All,
+            hosts: Keys::All,
+            executions: Keys::All,
+            revisions: Keys::All,
+            workflow_runs: Keys::All,
+            settings: true,
+        }
+    }
+
+    fn of(change: &StoreChange) -> Self {
+        match change {
+            StoreChange::CommandSaved(cmd) => Touched::command(cmd.id),
+            StoreChange::CommandRemoved(id) => Touched::command(*id),
+            StoreChange::HostSaved(host) => Touched {
+                hosts: Keys::one(host.id),
+                ..Default::default()
+            },
+            // Commands stop targeting the host too
+            StoreChange::HostRemoved(id) => Touched {
+                hosts: Keys::one(*id),
+                commands: Keys::All,
+                ..Default::default()
+            },
+            StoreChange::HostsImported(items) => Touched {
+                hosts: Keys::Some(items.iter().map(|i| i.host.id).collect()),
+                ..Default::default()
+            },
+            StoreChange::WorkflowSaved(workflow) => Touched {
+                workflows: Keys::one(workflow.id),
+                ..Default::default()
+            },
+            StoreChange::WorkflowRemoved(id) => Touched {
+                workflows: Keys::one(*id),
+                ..Default::default()
+            },
+            StoreChange::RevisionRestored { command_id, .. } => Touched::command(*command_id),
+            StoreChange::RevisionPinned(command_id) => Touched {
+                revisions: Keys::one(*command_id),
+                ..Default::default()
+            },
+            StoreChange::ExecutionRecorded(result) => Touched {
+                executions: Keys::one(result.id),
+                ..Default::default()
+            },
+            StoreChange::WorkflowRunRecorded(run) => Touched {
+                workflow_runs: Keys::one(run.id),
+                ..Default::default()
+            },
+            StoreChange::Imported(_)
+            | StoreChange::Merged(_)
+            | StoreChange::SnapshotRestored { .. } => Touched::everything(),
+            StoreChange::SettingsSaved(_) => Touched {
+                settings: true,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// A command and its revisions.
+    fn command(id: Uuid) -> Self {
+        Touched {
+            commands: Keys::one(id),
+            revisions: Keys::one(id),
+            ..Default::default()
+        }
+    }
+
+    fn add(&mut self, other: &Touched) {
+        self.commands.add(&other.commands);
+        self.workflows.add(&other.workflows);
+        self.hosts.add(&other.hosts);
+        self.executions.add(&other.executions);
+        self.revisions.add(&other.revisions);
+        self.workflow_runs.add(&other.workflow_runs);
+        self.settings |= other.settings;
+    }
+}
+
+/// The items of one collection under some keys, as they are now: a
+/// connected store replaces its own items under those keys with them. No
+/// keys stands for the whole collection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Changed<T> {
+    keys: Option<Vec<Uuid>>,
+    items: Vec<T>,
+}
+
+impl<T: Keyed + Clone> Changed<T> {
+    fn collect(keys: &Keys, items: &[T]) -> Self {
+        match keys {
+            Keys::All => Changed {
+                keys: None,
+                items: items.to_vec(),
+            },
+            Keys::Some(keys) => Changed {
+                keys: Some(keys.iter().copied().collect()),
+                items: items
+                    .iter()
+                    .filter(|i| keys.contains(&i.key()))
+                    .cloned()
+                    .collect(),
+            },
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.keys.as_ref().is_some_and(Vec::is_empty)
+    }
+
+    fn apply_to(self, items: &mut Vec<T>) {
+        match self.keys {
+            None => *items = self.items,
+            Some(keys) => {
+                let keys: HashSet<Uuid> = keys.into_iter().collect();
+                items.retain(|i| !keys.contains(&i.key()));
+                items.extend(self.items);
+            }
+        }
+    }
+}
+
+/// What changed in an owned store since a connected store last caught up:
+/// what it's sent instead of the whole store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreDelta {
+    commands: Changed<Command>,
+    workflows: Changed<Workflow>,
+    hosts: Changed<Host>,
+    executions: Changed<ExecutionResult>,
+    revisions: Changed<CommandRevision>,
+    workflow_runs: Changed<WorkflowRun>,
+    settings: Option<Settings>,
+}
+
+impl StoreDelta {
+    fn collect(touched: &Touched, data: &StoreData) -> Self {
+        StoreDelta {
+            commands: Changed::collect(&touched.commands, &data.commands),
+            workflows: Changed::collect(&touched.workflows, &data.workflows),
+            hosts: Changed::collect(&touched.hosts, &data.hosts),
+            executions: Changed::collect(&touched.executions, &data.executions),
+            revisions: Changed::collect(&touched.revisions, &data.revisions),
+            workflow_runs: Changed::collect(&touched.workflow_runs, &data.workflow_runs),
+            settings: touched.settings.then(|| data.settings.clone()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+            && self.workflows.is_empty()
+            && self.hosts.is_empty()
+            && self.executions.is_empty()
+            && self.revisions.is_empty()
+            && self.workflow_runs.is_empty()
+            && self.settings.is_none()
+    }
+
+    fn apply_to(self, data: &mut StoreData) {
+        self.commands.apply_to(&mut data.commands);
+        self.workflows.apply_to(&mut data.workflows);
+        self.hosts.apply_to(&mut data.hosts);
+        self.executions.apply_to(&mut data.executions);
+        self.revisions.apply_to(&mut data.revisions);
+        self.workflow_runs.apply_to(&mut data.workflow_runs);
+        if let Some(settings) = self.settings {
+            data.settings = settings;
+        }
+    }
+}
+
+/// What an owned store's recent changes touched, for connected stores to
+/// catch up with.
+struct Journal {
+    point: SyncPoint,
+    /// The version the first entry of `touched` starts from.
+    oldest: u64,
+    /// One entry per version since `oldest`, oldest first.
+    touched: VecDeque<Touched>,
+}
+
+impl Journal {
+    fn new() -> Self {
+        Journal {
+            point: SyncPoint {
+                generation: Uuid::new_v4(),
+                version: 0,
+            },
+            oldest: 0,
+            touched: VecDeque::new(),
+        }
+    }
+
+    fn record(&mut self, touched: Touched) {
+        self.point.version += 1;
+        self.touched.push_back(touched);
+        if self.touched.len() > MAX_JOURNAL {
+            self.touched.pop_front();
+            self.oldest += 1;
+        }
+    }
+
+    /// What changed since `since`; `None` when that's no longer known.
+    fn since(&self, since: SyncPoint) -> Option<Touched> {
+        if since.generation != self.point.generation
+            || since.version < self.oldest
+            || since.version > self.point.version
+        {
+            return None;
+        }
+        let mut all = Touched::default();
+        for touched in self
+            .touched
+            .iter()
+            .skip((since.version - self.oldest) as usize)
+        {
+            all.add(touched);
+        }
+        Some(all)
+    }
+}
+
+/// What a connected store's link thread is asked to do.
+enum LinkMessage {
+    /// Send a change to the daemon.
+    Change(Box<StoreChange>),
+    /// Pick up changes now, answering once done.
+    Sync(Sender<Result<(), DaemonError>>),
+}
+
+/// A connected store's way to the daemon: a thread that sends its changes
+/// in order and picks up other clients' changes, off the caller's thread.
+#[derive(Clone)]
+struct DaemonLink {
+    tx: Sender<LinkMessage>,
+    /// Bumped each time changes from the daemon are picked up.
+    syncs: Arc<AtomicU64>,
+}
+
+impl DaemonLink {
+    fn start(client: DaemonClient, data: Arc<RwLock<StoreData>>, point: SyncPoint) -> Self {
+        let (tx, rx) = channel();
+        let syncs = Arc::new(AtomicU64::new(0));
+        let link = DaemonLink {
+            tx,
+            syncs: syncs.clone(),
+        };
+        std::thread::spawn(move || run_link(client, data, point, syncs, rx));
+        link
+    }
+}
+
+/// The link thread: ends once every store holding the link is gone.
+fn run_link(
+    client: DaemonClient,
+    data: Arc<RwLock<StoreData>>,
+    mut point: SyncPoint,
+    syncs: Arc<AtomicU64>,
+    rx: Receiver<LinkMessage>,
+) {
+    let mut last_sync = Instant::now();
+    loop {
+        let reply = match rx.recv_timeout(SYNC_INTERVAL.saturating_sub(last_sync.elapsed())) {
+            Ok(LinkMessage::Change(change)) => {
+                if let Err(e) = client.request(&DaemonRequest::Store(change)) {
+                    eprintln!("Critical Error: Failed to save through the daemon: {}", e);
+                }
+                // Keep up with other clients while changes keep coming
+                if last_sync.elapsed() < SYNC_INTERVAL {
+                    continue;
+                }
+                None
+            }
+            Ok(LinkMessage::Sync(reply)) => Some(reply),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        last_sync = Instant::now();
+        let result = pull_changes(&client, &data, &mut point, &syncs);
+        match reply {
+            Some(reply) => {
+                let _ = reply.send(result);
+            }
+            None => {
+                if let Err(e) = result {
+                    eprintln!("Failed to pick up changes from switchboardd: {}", e);
+                }
+            }
+        }
+    }
+}
+
+fn pull_changes(
+    client: &DaemonClient,
+    data: &RwLock<StoreData>,
+    point: &mut SyncPoint,
+    syncs: &AtomicU64,
+) -> Result<(), DaemonError> {
+    match client.request(&DaemonRequest::Changes { since: *point })? {
+        DaemonResponse::Changes { point: now, delta } => {
+            *point = now;
+            if delta.is_empty() {
+                return Ok(());
+            }
+            delta.apply_to(&mut data.write().unwrap());
+        }
+        // Too far behind, or the daemon restarted
+        DaemonResponse::Store {
+            json, point: now, ..
+        } => {
+            *data.write().unwrap() = serde_json::from_str(&json)?;
+            *point = now;
+        }
+        other => return Err(DaemonError::Unexpected(Box::new(other))),
+    }
+    syncs.fetch_add(1, Ordering::Relaxed);
+    Ok(())
 }
 
 #[derive(Clone)]
 pub struct CommandStore {
     path: PathBuf,
     data: Arc<RwLock<StoreData>>,
+    /// Set when `switchboardd` owns the store; see `connect`.
+    daemon: Option<DaemonLink>,
+    /// Changes made to an owned store; see `changes_since`.
+    journal: Arc<Mutex<Journal>>,
+    /// Built by the first `search`.
+    search_state: Arc<Mutex<Option<SearchState>>>,
+    /// `Settings::libraries` as loaded; see `reload_libraries`.
+    libraries: Arc<RwLock<Vec<Library>>>,
 }
 
-impl CommandStore {
-    pub fn new() -> Self {
-        use directories::ProjectDirs;
+/// Where the store and everything next to it (logs, artifacts, the
+/// daemon's socket) live.
+pub fn default_data_dir() -> PathBuf {
+    use directories::ProjectDirs;
 
-        // Get platform-specific data directory
-        let db_path = if let Some(proj_dirs) = ProjectDirs::from("io", "nickw", "switchboard") {
-            let data_dir = proj_dirs.data_dir();
+    // Get platform-specific data directory
+    if let Some(proj_dirs) = ProjectDirs::from("io", "nickw", "switchboard") {
+        let data_dir = proj_dirs.data_dir();
 
-            // Create directory if it doesn't exist
-            if let Err(e) = std::fs::create_dir_all(data_dir) {
-                eprintln!("Warning: Failed to create data directory: {}", e);
-                eprintln!("Falling back to current directory");
-                std::path::PathBuf::from("store.json")
-            } else {
-                data_dir.join("store.json")
-            }
-        } else {
-            eprintln!("Warning: Could not determine data directory");
+        // Create directory if it doesn't exist
+        if let Err(e) = std::fs::create_dir_all(data_dir) {
+            eprintln!("Warning: Failed to create data directory: {}", e);
             eprintln!("Falling back to current directory");
-            std::path::PathBuf::from("store.json")
-        };
+            PathBuf::from(".")
+        } else {
+            data_dir.to_path_buf()
+        }
+    } else {
+        eprintln!("Warning: Could not determine data directory");
+        eprintln!("Falling back to current directory");
+        PathBuf::from(".")
+    }
+}
 
+impl Default for CommandStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandStore {
+    pub fn new() -> Self {
+        let db_path = default_data_dir().join("store.json");
         println!("Using database at: {}", db_path.display());
 
         let store = Self {
             path: db_path,
             data: Arc::new(RwLock::new(StoreData::default())),
+            daemon: None,
+            journal: Arc::new(Mutex::new(Journal::new())),
+            search_state: Arc::default(),
+            libraries: Arc::default(),
         };
 
         store.load();
+        store.reload_libraries();
         store
     }
 
+    /// A store owned by the daemon at the other end of `daemon`: reads come
+    /// from a copy kept up to date in the background (see `refresh`), and
+    /// changes are sent to the daemon in the background, in order. Logs and
+    /// artifacts are read from the daemon's data directory directly.
+    pub fn connect(daemon: DaemonClient) -> Result<Self, DaemonError> {
+        let (path, json, point) = match daemon.request(&DaemonRequest::LoadStore)? {
+            DaemonResponse::Store { path, json, point } => (path, json, point),
+            other => return Err(DaemonError::Unexpected(Box::new(other))),
+        };
+        let data = Arc::new(RwLock::new(serde_json::from_str(&json)?));
+        let store = Self {
+            path,
+            daemon: Some(DaemonLink::start(daemon, data.clone(), point)),
+            data,
+            journal: Arc::new(Mutex::new(Journal::new())),
+            search_state: Arc::default(),
+            libraries: Arc::default(),
+        };
+        store.reload_libraries();
+        Ok(store)
+    }
+
+    pub(crate) fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Whether the daemon owns this store (and records runs itself).
+    pub fn uses_daemon(&self) -> bool {
+        self.daemon.is_some()
+    }
+
+    /// Send the changes made so far to the daemon and pick up those other
+    /// clients made, waiting for both. Connected stores also pick up
+    /// changes by themselves every `SYNC_INTERVAL`. Does nothing for a
+    /// store that isn't connected to the daemon.
+    pub fn refresh(&self) -> Result<(), DaemonError> {
+        let Some(link) = &self.daemon else {
+            return Ok(());
+        };
+        let (reply_tx, reply_rx) = channel();
+        link.tx
+            .send(LinkMessage::Sync(reply_tx))
+            .map_err(|_| DaemonError::Closed)?;
+        reply_rx.recv().map_err(|_| DaemonError::Closed)?
+    }
+
+    /// How many times changes from the daemon have been picked up; compare
+    /// with an earlier count to notice them. Always 0 for an owned store.
+    pub fn sync_count(&self) -> u64 {
+        self.daemon
+            .as_ref()
+            .map_or(0, |link| link.syncs.load(Ordering::Relaxed))
+    }
+
+    /// The whole store, as JSON, for a store connecting to this one, and
+    /// the point it's at.
+    pub(crate) fn load_for_client(&self) -> anyhow::Result<(String, SyncPoint)> {
+        let point = self.journal.lock().unwrap().point;
+        Ok((serde_json::to_string(&*self.data.read().unwrap())?, point))
+    }
+
+    /// The changes since `since`, for a connected store to catch up with,
+    /// and the point they lead to. `None` when the journal no longer goes
+    /// back that far and the whole store is needed.
+    pub(crate) fn changes_since(&self, since: SyncPoint) -> (Option<StoreDelta>, SyncPoint) {
+        let (touched, point) = {
+            let journal = self.journal.lock().unwrap();
+            (journal.since(since), journal.point)
+        };
+        // Items changed after `point` may be sent twice, never missed
+        let delta =
+            touched.map(|touched| StoreDelta::collect(&touched, &self.data.read().unwrap()));
+        (delta, point)
+    }
+
     pub fn new_test() -> Self {
-        // Use a temporary file
-        let mut path = std::env::temp_dir();
-        path.push(format!("switchboard_test_{}.json", Uuid::new_v4()));
+        // Use a temporary directory of its own, so snapshots aren't shared
+        let dir = std::env::temp_dir().join(format!("switchboard_test_{}", Uuid::new_v4()));
+        let _ = std::fs::create_dir_all(&dir);
+        let path = dir.join("store.json");
 
         Self {
             path,
             data: Arc::new(RwLock::new(StoreData::default())),
+            daemon: None,
+            journal: Arc::new(Mutex::new(Journal::new())),
+            search_state: Arc::default(),
+            libraries: Arc::default(),
         }
     }
 
@@ -73,8 +809,12 @@ impl CommandStore {
             match std::fs::read_to_string(&self.path) {
                 Ok(content) => {
                     match serde_json::from_str::<StoreData>(&content) {
-                        Ok(data) => {
+                        Ok(mut data) => {
+                            let secured = data.secure_passwords();
                             *self.data.write().unwrap() = data;
+                            if secured {
+                                self.save();
+                            }
                         }
                         Err(e) => {
                             eprintln!("Failed to parse store.json: {}", e);
@@ -89,6 +829,62 @@ impl CommandStore {
         }
     }
 
+    /// Persist `change`: write `store.json`, or have it sent to the daemon.
+    fn commit(&self, change: StoreChange) {
+        match &self.daemon {
+            None => {
+                self.save();
+                self.journal.lock().unwrap().record(Touched::of(&change));
+            }
+            Some(link) => {
+                if link.tx.send(LinkMessage::Change(Box::new(change))).is_err() {
+                    eprintln!(
+                        "Critical Error: Failed to save through the daemon: the link is gone"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Apply a change sent by a connected store.
+    pub(crate) fn apply(&self, change: StoreChange) -> anyhow::Result<()> {
+        match change {
+            StoreChange::CommandSaved(cmd) => {
+                self.add_command(cmd);
+            }
+            StoreChange::CommandRemoved(id) => self.remove_command(&id),
+            StoreChange::HostSaved(host) => {
+                self.add_host(host);
+            }
+            StoreChange::HostRemoved(id) => self.remove_host(&id),
+            StoreChange::HostsImported(items) => {
+                self.apply_host_import(&items);
+            }
+            StoreChange::WorkflowSaved(workflow) => {
+                self.add_workflow(workflow);
+            }
+            StoreChange::WorkflowRemoved(id) => self.remove_workflow(&id),
+            StoreChange::RevisionRestored {
+                command_id,
+                revision_id,
+            } => {
+                self.restore_revision(&command_id, &revision_id);
+            }
+            StoreChange::RevisionPinned(command_id) => {
+                self.pin_revision(&command_id);
+            }
+            StoreChange::ExecutionRecorded(result) => self.record_execution(result),
+            StoreChange::WorkflowRunRecorded(run) => self.add_workflow_run(run),
+            StoreChange::Imported(json) => self.import_json(&json)?,
+            StoreChange::Merged(plan) => {
+                self.apply_merge(&plan);
+            }
+            StoreChange::SnapshotRestored { hash, items } => self.restore_snapshot(&hash, items)?,
+            StoreChange::SettingsSaved(settings) => self.set_settings(settings),
+        }
+        Ok(())
+    }
+
     fn save(&self) {
         println!("Saving store to: {}", self.path.display());
         let data = self.data.read().unwrap();
@@ -109,10 +905,10 @@ impl CommandStore {
                     std::fs::rename(&temp_path, &self.path)?;
 
                     // 4. Sync parent directory to ensure entry is persisted
-                    if let Some(parent) = self.path.parent() {
-                        if let Ok(dir) = std::fs::File::open(parent) {
-                            let _ = dir.sync_all();
-                        }
+                    if let Some(parent) = self.path.parent()
+                        && let Ok(dir) = std::fs::File::open(parent)
+                    {
+                        let _ = dir.sync_all();
                     }
                     Ok(())
                 })();
@@ -131,23 +927,46 @@ impl CommandStore {
 
     // --- Command Methods ---
 
+    /// Save `cmd`, recording a revision (see `CommandRevision`). Revisions
+    /// are recorded by whoever owns the store: a store connected to the
+    /// daemon picks them up with `refresh`.
     pub fn add_command(&self, cmd: Command) -> Uuid {
         let id = cmd.id;
         {
             let mut data = self.data.write().unwrap();
             // Upsert: Remove existing if present
-            data.commands.retain(|c| c.id != id);
-            data.commands.push(cmd);
+            let previous = data
+                .commands
+                .iter()
+                .position(|c| c.id == id)
+                .map(|i| data.commands.remove(i));
+            if self.daemon.is_none() {
+                data.record_revision(previous.as_ref(), &cmd, false);
+            }
+            data.commands.push(cmd.clone());
         }
-        self.save();
+        self.commit(StoreChange::CommandSaved(cmd));
         id
     }
 
+    /// The command with this id, in the store or else in a library.
     pub fn get_command(&self, id: &Uuid) -> Option<Command> {
         let data = self.data.read().unwrap();
-        data.commands.iter().find(|c| c.id == *id).cloned()
+        data.commands
+            .iter()
+            .find(|c| c.id == *id)
+            .cloned()
+            .or_else(|| {
+                let libraries = self.libraries.read().unwrap();
+                libraries
+                    .iter()
+                    .flat_map(|l| &l.commands)
+                    .find(|c| c.id == *id)
+                    .cloned()
+            })
     }
 
+    /// The store's own commands; see `list_libraries` for the rest.
     pub fn list_commands(&self) -> Vec<Command> {
         let data = self.data.read().unwrap();
         data.commands.clone()
@@ -157,8 +976,75 @@ impl CommandStore {
         {
             let mut data = self.data.write().unwrap();
             data.commands.retain(|c| c.id != *id);
+            data.revisions.retain(|r| r.command_id != *id);
         }
-        self.save();
+        self.commit(StoreChange::CommandRemoved(*id));
+    }
+
+    /// A command's revisions, newest first.
+    pub fn list_revisions(&self, command_id: &Uuid) -> Vec<CommandRevision> {
+        let data = self.data.read().unwrap();
+        let mut revisions: Vec<CommandRevision> = data
+            .revisions
+            .iter()
+            .filter(|r| r.command_id == *command_id)
+            .cloned()
+            .collect();
+        revisions.sort_by_key(|r| std::cmp::Reverse(r.number));
+        revisions
+    }
+
+    pub fn get_revision(&self, revision_id: &Uuid) -> Option<CommandRevision> {
+        let data = self.data.read().unwrap();
+        data.revisions
+            .iter()
+            .find(|r| r.id == *revision_id)
+            .cloned()
+    }
+
+    /// Put a command back the way it was at `revision_id`. The restored
+    /// version becomes a new revision, so the one it replaces is kept.
+    pub fn restore_revision(&self, command_id: &Uuid, revision_id: &Uuid) -> Option<Command> {
+        let restored = {
+            let mut data = self.data.write().unwrap();
+            let revision = data
+                .revisions
+                .iter()
+                .find(|r| r.id == *revision_id && r.command_id == *command_id)?
+                .clone();
+            let index = data.commands.iter().position(|c| c.id == *command_id)?;
+            let previous = data.commands.remove(index);
+            let mut restored = revision.command;
+            restored.source_path = previous.source_path.clone();
+            if self.daemon.is_none() {
+                data.record_revision(Some(&previous), &restored, true);
+            }
+            data.commands.push(restored.clone());
+            restored
+        };
+        self.commit(StoreChange::RevisionRestored {
+            command_id: *command_id,
+            revision_id: *revision_id,
+        });
+        Some(restored)
+    }
+
+    /// The revision a run of the command is about to use, kept as is from
+    /// now on. Call it when the run starts, from the store that records
+    /// the run.
+    pub fn pin_revision(&self, command_id: &Uuid) -> Option<Uuid> {
+        let revision_id = {
+            let mut data = self.data.write().unwrap();
+            let command = data.commands.iter().find(|c| c.id == *command_id)?.clone();
+            if self.daemon.is_none() {
+                data.record_revision(None, &command, false);
+            }
+            let latest = data.latest_revision(command_id)?;
+            latest.pinned = true;
+            latest.id
+        };
+        self.commit(StoreChange::RevisionPinned(*command_id));
+        Some(revision_id)
     }
 
     // --- Host Methods ---
@@ -168,9 +1054,9 @@ impl CommandStore {
         {
             let mut data = self.data.write().unwrap();
             data.hosts.retain(|h| h.id != id);
-            data.hosts.push(host);
+            data.hosts.push(host.clone());
         }
-        self.save();
+        self.commit(StoreChange::HostSaved(host));
         id
     }
 
@@ -184,6 +1070,59 @@ impl CommandStore {
         data.hosts.clone()
     }
 
+    /// Delete a host and drop it from every command's explicit targets.
+    pub fn remove_host(&self, id: &Uuid) {
+        {
+            let mut data = self.data.write().unwrap();
+            data.hosts.retain(|h| h.id != *id);
+            for cmd in &mut data.commands {
+                cmd.target_hosts.retain(|h| h != id);
+            }
+        }
+        self.commit(StoreChange::HostRemoved(*id));
+    }
+
+    /// Preview importing `hosts` (e.g. from an Ansible inventory); see
+    /// `inventory::plan_host_import`.
+    pub fn preview_host_import(&self, hosts: Vec<Host>, resync: bool) -> Vec<HostImportItem> {
+        plan_host_import(&self.list_hosts(), hosts, resync)
+    }
+
+    /// Apply a previewed import. Returns the number of hosts written.
+    pub fn apply_host_import(&self, items: &[HostImportItem]) -> usize {
+        self.snapshot_before_import();
+        let mut written = 0;
+        {
+            let mut data = self.data.write().unwrap();
+            for item in items {
+                match item.action {
+                    HostImportAction::Create | HostImportAction::Update => {
+                        data.hosts.retain(|h| h.id != item.host.id);
+                        data.hosts.push(item.host.clone());
+                        written += 1;
+                    }
+                    HostImportAction::Unchanged | HostImportAction::Skip => {}
+                }
+            }
+        }
+        self.commit(StoreChange::HostsImported(items.to_vec()));
+        written
+    }
+
+    /// The inventory hosts a command should run on: its explicit targets
+    /// plus every host matching its selector, sorted by name.
+    pub fn resolve_targets(&self, cmd: &Command) -> Vec<Host> {
+        let data = self.data.read().unwrap();
+        let mut hosts: Vec<Host> = data
+            .hosts
+            .iter()
+            .filter(|h| cmd.target_hosts.contains(&h.id) || h.matches_selector(&cmd.host_selector))
+            .cloned()
+            .collect();
+        hosts.sort_by(|a, b| a.name.cmp(&b.name));
+        hosts
+    }
+
     // --- Workflow Methods ---
 
     pub fn add_workflow(&self, workflow: Workflow) -> Uuid {
@@ -191,15 +1130,27 @@ impl CommandStore {
         {
             let mut data = self.data.write().unwrap();
             data.workflows.retain(|w| w.id != id);
-            data.workflows.push(workflow);
+            data.workflows.push(workflow.clone());
         }
-        self.save();
+        self.commit(StoreChange::WorkflowSaved(workflow));
         id
     }
 
+    /// The workflow with this id, in the store or else in a library.
     pub fn get_workflow(&self, id: &Uuid) -> Option<Workflow> {
         let data = self.data.read().unwrap();
-        data.workflows.iter().find(|w| w.id == *id).cloned()
+        data.workflows
+            .iter()
+            .find(|w| w.id == *id)
+            .cloned()
+            .or_else(|| {
+                let libraries = self.libraries.read().unwrap();
+                libraries
+                    .iter()
+                    .flat_map(|l| &l.workflows)
+                    .find(|w| w.id == *id)
+                    .cloned()
+            })
     }
 
     pub fn list_workflows(&self) -> Vec<Workflow> {
@@ -212,7 +1163,7 @@ impl CommandStore {
             let mut data = self.data.write().unwrap();
             data.workflows.retain(|w| w.id != *id);
         }
-        self.save();
+        self.commit(StoreChange::WorkflowRemoved(*id));
     }
 
     pub fn is_command_in_workflow(&self, cmd_id: &Uuid) -> bool {
@@ -224,6 +1175,15 @@ impl CommandStore {
         self.path.parent().expect("store path has no parent").join("executions")
     }
 
+    /// Directory holding the files fetched from the host for one execution.
+    pub fn artifacts_dir(&self, exec_id: &Uuid) -> PathBuf {
+        self.path
+            .parent()
+            .expect("store path has no parent")
+            .join("artifacts")
+            .join(exec_id.to_string())
+    }
+
     // --- Execution Methods ---
 
     pub fn add_execution(&self, result: &ExecutionResult, output: &str) {
@@ -243,13 +1203,87 @@ impl CommandStore {
                 eprintln!("Warning: Failed to write execution log {}: {}", gz_path.display(), e);
             }
         }
+        if let Some(state) = self.search_state.lock().unwrap().as_mut() {
+            state.index.add(result, output);
+        }
+
+        self.record_execution(result.clone());
+    }
 
+    fn record_execution(&self, result: ExecutionResult) {
         {
             let mut data = self.data.write().unwrap();
             data.executions.retain(|e| e.id != result.id);
             data.executions.push(result.clone());
         }
-        self.save();
+        self.commit(StoreChange::ExecutionRecorded(result));
+    }
+
+    /// Record a finished workflow run (its steps are recorded by
+    /// `add_execution`).
+    pub fn add_workflow_run(&self, run: WorkflowRun) {
+        {
+            let mut data = self.data.write().unwrap();
+            data.workflow_runs.retain(|r| r.id != run.id);
+            data.workflow_runs.push(run.clone());
+        }
+        self.commit(StoreChange::WorkflowRunRecorded(run));
+    }
+
+    pub fn list_workflow_runs(&self, workflow_id: &Uuid) -> Vec<WorkflowRun> {
+        let data = self.data.read().unwrap();
+        data.workflow_runs
+            .iter()
+            .filter(|r| r.workflow_id == *workflow_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Save a fetched artifact under the execution's artifacts directory.
+    /// `name` is a relative path and may not escape that directory.
+    pub fn add_artifact(&self, exec_id: &Uuid, name: &str, data: &[u8]) -> anyhow::Result<PathBuf> {
+        let relative = std::path::Path::new(name);
+        if name.is_empty()
+            || !relative
+                .components()
+                .all(|c| matches!(c, std::path::Component::Normal(_)))
+        {
+            anyhow::bail!("invalid artifact name: {:?}", name);
+        }
+
+        let path = self.artifacts_dir(exec_id).join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, data)?;
+        Ok(path)
+    }
+
+    /// Relative names of an execution's artifacts, sorted.
+    pub fn list_artifacts(&self, exec_id: &Uuid) -> Vec<String> {
+        fn walk(dir: &std::path::Path, prefix: &str, out: &mut Vec<String>) {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                return;
+            };
+            for entry in entries.flatten() {
+                let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+                if entry.path().is_dir() {
+                    walk(&entry.path(), &format!("{}/", name), out);
+                } else {
+                    out.push(name);
+                }
+            }
+        }
+
+        let mut names = Vec::new();
+        walk(&self.artifacts_dir(exec_id), "", &mut names);
+        names.sort();
+        names
+    }
+
+    pub fn get_execution(&self, exec_id: &Uuid) -> Option<ExecutionResult> {
+        let data = self.data.read().unwrap();
+        data.executions.iter().find(|e| e.id == *exec_id).cloned()
     }
 
     pub fn get_execution_history(&self, cmd_id: &Uuid) -> Vec<ExecutionResult> {
@@ -285,6 +1319,237 @@ impl CommandStore {
         }
     }
 
+    /// Compare two recorded runs (see `compare`), masking their logs with
+    /// the enabled `Settings::compare` normalizers when `normalize` is set.
+    pub fn compare_executions(
+        &self,
+        old_id: &Uuid,
+        new_id: &Uuid,
+        normalize: bool,
+    ) -> Option<RunComparison> {
+        let summary = |id: &Uuid| -> Option<(RunSummary, Option<String>)> {
+            let result = self.get_execution(id)?;
+            let summary = RunSummary {
+                command: self
+                    .get_command(&result.command_id)
+                    .map(|c| c.name)
+                    .unwrap_or_else(|| "(deleted command)".to_string()),
+                host: self
+                    .get_host(&result.host_id)
+                    .map(|h| h.name)
+                    .unwrap_or_else(|| "(ad hoc)".to_string()),
+                started_at: result.started_at,
+                exit_code: result.exit_code,
+                duration_ms: result.duration_ms,
+                revision: result
+                    .revision
+                    .and_then(|r| self.get_revision(&r))
+                    .map(|r| r.number),
+                library_commit: result.library_commit,
+            };
+            Some((summary, self.get_execution_log(id)))
+        };
+        let (old, old_log) = summary(old_id)?;
+        let (new, new_log) = summary(new_id)?;
+
+        let normalizers = self.get_settings().compare.normalizers;
+        let mut comparison = compare::compare(
+            &old,
+            old_log.as_deref().unwrap_or_default(),
+            &new,
+            new_log.as_deref().unwrap_or_default(),
+            normalize.then_some(normalizers.as_slice()),
+        );
+        for (log, which) in [(&old_log, "earlier"), (&new_log, "later")] {
+            if log.is_none() {
+                comparison
+                    .notes
+                    .push(format!("The {} run's log couldn't be read", which));
+            }
+        }
+        Some(comparison)
+    }
+
+    // --- Stats ---
+
+    /// Stats (see `stats`) over the runs finished since `since`, with days
+    /// in the local time zone.
+    pub fn stats(&self, since: chrono::DateTime<chrono::Utc>) -> StatsReport {
+        let (executions, workflow_runs) = {
+            let data = self.data.read().unwrap();
+            (data.executions.clone(), data.workflow_runs.clone())
+        };
+        let command_name = |id: &Uuid| {
+            self.get_command(id)
+                .map(|c| c.name)
+                .unwrap_or_else(|| "(deleted command)".to_string())
+        };
+        let host_name = |id: &Uuid| match self.get_host(id) {
+            Some(host) => host.name,
+            None if id.is_nil() => "(local or ad hoc)".to_string(),
+            None => "(deleted host)".to_string(),
+        };
+        let workflow_name = |id: &Uuid| {
+            self.get_workflow(id)
+                .map(|w| w.name)
+                .unwrap_or_else(|| "(deleted workflow)".to_string())
+        };
+
+        let mut runs = Vec::new();
+        for result in executions.iter().filter(|e| e.finished_at.is_some()) {
+            let items = vec![
+                (
+                    SnapshotItem {
+                        kind: ItemKind::Command,
+                        id: result.command_id,
+                    },
+                    command_name(&result.command_id),
+                ),
+                (
+                    SnapshotItem {
+                        kind: ItemKind::Host,
+                        id: result.host_id,
+                    },
+                    host_name(&result.host_id),
+                ),
+            ];
+            let run = RunRecord {
+                started_at: result.started_at,
+                duration_ms: result.duration_ms,
+                success: result.exit_code == Some(0),
+            };
+            runs.push((items, run));
+        }
+        for run in &workflow_runs {
+            let items = vec![(
+                SnapshotItem {
+                    kind: ItemKind::Workflow,
+                    id: run.workflow_id,
+                },
+                workflow_name(&run.workflow_id),
+            )];
+            let record = RunRecord {
+                started_at: run.started_at,
+                duration_ms: Some(run.duration_ms),
+                success: run.exit_code == 0,
+            };
+            runs.push((items, record));
+        }
+        stats::build(&runs, since, chrono::Utc::now(), &chrono::Local)
+    }
+
+    // --- Search ---
+
+    /// Commands and log lines matching `query`; see `search`. The log index
+    /// is built on first use, then kept up to date by `add_execution` and
+    /// by catching up with runs recorded elsewhere (e.g. by the daemon).
+    /// Repeating a query only reads the logs of runs that finished since.
+    pub fn search(&self, query: &str) -> SearchResults {
+        let terms = search::query_terms(query);
+        let mut results = SearchResults::default();
+        if terms.is_empty() {
+            return results;
+        }
+
+        results.commands = self
+            .list_commands()
+            .iter()
+            .filter_map(|c| search::match_command(c, &terms))
+            .collect();
+        results.commands.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let executions = self.data.read().unwrap().executions.clone();
+        (results.logs, results.truncated) =
+            search::search_logs(&self.search_state, &executions, &terms, |id| {
+                self.get_execution_log(id)
+            });
+        results
+    }
+
+    // --- Settings ---
+
+    pub fn get_settings(&self) -> Settings {
+        self.data.read().unwrap().settings.clone()
+    }
+
+    pub fn set_settings(&self, settings: Settings) {
+        self.data.write().unwrap().settings = settings.clone();
+        self.commit(StoreChange::SettingsSaved(settings));
+        self.reload_libraries();
+    }
+
+    // --- Libraries ---
+
+    /// Load the enabled libraries in the settings, reading again those
+    /// whose files changed since. Returns whether anything was (re)loaded.
+    pub fn reload_libraries(&self) -> bool {
+        let sources: Vec<LibrarySource> = self
+            .get_settings()
+            .libraries
+            .into_iter()
+            .filter(|l| l.enabled)
+            .collect();
+        let current = self.libraries.read().unwrap().clone();
+        let mut changed = current.len() != sources.len();
+        let libraries = sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| match current.get(i) {
+                Some(library) if library.is_current(source) => library.clone(),
+                _ => {
+                    changed = true;
+                    Library::load(source)
+                }
+            })
+            .collect();
+        if changed {
+            *self.libraries.write().unwrap() = libraries;
+        }
+        changed
+    }
+
+    pub fn list_libraries(&self) -> Vec<Library> {
+        self.libraries.read().unwrap().clone()
+    }
+
+    /// The library a command or workflow comes from; `None` for the
+    /// store's own items, which hide library items with the same id.
+    pub fn library_of(&self, id: &Uuid) -> Option<Library> {
+        let data = self.data.read().unwrap();
+        if data.commands.iter().any(|c| c.id == *id) || data.workflows.iter().any(|w| w.id == *id) {
+            return None;
+        }
+        self.libraries
+            .read()
+            .unwrap()
+            .iter()
+            .find(|l| l.contains(id))
+            .cloned()
+    }
+
+    /// Copy a library command into the store, where it can be changed. The
+    /// copy is a new command; the library's stays as it is.
+    pub fn fork_command(&self, id: &Uuid) -> Option<Command> {
+        self.library_of(id)?;
+        let mut command = self.get_command(id)?;
+        command.id = Uuid::new_v4();
+        command.created_at = chrono::Utc::now();
+        command.source_path = None;
+        self.add_command(command.clone());
+        Some(command)
+    }
+
+    /// Copy a library workflow into the store. Its steps still run the
+    /// library's commands.
+    pub fn fork_workflow(&self, id: &Uuid) -> Option<Workflow> {
+        self.library_of(id)?;
+        let mut workflow = self.get_workflow(id)?;
+        workflow.id = Uuid::new_v4();
+        workflow.created_at = chrono::Utc::now();
+        self.add_workflow(workflow.clone());
+        Some(workflow)
+    }
+
     // --- Export/Import ---
 
     pub fn export_json(&self) -> anyhow::Result<String> {
@@ -294,41 +1559,265 @@ impl CommandStore {
     }
 
     pub fn import_json(&self, json: &str) -> anyhow::Result<()> {
-        let new_data: StoreData = serde_json::from_str(json)?;
+        let mut new_data: StoreData = serde_json::from_str(json)?;
+        new_data.secure_passwords();
+        // Passed on without any passwords the export had in plain text
+        let json = serde_json::to_string(&new_data)?;
+        self.snapshot_before_import();
         {
             let mut data = self.data.write().unwrap();
             *data = new_data;
         }
-        self.save();
+        self.commit(StoreChange::Imported(json));
         Ok(())
     }
 
-    pub fn snapshot_state(&self) -> anyhow::Result<String> {
+    /// Preview merging an export (see `merge`) into this store.
+    pub fn preview_merge(&self, json: &str) -> anyhow::Result<MergePlan> {
+        let source: MergeSource = serde_json::from_str(json)?;
+        Ok(self.plan_merge(source))
+    }
+
+    fn plan_merge(&self, source: MergeSource) -> MergePlan {
         let data = self.data.read().unwrap();
-        let json = serde_json::to_string_pretty(&*data)?;
+        merge::plan(&data.commands, &data.workflows, &data.hosts, source)
+    }
+
+    /// Write the given commands and workflows, and the commands those
+    /// workflows run, as a bundle (see `bundle`) in `dir`.
+    pub fn export_bundle(
+        &self,
+        dir: &std::path::Path,
+        command_ids: &[Uuid],
+        workflow_ids: &[Uuid],
+    ) -> Result<(), BundleError> {
+        let (commands, workflows) = {
+            let data = self.data.read().unwrap();
+            let workflows: Vec<Workflow> = data
+                .workflows
+                .iter()
+                .filter(|w| workflow_ids.contains(&w.id))
+                .cloned()
+                .collect();
+            let commands: Vec<Command> = data
+                .commands
+                .iter()
+                .filter(|c| {
+                    command_ids.contains(&c.id)
+                        || workflows.iter().any(|w| w.commands.contains(&c.id))
+                })
+                .cloned()
+                .collect();
+            (commands, workflows)
+        };
+        bundle::write_bundle(dir, &commands, &workflows)
+    }
+
+    /// Preview importing the bundle in `dir`; apply it with `apply_merge`.
+    pub fn preview_bundle(&self, dir: &std::path::Path) -> Result<MergePlan, BundleError> {
+        Ok(self.plan_merge(bundle::read_bundle(dir)?))
+    }
+
+    /// Apply a previewed merge. Returns the number of items written.
+    pub fn apply_merge(&self, plan: &MergePlan) -> usize {
+        // Passed on to the daemon with the keychain references, as the
+        // passwords themselves aren't serialized
+        let mut plan = plan.clone();
+        secure_passwords(plan.written_run_as());
+        self.snapshot_before_import();
+        let written = {
+            let mut data = self.data.write().unwrap();
+            let writes = merge::resolve(&plan, &data.commands, &data.hosts);
+            for host in &writes.hosts {
+                data.hosts.retain(|h| h.id != host.id);
+                data.hosts.push(host.clone());
+            }
+            for cmd in &writes.commands {
+                let previous = data
+                    .commands
+                    .iter()
+                    .position(|c| c.id == cmd.id)
+                    .map(|i| data.commands.remove(i));
+                if self.daemon.is_none() {
+                    data.record_revision(previous.as_ref(), cmd, true);
+                }
+                data.commands.push(cmd.clone());
+            }
+            for workflow in &writes.workflows {
+                data.workflows.retain(|w| w.id != workflow.id);
+                data.workflows.push(workflow.clone());
+            }
+            writes.hosts.len() + writes.commands.len() + writes.workflows.len()
+        };
+        self.commit(StoreChange::Merged(Box::new(plan)));
+        written
+    }
 
-        // 1. Compute SHA-256 hash
-        let mut hasher = Sha256::new();
-        hasher.update(json.as_bytes());
-        let hash_result = hasher.finalize();
-        let hash_hex = hex::encode(hash_result);
+    // --- Snapshots ---
 
-        // 2. Determine snapshot directory and path
-        let parent = self
-            .path
+    fn snapshots_dir(&self) -> PathBuf {
+        self.path
             .parent()
-            .ok_or_else(|| anyhow::anyhow!("No parent directory for store path"))?;
-        let snapshots_dir = parent.join("snapshots");
-        std::fs::create_dir_all(&snapshots_dir)?;
+            .expect("store path has no parent")
+            .join("snapshots")
+    }
+
+    /// Take a manual snapshot; returns its hash.
+    pub fn snapshot_state(&self) -> anyhow::Result<String> {
+        Ok(self.create_snapshot("", SnapshotKind::Manual)?.hash)
+    }
+
+    /// Save the current state under `snapshots/`. A state that was saved
+    /// before keeps its file and gets the new time (and `label`, if any).
+    pub fn create_snapshot(&self, label: &str, kind: SnapshotKind) -> anyhow::Result<SnapshotInfo> {
+        let json = {
+            let data = self.data.read().unwrap();
+            serde_json::to_string_pretty(&*data)?
+        };
+        let hash = hex::encode(Sha256::digest(json.as_bytes()));
+
+        // Read before writing, or a new file would be listed as unindexed
+        let dir = self.snapshots_dir();
+        let mut index = snapshots::read_index(&dir);
+        if !snapshots::snapshot_path(&dir, &hash)?.exists() {
+            snapshots::write_snapshot(&dir, &hash, &json)?;
+        }
+        let info = match index.iter_mut().find(|s| s.hash == hash) {
+            Some(existing) => {
+                existing.created_at = chrono::Utc::now();
+                if !label.is_empty() {
+                    existing.label = label.to_string();
+                }
+                if kind == SnapshotKind::Manual {
+                    existing.kind = kind;
+                }
+                existing.clone()
+            }
+            None => {
+                let info = SnapshotInfo {
+                    hash,
+                    created_at: chrono::Utc::now(),
+                    label: label.to_string(),
+                    kind,
+                };
+                index.push(info.clone());
+                info
+            }
+        };
+        snapshots::write_index(&dir, &index)?;
+        Ok(info)
+    }
+
+    /// Snapshots, newest first.
+    pub fn list_snapshots(&self) -> Vec<SnapshotInfo> {
+        snapshots::read_index(&self.snapshots_dir())
+    }
+
+    pub fn delete_snapshot(&self, hash: &str) -> anyhow::Result<()> {
+        let dir = self.snapshots_dir();
+        std::fs::remove_file(snapshots::snapshot_path(&dir, hash)?)?;
+        let mut index = snapshots::read_index(&dir);
+        index.retain(|s| s.hash != hash);
+        snapshots::write_index(&dir, &index)
+    }
+
+    /// Delete all but the newest `keep` automatic snapshots. Returns how
+    /// many were deleted.
+    pub fn prune_snapshots(&self, keep: usize) -> anyhow::Result<usize> {
+        let dir = self.snapshots_dir();
+        let (mut kept, mut pruned) = (0, 0);
+        for snapshot in snapshots::read_index(&dir) {
+            if snapshot.kind == SnapshotKind::Manual {
+                continue;
+            }
+            kept += 1;
+            if kept > keep {
+                std::fs::remove_file(snapshots::snapshot_path(&dir, &snapshot.hash)?)?;
+                pruned += 1;
+            }
+        }
+        // Drops the entries of the deleted files
+        snapshots::write_index(&dir, &snapshots::read_index(&dir))?;
+        Ok(pruned)
+    }
+
+    /// Take a scheduled snapshot if the newest one is older than the
+    /// settings' interval, then prune. Meant to be called every minute or
+    /// so by whoever owns the store.
+    pub fn snapshot_if_due(&self) -> anyhow::Result<Option<SnapshotInfo>> {
+        let settings = self.get_settings().snapshots;
+        if settings.interval_hours == 0 {
+            return Ok(None);
+        }
+        let interval = chrono::Duration::hours(settings.interval_hours.into());
+        if self
+            .list_snapshots()
+            .first()
+            .is_some_and(|newest| chrono::Utc::now() - newest.created_at < interval)
+        {
+            return Ok(None);
+        }
+        let info = self.create_snapshot("", SnapshotKind::Scheduled)?;
+        self.prune_snapshots(settings.keep)?;
+        Ok(Some(info))
+    }
+
+    fn load_snapshot(&self, hash: &str) -> anyhow::Result<StoreData> {
+        let json = snapshots::read_snapshot(&self.snapshots_dir(), hash)?;
+        Ok(serde_json::from_str(&json)?)
+    }
 
-        let snapshot_path = snapshots_dir.join(format!("{}.json.gz", hash_hex));
+    /// The commands, workflows and hosts that differ between a snapshot and
+    /// the current state.
+    pub fn diff_snapshot(&self, hash: &str) -> anyhow::Result<Vec<ItemDiff>> {
+        let snapshot = self.load_snapshot(hash)?;
+        Ok(self.data.read().unwrap().diff(&snapshot))
+    }
 
-        // 3. Gzip the content
-        let file = std::fs::File::create(&snapshot_path)?;
-        let mut encoder = GzEncoder::new(file, Compression::default());
-        encoder.write_all(json.as_bytes())?;
-        encoder.finish()?;
+    /// Restore `items` from a snapshot, or with `None` every command,
+    /// workflow and host plus the settings. Run history is kept as is,
+    /// and the state being replaced is snapshotted first.
+    pub fn restore_snapshot(
+        &self,
+        hash: &str,
+        items: Option<Vec<SnapshotItem>>,
+    ) -> anyhow::Result<()> {
+        let snapshot = self.load_snapshot(hash)?;
+        if self.daemon.is_none() {
+            self.create_snapshot("", SnapshotKind::BeforeRestore)?;
+        }
+        {
+            let mut data = self.data.write().unwrap();
+            let restored: Vec<SnapshotItem> = match &items {
+                Some(items) => items.clone(),
+                None => {
+                    data.settings = snapshot.settings.clone();
+                    data.diff(&snapshot).into_iter().map(|d| d.item).collect()
+                }
+            };
+            data.restore(&snapshot, &restored, self.daemon.is_none());
+            // The daemon restores it too, and keeps the passwords
+            if self.daemon.is_none() {
+                data.secure_passwords();
+            }
+        }
+        self.commit(StoreChange::SnapshotRestored {
+            hash: hash.to_string(),
+            items,
+        });
+        Ok(())
+    }
 
-        Ok(hash_hex)
+    /// Snapshot the state an import is about to change. Only the store's
+    /// owner does; a connected store's import is snapshotted by the daemon.
+    fn snapshot_before_import(&self) {
+        if self.daemon.is_none()
+            && let Err(e) = self.create_snapshot("", SnapshotKind::BeforeImport)
+        {
+            eprintln!(
+                "Warning: Failed to snapshot the store before importing: {}",
+                e
+            );
+        }
     }
 }
@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests {
     use crate::models::{Command, ExecutionResult, ExecutionStatus, Host, Workflow};
-    use crate::store::CommandStore;
+    use crate::store::{CommandStore, MAX_REVISIONS};
     use uuid::Uuid;
 
     fn make_exec(cmd_id: Uuid, host_id: Uuid) -> (Uuid, ExecutionResult) {
@@ -16,6 +16,9 @@ mod tests {
             duration_ms: Some(100),
             status: ExecutionStatus::Completed,
             log_file: format!("{}.log.gz", exec_id),
+            background: None,
+            revision: None,
+            library_commit: None,
         };
         (exec_id, exec)
     }
@@ -49,9 +52,15 @@ mod tests {
             env_vars: vec![],
             host: None,
             user: None,
+            run_as: None,
             target_hosts: vec![],
+            host_selector: String::new(),
             created_at: chrono::Utc::now(),
             background: false,
+            target: Default::default(),
+            uploads: vec![],
+            artifacts: vec![],
+            notify: Default::default(),
             source_path: None,
         };
         store.add_command(cmd.clone());
@@ -63,6 +72,10 @@ mod tests {
             port: 22,
             username: "user".into(),
             auth: crate::models::AuthMethod::Agent,
+            kind: Default::default(),
+            groups: vec![],
+            tags: vec![],
+            workspace: Default::default(),
         };
         store.add_host(host.clone());
 
@@ -73,6 +86,8 @@ mod tests {
             commands: vec![cmd.id],
             env_vars: vec![],
             created_at: chrono::Utc::now(),
+            step_run_as: Vec::new(),
+            notify: Default::default(),
         };
         store.add_workflow(wf.clone());
 
@@ -104,4 +119,204 @@ mod tests {
         assert_eq!(history[0].id, exec.id);
         // Log file is in store's executions dir, not store2's, so we only check metadata here.
     }
+
+    #[test]
+    fn test_artifacts_write_list() {
+        let store = CommandStore::new_test();
+        let exec_id = Uuid::new_v4();
+
+        store
+            .add_artifact(&exec_id, "out/b.txt", b"b")
+            .expect("write failed");
+        store
+            .add_artifact(&exec_id, "a.txt", b"a")
+            .expect("write failed");
+        assert!(store.add_artifact(&exec_id, "../escape.txt", b"x").is_err());
+        assert!(store.add_artifact(&exec_id, "/etc/passwd", b"x").is_err());
+
+        assert_eq!(store.list_artifacts(&exec_id), vec!["a.txt", "out/b.txt"]);
+        let path = store.artifacts_dir(&exec_id).join("out/b.txt");
+        assert_eq!(std::fs::read(path).unwrap(), b"b");
+    }
+
+    fn make_host(name: &str, groups: &[&str], tags: &[&str]) -> Host {
+        Host {
+            id: Uuid::new_v4(),
+            name: name.into(),
+            hostname: format!("{}.example.com", name),
+            port: 22,
+            username: "deploy".into(),
+            auth: crate::models::AuthMethod::Agent,
+            kind: Default::default(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            workspace: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_host_selector_matching() {
+        let host = make_host(
+            "web1",
+            &["webservers"],
+            &["role=web", "env=staging", "canary"],
+        );
+
+        assert!(host.matches_selector("role=web"));
+        assert!(host.matches_selector(" role = web , env=staging "));
+        assert!(host.matches_selector("webservers,canary"));
+        assert!(host.matches_selector("web1"));
+        assert!(!host.matches_selector("role=web,env=prod"));
+        assert!(!host.matches_selector("role"));
+        assert!(!host.matches_selector(""));
+        assert!(!host.matches_selector(" , "));
+    }
+
+    #[test]
+    fn test_resolve_targets_and_remove_host() {
+        let store = CommandStore::new_test();
+        let web2 = make_host("web2", &[], &["role=web", "env=staging"]);
+        let web1 = make_host("web1", &[], &["role=web", "env=staging"]);
+        let prod = make_host("web-prod", &[], &["role=web", "env=prod"]);
+        let db = make_host("db", &[], &["role=db"]);
+        for h in [&web2, &web1, &prod, &db] {
+            store.add_host(h.clone());
+        }
+
+        let mut cmd = Command {
+            target_hosts: vec![db.id],
+            ..Command::new("Deploy", "true")
+        };
+        cmd.host_selector = "role=web,env=staging".into();
+        store.add_command(cmd.clone());
+
+        let names: Vec<String> = store
+            .resolve_targets(&cmd)
+            .into_iter()
+            .map(|h| h.name)
+            .collect();
+        assert_eq!(names, vec!["db", "web1", "web2"]);
+
+        store.remove_host(&db.id);
+        assert!(store.get_host(&db.id).is_none());
+        let cmd = store.get_command(&cmd.id).unwrap();
+        assert!(cmd.target_hosts.is_empty());
+        let names: Vec<String> = store
+            .resolve_targets(&cmd)
+            .into_iter()
+            .map(|h| h.name)
+            .collect();
+        assert_eq!(names, vec!["web1", "web2"]);
+    }
+
+    #[test]
+    fn test_command_revisions() {
+        let store = CommandStore::new_test();
+        let mut cmd = Command::new("Deploy", "make");
+        store.add_command(cmd.clone());
+
+        // Quick successive edits share a revision
+        cmd.script = "make deploy".into();
+        store.add_command(cmd.clone());
+        store.add_command(cmd.clone());
+        let revisions = store.list_revisions(&cmd.id);
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].command.script, "make deploy");
+
+        // Once a run used it, the next edit starts a new one
+        let ran = store.pin_revision(&cmd.id).unwrap();
+        assert_eq!(ran, revisions[0].id);
+        cmd.script = "make deploy ENV=prod".into();
+        store.add_command(cmd.clone());
+        let revisions = store.list_revisions(&cmd.id);
+        assert_eq!(
+            revisions.iter().map(|r| r.number).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+
+        // Restoring keeps what it replaces
+        let restored = store.restore_revision(&cmd.id, &ran).unwrap();
+        assert_eq!(restored.script, "make deploy");
+        assert_eq!(store.get_command(&cmd.id).unwrap().script, "make deploy");
+        let revisions = store.list_revisions(&cmd.id);
+        assert_eq!(revisions.len(), 3);
+        assert_eq!(revisions[1].command.script, "make deploy ENV=prod");
+
+        store.remove_command(&cmd.id);
+        assert!(store.list_revisions(&cmd.id).is_empty());
+    }
+
+    #[test]
+    fn test_pruning_keeps_revisions_that_ran() {
+        let store = CommandStore::new_test();
+        let mut cmd = Command::new("Deploy", "make deploy");
+        store.add_command(cmd.clone());
+        let ran = store.pin_revision(&cmd.id).unwrap();
+
+        cmd.script = "make deploy ENV=prod".into();
+        store.add_command(cmd.clone());
+        // Each restore of the one before adds a revision
+        for _ in 0..MAX_REVISIONS + 10 {
+            let previous = store.list_revisions(&cmd.id)[1].id;
+            store.restore_revision(&cmd.id, &previous).unwrap();
+        }
+
+        let revisions = store.list_revisions(&cmd.id);
+        assert_eq!(revisions.len(), MAX_REVISIONS + 1);
+        assert_eq!(
+            store.get_revision(&ran).unwrap().command.script,
+            "make deploy"
+        );
+    }
+
+    #[test]
+    fn test_revisions_of_older_commands() {
+        // A store saved before revisions were recorded
+        let store = CommandStore::new_test();
+        let mut cmd = Command {
+            created_at: chrono::Utc::now() - chrono::Duration::days(30),
+            ..Command::new("Backup", "tar czf backup.tgz /srv")
+        };
+        let json = serde_json::json!({ "commands": [cmd], "workflows": [], "hosts": [] });
+        store.import_json(&json.to_string()).unwrap();
+        assert!(store.list_revisions(&cmd.id).is_empty());
+
+        // The version being replaced becomes the first revision
+        cmd.script = "tar czf backup.tgz /srv /etc".into();
+        store.add_command(cmd.clone());
+        let revisions = store.list_revisions(&cmd.id);
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[1].command.script, "tar czf backup.tgz /srv");
+        assert_eq!(revisions[1].saved_at, cmd.created_at);
+    }
+
+    #[test]
+    fn test_plain_text_passwords_move_to_the_keychain() {
+        use crate::models::{EscalationPassword, RunAs};
+
+        // Stores used to keep a saved "run as" password as is
+        let store = CommandStore::new_test();
+        let mut cmd = Command::new("Reboot", "reboot");
+        cmd.run_as = Some(RunAs::default());
+        let mut json = serde_json::json!({ "commands": [cmd], "workflows": [], "hosts": [] });
+        json["commands"][0]["run_as"]["password"] = serde_json::json!({ "Saved": "hunter2" });
+        store.import_json(&json.to_string()).unwrap();
+
+        let password = store.get_command(&cmd.id).unwrap().run_as.unwrap().password;
+        let EscalationPassword::Saved(id) = password else {
+            panic!("not moved: {:?}", password)
+        };
+        assert_eq!(crate::secrets::load(&id).unwrap(), "hunter2");
+        assert!(!store.export_json().unwrap().contains("hunter2"));
+
+        // One typed in for a run is never written out
+        cmd.run_as = Some(RunAs {
+            password: EscalationPassword::Entered("hunter2".into()),
+            ..Default::default()
+        });
+        let json = serde_json::to_string(&cmd).unwrap();
+        assert!(!json.contains("hunter2"));
+        let reread: Command = serde_json::from_str(&json).unwrap();
+        assert_eq!(reread.run_as.unwrap().password, EscalationPassword::Prompt);
+    }
 }
@@ -0,0 +1,349 @@
+use crate::models::{ExecutionUpdate, FileTransfer};
+use crate::run_environment::{DirEntry, KillSwitch, RunEnvironment, RunEnvironmentError};
+use crate::shell::quote;
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path, PathBuf};
+
+/// Resolve a host path against the command's working directory.
+pub(crate) fn resolve_remote(path: &str, work_dir: &str) -> String {
+    if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("{}/{}", work_dir.trim_end_matches('/'), path)
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+struct PlannedFile {
+    local: PathBuf,
+    remote: String,
+    size: u64,
+}
+
+/// The directories to create and the individual files to copy.
+#[derive(Default)]
+struct UploadPlan {
+    dirs: Vec<String>,
+    files: Vec<PlannedFile>,
+}
+
+impl UploadPlan {
+    fn new(transfers: &[FileTransfer], work_dir: &str) -> std::io::Result<Self> {
+        let mut plan = UploadPlan::default();
+        for t in transfers {
+            let remote = resolve_remote(&t.remote_path, work_dir);
+            plan.walk(Path::new(&t.local_path), &remote)
+                .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", t.local_path, e)))?;
+        }
+        Ok(plan)
+    }
+
+    fn walk(&mut self, local: &Path, remote: &str) -> std::io::Result<()> {
+        let meta = std::fs::metadata(local)?;
+        if meta.is_dir() {
+            self.dirs.push(remote.to_string());
+            let mut entries = std::fs::read_dir(local)?.collect::<Result<Vec<_>, _>>()?;
+            entries.sort_by_key(|e| e.file_name());
+            for entry in entries {
+                let name = entry.file_name().to_string_lossy().to_string();
+                self.walk(&entry.path(), &format!("{}/{}", remote, name))?;
+            }
+        } else {
+            self.files.push(PlannedFile {
+                local: local.to_path_buf(),
+                remote: remote.to_string(),
+                size: meta.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// SHA-256 of a file on the host, or `None` if it has no hashing tool.
+fn remote_sha256(
+    env: &dyn RunEnvironment,
+    path: &str,
+) -> Result<Option<String>, RunEnvironmentError> {
+    let quoted = quote(path);
+    let (code, stdout) = env.capture(&format!(
+        "if command -v sha256sum >/dev/null 2>&1; then sha256sum {0}; \
+         elif command -v shasum >/dev/null 2>&1; then shasum -a 256 {0}; \
+         else exit 127; fi",
+        quoted
+    ))?;
+    if code == 127 {
+        return Ok(None);
+    }
+    Ok(stdout.split_whitespace().next().map(str::to_string))
+}
+
+/// Create `dir` and any missing parents, with `mkdir` when the host has no
+/// file transfer.
+fn make_dir(env: &dyn RunEnvironment, dir: &str) -> Result<(), RunEnvironmentError> {
+    if env.has_file_transfer() {
+        return env.create_dir(dir);
+    }
+    match env.capture(&format!("mkdir -p {}", quote(dir)))? {
+        (0, _) => Ok(()),
+        (code, _) => Err(RunEnvironmentError::UploadFailed(format!(
+            "could not create {} (exit code {})",
+            dir, code
+        ))),
+    }
+}
+
+/// Write `contents` to `path`, streaming them into `cat` when the host has
+/// no file transfer.
+fn put_file(
+    env: &dyn RunEnvironment,
+    path: &str,
+    contents: &[u8],
+) -> Result<(), RunEnvironmentError> {
+    if env.has_file_transfer() {
+        return env.write_file(path, contents);
+    }
+    let command = format!("cat > {}", quote(path));
+    match env.run_with_input(&command, contents, &|_| {}, &KillSwitch::never())? {
+        0 => Ok(()),
+        code => Err(RunEnvironmentError::UploadFailed(format!(
+            "could not write {} (exit code {})",
+            path, code
+        ))),
+    }
+}
+
+/// Copy the command's uploads to the host, reporting progress and verifying
+/// each file's checksum after it lands.
+pub(crate) fn upload(
+    env: &dyn RunEnvironment,
+    transfers: &[FileTransfer],
+    work_dir: &str,
+    on_update: &dyn Fn(ExecutionUpdate),
+) -> Result<(), RunEnvironmentError> {
+    if transfers.is_empty() {
+        return Ok(());
+    }
+
+    let plan = UploadPlan::new(transfers, work_dir)?;
+    let total_bytes: u64 = plan.files.iter().map(|f| f.size).sum();
+
+    for dir in &plan.dirs {
+        make_dir(env, dir)?;
+    }
+
+    let mut sent_bytes = 0;
+    let mut unverified = 0;
+    for (i, file) in plan.files.iter().enumerate() {
+        let remote = &file.remote;
+        let contents = std::fs::read(&file.local)?;
+        if let Some(parent) = Path::new(remote).parent() {
+            make_dir(env, &parent.to_string_lossy())?;
+        }
+        put_file(env, remote, &contents)?;
+
+        let expected = hex::encode(Sha256::digest(&contents));
+        match remote_sha256(env, remote)? {
+            Some(actual) if actual == expected => {}
+            Some(actual) => {
+                return Err(RunEnvironmentError::UploadFailed(format!(
+                    "checksum mismatch for {}: expected {}, got {}",
+                    remote, expected, actual
+                )));
+            }
+            None => unverified += 1,
+        }
+
+        sent_bytes += file.size;
+        on_update(ExecutionUpdate::Stdout(format!(
+            "[upload] {}/{} {} -> {} ({} of {})\n",
+            i + 1,
+            plan.files.len(),
+            file.local.display(),
+            remote,
+            format_bytes(sent_bytes),
+            format_bytes(total_bytes)
+        )));
+    }
+
+    if unverified > 0 {
+        on_update(ExecutionUpdate::Stderr(format!(
+            "[upload] {} file(s) not verified: no sha256sum or shasum on the host\n",
+            unverified
+        )));
+    }
+    Ok(())
+}
+
+/// Whether `path` is a directory, asking `test` when the host has no file
+/// transfer. Errors if it doesn't exist.
+fn remote_is_dir(env: &dyn RunEnvironment, path: &str) -> Result<bool, RunEnvironmentError> {
+    if env.has_file_transfer() {
+        return env.is_dir(path);
+    }
+    let quoted = quote(path);
+    let (code, stdout) = env.capture(&format!(
+        "if [ -d {0} ]; then echo dir; elif [ -e {0} ]; then echo file; fi",
+        quoted
+    ))?;
+    match stdout.trim() {
+        "dir" if code == 0 => Ok(true),
+        "file" if code == 0 => Ok(false),
+        _ => Err(RunEnvironmentError::DownloadFailed(format!(
+            "{}: no such file or directory",
+            path
+        ))),
+    }
+}
+
+/// The entries of `dir`, from `find` when the host has no file transfer.
+fn list_remote_dir(
+    env: &dyn RunEnvironment,
+    dir: &str,
+) -> Result<Vec<DirEntry>, RunEnvironmentError> {
+    if env.has_file_transfer() {
+        return env.list_dir(dir);
+    }
+    let mut entries = Vec::new();
+    for (test, is_dir) in [("-type d", true), ("! -type d", false)] {
+        let command = format!("find {} -mindepth 1 -maxdepth 1 {}", quote(dir), test);
+        let (code, stdout) = env.capture(&command)?;
+        if code != 0 {
+            return Err(RunEnvironmentError::DownloadFailed(format!(
+                "could not list {} (exit code {})",
+                dir, code
+            )));
+        }
+        entries.extend(stdout.lines().filter_map(|line| {
+            let name = line.rsplit('/').next()?;
+            Some(DirEntry {
+                name: name.to_string(),
+                is_dir,
+            })
+        }));
+    }
+    Ok(entries)
+}
+
+/// Read `path`, as a hex dump from `od` when the host has no file
+/// transfer: a command's output comes back as text, which would mangle
+/// binary files.
+fn get_file(env: &dyn RunEnvironment, path: &str) -> Result<Vec<u8>, RunEnvironmentError> {
+    if env.has_file_transfer() {
+        return env.read_file(path);
+    }
+    let (code, stdout) = env.capture(&format!("od -An -v -tx1 {}", quote(path)))?;
+    let hex: String = stdout.split_whitespace().collect();
+    match hex::decode(hex) {
+        Ok(data) if code == 0 => Ok(data),
+        _ => Err(RunEnvironmentError::DownloadFailed(format!(
+            "could not read {} (exit code {})",
+            path, code
+        ))),
+    }
+}
+
+/// Fetch a file or directory tree, returning the number of files and bytes.
+fn fetch(
+    env: &dyn RunEnvironment,
+    remote: &str,
+    is_dir: bool,
+    name: &str,
+    on_update: &dyn Fn(ExecutionUpdate),
+) -> Result<(usize, u64), RunEnvironmentError> {
+    if !is_dir {
+        let data = get_file(env, remote)?;
+        let size = data.len() as u64;
+        on_update(ExecutionUpdate::Artifact {
+            name: name.to_string(),
+            data,
+        });
+        return Ok((1, size));
+    }
+
+    let (mut files, mut bytes) = (0, 0);
+    let mut entries = list_remote_dir(env, remote)?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    for entry in entries {
+        let child_remote = format!("{}/{}", remote.trim_end_matches('/'), entry.name);
+        let child_name = format!("{}/{}", name, entry.name);
+        let (f, b) = fetch(env, &child_remote, entry.is_dir, &child_name, on_update)?;
+        files += f;
+        bytes += b;
+    }
+    Ok((files, bytes))
+}
+
+/// Lexically resolve `.` and `..` in `path`, keeping only the names.
+fn normal_components(path: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(name) => names.push(name.to_string_lossy().to_string()),
+            Component::ParentDir => {
+                names.pop();
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+/// Where an artifact fetched from `remote` is saved: its path relative to
+/// `work_dir`, or its full path for one outside it, so artifacts with the
+/// same file name don't overwrite each other.
+pub(crate) fn artifact_name(remote: &str, work_dir: &str) -> String {
+    let remote = normal_components(remote);
+    let work_dir = normal_components(work_dir);
+    let relative = match remote.strip_prefix(work_dir.as_slice()) {
+        Some(relative) if !relative.is_empty() => relative,
+        _ => &remote,
+    };
+    if relative.is_empty() {
+        "artifact".to_string()
+    } else {
+        relative.join("/")
+    }
+}
+
+/// Fetch the command's artifacts from the host. Missing artifacts are
+/// reported but don't fail the run.
+pub(crate) fn fetch_artifacts(
+    env: &dyn RunEnvironment,
+    paths: &[String],
+    work_dir: &str,
+    on_update: &dyn Fn(ExecutionUpdate),
+) {
+    for path in paths {
+        let remote = resolve_remote(path, work_dir);
+        let name = artifact_name(&remote, work_dir);
+
+        let fetched = remote_is_dir(env, &remote)
+            .and_then(|is_dir| fetch(env, &remote, is_dir, &name, on_update));
+        match fetched {
+            Ok((files, bytes)) => on_update(ExecutionUpdate::Stdout(format!(
+                "[artifacts] fetched {} ({} file(s), {})\n",
+                remote,
+                files,
+                format_bytes(bytes)
+            ))),
+            Err(e) => on_update(ExecutionUpdate::Stderr(format!(
+                "[artifacts] could not fetch {}: {}\n",
+                remote, e
+            ))),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+#[cfg(test)]
+mod tests {
+    use crate::models::{ExecutionUpdate, FileTransfer};
+    use crate::run_environment::LocalRunEnvironment;
+    use crate::transfer::{artifact_name, fetch_artifacts, resolve_remote, upload};
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+    use uuid::Uuid;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("switchboard_transfer_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_resolve_remote() {
+        assert_eq!(resolve_remote("/abs/file", "/work"), "/abs/file");
+        assert_eq!(resolve_remote("out/file", "/work/"), "/work/out/file");
+        assert_eq!(resolve_remote("file", "/"), "/file");
+    }
+
+    #[test]
+    fn test_artifact_name() {
+        assert_eq!(artifact_name("/work/a/out.log", "/work"), "a/out.log");
+        assert_eq!(artifact_name("/work/./dist/", "/work/"), "dist");
+        assert_eq!(
+            artifact_name("/work/../var/log/app.log", "/work"),
+            "var/log/app.log"
+        );
+        assert_eq!(
+            artifact_name("/var/log/app.log", "/work"),
+            "var/log/app.log"
+        );
+        assert_eq!(artifact_name("/work", "/work"), "work");
+        assert_eq!(artifact_name("/", "/work"), "artifact");
+    }
+
+    #[test]
+    fn test_upload_copies_directory_tree() {
+        let local = temp_dir();
+        std::fs::create_dir_all(local.join("nested")).unwrap();
+        std::fs::write(local.join("top.txt"), "top").unwrap();
+        std::fs::write(local.join("nested/inner.txt"), "inner").unwrap();
+        let remote = temp_dir();
+
+        let updates = Mutex::new(Vec::new());
+        let transfers = vec![FileTransfer {
+            local_path: local.to_string_lossy().to_string(),
+            remote_path: "bundle".into(),
+        }];
+        upload(
+            &LocalRunEnvironment::new(),
+            &transfers,
+            &remote.to_string_lossy(),
+            &|u| updates.lock().unwrap().push(u),
+        )
+        .expect("upload failed");
+
+        let bundle = remote.join("bundle");
+        assert_eq!(
+            std::fs::read_to_string(bundle.join("top.txt")).unwrap(),
+            "top"
+        );
+        assert_eq!(
+            std::fs::read_to_string(bundle.join("nested/inner.txt")).unwrap(),
+            "inner"
+        );
+        let progress = updates
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .filter(|u| matches!(u, ExecutionUpdate::Stdout(s) if s.starts_with("[upload]")))
+            .count();
+        assert_eq!(progress, 2);
+    }
+
+    #[test]
+    fn test_upload_missing_local_file_fails() {
+        let transfers = vec![FileTransfer {
+            local_path: "/nonexistent/switchboard/file".into(),
+            remote_path: "file".into(),
+        }];
+        let result = upload(&LocalRunEnvironment::new(), &transfers, "/tmp", &|_| {});
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_fetch_artifacts_emits_files_and_reports_missing() {
+        let remote = temp_dir();
+        std::fs::create_dir_all(remote.join("out/sub")).unwrap();
+        std::fs::write(remote.join("out/a.txt"), "a").unwrap();
+        std::fs::write(remote.join("out/sub/b.txt"), "b").unwrap();
+        std::fs::write(remote.join("report.json"), "{}").unwrap();
+
+        let updates = Mutex::new(Vec::new());
+        fetch_artifacts(
+            &LocalRunEnvironment::new(),
+            &["out".into(), "report.json".into(), "missing.log".into()],
+            &remote.to_string_lossy(),
+            &|u| updates.lock().unwrap().push(u),
+        );
+
+        let updates = updates.into_inner().unwrap();
+        let artifacts: Vec<(String, Vec<u8>)> = updates
+            .iter()
+            .filter_map(|u| match u {
+                ExecutionUpdate::Artifact { name, data } => Some((name.clone(), data.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            artifacts,
+            vec![
+                ("out/a.txt".to_string(), b"a".to_vec()),
+                ("out/sub/b.txt".to_string(), b"b".to_vec()),
+                ("report.json".to_string(), b"{}".to_vec()),
+            ]
+        );
+        assert!(
+            updates
+                .iter()
+                .any(|u| matches!(u, ExecutionUpdate::Stderr(s) if s.contains("missing.log")))
+        );
+    }
+
+    #[test]
+    fn test_fetch_artifacts_with_the_same_file_name() {
+        let remote = temp_dir();
+        for dir in ["a", "b", "a/dist", "b/dist"] {
+            std::fs::create_dir_all(remote.join(dir)).unwrap();
+        }
+        std::fs::write(remote.join("a/out.log"), "a").unwrap();
+        std::fs::write(remote.join("b/out.log"), "b").unwrap();
+        std::fs::write(remote.join("a/dist/app"), "a app").unwrap();
+        std::fs::write(remote.join("b/dist/app"), "b app").unwrap();
+
+        let names = Mutex::new(Vec::new());
+        fetch_artifacts(
+            &LocalRunEnvironment::new(),
+            &[
+                "a/out.log".into(),
+                "b/out.log".into(),
+                "a/dist".into(),
+                "b/dist".into(),
+            ],
+            &remote.to_string_lossy(),
+            &|u| {
+                if let ExecutionUpdate::Artifact { name, .. } = u {
+                    names.lock().unwrap().push(name);
+                }
+            },
+        );
+
+        assert_eq!(
+            names.into_inner().unwrap(),
+            vec!["a/out.log", "b/out.log", "a/dist/app", "b/dist/app"]
+        );
+    }
+}
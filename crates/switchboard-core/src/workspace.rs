@@ -0,0 +1,97 @@
+use crate::models::{LogCleanup, RemoteWorkspace};
+use crate::run_environment::{RunEnvironment, RunEnvironmentError};
+use crate::shell::{quote, quote_home_path};
+
+/// Names of a run's files inside its directory.
+const SCRIPT_FILE: &str = "script.sh";
+const LOG_FILE: &str = "output.log";
+const EXIT_FILE: &str = "exit";
+
+/// Shell glob matching run directory names (UUIDs), so cleanup never
+/// touches anything else that lives in the workspace.
+const RUN_DIR_GLOB: &str = "????????-????-????-????-????????????";
+
+/// A run's private directory on the host.
+pub(crate) struct RunDir {
+    /// Absolute path, as reported by the host.
+    pub path: String,
+}
+
+impl RunDir {
+    pub fn script(&self) -> String {
+        format!("{}/{}", self.path, SCRIPT_FILE)
+    }
+
+    pub fn log(&self) -> String {
+        format!("{}/{}", self.path, LOG_FILE)
+    }
+
+    pub fn exit(&self) -> String {
+        format!("{}/{}", self.path, EXIT_FILE)
+    }
+}
+
+/// The workspace directory, quoted for the shell; empty means the home
+/// directory.
+pub(crate) fn quoted_base(workspace: &RemoteWorkspace) -> String {
+    match workspace.dir.trim() {
+        "" => quote_home_path("~"),
+        dir => quote_home_path(dir),
+    }
+}
+
+/// Apply the age-based cleanup policy, then create this run's directory
+/// with mode 700 (the workspace itself is created 700 if missing).
+pub(crate) fn prepare_run_dir(
+    env: &dyn RunEnvironment,
+    workspace: &RemoteWorkspace,
+    exec_id: uuid::Uuid,
+) -> Result<RunDir, RunEnvironmentError> {
+    let base = quoted_base(workspace);
+    if let LogCleanup::OlderThanDays(days) = workspace.log_cleanup {
+        let _ = env.capture(&format!(
+            "cd {base} 2>/dev/null && find . -mindepth 2 -maxdepth 2 -path './{glob}/{log}' -mtime +{days} \
+             | while read -r f; do rm -rf \"${{f%/{log}}}\"; done; true",
+            base = base,
+            glob = RUN_DIR_GLOB,
+            log = LOG_FILE,
+            days = days,
+        ));
+    }
+
+    let (code, out) = env.capture(&format!(
+        "umask 077 && mkdir -p {base} && cd {base} && mkdir {id} && cd {id} && pwd",
+        base = base,
+        id = exec_id,
+    ))?;
+    let path = out.trim();
+    if code != 0 || !path.starts_with('/') {
+        return Err(RunEnvironmentError::Workspace(format!(
+            "could not create a run directory in {}",
+            workspace.dir
+        )));
+    }
+    Ok(RunDir {
+        path: path.to_string(),
+    })
+}
+
+/// Apply the after-run cleanup policy to a finished run's directory.
+pub(crate) fn finish_run_dir(env: &dyn RunEnvironment, workspace: &RemoteWorkspace, run_dir: &str) {
+    if workspace.log_cleanup == LogCleanup::DeleteAfterRun {
+        remove_run_dir(env, run_dir);
+    }
+}
+
+/// Remove a run's directory, e.g. of a run that failed before its script
+/// started.
+pub(crate) fn remove_run_dir(env: &dyn RunEnvironment, run_dir: &str) {
+    let is_run_dir = run_dir.starts_with('/')
+        && run_dir
+            .rsplit('/')
+            .next()
+            .is_some_and(|name| uuid::Uuid::parse_str(name).is_ok());
+    if is_run_dir {
+        let _ = env.capture(&format!("rm -rf {}", quote(run_dir)));
+    }
+}
@@ -15,6 +15,8 @@ fn test_workflow_crud() {
         commands: vec![],
         env_vars: vec![],
         created_at: chrono::Utc::now(),
+        step_run_as: Vec::new(),
+        notify: Default::default(),
     };
 
     store.add_workflow(wf.clone());
@@ -48,9 +50,15 @@ fn test_workflow_integrity() {
         env_vars: vec![],
         host: None,
         user: None,
+        run_as: None,
         target_hosts: vec![],
+        host_selector: String::new(),
         created_at: chrono::Utc::now(),
         background: false,
+        target: Default::default(),
+        uploads: vec![],
+        artifacts: vec![],
+        notify: Default::default(),
         source_path: None,
     };
     store.add_command(cmd);
@@ -64,6 +72,8 @@ fn test_workflow_integrity() {
         commands: vec![cmd_id],
         env_vars: vec![],
         created_at: chrono::Utc::now(),
+        step_run_as: Vec::new(),
+        notify: Default::default(),
     };
     store.add_workflow(wf);
 
@@ -0,0 +1,192 @@
+//! `switchboardctl`: drive a running `switchboardd` from the shell.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use switchboard_core::daemon::{
+    DaemonClient, DaemonError, DaemonRequest, DaemonResponse, default_socket_path,
+};
+use switchboard_core::runs::RunRequest;
+use switchboard_core::{CommandStore, ExecutionUpdate};
+use uuid::Uuid;
+
+const USAGE: &str = "Usage: switchboardctl [--socket PATH] <command>
+
+Commands:
+  commands                             List saved commands
+  runs                                 List runs still going
+  run <name|id> [KEY=VALUE..] [--detach]
+                                       Start a command and follow it; exits
+                                       with its exit code
+  attach <run-id>                      Follow a run until it finishes
+  kill <run-id>                        Stop a run
+  log <run-id>                         Print a run's output";
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut socket = default_socket_path();
+    if args.first().map(String::as_str) == Some("--socket") && args.len() >= 2 {
+        socket = PathBuf::from(args.remove(1));
+        args.remove(0);
+    }
+    let Some(command) = args.first().cloned() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+
+    let client = match DaemonClient::connect(&socket) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!(
+                "switchboardctl: can't reach switchboardd at {}: {}",
+                socket.display(),
+                e
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match (command.as_str(), &args[1..]) {
+        ("commands", []) => list_commands(&client),
+        ("runs", []) => list_runs(&client),
+        ("run", [target, rest @ ..]) => run(&client, target, rest),
+        ("attach", [id]) => parse_id(id).and_then(|id| follow(&client, &[id])),
+        ("kill", [id]) => parse_id(id).and_then(|run_id| {
+            client.request(&DaemonRequest::Kill { run_id })?;
+            Ok(ExitCode::SUCCESS)
+        }),
+        ("log", [id]) => parse_id(id).and_then(|run_id| {
+            match client.request(&DaemonRequest::Log { run_id })? {
+                DaemonResponse::Log(log) => {
+                    print!("{}", log);
+                    Ok(ExitCode::SUCCESS)
+                }
+                other => Err(DaemonError::Unexpected(Box::new(other))),
+            }
+        }),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("switchboardctl: {}", e);
+        ExitCode::FAILURE
+    })
+}
+
+fn parse_id(id: &str) -> Result<Uuid, DaemonError> {
+    id.parse()
+        .map_err(|_| DaemonError::Daemon(format!("Not a run id: {}", id)))
+}
+
+fn list_commands(client: &DaemonClient) -> Result<ExitCode, DaemonError> {
+    let store = CommandStore::connect(client.clone())?;
+    let mut commands = store.list_commands();
+    commands.sort_by(|a, b| a.name.cmp(&b.name));
+    for command in commands {
+        println!("{}  {}", command.id, command.name);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn list_runs(client: &DaemonClient) -> Result<ExitCode, DaemonError> {
+    let runs = match client.request(&DaemonRequest::ListRuns)? {
+        DaemonResponse::Runs(runs) => runs,
+        other => return Err(DaemonError::Unexpected(Box::new(other))),
+    };
+    let store = CommandStore::connect(client.clone())?;
+    for run in runs {
+        let name = store
+            .get_command(&run.command_id)
+            .map_or_else(|| "(deleted command)".to_string(), |c| c.name);
+        println!(
+            "{}  {}  started {}",
+            run.id,
+            name,
+            run.started_at.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run(client: &DaemonClient, target: &str, rest: &[String]) -> Result<ExitCode, DaemonError> {
+    let mut env = HashMap::new();
+    let mut detach = false;
+    for arg in rest {
+        match arg.split_once('=') {
+            _ if arg == "--detach" => detach = true,
+            Some((key, value)) => {
+                env.insert(key.to_string(), value.to_string());
+            }
+            None => {
+                return Err(DaemonError::Daemon(format!(
+                    "Expected KEY=VALUE, got {}",
+                    arg
+                )));
+            }
+        }
+    }
+
+    // By id, or else by (unique) name
+    let store = CommandStore::connect(client.clone())?;
+    let commands = store.list_commands();
+    let matching: Vec<_> = commands
+        .iter()
+        .filter(|c| c.id.to_string() == target || c.name == target)
+        .collect();
+    let command_id = match matching.as_slice() {
+        [command] => command.id,
+        [] => return Err(DaemonError::Daemon(format!("No command named {}", target))),
+        _ => {
+            return Err(DaemonError::Daemon(format!(
+                "Several commands are named {}; use its id",
+                target
+            )));
+        }
+    };
+
+    let request = DaemonRequest::StartRun {
+        command_id,
+        request: RunRequest {
+            env,
+            password: None,
+        },
+    };
+    let ids = match client.request(&request)? {
+        DaemonResponse::Started(ids) => ids,
+        other => return Err(DaemonError::Unexpected(Box::new(other))),
+    };
+    if detach {
+        for id in ids {
+            println!("{}", id);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    follow(client, &ids)
+}
+
+/// Print the runs' output as it comes; the exit code is the first failing
+/// run's.
+fn follow(client: &DaemonClient, ids: &[Uuid]) -> Result<ExitCode, DaemonError> {
+    let mut failed = None;
+    for &run_id in ids {
+        let result = client.attach(run_id, 0, &|update| match update {
+            ExecutionUpdate::Stdout(text) => print!("{}", text),
+            ExecutionUpdate::Stderr(text) => eprint!("{}", text),
+            ExecutionUpdate::Detached(job) => {
+                eprintln!("[running in the background as pid {}]", job.pid)
+            }
+            ExecutionUpdate::Artifact { name, .. } => eprintln!("[saved artifact {}]", name),
+            ExecutionUpdate::Started(_) | ExecutionUpdate::Exit(_) => {}
+        })?;
+        let code = result.exit_code.unwrap_or(-1);
+        if code != 0 && failed.is_none() {
+            failed = Some(code);
+        }
+    }
+    Ok(match failed {
+        None => ExitCode::SUCCESS,
+        Some(code) => ExitCode::from(u8::try_from(code).unwrap_or(1)),
+    })
+}
@@ -0,0 +1,66 @@
+//! `switchboardd [--socket PATH]`: owns the store and the runs so they
+//! outlive the GUI. The GUI and `switchboardctl` connect to it when it's up.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use switchboard_core::daemon::{Daemon, bind, default_socket_path};
+use switchboard_core::library;
+use switchboard_core::runs::RunManager;
+use switchboard_core::snapshots::SCHEDULE_CHECK_INTERVAL;
+use switchboard_core::{CommandStore, Executor};
+
+fn main() -> ExitCode {
+    let mut socket = default_socket_path();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--socket", Some(path)) => socket = PathBuf::from(path),
+            _ => {
+                eprintln!("Usage: switchboardd [--socket PATH]");
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    let listener = match bind(&socket) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("switchboardd: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let runs = RunManager::new(CommandStore::new(), Arc::new(Executor));
+    runs.reattach_background();
+
+    let store = runs.store().clone();
+    std::thread::spawn(move || {
+        loop {
+            if let Err(e) = store.snapshot_if_due() {
+                eprintln!("switchboardd: scheduled snapshot failed: {}", e);
+            }
+            std::thread::sleep(SCHEDULE_CHECK_INTERVAL);
+        }
+    });
+    let store = runs.store().clone();
+    std::thread::spawn(move || {
+        loop {
+            store.reload_libraries();
+            std::thread::sleep(library::CHECK_INTERVAL);
+        }
+    });
+    let daemon = Arc::new(Daemon::new(runs));
+    daemon.restart_api();
+
+    println!("switchboardd listening on {}", socket.display());
+    let served = daemon.serve(listener);
+    let _ = std::fs::remove_file(&socket);
+    match served {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("switchboardd: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
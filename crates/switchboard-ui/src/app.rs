@@ -4,19 +4,32 @@ use switchboard_core::{
     CommandExecutor, CommandStore, ExecutionUpdate,
     save_command,
 };
+use switchboard_core::daemon::{DaemonClient, DaemonExecutor, DaemonRequest, DaemonResponse};
+use switchboard_core::diagnostics::{CheckStatus, HostDiagnostics};
+use switchboard_core::library::{self, short_commit};
 use uuid::Uuid;
 use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use switchboard_core::search::SearchResults;
+use switchboard_core::store::SYNC_INTERVAL;
+
+/// How long the search box is left alone before its query is searched.
+const SEARCH_DEBOUNCE: Duration = Duration::from_millis(250);
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum Selection {
     Command(Uuid),
     Execution(Uuid),
     Workflow(Uuid),
+    Host(Uuid),
 }
 
 pub struct ExecutionState {
     pub id: Uuid,
     pub _command_id: Uuid,
+    /// Inventory host the run targets; nil for the command's ad hoc host.
+    pub host_id: Uuid,
     pub command_name: String,
     pub output_buffer: String,
     pub is_running: bool,
@@ -27,6 +40,59 @@ pub struct ExecutionState {
     pub started_at: chrono::DateTime<chrono::Utc>,
     pub output_loaded: bool,
     pub is_from_history: bool,
+    pub background: Option<switchboard_core::models::BackgroundJob>,
+    pub artifacts: Vec<String>,
+    /// The command revision that ran, once known.
+    pub revision: Option<Uuid>,
+    /// For library commands, the commit the library was at.
+    pub library_commit: Option<String>,
+}
+
+/// The "Snapshots" window.
+struct SnapshotBrowser {
+    label: String,
+    snapshots: Vec<switchboard_core::snapshots::SnapshotInfo>,
+    /// The snapshot compared with the current state, how they differ, and
+    /// which of the differences to restore (by index).
+    compared: Option<(String, Vec<switchboard_core::snapshots::ItemDiff>, std::collections::HashSet<usize>)>,
+    message: Option<String>,
+}
+
+/// The "Compare Runs" window.
+struct RunCompare {
+    /// The earlier run and the later one.
+    old: Uuid,
+    new: Uuid,
+    normalize: bool,
+    changes_only: bool,
+    /// The last comparison computed; `None` if one of the runs is no
+    /// longer recorded, or before the first one is done.
+    comparison: Option<switchboard_core::compare::RunComparison>,
+    /// Computed again on a worker thread when set.
+    outdated: bool,
+    /// Delivers the comparison being computed.
+    computing: Option<Receiver<Option<switchboard_core::compare::RunComparison>>>,
+    /// `Settings::compare` patterns, applied with the "Apply" button.
+    normalizers: Vec<switchboard_core::Normalizer>,
+}
+
+/// The "Statistics" window.
+struct StatsView {
+    /// The period shown: this many days up to now.
+    days: i64,
+    /// Computed again when cleared.
+    report: Option<switchboard_core::stats::StatsReport>,
+    kind: switchboard_core::snapshots::ItemKind,
+    /// The item whose days are charted; all command runs when `None`.
+    selected: Option<switchboard_core::snapshots::SnapshotItem>,
+    message: Option<String>,
+}
+
+/// The "History" window listing a command's revisions.
+struct RevisionView {
+    command_id: Uuid,
+    /// The revision compared with the current version.
+    selected: Option<Uuid>,
 }
 
 struct PendingExecution {
@@ -40,6 +106,9 @@ struct PendingExecution {
     // The variables that require user input.
     // We store them as EnvVars so we can edit the value.
     vars_to_ask: Vec<switchboard_core::models::EnvVar>,
+
+    // Some when a "Run as" step asks for its password at run time.
+    password: Option<String>,
 }
 
 #[derive(Clone, Default)]
@@ -52,7 +121,14 @@ struct CommandEditState {
     script: String,
     is_local: bool,
     background: bool,
+    target: switchboard_core::HostKind,
+    target_hosts: Vec<Uuid>,
+    host_selector: String,
     env_vars: Vec<switchboard_core::models::EnvVar>,
+    uploads: Vec<switchboard_core::models::FileTransfer>,
+    artifacts: Vec<String>,
+    run_as: Option<switchboard_core::models::RunAs>,
+    notify: switchboard_core::NotifyOn,
 }
 
 impl CommandEditState {
@@ -66,7 +142,14 @@ impl CommandEditState {
             script: cmd.script.clone(),
             is_local: cmd.host.is_none(),
             background: cmd.background,
+            target: cmd.target.clone(),
+            target_hosts: cmd.target_hosts.clone(),
+            host_selector: cmd.host_selector.clone(),
             env_vars: cmd.env_vars.clone(),
+            uploads: cmd.uploads.clone(),
+            artifacts: cmd.artifacts.clone(),
+            run_as: cmd.run_as.clone(),
+            notify: cmd.notify,
         }
     }
     
@@ -74,7 +157,8 @@ impl CommandEditState {
         cmd.name = self.name.clone();
         cmd.description = if self.description.is_empty() { None } else { Some(self.description.clone()) };
         
-        if self.is_local {
+        // Containers and pods are driven through local CLIs.
+        if self.is_local || self.target != switchboard_core::HostKind::Ssh {
             cmd.host = None;
             cmd.user = None;
         } else {
@@ -87,7 +171,14 @@ impl CommandEditState {
         cmd.working_directory = if self.working_directory.is_empty() { None } else { Some(self.working_directory.clone()) };
         cmd.script = self.script.clone();
         cmd.background = self.background;
+        cmd.target = self.target.clone();
+        cmd.target_hosts = self.target_hosts.clone();
+        cmd.host_selector = self.host_selector.trim().to_string();
         cmd.env_vars = self.env_vars.clone();
+        cmd.uploads = self.uploads.clone();
+        cmd.artifacts = self.artifacts.clone();
+        cmd.run_as = self.run_as.clone();
+        cmd.notify = self.notify;
     }
 }
 
@@ -97,6 +188,8 @@ struct WorkflowEditState {
     description: String,
     commands: Vec<Uuid>,
     env_vars: Vec<switchboard_core::models::EnvVar>,
+    step_run_as: Vec<Option<switchboard_core::models::RunAs>>,
+    notify: switchboard_core::NotifyOn,
 }
 
 impl WorkflowEditState {
@@ -106,6 +199,8 @@ impl WorkflowEditState {
             description: wf.description.clone().unwrap_or_default(),
             commands: wf.commands.clone(),
             env_vars: wf.env_vars.clone(),
+            step_run_as: (0..wf.commands.len()).map(|i| wf.step_run_as.get(i).cloned().flatten()).collect(),
+            notify: wf.notify,
         }
     }
     
@@ -114,19 +209,327 @@ impl WorkflowEditState {
         wf.description = if self.description.is_empty() { None } else { Some(self.description.clone()) };
         wf.commands = self.commands.clone();
         wf.env_vars = self.env_vars.clone();
+        wf.step_run_as = self.step_run_as.clone();
+        wf.notify = self.notify;
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum AuthChoice {
+    #[default]
+    Agent,
+    KeyFile,
+    Password,
+}
+
+#[derive(Clone, Default)]
+struct HostEditState {
+    name: String,
+    kind: switchboard_core::HostKind,
+    hostname: String,
+    port: String,
+    username: String,
+    auth: AuthChoice,
+    key_file: String,
+    password: String,
+    groups: String,
+    tags: String,
+    workspace_dir: String,
+    log_cleanup: switchboard_core::LogCleanup,
+}
+
+fn split_list(text: &str) -> Vec<String> {
+    text.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect()
+}
+
+impl HostEditState {
+    fn from_host(host: &switchboard_core::Host) -> Self {
+        use switchboard_core::AuthMethod;
+
+        let (auth, key_file, password) = match &host.auth {
+            AuthMethod::Agent => (AuthChoice::Agent, String::new(), String::new()),
+            AuthMethod::KeyFile(path) => (AuthChoice::KeyFile, path.clone(), String::new()),
+            AuthMethod::Password(pw) => (AuthChoice::Password, String::new(), pw.clone()),
+        };
+        Self {
+            name: host.name.clone(),
+            kind: host.kind.clone(),
+            hostname: host.hostname.clone(),
+            port: host.port.to_string(),
+            username: host.username.clone(),
+            auth,
+            key_file,
+            password,
+            groups: host.groups.join(", "),
+            tags: host.tags.join(", "),
+            workspace_dir: host.workspace.dir.clone(),
+            log_cleanup: host.workspace.log_cleanup,
+        }
+    }
+
+    fn apply_to_host(&self, host: &mut switchboard_core::Host) {
+        use switchboard_core::AuthMethod;
+
+        host.name = self.name.clone();
+        host.kind = self.kind.clone();
+        host.hostname = self.hostname.trim().to_string();
+        // Keep the old port while the field holds something unparsable
+        if let Ok(port) = self.port.trim().parse() {
+            host.port = port;
+        }
+        host.username = self.username.trim().to_string();
+        host.auth = match self.auth {
+            AuthChoice::Agent => AuthMethod::Agent,
+            AuthChoice::KeyFile => AuthMethod::KeyFile(self.key_file.trim().to_string()),
+            AuthChoice::Password => AuthMethod::Password(self.password.clone()),
+        };
+        host.groups = split_list(&self.groups);
+        host.tags = split_list(&self.tags);
+        host.workspace.dir = match self.workspace_dir.trim() {
+            "" => switchboard_core::RemoteWorkspace::default_dir(),
+            dir => dir.to_string(),
+        };
+        host.workspace.log_cleanup = self.log_cleanup;
+    }
+}
+
+/// An Ansible inventory waiting for the user to confirm the import.
+struct InventoryImport {
+    source: std::path::PathBuf,
+    hosts: Vec<switchboard_core::Host>,
+    resync: bool,
+}
+
+/// A store export or bundle being merged in, while its preview is open.
+struct StoreImport {
+    source: std::path::PathBuf,
+    /// A whole store export, which may replace the store instead.
+    json: Option<String>,
+    plan: switchboard_core::merge::MergePlan,
+}
+
+/// Scripts or shell history being turned into commands, while the preview
+/// is open.
+struct ScriptImport {
+    source: String,
+    /// Lines of shell history rather than script files.
+    history: bool,
+    candidates: Vec<switchboard_core::script_import::ImportCandidate>,
+    filter: String,
+}
+
+/// What the "Export Bundle" window will export.
+#[derive(Default)]
+struct BundleExport {
+    commands: std::collections::HashSet<Uuid>,
+    workflows: std::collections::HashSet<Uuid>,
+    error: Option<String>,
+}
+
+/// Result of the last "Test Connection" for a host.
+enum HostTestStatus {
+    Running,
+    Done(HostDiagnostics),
+}
+
+/// Grid rows for the container/pod fields of a target. Returns whether
+/// anything changed.
+fn host_kind_rows(ui: &mut egui::Ui, kind: &mut switchboard_core::HostKind, id_salt: &str) -> bool {
+    use switchboard_core::HostKind;
+    use switchboard_core::models::{ContainerMode, ContainerRuntime};
+
+    let mut changed = false;
+    match kind {
+        HostKind::Ssh => {}
+        HostKind::Kubernetes(target) => {
+            for (label, value, hint) in [
+                ("Context:", &mut target.context, "current context"),
+                ("Namespace:", &mut target.namespace, "default"),
+                ("Selector:", &mut target.selector, "app=web"),
+                ("Pod:", &mut target.pod, "first pod matching selector"),
+                ("Container:", &mut target.container, "default container"),
+            ] {
+                ui.label(label);
+                if ui.add(egui::TextEdit::singleline(value).hint_text(hint)).changed() {
+                    changed = true;
+                }
+                ui.end_row();
+            }
+        }
+        HostKind::Container(target) => {
+            ui.label("Container:");
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt(id_salt)
+                    .selected_text(target.runtime.binary())
+                    .show_ui(ui, |ui| {
+                        for runtime in [ContainerRuntime::Docker, ContainerRuntime::Podman] {
+                            if ui.selectable_value(&mut target.runtime, runtime, runtime.binary()).changed() {
+                                changed = true;
+                            }
+                        }
+                    });
+
+                let is_image = matches!(target.mode, ContainerMode::Image { .. });
+                if ui.radio(!is_image, "Existing container").clicked() && is_image {
+                    target.mode = ContainerMode::Exec { container: String::new() };
+                    changed = true;
+                }
+                if ui.radio(is_image, "New from image").clicked() && !is_image {
+                    target.mode = ContainerMode::Image { image: String::new() };
+                    changed = true;
+                }
+            });
+            ui.end_row();
+
+            let (label, value) = match &mut target.mode {
+                ContainerMode::Exec { container } => ("Name:", container),
+                ContainerMode::Image { image } => ("Image:", image),
+            };
+            ui.label(label);
+            if ui.text_edit_singleline(value).changed() {
+                changed = true;
+            }
+            ui.end_row();
+        }
+    }
+    changed
+}
+
+/// Grid rows for running as another user through sudo or su. Returns
+/// whether anything changed.
+fn run_as_rows(ui: &mut egui::Ui, run_as: &mut Option<switchboard_core::models::RunAs>, id_salt: &str) -> bool {
+    use switchboard_core::models::{EscalationMethod, EscalationPassword};
+
+    let mut changed = false;
+    ui.label("Run as:");
+    ui.horizontal(|ui| {
+        let mut enabled = run_as.is_some();
+        if ui.checkbox(&mut enabled, "").changed() {
+            *run_as = enabled.then(Default::default);
+            changed = true;
+        }
+        if let Some(run_as) = run_as {
+            if ui.add(egui::TextEdit::singleline(&mut run_as.user).hint_text("root").desired_width(120.0)).changed() {
+                changed = true;
+            }
+            egui::ComboBox::from_id_salt(format!("{}_method", id_salt))
+                .selected_text(match run_as.method {
+                    EscalationMethod::Sudo => "via sudo",
+                    EscalationMethod::Su => "via su",
+                })
+                .show_ui(ui, |ui| {
+                    changed |= ui.selectable_value(&mut run_as.method, EscalationMethod::Sudo, "via sudo").changed();
+                    changed |= ui.selectable_value(&mut run_as.method, EscalationMethod::Su, "via su").changed();
+                });
+        }
+    });
+    ui.end_row();
+
+    if let Some(run_as) = run_as {
+        ui.label("Password:");
+        ui.horizontal(|ui| {
+            let current = match &run_as.password {
+                EscalationPassword::None => "Not needed",
+                EscalationPassword::Prompt | EscalationPassword::Entered(_) => "Ask each run",
+                EscalationPassword::Saved(_) => "Saved",
+            };
+            egui::ComboBox::from_id_salt(format!("{}_password", id_salt))
+                .selected_text(current)
+                .show_ui(ui, |ui| {
+                    for option in ["Not needed", "Ask each run", "Saved"] {
+                        if ui.selectable_label(current == option, option).clicked() && current != option {
+                            run_as.password = match option {
+                                "Ask each run" => EscalationPassword::Prompt,
+                                "Saved" => EscalationPassword::Saved(Uuid::new_v4()),
+                                _ => EscalationPassword::None,
+                            };
+                            changed = true;
+                        }
+                    }
+                });
+            // Typed into a buffer and saved to the keychain; the command
+            // only refers to the entry
+            if let EscalationPassword::Saved(entry) = &run_as.password {
+                let buffer_id = ui.id().with((id_salt, "password"));
+                let mut buffer: String = ui.data_mut(|d| d.get_temp(buffer_id)).unwrap_or_default();
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut buffer)
+                        .password(true)
+                        .hint_text("In the keychain; type to change"),
+                );
+                if response.lost_focus() && !buffer.is_empty() {
+                    let status = match switchboard_core::secrets::save(entry, &buffer) {
+                        Ok(()) => "Saved to the keychain".to_string(),
+                        Err(e) => e.to_string(),
+                    };
+                    ui.data_mut(|d| d.insert_temp(buffer_id.with("status"), status));
+                    buffer.clear();
+                }
+                ui.data_mut(|d| d.insert_temp(buffer_id, buffer));
+                if let Some(status) = ui.data(|d| d.get_temp::<String>(buffer_id.with("status"))) {
+                    ui.label(egui::RichText::new(status).weak());
+                }
+            }
+        });
+        ui.end_row();
     }
+    changed
+}
+
+/// A "Notify" picker; returns true if it changed.
+fn notify_combo(ui: &mut egui::Ui, notify: &mut switchboard_core::NotifyOn, id_salt: &str) -> bool {
+    use switchboard_core::NotifyOn;
+
+    let label = |n: NotifyOn| match n {
+        NotifyOn::Never => "Never",
+        NotifyOn::Failure => "On failure",
+        NotifyOn::Success => "On success",
+        NotifyOn::Always => "Always",
+    };
+    let mut changed = false;
+    egui::ComboBox::from_id_salt(id_salt)
+        .selected_text(label(*notify))
+        .show_ui(ui, |ui| {
+            for option in [NotifyOn::Never, NotifyOn::Failure, NotifyOn::Success, NotifyOn::Always] {
+                if ui.selectable_value(notify, option, label(option)).changed() {
+                    changed = true;
+                }
+            }
+        });
+    changed
+}
+
+fn asks_password(run_as: Option<&switchboard_core::models::RunAs>) -> bool {
+    run_as.is_some_and(|r| r.password == switchboard_core::models::EscalationPassword::Prompt)
 }
 
 pub struct ActiveWorkflow {
     pub workflow_id: Uuid,
     pub current_step_index: usize,
-    pub current_execution_id: Option<Uuid>,
+    /// Runs of the current step still in progress (one per target host).
+    pub current_execution_ids: Vec<Uuid>,
+    pub step_failed: bool,
     pub resolved_env: std::collections::HashMap<String, String>,
+    /// Answer for steps whose "Run as" password is asked at run time.
+    pub password: Option<String>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// The first failing run's exit code, or 0.
+    pub exit_code: i32,
 }
 
 pub struct SwitchboardApp {
     store: CommandStore,
-    executor: Box<dyn CommandExecutor>,
+    executor: Arc<dyn CommandExecutor>,
+    /// Set when switchboardd owns the store and runs; it then records runs
+    /// and serves the HTTP API itself.
+    daemon: Option<Arc<DaemonExecutor>>,
+    last_daemon_sync: Instant,
+    /// `CommandStore::sync_count` when history was last merged in.
+    store_syncs: u64,
+    /// Scheduled snapshots are taken by whoever owns the store: this
+    /// process, unless switchboardd does.
+    last_snapshot_check: Option<Instant>,
+    last_library_check: Instant,
     
     // Selection State
     active_selection: Option<Selection>,
@@ -139,6 +542,14 @@ pub struct SwitchboardApp {
     // Editing State
     edited_command: Option<CommandEditState>,
     edited_workflow: Option<WorkflowEditState>,
+    edited_host: Option<HostEditState>,
+    host_tests: HashMap<Uuid, HostTestStatus>,
+    host_test_tx: Sender<(Uuid, HostDiagnostics)>,
+    host_test_rx: Receiver<(Uuid, HostDiagnostics)>,
+    inventory_import: Option<InventoryImport>,
+    store_import: Option<StoreImport>,
+    bundle_export: Option<BundleExport>,
+    script_import: Option<ScriptImport>,
     
     // Prompt State
     pending_execution: Option<PendingExecution>,
@@ -149,6 +560,196 @@ pub struct SwitchboardApp {
     // We send (ExecutionID, Update) to identify which run the update belongs to
     execution_tx: Sender<(Uuid, ExecutionUpdate)>,
     execution_rx: Receiver<(Uuid, ExecutionUpdate)>,
+
+    // HTTP API
+    api_server: Option<switchboard_core::api::ApiServer>,
+    api_error: Option<String>,
+    /// Settings being edited in the "HTTP API" window, while it's open.
+    edited_api: Option<switchboard_core::ApiSettings>,
+
+    // Notifications
+    /// Settings being edited in the "Notifications" window, while it's open.
+    edited_notifications: Option<switchboard_core::NotificationSettings>,
+    /// Libraries being edited in the "Libraries" window, while it's open.
+    edited_libraries: Option<Vec<switchboard_core::LibrarySource>>,
+    /// Outcome of each channel's "Test" button, by index.
+    notification_tests: HashMap<usize, String>,
+    notification_test_tx: Sender<(usize, String)>,
+    notification_test_rx: Receiver<(usize, String)>,
+
+    // Search
+    search_query: String,
+    /// When `search_query` was last edited.
+    search_edited: Instant,
+    /// Results for the query they were computed for.
+    search_results: Option<(String, SearchResults)>,
+    /// Runs finished since `search_results` were computed.
+    search_outdated: bool,
+    /// Whether a search is running on a worker thread.
+    search_running: bool,
+    search_tx: Sender<(String, SearchResults)>,
+    search_rx: Receiver<(String, SearchResults)>,
+
+    // Revisions
+    revision_view: Option<RevisionView>,
+    run_compare: Option<RunCompare>,
+    stats_view: Option<StatsView>,
+
+    // Snapshots
+    snapshot_browser: Option<SnapshotBrowser>,
+}
+
+impl ExecutionState {
+    fn to_result(&self, exit_code: Option<i32>) -> switchboard_core::models::ExecutionResult {
+        use switchboard_core::models::ExecutionStatus;
+
+        let finished_at = exit_code.map(|_| chrono::Utc::now());
+        let duration = finished_at
+            .map(|f| f.signed_duration_since(self.started_at).num_milliseconds().max(0) as u64);
+        let status = match exit_code {
+            None => ExecutionStatus::Running,
+            Some(0) => ExecutionStatus::Completed,
+            Some(_) => ExecutionStatus::Failed,
+        };
+
+        switchboard_core::models::ExecutionResult {
+            id: self.id,
+            command_id: self._command_id,
+            host_id: self.host_id,
+            started_at: self.started_at,
+            finished_at,
+            exit_code,
+            duration_ms: duration,
+            status,
+            log_file: format!("{}.log.gz", self.id),
+            background: self.background.clone(),
+            revision: self.revision,
+            library_commit: self.library_commit.clone(),
+        }
+    }
+}
+
+/// Every recorded run, oldest first, with logs loaded on demand.
+fn history_executions(store: &CommandStore) -> Vec<ExecutionState> {
+    let libraries = store.list_libraries();
+    let mut executions: Vec<ExecutionState> = store
+        .list_commands()
+        .iter()
+        .chain(libraries.iter().flat_map(|l| &l.commands))
+        .flat_map(|cmd| {
+            store.get_execution_history(&cmd.id).into_iter().map(|item| ExecutionState {
+                id: item.id,
+                _command_id: item.command_id,
+                host_id: item.host_id,
+                command_name: match store.get_host(&item.host_id) {
+                    Some(host) => format!("{} @ {}", cmd.name, host.name),
+                    None => cmd.name.clone(),
+                },
+                output_buffer: String::from("(Click to load logs)"),
+                is_running: false,
+                exit_code: item.exit_code,
+                kill_tx: None,
+                working_directory: None,
+                is_local: false,
+                started_at: item.started_at,
+                output_loaded: false,
+                is_from_history: true,
+                background: item.background,
+                artifacts: Vec::new(),
+                revision: item.revision,
+                library_commit: item.library_commit,
+            })
+        })
+        .collect();
+    executions.sort_by_key(|a| a.started_at);
+    executions
+}
+
+/// A side-by-side diff (see `switchboard_core::diff`), colored by change.
+/// With `context`, unchanged lines further than that from a change are
+/// left out. Only the rows scrolled into view are laid out.
+fn diff_grid(ui: &mut egui::Ui, id_salt: &str, rows: &[switchboard_core::diff::DiffRow], context: Option<usize>) {
+    use switchboard_core::diff::DiffKind;
+
+    let red = egui::Color32::from_rgb(255, 100, 100);
+    let green = egui::Color32::from_rgb(100, 200, 100);
+    let yellow = egui::Color32::from_rgb(230, 200, 90);
+    let mut shown = vec![context.is_none(); rows.len()];
+    if let Some(context) = context {
+        for (i, _) in rows.iter().enumerate().filter(|(_, r)| r.kind != DiffKind::Same) {
+            let end = (i + context + 1).min(rows.len());
+            shown[i.saturating_sub(context)..end].fill(true);
+        }
+    }
+    // Indices of the rows drawn, with `None` where hidden ones were left out
+    let mut lines: Vec<Option<usize>> = Vec::new();
+    for (i, row_shown) in shown.iter().enumerate() {
+        if *row_shown {
+            lines.push(Some(i));
+        } else if lines.last().is_none_or(|line| line.is_some()) {
+            lines.push(None);
+        }
+    }
+
+    // Everything is monospace, so padding lines up the columns
+    let expand = |text: &str| text.replace('\t', "    ");
+    let number_width = rows
+        .iter()
+        .flat_map(|r| [&r.old, &r.new])
+        .filter_map(|line| line.as_ref().map(|(n, _)| n.to_string().len()))
+        .max()
+        .unwrap_or(1);
+    let old_width = rows
+        .iter()
+        .filter_map(|r| r.old.as_ref().map(|(_, text)| expand(text).chars().count()))
+        .max()
+        .unwrap_or(0);
+    let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+
+    egui::ScrollArea::both().id_salt(format!("{}_scroll", id_salt)).show_rows(ui, row_height, lines.len(), |ui, range| {
+        ui.spacing_mut().item_spacing.x = 8.0;
+        for line in &lines[range] {
+            ui.horizontal(|ui| {
+                let Some(i) = line else {
+                    ui.label(egui::RichText::new(format!("{:>1$}", "⋯", number_width)).monospace().weak());
+                    return;
+                };
+                let row = &rows[*i];
+                let (old_color, new_color) = match row.kind {
+                    DiffKind::Same => (None, None),
+                    DiffKind::Changed => (Some(yellow), Some(yellow)),
+                    DiffKind::Removed => (Some(red), None),
+                    DiffKind::Added => (None, Some(green)),
+                };
+                for (side, color, width) in [(&row.old, old_color, old_width), (&row.new, new_color, 0)] {
+                    let (number, text) = match side {
+                        Some((number, text)) => (number.to_string(), expand(text)),
+                        None => (String::new(), String::new()),
+                    };
+                    ui.label(egui::RichText::new(format!("{:>1$}", number, number_width)).monospace().weak());
+                    let text = egui::RichText::new(format!("{:<1$}", text, width)).monospace();
+                    ui.add(
+                        egui::Label::new(match color {
+                            Some(color) => text.color(color),
+                            None => text,
+                        })
+                        .extend(),
+                    );
+                }
+            });
+        }
+    });
+}
+
+/// Says where a library command or workflow comes from, above its
+/// (read-only) editor.
+fn library_banner(ui: &mut egui::Ui, library: &switchboard_core::library::Library) {
+    let at = library.commit.as_deref().map(|c| format!(" at {}", short_commit(c))).unwrap_or_default();
+    ui.label(
+        egui::RichText::new(format!("📚 From the {} library{}; read-only. Fork it to make changes.", library.source.name, at))
+            .weak(),
+    )
+    .on_hover_text(library.source.path.display().to_string());
 }
 
 fn parse_group_prefix(name: &str) -> (Option<&str>, &str) {
@@ -170,50 +771,205 @@ impl SwitchboardApp {
         visuals.selection.stroke.color = egui::Color32::WHITE;
         cc.egui_ctx.set_visuals(visuals);
 
-        let store = CommandStore::new();
+        // Share the store and runs with switchboardd when it's running
+        let connected = DaemonClient::connect(switchboard_core::daemon::default_socket_path())
+            .and_then(|client| Ok((CommandStore::connect(client.clone())?, client)));
+        let (store, daemon) = match connected {
+            Ok((store, client)) => (store, Some(Arc::new(DaemonExecutor::new(client)))),
+            Err(_) => (CommandStore::new(), None),
+        };
+        let executor: Arc<dyn CommandExecutor> = match &daemon {
+            Some(daemon) => daemon.clone(),
+            None => Arc::new(switchboard_core::Executor),
+        };
 
         // Pre-load all execution history from the store
-        let all_commands = store.list_commands();
-        let mut executions: Vec<ExecutionState> = all_commands
-            .iter()
-            .flat_map(|cmd| {
-                store.get_execution_history(&cmd.id).into_iter().map(|item| ExecutionState {
-                    id: item.id,
-                    _command_id: item.command_id,
-                    command_name: cmd.name.clone(),
-                    output_buffer: String::from("(Click to load logs)"),
-                    is_running: false,
-                    exit_code: item.exit_code,
-                    kill_tx: None,
-                    working_directory: None,
-                    is_local: false,
-                    started_at: item.started_at,
-                    output_loaded: false,
-                    is_from_history: true,
-                })
-            })
-            .collect();
-        executions.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        let executions = history_executions(&store);
 
         // Execution channel
         let (exec_tx, exec_rx) = channel();
+        let (host_test_tx, host_test_rx) = channel();
+        let (notification_test_tx, notification_test_rx) = channel();
+        let (search_tx, search_rx) = channel();
 
-        use switchboard_core::Executor;
-
-        Self {
+        let mut app = Self {
             store,
-            executor: Box::new(Executor),
+            executor,
+            daemon,
+            last_daemon_sync: Instant::now(),
+            store_syncs: 0,
+            last_snapshot_check: None,
+            last_library_check: Instant::now(),
             active_selection: None,
             navigation_history: Vec::new(),
             sidebar_width: 250.0,
             show_delete_confirmation: false,
             edited_command: None,
             edited_workflow: None,
+            edited_host: None,
+            host_tests: HashMap::new(),
+            host_test_tx,
+            host_test_rx,
+            inventory_import: None,
+            store_import: None,
+            bundle_export: None,
+            script_import: None,
             pending_execution: None,
             active_workflow: None,
             executions,
             execution_tx: exec_tx,
             execution_rx: exec_rx,
+            api_server: None,
+            api_error: None,
+            edited_api: None,
+            edited_notifications: None,
+            edited_libraries: None,
+            notification_tests: HashMap::new(),
+            notification_test_tx,
+            notification_test_rx,
+            search_query: String::new(),
+            search_edited: Instant::now(),
+            search_results: None,
+            search_outdated: false,
+            search_running: false,
+            search_tx,
+            search_rx,
+            revision_view: None,
+            run_compare: None,
+            stats_view: None,
+            snapshot_browser: None,
+        };
+        if app.daemon.is_some() {
+            app.sync_daemon();
+        } else {
+            app.reattach_background_runs();
+            app.restart_api_server();
+        }
+        app
+    }
+
+    /// Pick up what other switchboardd clients did: their finished runs,
+    /// once the store has caught up with them, and runs still going, which
+    /// are followed here too.
+    fn sync_daemon(&mut self) {
+        let Some(daemon) = self.daemon.clone() else { return };
+        self.last_daemon_sync = Instant::now();
+
+        let syncs = self.store.sync_count();
+        if syncs != self.store_syncs {
+            self.store_syncs = syncs;
+            for state in history_executions(&self.store) {
+                if !self.executions.iter().any(|e| e.id == state.id) {
+                    self.executions.push(state);
+                    self.search_outdated = true;
+                }
+            }
+        }
+
+        let active = match daemon.client().request(&DaemonRequest::ListRuns) {
+            Ok(DaemonResponse::Runs(active)) => active,
+            Ok(other) => {
+                eprintln!("Unexpected answer from switchboardd: {:?}", other);
+                return;
+            }
+            Err(e) => {
+                eprintln!("Failed to list switchboardd runs: {}", e);
+                return;
+            }
+        };
+        for run in active {
+            if self.executions.iter().any(|e| e.id == run.id && e.is_running) {
+                continue;
+            }
+            let command_name = match (self.store.get_command(&run.command_id), self.store.get_host(&run.host_id)) {
+                (Some(cmd), Some(host)) => format!("{} @ {}", cmd.name, host.name),
+                (Some(cmd), None) => cmd.name.clone(),
+                (None, _) => "(deleted command)".to_string(),
+            };
+            let (kill_tx, kill_rx) = channel();
+            let state = ExecutionState {
+                id: run.id,
+                _command_id: run.command_id,
+                host_id: run.host_id,
+                command_name,
+                // Followed from the start, so the output arrives in full
+                output_buffer: String::new(),
+                is_running: true,
+                exit_code: None,
+                kill_tx: Some(kill_tx),
+                working_directory: None,
+                is_local: false,
+                started_at: run.started_at,
+                output_loaded: true,
+                is_from_history: false,
+                background: run.background,
+                artifacts: Vec::new(),
+                revision: run.revision,
+                library_commit: run.library_commit,
+            };
+            match self.executions.iter_mut().find(|e| e.id == run.id) {
+                Some(existing) => *existing = state,
+                None => self.executions.push(state),
+            }
+
+            let tx = self.execution_tx.clone();
+            let exec_id = run.id;
+            let cb = Box::new(move |update| {
+                let _ = tx.send((exec_id, update));
+            });
+            daemon.follow(exec_id, cb, kill_rx);
+        }
+        self.executions.sort_by_key(|a| a.started_at);
+    }
+
+    /// (Re)start the HTTP API from the saved settings, or stop it if it's
+    /// disabled.
+    fn restart_api_server(&mut self) {
+        use switchboard_core::api::ApiServer;
+        use switchboard_core::runs::RunManager;
+
+        // Free the port before binding it again
+        self.api_server = None;
+        self.api_error = None;
+        if self.daemon.is_some() {
+            return;
+        }
+        let settings = self.store.get_settings().api;
+        if !settings.enabled {
+            return;
+        }
+        match ApiServer::start(&settings, RunManager::new(self.store.clone(), self.executor.clone())) {
+            Ok(server) => self.api_server = Some(server),
+            Err(e) => self.api_error = Some(e.to_string()),
+        }
+    }
+
+    /// Resume tracking background runs that were still alive when the app
+    /// last closed.
+    fn reattach_background_runs(&mut self) {
+        for state in &mut self.executions {
+            let Some(job) = state.background.clone() else { continue };
+            if state.exit_code.is_some() {
+                continue;
+            }
+            let Some(cmd) = self.store.get_command(&state._command_id) else { continue };
+
+            state.output_buffer = self.store.get_execution_log(&state.id).unwrap_or_default();
+            state.output_loaded = true;
+            state.is_running = true;
+
+            let exec_id = state.id;
+            let tx = self.execution_tx.clone();
+            let (kill_tx, kill_rx) = channel();
+            state.kill_tx = Some(kill_tx);
+
+            let cb = Box::new(move |update| {
+                let _ = tx.send((exec_id, update));
+            });
+            let host = self.store.get_host(&state.host_id).unwrap_or_else(|| cmd.adhoc_host());
+            if let Err(e) = self.executor.attach_background(&host, &job, cb, kill_rx) {
+                eprintln!("Failed to reattach to background run {}: {}", exec_id, e);
+            }
         }
     }
 
@@ -222,6 +978,7 @@ impl SwitchboardApp {
             if current != selection {
                 self.save_current_command();
                 self.save_current_workflow();
+                self.save_current_host();
                 self.navigation_history.push(current);
                 self.active_selection = Some(selection);
             }
@@ -234,6 +991,7 @@ impl SwitchboardApp {
         if let Some(prev) = self.navigation_history.pop() {
             self.save_current_command();
             self.save_current_workflow();
+            self.save_current_host();
             self.active_selection = Some(prev);
             
             // Re-initialize edit state if needed based on selection type
@@ -250,6 +1008,11 @@ impl SwitchboardApp {
                         self.edited_command = None;
                     }
                 },
+                Selection::Host(id) => {
+                    if let Some(host) = self.store.get_host(&id) {
+                        self.edited_host = Some(HostEditState::from_host(&host));
+                    }
+                },
                 _ => {}
             }
         } else {
@@ -257,6 +1020,7 @@ impl SwitchboardApp {
              if self.active_selection.is_some() {
                  self.save_current_command();
                  self.save_current_workflow();
+                 self.save_current_host();
                  self.active_selection = None;
                  self.edited_command = None;
                  self.edited_workflow = None;
@@ -322,38 +1086,46 @@ impl SwitchboardApp {
                                      v.ask_user
                                  };
                                  
-                                 if effective_ask {
-                                     if !vars_to_ask.iter().any(|existing: &switchboard_core::models::EnvVar| existing.key == v.key) {
-                                         // Use the resolved value as default
-                                         let val = resolved_env.get(&v.key).cloned().unwrap_or_default();
-                                         vars_to_ask.push(switchboard_core::models::EnvVar {
-                                             key: v.key.clone(),
-                                             value: val,
-                                             ask_user: true
-                                         });
-                                     }
+                                 if effective_ask
+                                     && !vars_to_ask.iter().any(|existing: &switchboard_core::models::EnvVar| existing.key == v.key)
+                                 {
+                                     // Use the resolved value as default
+                                     let val = resolved_env.get(&v.key).cloned().unwrap_or_default();
+                                     vars_to_ask.push(switchboard_core::models::EnvVar {
+                                         key: v.key.clone(),
+                                         value: val,
+                                         ask_user: true
+                                     });
                                  }
                              }
                         }
                     }
                 }
                 
-                if !vars_to_ask.is_empty() {
+                let asks = wf.commands.iter().enumerate().any(|(i, cmd_id)| {
+                    self.store.get_command(cmd_id).is_some_and(|cmd| asks_password(wf.run_as_for_step(i, &cmd).as_ref()))
+                });
+                if !vars_to_ask.is_empty() || asks {
                     self.pending_execution = Some(PendingExecution {
                         cmd_id: None,
                         workflow_id: Some(workflow_id),
                         initial_vars: resolved_env,
                         vars_to_ask,
+                        password: asks.then(String::new),
                     });
                 } else {
                     // Start immediately
                     self.active_workflow = Some(ActiveWorkflow {
                         workflow_id,
                         current_step_index: 0,
-                        current_execution_id: None,
+                        current_execution_ids: Vec::new(),
+                        step_failed: false,
                         resolved_env: resolved_env.clone(),
+                        password: None,
+                        started_at: chrono::Utc::now(),
+                        exit_code: 0,
                     });
-                    self.perform_execution(*first_cmd_id, None);
+                    self.perform_execution(*first_cmd_id, None, None);
                 }
             }
          }
@@ -364,35 +1136,160 @@ impl SwitchboardApp {
 
      fn check_workflow_progress(&mut self, finished_exec_id: Uuid, exit_code: i32) {
         if let Some(active_wf) = &mut self.active_workflow {
-            // Check if the finished execution matches our current step
-             if active_wf.current_execution_id == Some(finished_exec_id) {
-                 if exit_code == 0 {
+            // Check if the finished execution belongs to our current step
+             if let Some(pos) = active_wf.current_execution_ids.iter().position(|id| *id == finished_exec_id) {
+                 active_wf.current_execution_ids.remove(pos);
+                 active_wf.step_failed |= exit_code != 0;
+                 if active_wf.exit_code == 0 {
+                     active_wf.exit_code = exit_code;
+                 }
+                 // Wait for the step's runs on other hosts
+                 if !active_wf.current_execution_ids.is_empty() {
+                     return;
+                 }
+                 if !active_wf.step_failed {
                      // Success, move to next step
                      if let Some(wf) = self.store.get_workflow(&active_wf.workflow_id) {
                          let next_idx = active_wf.current_step_index + 1;
                          if next_idx < wf.commands.len() {
                              active_wf.current_step_index = next_idx;
+                             active_wf.step_failed = false;
                              let next_cmd_id = wf.commands[next_idx];
-                             self.perform_execution(next_cmd_id, None); 
+                             self.perform_execution(next_cmd_id, None, None);
                          } else {
                              // Workflow finished
-                             self.active_workflow = None;
+                             self.finish_workflow(finished_exec_id);
                          }
                      }
                  } else {
                      // Failure, stop workflow
-                     self.active_workflow = None;
+                     self.finish_workflow(finished_exec_id);
                  }
              }
         }
     }
 
+    /// Search `query` on a worker thread once it has been left alone for
+    /// `SEARCH_DEBOUNCE`, unless it's what `search_results` are for and no
+    /// runs finished since. One search runs at a time; the results land in
+    /// `search_results`.
+    fn refresh_search(&mut self, ctx: &egui::Context, query: &str) {
+        let current = self.search_results.as_ref().is_some_and(|(q, _)| q == query);
+        if self.search_running || (current && !self.search_outdated) {
+            return;
+        }
+        let waited = self.search_edited.elapsed();
+        if waited < SEARCH_DEBOUNCE {
+            ctx.request_repaint_after(SEARCH_DEBOUNCE - waited);
+            return;
+        }
+
+        self.search_running = true;
+        self.search_outdated = false;
+        let store = self.store.clone();
+        let tx = self.search_tx.clone();
+        let query = query.to_string();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let results = store.search(&query);
+            let _ = tx.send((query, results));
+            ctx.request_repaint();
+        });
+    }
+
+    /// Search hits in place of the sidebar's lists; returns what was
+    /// clicked.
+    fn show_search_results(&self, ui: &mut egui::Ui) -> Option<Selection> {
+        let (_, results) = self.search_results.as_ref()?;
+        let mut clicked = None;
+        egui::ScrollArea::vertical().id_salt("sidebar_search_scroll").show(ui, |ui| {
+            if results.is_empty() {
+                ui.label(egui::RichText::new("No matches").weak());
+                return;
+            }
+
+            if !results.commands.is_empty() {
+                ui.label(egui::RichText::new("Commands").strong());
+                for hit in &results.commands {
+                    if ui.selectable_label(false, &hit.name).clicked() {
+                        clicked = Some(Selection::Command(hit.command_id));
+                    }
+                    for (number, line) in hit.script_lines.iter().take(3) {
+                        ui.label(egui::RichText::new(format!("{:>4}  {}", number, line.trim())).monospace().small().weak());
+                    }
+                    if hit.in_description && !hit.in_name && hit.script_lines.is_empty() {
+                        ui.label(egui::RichText::new("(in description)").small().weak());
+                    }
+                }
+                ui.separator();
+            }
+
+            if !results.logs.is_empty() {
+                ui.label(egui::RichText::new("Run Logs").strong());
+                let mut last_exec = None;
+                for hit in &results.logs {
+                    if last_exec != Some(hit.exec_id) {
+                        last_exec = Some(hit.exec_id);
+                        let name = match self.executions.iter().find(|e| e.id == hit.exec_id) {
+                            Some(state) => state.command_name.clone(),
+                            None => "(deleted command)".to_string(),
+                        };
+                        let local_time: chrono::DateTime<chrono::Local> = hit.started_at.into();
+                        let label = format!("{} · {}", name, local_time.format("%Y-%m-%d %H:%M"));
+                        if ui.selectable_label(false, label).clicked() {
+                            clicked = Some(Selection::Execution(hit.exec_id));
+                        }
+                    }
+                    ui.indent((hit.exec_id, hit.line_number), |ui| {
+                        for line in &hit.before {
+                            ui.label(egui::RichText::new(line).monospace().small().weak());
+                        }
+                        ui.label(egui::RichText::new(format!("{}: {}", hit.line_number, hit.line)).monospace().small().strong());
+                        for line in &hit.after {
+                            ui.label(egui::RichText::new(line).monospace().small().weak());
+                        }
+                    });
+                }
+                if results.truncated {
+                    ui.label(egui::RichText::new("More matches not shown; refine the search").weak());
+                }
+            }
+        });
+        clicked
+    }
+
+    /// End the active workflow, whose last run was `last_exec_id`, and send
+    /// any notification it asks for.
+    fn finish_workflow(&mut self, last_exec_id: Uuid) {
+        let Some(active_wf) = self.active_workflow.take() else { return };
+        let duration_ms = chrono::Utc::now().signed_duration_since(active_wf.started_at).num_milliseconds().max(0) as u64;
+        self.store.add_workflow_run(switchboard_core::models::WorkflowRun {
+            id: Uuid::new_v4(),
+            workflow_id: active_wf.workflow_id,
+            started_at: active_wf.started_at,
+            duration_ms,
+            exit_code: active_wf.exit_code,
+        });
+        let Some(wf) = self.store.get_workflow(&active_wf.workflow_id) else { return };
+        let (host, output) = match self.executions.iter().find(|e| e.id == last_exec_id) {
+            Some(state) => {
+                let host = match self.store.get_host(&state.host_id) {
+                    Some(host) => host.name,
+                    None => self.store.get_command(&state._command_id).map(|c| c.adhoc_host().hostname).unwrap_or_default(),
+                };
+                (host, state.output_buffer.clone())
+            }
+            None => (String::new(), String::new()),
+        };
+        switchboard_core::notifications::workflow_finished(&self.store, &wf, active_wf.exit_code, duration_ms, host, &output);
+    }
+
     fn trigger_command_execution(&mut self, cmd_id: Uuid) {
          // Save first
-        if let Some(Selection::Command(active_id)) = self.active_selection {
-            if active_id == cmd_id {
-                self.save_current_command();
-            }
+        if let Some(Selection::Command(active_id)) = self.active_selection
+            && active_id == cmd_id
+        {
+            self.save_current_command();
         }
         
         // If we are in a workflow, we don't prompt (already done).
@@ -411,74 +1308,56 @@ impl SwitchboardApp {
                 }
             }
             
-            if !vars_to_ask.is_empty() {
+            let asks = asks_password(cmd.run_as.as_ref());
+            if !vars_to_ask.is_empty() || asks {
                 self.pending_execution = Some(PendingExecution {
                     cmd_id: Some(cmd_id),
                     workflow_id: None,
                     initial_vars,
                     vars_to_ask,
+                    password: asks.then(String::new),
                 });
             } else {
                 // Determine env map from command only
-                self.perform_execution(cmd_id, None);
+                self.perform_execution(cmd_id, None, None);
             }
         }
     }
     
-    fn perform_execution(&mut self, cmd_id: Uuid, explicit_env: Option<std::collections::HashMap<String, String>>) {
-        use switchboard_core::{Host, AuthMethod};
+    fn perform_execution(
+        &mut self,
+        cmd_id: Uuid,
+        explicit_env: Option<std::collections::HashMap<String, String>>,
+        password: Option<String>,
+    ) {
         use std::collections::HashMap;
+        use switchboard_core::models::EscalationPassword;
 
         // Fetch command to run
-        if let Some(cmd) = self.store.get_command(&cmd_id) {
-             
-             // Detect current user for "localhost" test
-             let default_user = std::env::var("USER").unwrap_or_else(|_| "root".into());
-             
-             let hostname = cmd.host.clone().unwrap_or_else(|| "localhost".to_string());
-             let username = cmd.user.clone().unwrap_or(default_user);
-             let name = if cmd.host.is_some() { "Remote".into() } else { "local".into() };
-
-             let dummy_host = Host {
-                id: Uuid::new_v4(),
-                name,
-                hostname,
-                port: 22,
-                username,
-                auth: AuthMethod::Agent,
-            };
-            
-            let exec_id = Uuid::new_v4();
-            let tx = self.execution_tx.clone();
-            
-            // Create kill channel
-            let (kill_tx, kill_rx) = channel();
-            
-            let cb = Box::new(move |update| {
-                let _ = tx.send((exec_id, update));
-            });
-            
-            // Create State
-            let state = ExecutionState {
-                id: exec_id,
-                _command_id: cmd_id,
-                command_name: cmd.name.clone(),
-                output_buffer: String::new(),
-                is_running: true,
-                exit_code: None,
-                kill_tx: Some(kill_tx),
-                working_directory: cmd.working_directory.clone(),
-                is_local: cmd.host.is_none(),
-                started_at: chrono::Utc::now(),
-                output_loaded: true,
-                is_from_history: false,
+        if let Some(mut cmd) = self.store.get_command(&cmd_id) {
+            // switchboardd records (and pins) the revision of its own runs
+            let (revision, library_commit) = if self.daemon.is_none() {
+                (self.store.pin_revision(&cmd_id), self.store.library_of(&cmd_id).and_then(|l| l.commit))
+            } else {
+                (None, None)
             };
-            self.executions.push(state);
-            
-            // Switch view
-            self.navigate_to(Selection::Execution(exec_id));
-            
-            // Update active workflow if applicable
+
+            // A workflow step may run as a different user than the command
+            let mut password = password;
+            if let Some(active_wf) = &self.active_workflow
+                && let Some(wf) = self.store.get_workflow(&active_wf.workflow_id)
+            {
+                cmd.run_as = wf.run_as_for_step(active_wf.current_step_index, &cmd);
+                password = active_wf.password.clone();
+            }
+            // The executor can't ask, so hand it the answer from the prompt
+            if let Some(run_as) = &mut cmd.run_as
+                && run_as.password == EscalationPassword::Prompt
+                && let Some(password) = password
+            {
+                run_as.password = EscalationPassword::Entered(password);
+            }
+
             let mut execution_env_vars = HashMap::new();
             
             // 1. Command Defaults
@@ -486,8 +1365,7 @@ impl SwitchboardApp {
                 execution_env_vars.insert(v.key.clone(), v.value.clone());
             }
 
-             if let Some(active_wf) = &mut self.active_workflow {
-                 active_wf.current_execution_id = Some(exec_id);
+            if let Some(active_wf) = &self.active_workflow {
                  // 2. Workflow Overrides / Context
                  for (k, v) in &active_wf.resolved_env {
                      execution_env_vars.insert(k.clone(), v.clone());
@@ -498,55 +1376,137 @@ impl SwitchboardApp {
                     execution_env_vars.insert(k, v);
                 }
             }
-            
-            // Run
-            if let Err(e) = self.executor.execute(exec_id, &cmd, &dummy_host, execution_env_vars, cb, kill_rx) {
-                 eprintln!("Failed to start execution: {}", e);
+
+            // Inventory targets (picked or matched by tag) take precedence
+            // over the command's own host field.
+            let inventory = self.store.resolve_targets(&cmd);
+            let from_inventory = !inventory.is_empty();
+            let targets = if from_inventory { inventory } else { vec![cmd.adhoc_host()] };
+
+            let mut exec_ids = Vec::new();
+            for host in targets {
+                let exec_id = Uuid::new_v4();
+                let tx = self.execution_tx.clone();
+                
+                // Create kill channel
+                let (kill_tx, kill_rx) = channel();
+                
+                let cb = Box::new(move |update| {
+                    let _ = tx.send((exec_id, update));
+                });
+                
+                // Create State
+                let state = ExecutionState {
+                    id: exec_id,
+                    _command_id: cmd_id,
+                    host_id: if from_inventory { host.id } else { Uuid::nil() },
+                    command_name: if from_inventory { format!("{} @ {}", cmd.name, host.name) } else { cmd.name.clone() },
+                    output_buffer: String::new(),
+                    is_running: true,
+                    exit_code: None,
+                    kill_tx: Some(kill_tx),
+                    working_directory: cmd.working_directory.clone(),
+                    is_local: !from_inventory && cmd.host.is_none() && cmd.target == switchboard_core::HostKind::Ssh,
+                    started_at: chrono::Utc::now(),
+                    output_loaded: true,
+                    is_from_history: false,
+                    background: None,
+                    artifacts: Vec::new(),
+                    revision,
+                    library_commit: library_commit.clone(),
+                };
+                self.executions.push(state);
+                exec_ids.push(exec_id);
+                
+                // Run
+                if let Err(e) = self.executor.execute(exec_id, &cmd, &host, execution_env_vars.clone(), cb, kill_rx) {
+                     eprintln!("Failed to start execution: {}", e);
+                }
+            }
+
+            if let Some(active_wf) = &mut self.active_workflow {
+                active_wf.current_execution_ids = exec_ids.clone();
+            }
+
+            // Switch view
+            if let Some(first) = exec_ids.first() {
+                self.navigate_to(Selection::Execution(*first));
             }
         }
     }
 
     fn save_current_command(&mut self) {
-        if let Some(Selection::Command(cmd_id)) = self.active_selection {
-            if let Some(mut cmd) = self.store.get_command(&cmd_id) {
-                if let Some(edit_state) = &self.edited_command {
-                    edit_state.apply_to_command(&mut cmd);
-                    save_command(&self.store, &cmd);
-                }
-            }
+        if let Some(Selection::Command(cmd_id)) = self.active_selection
+            && self.store.library_of(&cmd_id).is_none()
+            && let Some(mut cmd) = self.store.get_command(&cmd_id)
+            && let Some(edit_state) = &self.edited_command
+        {
+            edit_state.apply_to_command(&mut cmd);
+            save_command(&self.store, &cmd);
         }
     }
     
     fn save_current_workflow(&mut self) {
-        if let Some(Selection::Workflow(wf_id)) = self.active_selection {
-            if let Some(mut wf) = self.store.get_workflow(&wf_id) {
-                if let Some(edit_state) = &self.edited_workflow {
-                    edit_state.apply_to_workflow(&mut wf);
-                    self.store.add_workflow(wf); // add_workflow acts as upsert
-                }
-            }
+        if let Some(Selection::Workflow(wf_id)) = self.active_selection
+            && self.store.library_of(&wf_id).is_none()
+            && let Some(mut wf) = self.store.get_workflow(&wf_id)
+            && let Some(edit_state) = &self.edited_workflow
+        {
+            edit_state.apply_to_workflow(&mut wf);
+            self.store.add_workflow(wf); // add_workflow acts as upsert
         }
     }
 
+    fn save_current_host(&mut self) {
+        if let Some(Selection::Host(host_id)) = self.active_selection
+            && let Some(mut host) = self.store.get_host(&host_id)
+            && let Some(edit_state) = &self.edited_host
+        {
+            edit_state.apply_to_host(&mut host);
+            self.store.add_host(host); // add_host acts as upsert
+        }
+    }
+
+    fn create_new_host(&mut self) {
+        let host = switchboard_core::Host {
+            id: Uuid::new_v4(),
+            name: "New Host".to_string(),
+            hostname: String::new(),
+            port: 22,
+            username: std::env::var("USER").unwrap_or_default(),
+            auth: switchboard_core::AuthMethod::Agent,
+            kind: Default::default(),
+            groups: Vec::new(),
+            tags: Vec::new(),
+            workspace: Default::default(),
+        };
+
+        self.store.add_host(host.clone());
+        self.navigate_to(Selection::Host(host.id));
+        self.edited_host = Some(HostEditState::from_host(&host));
+    }
+
+    /// Diagnose a host's connection in the background; the report lands in
+    /// `host_tests`.
+    fn test_host(&mut self, host_id: Uuid) {
+        let Some(host) = self.store.get_host(&host_id) else { return };
+        self.host_tests.insert(host_id, HostTestStatus::Running);
+
+        let executor = self.executor.clone();
+        let tx = self.host_test_tx.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send((host_id, executor.diagnose(&host)));
+        });
+    }
+
     fn create_new_command(&mut self) {
-        let id = Uuid::new_v4();
         let cmd = switchboard_core::models::Command {
-            id,
-            name: "New Command".to_string(),
-            description: None,
-            script: "".to_string(),
-            working_directory: None,
-            env_vars: Vec::new(),
-            host: None,
             user: std::env::var("USER").ok(),
-            target_hosts: Vec::new(),
-            created_at: chrono::Utc::now(),
-            background: false,
-            source_path: None,
+            ..switchboard_core::models::Command::new("New Command", "")
         };
 
         save_command(&self.store, &cmd);
-        self.navigate_to(Selection::Command(id));
+        self.navigate_to(Selection::Command(cmd.id));
         self.edited_command = Some(CommandEditState::from_command(&cmd));
     }
     
@@ -559,6 +1519,8 @@ impl SwitchboardApp {
             commands: Vec::new(),
             env_vars: Vec::new(),
             created_at: chrono::Utc::now(),
+            step_run_as: Vec::new(),
+            notify: Default::default(),
         };
 
         self.store.add_workflow(wf.clone());
@@ -595,31 +1557,127 @@ impl App for SwitchboardApp {
                             .pick_file() 
                         {
                             match std::fs::read_to_string(&path) {
-                                Ok(json) => {
-                                    if let Err(e) = self.store.import_json(&json) {
-                                         eprintln!("Failed to import store: {}", e);
-                                    } else {
-                                        // Reset selection as data has changed
-                                        self.active_selection = None;
-                                        self.edited_command = None;
-                                        self.edited_workflow = None;
-                                        self.active_workflow = None;
-                                        // TODO: Maybe reload or refresh specific UI parts if needed
+                                Ok(json) => match self.store.preview_merge(&json) {
+                                    Ok(plan) => self.store_import = Some(StoreImport { source: path, json: Some(json), plan }),
+                                    Err(e) => eprintln!("Failed to import store: {}", e),
+                                },
+                                Err(e) => eprintln!("Failed to read import file: {}", e),
+                            }
+                        }
+                        ui.close();
+                    }
+
+                    if ui.button("Export Bundle...").clicked() {
+                        self.bundle_export = Some(BundleExport::default());
+                        ui.close();
+                    }
+
+                    if ui.button("Import Bundle...").clicked() {
+                        if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                            match self.store.preview_bundle(&path) {
+                                Ok(plan) => self.store_import = Some(StoreImport { source: path, json: None, plan }),
+                                Err(e) => eprintln!("Failed to read bundle: {}", e),
+                            }
+                        }
+                        ui.close();
+                    }
+
+                    if ui.button("Import Scripts...").clicked() {
+                        if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                            match switchboard_core::script_import::from_directory(&path, &self.store.list_commands()) {
+                                Ok(candidates) => {
+                                    self.script_import = Some(ScriptImport {
+                                        source: path.display().to_string(),
+                                        history: false,
+                                        candidates,
+                                        filter: String::new(),
+                                    });
+                                }
+                                Err(e) => eprintln!("Failed to read scripts in {}: {}", path.display(), e),
+                            }
+                        }
+                        ui.close();
+                    }
+
+                    if ui.button("Import Shell History...").clicked() {
+                        use switchboard_core::script_import::{from_history, history_files, read_history};
+
+                        let mut entries = Vec::new();
+                        let mut sources = Vec::new();
+                        for file in history_files() {
+                            match read_history(&file) {
+                                Ok(lines) => {
+                                    for line in lines {
+                                        if !entries.contains(&line) {
+                                            entries.push(line);
+                                        }
                                     }
+                                    sources.push(file.display().to_string());
                                 }
-                                Err(e) => eprintln!("Failed to read import file: {}", e),
+                                Err(e) => eprintln!("Failed to read {}: {}", file.display(), e),
                             }
                         }
+                        self.script_import = Some(ScriptImport {
+                            source: if sources.is_empty() { "No shell history found".to_string() } else { sources.join(", ") },
+                            history: true,
+                            candidates: from_history(&entries, &self.store.list_commands()),
+                            filter: String::new(),
+                        });
                         ui.close();
                     }
 
-                    if ui.button("Snapshot State").clicked() {
-                        match self.store.snapshot_state() {
-                            Ok(hash) => println!("Snapshot created: {}", hash),
-                            Err(e) => eprintln!("Failed to create snapshot: {}", e),
+                    if ui.button("Import Ansible Inventory...").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("Inventory", &["ini", "yml", "yaml", "cfg", "hosts"])
+                            .add_filter("All files", &["*"])
+                            .pick_file()
+                        {
+                            match std::fs::read_to_string(&path) {
+                                Ok(text) => match switchboard_core::inventory::parse_inventory(&text) {
+                                    Ok(hosts) => {
+                                        self.inventory_import = Some(InventoryImport { source: path, hosts, resync: true });
+                                    }
+                                    Err(e) => eprintln!("Failed to parse inventory {}: {}", path.display(), e),
+                                },
+                                Err(e) => eprintln!("Failed to read inventory file: {}", e),
+                            }
                         }
                         ui.close();
                     }
+
+                    if ui.button("Notifications...").clicked() {
+                        self.edited_notifications = Some(self.store.get_settings().notifications);
+                        self.notification_tests.clear();
+                        ui.close();
+                    }
+                    if ui.button("HTTP API...").clicked() {
+                        self.edited_api = Some(self.store.get_settings().api);
+                        ui.close();
+                    }
+                    if ui.button("Libraries...").clicked() {
+                        self.edited_libraries = Some(self.store.get_settings().libraries);
+                        ui.close();
+                    }
+
+                    if ui.button("Snapshots...").clicked() {
+                        self.snapshot_browser = Some(SnapshotBrowser {
+                            label: String::new(),
+                            snapshots: self.store.list_snapshots(),
+                            compared: None,
+                            message: None,
+                        });
+                        ui.close();
+                    }
+                    if ui.button("Statistics...").clicked() {
+                        self.stats_view = Some(StatsView {
+                            days: 30,
+                            report: None,
+                            kind: switchboard_core::snapshots::ItemKind::Command,
+                            selected: None,
+                            message: None,
+                        });
+                        ui.close();
+                    }
                 });
             });
         });
@@ -646,7 +1704,12 @@ impl App for SwitchboardApp {
                                 .color(egui::Color32::from_rgb(255, 80, 80))
                         );
                         ui.add_space(10.0);
-                        ui.label("Are you sure you want to permanently delete this command?");
+                        let what = match self.active_selection {
+                            Some(Selection::Workflow(_)) => "workflow",
+                            Some(Selection::Host(_)) => "host",
+                            _ => "command",
+                        };
+                        ui.label(format!("Are you sure you want to permanently delete this {}?", what));
                         ui.add_space(15.0);
                         
                         ui.horizontal(|ui| {
@@ -674,6 +1737,11 @@ impl App for SwitchboardApp {
                                      self.store.remove_workflow(&wf_id);
                                      self.active_selection = None;
                                      self.edited_workflow = None;
+                                } else if let Some(Selection::Host(host_id)) = self.active_selection {
+                                     self.store.remove_host(&host_id);
+                                     self.host_tests.remove(&host_id);
+                                     self.active_selection = None;
+                                     self.edited_host = None;
                                 }
                                 self.show_delete_confirmation = false;
                             }
@@ -683,6 +1751,1149 @@ impl App for SwitchboardApp {
                 });
         }
         
+        // Inventory import preview
+        let mut import_confirmed = false;
+        let mut import_cancelled = false;
+        if let Some(import) = &mut self.inventory_import {
+            use switchboard_core::inventory::HostImportAction;
+
+            let plan = self.store.preview_host_import(import.hosts.clone(), import.resync);
+            egui::Window::new("Import Ansible Inventory")
+                .collapsible(false)
+                .resizable(true)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label(egui::RichText::new(import.source.display().to_string()).weak());
+                    ui.checkbox(&mut import.resync, "Re-sync: update existing hosts with the same name");
+                    ui.separator();
+
+                    egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                        egui::Grid::new("inventory_preview_grid").num_columns(4).striped(true).show(ui, |ui| {
+                            ui.label(egui::RichText::new("Host").strong());
+                            ui.label(egui::RichText::new("Address").strong());
+                            ui.label(egui::RichText::new("Groups").strong());
+                            ui.label(egui::RichText::new("Action").strong());
+                            ui.end_row();
+
+                            for item in &plan {
+                                ui.label(&item.host.name);
+                                ui.label(format!("{}@{}:{}", item.host.username, item.host.hostname, item.host.port));
+                                ui.label(item.host.groups.join(", "));
+                                let (text, color) = match item.action {
+                                    HostImportAction::Create => ("New", egui::Color32::from_rgb(100, 200, 100)),
+                                    HostImportAction::Update => ("Update", egui::Color32::from_rgb(230, 180, 80)),
+                                    HostImportAction::Unchanged => ("Unchanged", egui::Color32::GRAY),
+                                    HostImportAction::Skip => ("Exists, skipped", egui::Color32::GRAY),
+                                };
+                                ui.label(egui::RichText::new(text).color(color));
+                                ui.end_row();
+                            }
+                        });
+                    });
+
+                    ui.separator();
+                    let changes = plan.iter()
+                        .filter(|i| matches!(i.action, HostImportAction::Create | HostImportAction::Update))
+                        .count();
+                    ui.horizontal(|ui| {
+                        if ui.button("Cancel").clicked() {
+                            import_cancelled = true;
+                        }
+                        if ui.add_enabled(changes > 0, egui::Button::new(format!("Import {} host(s)", changes))).clicked() {
+                            import_confirmed = true;
+                        }
+                    });
+                });
+
+            if import_confirmed {
+                self.store.apply_host_import(&plan);
+                // The open editor may show a host that was just updated
+                if let Some(Selection::Host(id)) = self.active_selection
+                    && let Some(host) = self.store.get_host(&id)
+                {
+                    self.edited_host = Some(HostEditState::from_host(&host));
+                }
+            }
+        }
+        if import_confirmed || import_cancelled {
+            self.inventory_import = None;
+        }
+
+        // Bundle export
+        let mut bundle_done = false;
+        if let Some(export) = &mut self.bundle_export {
+            let mut commands = self.store.list_commands();
+            commands.sort_by(|a, b| a.name.cmp(&b.name));
+            let mut workflows = self.store.list_workflows();
+            workflows.sort_by(|a, b| a.name.cmp(&b.name));
+
+            egui::Window::new("Export Bundle")
+                .collapsible(false)
+                .resizable(true)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label("Commands and workflows are written as YAML files and .sh scripts into a folder, replacing an earlier export there. Workflows bring the commands they run along.");
+                    ui.separator();
+                    let toggle = |ui: &mut egui::Ui, picked: &mut std::collections::HashSet<Uuid>, id: Uuid, name: &str| {
+                        let mut on = picked.contains(&id);
+                        if ui.checkbox(&mut on, name).changed() {
+                            if on {
+                                picked.insert(id);
+                            } else {
+                                picked.remove(&id);
+                            }
+                        }
+                    };
+                    egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                        ui.strong("Workflows");
+                        if workflows.is_empty() {
+                            ui.label("None");
+                        }
+                        for workflow in &workflows {
+                            toggle(ui, &mut export.workflows, workflow.id, &workflow.name);
+                        }
+                        ui.add_space(6.0);
+                        ui.strong("Commands");
+                        for command in &commands {
+                            toggle(ui, &mut export.commands, command.id, &command.name);
+                        }
+                    });
+                    if let Some(error) = &export.error {
+                        ui.label(egui::RichText::new(error).color(egui::Color32::from_rgb(255, 100, 100)));
+                    }
+
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        if ui.button("Cancel").clicked() {
+                            bundle_done = true;
+                        }
+                        let picked = export.commands.len() + export.workflows.len();
+                        if ui.add_enabled(picked > 0, egui::Button::new("Export to Folder...")).clicked()
+                            && let Some(dir) = rfd::FileDialog::new().pick_folder()
+                        {
+                            let command_ids: Vec<Uuid> = export.commands.iter().copied().collect();
+                            let workflow_ids: Vec<Uuid> = export.workflows.iter().copied().collect();
+                            match self.store.export_bundle(&dir, &command_ids, &workflow_ids) {
+                                Ok(()) => bundle_done = true,
+                                Err(e) => export.error = Some(format!("Export failed: {}", e)),
+                            }
+                        }
+                    });
+                });
+        }
+        if bundle_done {
+            self.bundle_export = None;
+        }
+
+        // Script and shell history import preview
+        let mut script_import_done = false;
+        if let Some(import) = &mut self.script_import {
+            egui::Window::new(if import.history { "Import Shell History" } else { "Import Scripts" })
+                .collapsible(false)
+                .resizable(true)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label(egui::RichText::new(&import.source).weak());
+                    if import.history {
+                        ui.label("Tick the lines to keep as commands, newest first.");
+                        ui.horizontal(|ui| {
+                            ui.add(egui::TextEdit::singleline(&mut import.filter).hint_text("Filter").desired_width(240.0));
+                            let filter = import.filter.to_lowercase();
+                            if ui.small_button("Select Shown").clicked() {
+                                for candidate in &mut import.candidates {
+                                    candidate.selected |= candidate.command.script.to_lowercase().contains(&filter);
+                                }
+                            }
+                            if ui.small_button("Select None").clicked() {
+                                import.candidates.iter_mut().for_each(|c| c.selected = false);
+                            }
+                        });
+                    } else {
+                        ui.label("Scripts whose shebang names another interpreter than bash are handed to it when they run.");
+                    }
+                    ui.separator();
+
+                    let filter = import.filter.to_lowercase();
+                    egui::ScrollArea::vertical().max_height(360.0).show(ui, |ui| {
+                        egui::Grid::new("script_import_grid").num_columns(5).striped(true).show(ui, |ui| {
+                            ui.label("");
+                            ui.label(egui::RichText::new("Name").strong());
+                            ui.label(egui::RichText::new("Description").strong());
+                            ui.label(egui::RichText::new(if import.history { "Command" } else { "Interpreter" }).strong());
+                            ui.label("");
+                            ui.end_row();
+
+                            for candidate in &mut import.candidates {
+                                if import.history && !candidate.command.script.to_lowercase().contains(&filter) {
+                                    continue;
+                                }
+                                let command = &mut candidate.command;
+                                ui.checkbox(&mut candidate.selected, "");
+                                ui.add(egui::TextEdit::singleline(&mut command.name).desired_width(180.0));
+                                let mut description = command.description.clone().unwrap_or_default();
+                                if ui.add(egui::TextEdit::singleline(&mut description).desired_width(220.0)).changed() {
+                                    command.description = (!description.trim().is_empty()).then_some(description);
+                                }
+                                if import.history {
+                                    let first_line = command.script.lines().next().unwrap_or_default();
+                                    ui.label(egui::RichText::new(first_line).monospace()).on_hover_text(&command.script);
+                                } else {
+                                    ui.label(&candidate.interpreter).on_hover_text(&candidate.origin);
+                                }
+                                match &candidate.existing {
+                                    Some(name) => ui.label(egui::RichText::new(format!("Already saved as \"{}\"", name)).weak()),
+                                    None => ui.label(""),
+                                };
+                                ui.end_row();
+                            }
+                        });
+                    });
+
+                    ui.separator();
+                    let selected = import.candidates.iter().filter(|c| c.selected).count();
+                    ui.horizontal(|ui| {
+                        if ui.button("Cancel").clicked() {
+                            script_import_done = true;
+                        }
+                        if ui.add_enabled(selected > 0, egui::Button::new(format!("Import {} command(s)", selected))).clicked() {
+                            for candidate in import.candidates.iter().filter(|c| c.selected) {
+                                save_command(&self.store, &candidate.command);
+                            }
+                            script_import_done = true;
+                        }
+                    });
+                });
+        }
+        if script_import_done {
+            self.script_import = None;
+        }
+
+        // Store import preview
+        let mut store_imported = false;
+        let mut store_import_cancelled = false;
+        if let Some(import) = &mut self.store_import {
+            use switchboard_core::merge::{MergeAction, MergeConflict};
+            use switchboard_core::snapshots::ItemKind;
+
+            egui::Window::new("Import Store")
+                .collapsible(false)
+                .resizable(true)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label(egui::RichText::new(import.source.display().to_string()).weak());
+                    ui.label("Items are merged by id; nothing in this store is deleted. Run history isn't imported.");
+                    ui.separator();
+
+                    egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                        egui::Grid::new("store_import_grid").num_columns(4).striped(true).show(ui, |ui| {
+                            ui.label(egui::RichText::new("Kind").strong());
+                            ui.label(egui::RichText::new("Name").strong());
+                            ui.label(egui::RichText::new("Conflict").strong());
+                            ui.label(egui::RichText::new("Action").strong());
+                            ui.end_row();
+
+                            for (i, item) in import.plan.items.iter_mut().enumerate() {
+                                ui.label(match item.kind {
+                                    ItemKind::Command => "Command",
+                                    ItemKind::Workflow => "Workflow",
+                                    ItemKind::Host => "Host",
+                                });
+                                ui.label(&item.name);
+                                ui.label(match item.conflict {
+                                    MergeConflict::None => "",
+                                    MergeConflict::SameId => "Differs from mine",
+                                    MergeConflict::SameName(_) => "Name taken by another",
+                                });
+                                if item.choices().is_empty() {
+                                    let color = if item.action == MergeAction::Create { egui::Color32::from_rgb(100, 200, 100) } else { egui::Color32::GRAY };
+                                    ui.label(egui::RichText::new(item.action.label()).color(color));
+                                } else {
+                                    egui::ComboBox::from_id_salt(format!("store_import_action_{}", i))
+                                        .selected_text(item.action.label())
+                                        .show_ui(ui, |ui| {
+                                            for choice in item.choices() {
+                                                ui.selectable_value(&mut item.action, *choice, choice.label());
+                                            }
+                                        });
+                                }
+                                ui.end_row();
+                            }
+                        });
+                    });
+
+                    if !import.plan.problems.is_empty() {
+                        ui.separator();
+                        for problem in &import.plan.problems {
+                            ui.label(egui::RichText::new(format!("⚠ {}", problem)).color(egui::Color32::from_rgb(230, 180, 80)));
+                        }
+                    }
+
+                    ui.separator();
+                    let changes = import.plan.changes();
+                    ui.horizontal(|ui| {
+                        if ui.button("Cancel").clicked() {
+                            store_import_cancelled = true;
+                        }
+                        if ui.add_enabled(changes > 0, egui::Button::new(format!("Import {} item(s)", changes))).clicked() {
+                            self.store.apply_merge(&import.plan);
+                            store_imported = true;
+                        }
+                        if let Some(json) = &import.json
+                            && ui.button("Replace Everything")
+                                .on_hover_text("Replace this whole store, run history included, with the file. A snapshot is taken first.")
+                                .clicked()
+                        {
+                            match self.store.import_json(json) {
+                                Ok(()) => store_imported = true,
+                                Err(e) => eprintln!("Failed to import store: {}", e),
+                            }
+                        }
+                    });
+                });
+        }
+        if store_imported {
+            // Reset selection as data has changed
+            self.active_selection = None;
+            self.edited_command = None;
+            self.edited_workflow = None;
+            self.edited_host = None;
+            self.active_workflow = None;
+            self.revision_view = None;
+        }
+        if store_imported || store_import_cancelled {
+            self.store_import = None;
+        }
+
+        // HTTP API settings
+        let mut api_saved = false;
+        let mut api_cancelled = false;
+        if let Some(api) = &mut self.edited_api {
+            egui::Window::new("HTTP API")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label("Lets other tools list commands, start runs and follow their output.");
+                    ui.add_space(10.0);
+
+                    egui::Grid::new("api_settings_grid").num_columns(2).spacing([10.0, 10.0]).show(ui, |ui| {
+                        ui.label("Enabled:");
+                        ui.checkbox(&mut api.enabled, "");
+                        ui.end_row();
+
+                        ui.label("Listen on:");
+                        ui.add(egui::TextEdit::singleline(&mut api.bind).hint_text("127.0.0.1:7323"));
+                        ui.end_row();
+
+                        ui.label("Token:");
+                        ui.horizontal(|ui| {
+                            ui.add(egui::TextEdit::singleline(&mut api.token).password(true).interactive(false));
+                            if ui.small_button("📋").on_hover_text("Copy").clicked() {
+                                ctx.copy_text(api.token.clone());
+                            }
+                            if ui.small_button("🔄").on_hover_text("Generate a new token").clicked() {
+                                api.token = switchboard_core::ApiSettings::generate_token();
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Status:");
+                        match (&self.api_server, &self.api_error) {
+                            _ if self.daemon.is_some() => ui.label("Served by switchboardd"),
+                            (Some(server), _) => ui.label(format!("Listening on http://{}", server.addr())),
+                            (None, Some(e)) => ui.label(egui::RichText::new(e).color(egui::Color32::from_rgb(255, 100, 100))),
+                            (None, None) => ui.label("Stopped"),
+                        };
+                        ui.end_row();
+                    });
+
+                    let bind_host = api.bind.rsplit_once(':').map_or(api.bind.as_str(), |(host, _)| host);
+                    if !matches!(bind_host, "127.0.0.1" | "localhost" | "[::1]") {
+                        ui.label(egui::RichText::new("⚠ Reachable from other machines").color(egui::Color32::from_rgb(230, 180, 80)));
+                    }
+
+                    ui.add_space(15.0);
+                    ui.horizontal(|ui| {
+                        if ui.button("Cancel").clicked() {
+                            api_cancelled = true;
+                        }
+                        if ui.button("Save").clicked() {
+                            api_saved = true;
+                        }
+                    });
+                });
+        }
+        if api_saved
+            && let Some(mut api) = self.edited_api.take()
+        {
+            if api.token.is_empty() {
+                api.token = switchboard_core::ApiSettings::generate_token();
+            }
+            let mut settings = self.store.get_settings();
+            settings.api = api;
+            self.store.set_settings(settings);
+            self.restart_api_server();
+        }
+        if api_cancelled {
+            self.edited_api = None;
+        }
+
+        // Notification settings
+        let mut notifications_saved = false;
+        let mut notifications_cancelled = false;
+        while let Ok((index, outcome)) = self.notification_test_rx.try_recv() {
+            self.notification_tests.insert(index, outcome);
+        }
+        if let Some(notifications) = &mut self.edited_notifications {
+            use switchboard_core::NotificationChannelKind;
+
+            egui::Window::new("Notifications")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label("Sent when a command or workflow set to notify finishes.");
+                    ui.add_space(10.0);
+
+                    let mut remove_idx = None;
+                    egui::Grid::new("notification_channels_grid").num_columns(5).spacing([10.0, 8.0]).show(ui, |ui| {
+                        for (i, channel) in notifications.channels.iter_mut().enumerate() {
+                            ui.checkbox(&mut channel.enabled, "").on_hover_text("Enabled");
+                            ui.add(egui::TextEdit::singleline(&mut channel.name).hint_text("Name").desired_width(120.0));
+
+                            let current = match &channel.kind {
+                                NotificationChannelKind::Webhook { .. } => "Webhook",
+                                NotificationChannelKind::Slack { .. } => "Slack",
+                                NotificationChannelKind::Desktop => "Desktop",
+                            };
+                            egui::ComboBox::from_id_salt(format!("notification_kind_{}", i))
+                                .selected_text(current)
+                                .show_ui(ui, |ui| {
+                                    for option in ["Webhook", "Slack", "Desktop"] {
+                                        if ui.selectable_label(current == option, option).clicked() && current != option {
+                                            let url = match &channel.kind {
+                                                NotificationChannelKind::Webhook { url } | NotificationChannelKind::Slack { url } => url.clone(),
+                                                NotificationChannelKind::Desktop => String::new(),
+                                            };
+                                            channel.kind = match option {
+                                                "Webhook" => NotificationChannelKind::Webhook { url },
+                                                "Slack" => NotificationChannelKind::Slack { url },
+                                                _ => NotificationChannelKind::Desktop,
+                                            };
+                                        }
+                                    }
+                                });
+
+                            match &mut channel.kind {
+                                NotificationChannelKind::Webhook { url } | NotificationChannelKind::Slack { url } => {
+                                    ui.add(egui::TextEdit::singleline(url).hint_text("https://...").desired_width(260.0));
+                                }
+                                NotificationChannelKind::Desktop => {
+                                    ui.label("");
+                                }
+                            }
+
+                            ui.horizontal(|ui| {
+                                if ui.small_button("Test").clicked() {
+                                    self.notification_tests.insert(i, "Sending...".to_string());
+                                    let tx = self.notification_test_tx.clone();
+                                    let channel = channel.clone();
+                                    let template = notifications.template.clone();
+                                    std::thread::spawn(move || {
+                                        let sample = switchboard_core::notifications::Notification {
+                                            source: switchboard_core::notifications::NotificationSource::Command,
+                                            id: Uuid::nil(),
+                                            run_id: None,
+                                            name: "Test notification".to_string(),
+                                            host: "localhost".to_string(),
+                                            exit_code: 0,
+                                            duration_ms: 1500,
+                                            log_tail: "Sent from Switchboard's notification settings".to_string(),
+                                        };
+                                        let outcome = match switchboard_core::notifications::send(&channel, &sample, &template) {
+                                            Ok(()) => "Sent".to_string(),
+                                            Err(e) => e.to_string(),
+                                        };
+                                        let _ = tx.send((i, outcome));
+                                    });
+                                }
+                                if ui.small_button("🗑").clicked() {
+                                    remove_idx = Some(i);
+                                }
+                                if let Some(outcome) = self.notification_tests.get(&i) {
+                                    ui.label(outcome);
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+                    if let Some(i) = remove_idx {
+                        notifications.channels.remove(i);
+                        self.notification_tests.clear();
+                    }
+                    if ui.button("➕ Add Channel").clicked() {
+                        notifications.channels.push(switchboard_core::NotificationChannel {
+                            name: String::new(),
+                            kind: NotificationChannelKind::Webhook { url: String::new() },
+                            enabled: true,
+                        });
+                    }
+
+                    ui.add_space(10.0);
+                    ui.label("Message:");
+                    ui.add(egui::TextEdit::multiline(&mut notifications.template).desired_rows(3).desired_width(f32::INFINITY));
+                    ui.label(
+                        egui::RichText::new("{name}, {status}, {exit_code}, {duration}, {host} and {log_tail} are filled in").weak(),
+                    );
+
+                    ui.add_space(15.0);
+                    ui.horizontal(|ui| {
+                        if ui.button("Cancel").clicked() {
+                            notifications_cancelled = true;
+                        }
+                        if ui.button("Save").clicked() {
+                            notifications_saved = true;
+                        }
+                    });
+                });
+        }
+        if notifications_saved
+            && let Some(notifications) = self.edited_notifications.take()
+        {
+            let mut settings = self.store.get_settings();
+            settings.notifications = notifications;
+            self.store.set_settings(settings);
+        }
+        if notifications_cancelled {
+            self.edited_notifications = None;
+        }
+
+        // Libraries
+        let mut libraries_saved = false;
+        let mut libraries_cancelled = false;
+        if let Some(sources) = &mut self.edited_libraries {
+            let loaded = self.store.list_libraries();
+            egui::Window::new("Libraries")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(ctx, |ui| {
+                    ui.label("Commands and workflows from a checkout of a shared repository, in the bundle layout.");
+                    ui.label("They can't be changed here: fork a command to make your own copy.");
+                    ui.add_space(10.0);
+
+                    let mut remove_idx = None;
+                    egui::Grid::new("libraries_grid").num_columns(5).spacing([10.0, 8.0]).show(ui, |ui| {
+                        for (i, source) in sources.iter_mut().enumerate() {
+                            ui.checkbox(&mut source.enabled, "").on_hover_text("Enabled");
+                            ui.add(egui::TextEdit::singleline(&mut source.name).hint_text("Name").desired_width(120.0));
+                            ui.horizontal(|ui| {
+                                ui.label(egui::RichText::new(source.path.display().to_string()).monospace());
+                                if ui.small_button("📁").on_hover_text("Choose the checkout").clicked()
+                                    && let Some(path) = rfd::FileDialog::new().set_directory(&source.path).pick_folder()
+                                {
+                                    source.path = path;
+                                }
+                            });
+                            match loaded.iter().find(|l| l.source == *source) {
+                                Some(library) => match (&library.error, &library.commit) {
+                                    (Some(e), _) => { ui.label(egui::RichText::new(e).color(egui::Color32::from_rgb(255, 100, 100))); }
+                                    (None, commit) => {
+                                        let at = commit.as_deref().map(|c| format!(" at {}", short_commit(c))).unwrap_or_default();
+                                        ui.label(format!("{} commands, {} workflows{}", library.commands.len(), library.workflows.len(), at));
+                                    }
+                                },
+                                None => { ui.label(egui::RichText::new("Not loaded").weak()); }
+                            }
+                            if ui.small_button("🗑").clicked() {
+                                remove_idx = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    if let Some(i) = remove_idx {
+                        sources.remove(i);
+                    }
+                    if ui.button("➕ Add Library...").clicked()
+                        && let Some(path) = rfd::FileDialog::new().pick_folder()
+                    {
+                        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                        sources.push(switchboard_core::LibrarySource { name, path, enabled: true });
+                    }
+
+                    ui.add_space(15.0);
+                    ui.horizontal(|ui| {
+                        if ui.button("Cancel").clicked() {
+                            libraries_cancelled = true;
+                        }
+                        if ui.button("Save").clicked() {
+                            libraries_saved = true;
+                        }
+                    });
+                });
+        }
+        if libraries_saved
+            && let Some(sources) = self.edited_libraries.take()
+        {
+            let mut settings = self.store.get_settings();
+            settings.libraries = sources;
+            self.store.set_settings(settings);
+            // Runs of commands in libraries just added
+            for state in history_executions(&self.store) {
+                if !self.executions.iter().any(|e| e.id == state.id) {
+                    self.executions.push(state);
+                }
+            }
+            self.executions.sort_by_key(|e| e.started_at);
+        }
+        if libraries_cancelled {
+            self.edited_libraries = None;
+        }
+
+        // Snapshots
+        let mut snapshots_open = true;
+        let mut snapshot_restored = false;
+        if let Some(browser) = &mut self.snapshot_browser {
+            use switchboard_core::snapshots::{ItemChange, ItemKind, SnapshotKind};
+
+            let mut settings = self.store.get_settings();
+            egui::Window::new("Snapshots")
+                .open(&mut snapshots_open)
+                .default_size([640.0, 480.0])
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut browser.label).hint_text("Label (optional)").desired_width(220.0));
+                        if ui.button("📸 Take Snapshot").clicked() {
+                            browser.message = Some(match self.store.create_snapshot(browser.label.trim(), SnapshotKind::Manual) {
+                                Ok(info) => format!("Saved snapshot {}", &info.hash[..12]),
+                                Err(e) => format!("Failed to take a snapshot: {}", e),
+                            });
+                            browser.label.clear();
+                            browser.snapshots = self.store.list_snapshots();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let mut changed = false;
+                        ui.label("Automatically every");
+                        changed |= ui.add(egui::DragValue::new(&mut settings.snapshots.interval_hours).range(0..=720)).changed();
+                        ui.label("hours (0: never), keeping");
+                        changed |= ui.add(egui::DragValue::new(&mut settings.snapshots.keep).range(1..=1000)).changed();
+                        ui.label("automatic snapshots");
+                        if changed {
+                            self.store.set_settings(settings.clone());
+                        }
+                        if ui.button("Prune Now").clicked() {
+                            browser.message = Some(match self.store.prune_snapshots(settings.snapshots.keep) {
+                                Ok(pruned) => format!("Deleted {} old snapshots", pruned),
+                                Err(e) => format!("Failed to prune snapshots: {}", e),
+                            });
+                            browser.snapshots = self.store.list_snapshots();
+                        }
+                    });
+                    if let Some(message) = &browser.message {
+                        ui.label(egui::RichText::new(message).weak());
+                    }
+                    ui.separator();
+
+                    let mut to_delete = None;
+                    egui::ScrollArea::vertical().id_salt("snapshot_list_scroll").max_height(200.0).show(ui, |ui| {
+                        if browser.snapshots.is_empty() {
+                            ui.label("No snapshots yet.");
+                        }
+                        egui::Grid::new("snapshot_list").num_columns(5).spacing([10.0, 4.0]).show(ui, |ui| {
+                            for snapshot in &browser.snapshots {
+                                ui.label(snapshot.created_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string());
+                                ui.label(snapshot.kind.label());
+                                ui.label(&snapshot.label);
+                                ui.monospace(&snapshot.hash[..12]).on_hover_text(&snapshot.hash);
+                                ui.horizontal(|ui| {
+                                    let comparing = browser.compared.as_ref().is_some_and(|(hash, _, _)| *hash == snapshot.hash);
+                                    if ui.selectable_label(comparing, "Compare").clicked() {
+                                        match self.store.diff_snapshot(&snapshot.hash) {
+                                            Ok(diffs) => browser.compared = Some((snapshot.hash.clone(), diffs, Default::default())),
+                                            Err(e) => browser.message = Some(format!("Failed to read snapshot: {}", e)),
+                                        }
+                                    }
+                                    if ui.small_button("🗑").on_hover_text("Delete").clicked() {
+                                        to_delete = Some(snapshot.hash.clone());
+                                    }
+                                });
+                                ui.end_row();
+                            }
+                        });
+                    });
+                    if let Some(hash) = to_delete {
+                        if let Err(e) = self.store.delete_snapshot(&hash) {
+                            browser.message = Some(format!("Failed to delete snapshot: {}", e));
+                        }
+                        if browser.compared.as_ref().is_some_and(|(compared, _, _)| *compared == hash) {
+                            browser.compared = None;
+                        }
+                        browser.snapshots = self.store.list_snapshots();
+                    }
+
+                    let Some((hash, diffs, picked)) = &mut browser.compared else { return };
+                    ui.separator();
+                    ui.strong(format!("Snapshot {} compared with now", &hash[..12]));
+                    if diffs.is_empty() {
+                        ui.label("Commands, workflows and hosts are the same as now.");
+                    }
+                    egui::ScrollArea::vertical().id_salt("snapshot_diff_scroll").max_height(200.0).show(ui, |ui| {
+                        for (i, diff) in diffs.iter().enumerate() {
+                            let kind = match diff.item.kind {
+                                ItemKind::Command => "Command",
+                                ItemKind::Workflow => "Workflow",
+                                ItemKind::Host => "Host",
+                            };
+                            let change = match diff.change {
+                                ItemChange::Added => "created since (restoring deletes it)",
+                                ItemChange::Removed => "deleted since",
+                                ItemChange::Changed => "changed since",
+                            };
+                            let mut restore = picked.contains(&i);
+                            if ui.checkbox(&mut restore, format!("{} {}: {}", kind, diff.name, change)).changed() {
+                                if restore {
+                                    picked.insert(i);
+                                } else {
+                                    picked.remove(&i);
+                                }
+                            }
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let mut items = None;
+                        if ui.add_enabled(!picked.is_empty(), egui::Button::new("Restore Selected")).clicked() {
+                            items = Some(Some(picked.iter().map(|i| diffs[*i].item).collect::<Vec<_>>()));
+                        }
+                        if ui.button("Restore Everything").on_hover_text("Commands, workflows, hosts and settings; run history is kept").clicked() {
+                            items = Some(None);
+                        }
+                        if let Some(items) = items {
+                            match self.store.restore_snapshot(hash, items) {
+                                Ok(()) => {
+                                    browser.message = Some(format!("Restored from snapshot {}", &hash[..12]));
+                                    snapshot_restored = true;
+                                }
+                                Err(e) => browser.message = Some(format!("Failed to restore: {}", e)),
+                            }
+                        }
+                    });
+                });
+            if snapshot_restored {
+                browser.snapshots = self.store.list_snapshots();
+                browser.compared = None;
+            }
+        }
+        if !snapshots_open {
+            self.snapshot_browser = None;
+        }
+        if snapshot_restored {
+            // What's being edited may have changed underneath
+            self.active_selection = None;
+            self.edited_command = None;
+            self.edited_workflow = None;
+            self.edited_host = None;
+            self.revision_view = None;
+        }
+
+        // Command revisions
+        let mut revision_to_restore = None;
+        let mut revisions_open = true;
+        if let Some(view) = &mut self.revision_view {
+            use switchboard_core::diff::{DiffKind, changed_settings, side_by_side};
+
+            let revisions = self.store.list_revisions(&view.command_id);
+            match self.store.get_command(&view.command_id) {
+                None => revisions_open = false,
+                Some(current) => {
+                    // The version before the current one is the likeliest to compare with
+                    if view.selected.is_none_or(|id| !revisions.iter().any(|r| r.id == id)) {
+                        view.selected = revisions.get(1).or(revisions.first()).map(|r| r.id);
+                    }
+
+                    egui::Window::new(format!("History: {}", current.name))
+                        .open(&mut revisions_open)
+                        .default_size([900.0, 500.0])
+                        .show(ctx, |ui| {
+                            if revisions.is_empty() {
+                                ui.label("No revisions yet. They're recorded as the command is edited.");
+                                return;
+                            }
+
+                            egui::SidePanel::left("revision_list").resizable(false).show_inside(ui, |ui| {
+                                egui::ScrollArea::vertical().id_salt("revision_list_scroll").show(ui, |ui| {
+                                    for revision in &revisions {
+                                        let time = revision.saved_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M");
+                                        let mut label = format!("r{}  {}", revision.number, time);
+                                        if revision.matches(&current) {
+                                            label.push_str("  (current)");
+                                        }
+                                        if revision.pinned {
+                                            label.push_str("  ▶");
+                                        }
+                                        let response = ui.selectable_label(view.selected == Some(revision.id), label);
+                                        let response = if revision.pinned { response.on_hover_text("Used by a run") } else { response };
+                                        if response.clicked() {
+                                            view.selected = Some(revision.id);
+                                        }
+                                    }
+                                });
+                            });
+
+                            egui::CentralPanel::default().show_inside(ui, |ui| {
+                                let Some(revision) = revisions.iter().find(|r| Some(r.id) == view.selected) else { return };
+                                ui.horizontal(|ui| {
+                                    ui.strong(format!("r{} (left) compared with the current version (right)", revision.number));
+                                    ui.add_space(10.0);
+                                    if ui.add_enabled(!revision.matches(&current), egui::Button::new(format!("↩ Restore r{}", revision.number))).clicked() {
+                                        revision_to_restore = Some((revision.command_id, revision.id));
+                                    }
+                                });
+                                let settings = changed_settings(&revision.command, &current);
+                                if !settings.is_empty() {
+                                    ui.label(egui::RichText::new(format!("Also changed: {}", settings.join(", ").replace('_', " "))).weak());
+                                }
+                                ui.separator();
+
+                                let rows = side_by_side(&revision.command.script, &current.script);
+                                if rows.iter().all(|r| r.kind == DiffKind::Same) {
+                                    ui.label(egui::RichText::new("The script is the same.").weak());
+                                }
+                                diff_grid(ui, "revision_diff", &rows, None);
+                            });
+                        });
+                }
+            }
+        }
+        if !revisions_open {
+            self.revision_view = None;
+        }
+
+        // Run comparison
+        let mut compare_open = true;
+        if let Some(compare) = &mut self.run_compare {
+            // Long logs can take a while to diff, so it's done off the UI thread
+            if let Some(rx) = &compare.computing {
+                match rx.try_recv() {
+                    Ok(comparison) => {
+                        compare.comparison = comparison;
+                        compare.computing = None;
+                    }
+                    Err(std::sync::mpsc::TryRecvError::Empty) => {}
+                    Err(std::sync::mpsc::TryRecvError::Disconnected) => compare.computing = None,
+                }
+            }
+            if compare.outdated && compare.computing.is_none() {
+                compare.outdated = false;
+                let (tx, rx) = channel();
+                let store = self.store.clone();
+                let (old, new, normalize) = (compare.old, compare.new, compare.normalize);
+                let ctx = ctx.clone();
+                std::thread::spawn(move || {
+                    let _ = tx.send(store.compare_executions(&old, &new, normalize));
+                    ctx.request_repaint();
+                });
+                compare.computing = Some(rx);
+            }
+            egui::Window::new("Compare Runs")
+                .open(&mut compare_open)
+                .resizable(true)
+                .default_size([900.0, 600.0])
+                .show(ctx, |ui| {
+                    let Some(comparison) = &compare.comparison else {
+                        if compare.computing.is_some() {
+                            ui.horizontal(|ui| {
+                                ui.spinner();
+                                ui.label("Comparing…");
+                            });
+                        } else {
+                            ui.label("One of the runs is no longer recorded.");
+                        }
+                        return;
+                    };
+                    let mut recompute = false;
+                    ui.horizontal(|ui| {
+                        recompute |= ui
+                            .checkbox(&mut compare.normalize, "Mask timestamps and ids")
+                            .on_hover_text("Replace what matches the patterns below in both logs before comparing")
+                            .changed();
+                        ui.checkbox(&mut compare.changes_only, "Changed lines only");
+                        if compare.computing.is_some() {
+                            ui.spinner();
+                        }
+                    });
+                    ui.separator();
+
+                    egui::Grid::new("run_compare_fields").num_columns(3).spacing([16.0, 4.0]).show(ui, |ui| {
+                        ui.label("");
+                        ui.label(egui::RichText::new("Earlier").strong());
+                        ui.label(egui::RichText::new("Later").strong());
+                        ui.end_row();
+                        for field in &comparison.fields {
+                            ui.label(field.name);
+                            ui.label(&field.old);
+                            let new = egui::RichText::new(&field.new);
+                            ui.label(if field.differs { new.color(egui::Color32::from_rgb(230, 200, 90)) } else { new });
+                            ui.end_row();
+                        }
+                    });
+                    for note in &comparison.notes {
+                        ui.label(egui::RichText::new(format!("⚠ {}", note)).color(egui::Color32::from_rgb(230, 180, 80)));
+                    }
+
+                    egui::CollapsingHeader::new("Patterns").id_salt("run_compare_patterns").show(ui, |ui| {
+                        let mut remove_idx = None;
+                        egui::Grid::new("run_compare_normalizers").num_columns(5).spacing([8.0, 4.0]).show(ui, |ui| {
+                            for (i, normalizer) in compare.normalizers.iter_mut().enumerate() {
+                                ui.checkbox(&mut normalizer.enabled, "").on_hover_text("Enabled");
+                                ui.add(egui::TextEdit::singleline(&mut normalizer.name).hint_text("Name").desired_width(100.0));
+                                ui.add(egui::TextEdit::singleline(&mut normalizer.pattern).hint_text("Regular expression").code_editor().desired_width(320.0));
+                                ui.add(egui::TextEdit::singleline(&mut normalizer.replacement).hint_text("Replacement").desired_width(100.0));
+                                if ui.small_button("🗑").clicked() {
+                                    remove_idx = Some(i);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                        if let Some(i) = remove_idx {
+                            compare.normalizers.remove(i);
+                        }
+                        ui.horizontal(|ui| {
+                            if ui.button("➕ Add Pattern").clicked() {
+                                compare.normalizers.push(switchboard_core::Normalizer {
+                                    name: String::new(),
+                                    pattern: String::new(),
+                                    replacement: String::new(),
+                                    enabled: true,
+                                });
+                            }
+                            if ui.button("Apply").on_hover_text("Save the patterns and compare again").clicked() {
+                                let mut settings = self.store.get_settings();
+                                settings.compare.normalizers = compare.normalizers.clone();
+                                self.store.set_settings(settings);
+                                recompute = true;
+                            }
+                        });
+                    });
+                    ui.separator();
+
+                    match comparison.changed_lines() {
+                        0 => ui.label(egui::RichText::new("The logs are the same.").weak()),
+                        n => ui.label(format!("{} line(s) differ", n)),
+                    };
+                    diff_grid(ui, "run_compare_diff", &comparison.rows, compare.changes_only.then_some(3));
+
+                    if recompute {
+                        compare.outdated = true;
+                    }
+                });
+        }
+        if !compare_open {
+            self.run_compare = None;
+        }
+
+        // Statistics
+        let mut stats_open = true;
+        if let Some(view) = &mut self.stats_view {
+            use egui_plot::{Bar, BarChart, GridMark, Legend, Line, Plot};
+            use switchboard_core::notifications::format_duration;
+            use switchboard_core::snapshots::ItemKind;
+
+            if view.report.is_none() {
+                view.report = Some(self.store.stats(chrono::Utc::now() - chrono::Duration::days(view.days)));
+            }
+            let mut refresh = false;
+            let StatsView { days: period, report, kind, selected, message } = view;
+            egui::Window::new("Statistics")
+                .open(&mut stats_open)
+                .resizable(true)
+                .default_size([860.0, 680.0])
+                .show(ctx, |ui| {
+                    let Some(report) = report.as_ref() else { return };
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_salt("stats_period")
+                            .selected_text(format!("Last {} days", period))
+                            .show_ui(ui, |ui| {
+                                for days in [7, 30, 90, 365] {
+                                    refresh |= ui.selectable_value(period, days, format!("Last {} days", days)).changed();
+                                }
+                            });
+                        refresh |= ui.button("🔄 Refresh").clicked();
+                        ui.separator();
+                        for (label, file_name, daily) in [
+                            ("Export CSV...", "switchboard-stats.csv", false),
+                            ("Export Daily CSV...", "switchboard-stats-daily.csv", true),
+                        ] {
+                            if ui.button(label).clicked()
+                                && let Some(path) = rfd::FileDialog::new().add_filter("CSV", &["csv"]).set_file_name(file_name).save_file()
+                            {
+                                let csv = if daily { report.daily_csv() } else { report.summary_csv() };
+                                *message = Some(match std::fs::write(&path, csv) {
+                                    Ok(()) => format!("Saved {}", path.display()),
+                                    Err(e) => format!("Failed to write {}: {}", path.display(), e),
+                                });
+                            }
+                        }
+                    });
+                    if let Some(message) = message {
+                        ui.label(egui::RichText::new(message.as_str()).weak());
+                    }
+                    ui.separator();
+
+                    // Charts, of the selected item or of all command runs
+                    let shown = selected.and_then(|s| report.items.iter().find(|i| i.item == s));
+                    let days = shown.map_or(&report.days, |i| &i.days);
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(shown.map_or("All commands", |i| i.name.as_str())).strong());
+                        if shown.is_some() && ui.small_button("Show all").clicked() {
+                            *selected = None;
+                        }
+                    });
+                    let labels: Vec<String> = days.iter().map(|d| d.day.format("%m-%d").to_string()).collect();
+                    let date_axis = move |mark: GridMark, _: &std::ops::RangeInclusive<f64>| {
+                        if mark.value.fract() != 0.0 || mark.value < 0.0 {
+                            return String::new();
+                        }
+                        labels.get(mark.value as usize).cloned().unwrap_or_default()
+                    };
+                    let succeeded = BarChart::new(
+                        "Succeeded",
+                        days.iter().enumerate().map(|(x, d)| Bar::new(x as f64, (d.runs - d.failures) as f64)).collect(),
+                    )
+                    .color(egui::Color32::from_rgb(100, 180, 100));
+                    let failed = BarChart::new(
+                        "Failed",
+                        days.iter().enumerate().map(|(x, d)| Bar::new(x as f64, d.failures as f64)).collect(),
+                    )
+                    .color(egui::Color32::from_rgb(220, 90, 90))
+                    .stack_on(&[&succeeded]);
+                    Plot::new("stats_runs")
+                        .height(160.0)
+                        .legend(Legend::default())
+                        .x_axis_formatter(date_axis.clone())
+                        .y_axis_label("Runs")
+                        .include_y(0.0)
+                        .allow_zoom(false)
+                        .allow_drag(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot| {
+                            plot.bar_chart(succeeded);
+                            plot.bar_chart(failed);
+                        });
+                    let success: Vec<[f64; 2]> = days
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(x, d)| d.success_rate().map(|r| [x as f64, r * 100.0]))
+                        .collect();
+                    let durations: Vec<[f64; 2]> = days
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(x, d)| d.median_duration_ms.map(|ms| [x as f64, ms as f64 / 1000.0]))
+                        .collect();
+                    ui.columns(2, |columns| {
+                        Plot::new("stats_success")
+                            .height(140.0)
+                            .x_axis_formatter(date_axis.clone())
+                            .y_axis_label("Success %")
+                            .include_y(0.0)
+                            .include_y(100.0)
+                            .allow_zoom(false)
+                            .allow_drag(false)
+                            .allow_scroll(false)
+                            .show(&mut columns[0], |plot| plot.line(Line::new("Success rate", success)));
+                        Plot::new("stats_duration")
+                            .height(140.0)
+                            .x_axis_formatter(date_axis)
+                            .y_axis_label("Median (s)")
+                            .include_y(0.0)
+                            .allow_zoom(false)
+                            .allow_drag(false)
+                            .allow_scroll(false)
+                            .show(&mut columns[1], |plot| plot.line(Line::new("Median duration", durations)));
+                    });
+                    ui.separator();
+
+                    ui.horizontal(|ui| {
+                        for (item_kind, label) in [(ItemKind::Command, "Commands"), (ItemKind::Workflow, "Workflows"), (ItemKind::Host, "Hosts")] {
+                            ui.selectable_value(kind, item_kind, label);
+                        }
+                    });
+                    let failing = report.most_failing(*kind);
+                    if !failing.is_empty() {
+                        ui.label(egui::RichText::new("Most failing").strong());
+                        for item in failing.iter().take(5) {
+                            let rate = item.success_rate().unwrap_or_default() * 100.0;
+                            ui.label(format!("{}: {} of {} runs failed ({:.0}% succeeded)", item.name, item.failures, item.runs, rate));
+                        }
+                        ui.add_space(4.0);
+                    }
+
+                    let items: Vec<_> = report.items_of(*kind).collect();
+                    if items.is_empty() {
+                        ui.label(egui::RichText::new("No runs in this period.").weak());
+                        return;
+                    }
+                    egui::ScrollArea::vertical().id_salt("stats_items").show(ui, |ui| {
+                        egui::Grid::new("stats_items_grid").num_columns(9).striped(true).spacing([16.0, 4.0]).show(ui, |ui| {
+                            for heading in ["Name", "Runs", "Failures", "Success", "p50", "p90", "p99", "Trend", "Last run"] {
+                                ui.label(egui::RichText::new(heading).strong());
+                            }
+                            ui.end_row();
+                            let duration = |ms: Option<u64>| ms.map(format_duration).unwrap_or_else(|| "—".to_string());
+                            for item in items {
+                                let is_selected = *selected == Some(item.item);
+                                if ui.selectable_label(is_selected, &item.name).on_hover_text("Chart this one's days").clicked() {
+                                    *selected = if is_selected { None } else { Some(item.item) };
+                                }
+                                ui.label(item.runs.to_string());
+                                ui.label(item.failures.to_string());
+                                ui.label(item.success_rate().map(|r| format!("{:.0}%", r * 100.0)).unwrap_or_default());
+                                ui.label(duration(item.p50_ms));
+                                ui.label(duration(item.p90_ms));
+                                ui.label(duration(item.p99_ms));
+                                match item.duration_trend {
+                                    Some(trend) => {
+                                        let text = egui::RichText::new(format!("{:+.0}%", trend * 100.0));
+                                        let text = if trend > 0.1 {
+                                            text.color(egui::Color32::from_rgb(230, 140, 80))
+                                        } else if trend < -0.1 {
+                                            text.color(egui::Color32::from_rgb(100, 180, 100))
+                                        } else {
+                                            text
+                                        };
+                                        ui.label(text).on_hover_text("Median duration in the second half of the period against the first");
+                                    }
+                                    None => {
+                                        ui.label("—");
+                                    }
+                                }
+                                ui.label(
+                                    item.last_run
+                                        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                                        .unwrap_or_default(),
+                                );
+                                ui.end_row();
+                            }
+                        });
+                    });
+                });
+            if refresh {
+                view.report = None;
+            }
+        }
+        if !stats_open {
+            self.stats_view = None;
+        }
+        if let Some((command_id, revision_id)) = revision_to_restore {
+            // Keep pending edits of another command, then show the restored version
+            self.save_current_command();
+            if let Some(restored) = self.store.restore_revision(&command_id, &revision_id)
+                && self.active_selection == Some(Selection::Command(command_id))
+            {
+                self.edited_command = Some(CommandEditState::from_command(&restored));
+            }
+        }
+
         // Pending Execution Prompt
         let mut confirmed_pending = false;
         let mut cancelled_pending = false;
@@ -692,7 +2903,11 @@ impl App for SwitchboardApp {
                 .resizable(false)
                 .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
                 .show(ctx, |ui| {
-                     ui.label("Please provide values for the following variables:");
+                     if pending.vars_to_ask.is_empty() {
+                         ui.label("Please enter the password to run as another user:");
+                     } else {
+                         ui.label("Please provide values for the following variables:");
+                     }
                      ui.add_space(10.0);
                      
                      egui::Grid::new("prompt_grid").num_columns(2).spacing([10.0, 10.0]).show(ui, |ui| {
@@ -701,6 +2916,11 @@ impl App for SwitchboardApp {
                              ui.text_edit_singleline(&mut var.value);
                              ui.end_row();
                          }
+                         if let Some(password) = &mut pending.password {
+                             ui.label("Run as password");
+                             ui.add(egui::TextEdit::singleline(password).password(true));
+                             ui.end_row();
+                         }
                      });
                      
                      ui.add_space(15.0);
@@ -719,33 +2939,85 @@ impl App for SwitchboardApp {
             self.pending_execution = None;
         }
         
-        if confirmed_pending {
-            if let Some(pending) = self.pending_execution.take() {
-                // Merge initial vars with user inputs
-                let mut final_vars = pending.initial_vars;
-                for v in pending.vars_to_ask {
-                    final_vars.insert(v.key, v.value);
-                }
+        if confirmed_pending
+            && let Some(pending) = self.pending_execution.take()
+        {
+            // Merge initial vars with user inputs
+            let mut final_vars = pending.initial_vars;
+            for v in pending.vars_to_ask {
+                final_vars.insert(v.key, v.value);
+            }
                 
-                if let Some(wf_id) = pending.workflow_id {
-                    // Start Workflow
-                    if let Some(wf) = self.store.get_workflow(&wf_id) {
-                         if let Some(first_cmd_id) = wf.commands.first() {
-                             self.active_workflow = Some(ActiveWorkflow {
-                                 workflow_id: wf_id,
-                                 current_step_index: 0,
-                                 current_execution_id: None,
-                                 resolved_env: final_vars,
-                             });
-                             self.perform_execution(*first_cmd_id, None); // Workflow env vars are handled by active_workflow
-                         }
+            if let Some(wf_id) = pending.workflow_id {
+                // Start Workflow
+                if let Some(wf) = self.store.get_workflow(&wf_id)
+                    && let Some(first_cmd_id) = wf.commands.first()
+                {
+                    self.active_workflow = Some(ActiveWorkflow {
+                        workflow_id: wf_id,
+                        current_step_index: 0,
+                        current_execution_ids: Vec::new(),
+                        step_failed: false,
+                        resolved_env: final_vars,
+                        password: pending.password,
+                        started_at: chrono::Utc::now(),
+                        exit_code: 0,
+                    });
+                    self.perform_execution(*first_cmd_id, None, None); // Workflow env vars are handled by active_workflow
+                }
+            } else if let Some(cmd_id) = pending.cmd_id {
+                // Start Single Command
+                self.perform_execution(cmd_id, Some(final_vars), pending.password);
+            }
+        }
+
+        // Poll for connection test results
+        while let Ok((host_id, report)) = self.host_test_rx.try_recv() {
+            self.host_tests.insert(host_id, HostTestStatus::Done(report));
+            ctx.request_repaint();
+        }
+
+        while let Ok(results) = self.search_rx.try_recv() {
+            self.search_running = false;
+            self.search_results = Some(results);
+            ctx.request_repaint();
+        }
+
+        if self.daemon.is_some() {
+            if self.last_daemon_sync.elapsed() >= SYNC_INTERVAL {
+                self.sync_daemon();
+            }
+            ctx.request_repaint_after(SYNC_INTERVAL);
+        } else {
+            use switchboard_core::snapshots::SCHEDULE_CHECK_INTERVAL;
+
+            if self.last_snapshot_check.is_none_or(|t| t.elapsed() >= SCHEDULE_CHECK_INTERVAL) {
+                self.last_snapshot_check = Some(Instant::now());
+                if let Err(e) = self.store.snapshot_if_due() {
+                    eprintln!("Scheduled snapshot failed: {}", e);
+                }
+            }
+            ctx.request_repaint_after(SCHEDULE_CHECK_INTERVAL);
+        }
+
+        if self.last_library_check.elapsed() >= library::CHECK_INTERVAL {
+            self.last_library_check = Instant::now();
+            // Show what's in the files now if a library item is open
+            if self.store.reload_libraries() {
+                match self.active_selection {
+                    Some(Selection::Command(id)) if self.store.library_of(&id).is_some() => {
+                        self.edited_command = self.store.get_command(&id).map(|c| CommandEditState::from_command(&c));
                     }
-                } else if let Some(cmd_id) = pending.cmd_id {
-                    // Start Single Command
-                    self.perform_execution(cmd_id, Some(final_vars));
+                    Some(Selection::Workflow(id)) if self.store.library_of(&id).is_some() => {
+                        self.edited_workflow = self.store.get_workflow(&id).map(|w| WorkflowEditState::from_workflow(&w));
+                    }
+                    _ => {}
                 }
             }
         }
+        if !self.store.get_settings().libraries.is_empty() {
+            ctx.request_repaint_after(library::CHECK_INTERVAL);
+        }
 
         // Poll for execution updates
         while let Ok((exec_id, update)) = self.execution_rx.try_recv() {
@@ -768,32 +3040,44 @@ impl App for SwitchboardApp {
                         state.output_buffer.push_str(&text);
                         ctx.request_repaint();
                     }
+                    ExecutionUpdate::Detached(job) => {
+                        state.background = Some(job);
+
+                        // Persist the job now so it can be reattached if the app
+                        // closes; switchboardd does this for its own runs
+                        if self.daemon.is_none() {
+                            let result = state.to_result(None);
+                            self.store.add_execution(&result, &state.output_buffer);
+                        }
+
+                        // Background steps don't hold up the rest of a workflow
+                        self.check_workflow_progress(exec_id, 0);
+                        ctx.request_repaint();
+                    }
+                    // switchboardd has saved it already
+                    ExecutionUpdate::Artifact { name, .. } if self.daemon.is_some() => {
+                        state.artifacts.push(name);
+                        ctx.request_repaint();
+                    }
+                    ExecutionUpdate::Artifact { name, data } => {
+                        match self.store.add_artifact(&exec_id, &name, &data) {
+                            Ok(_) => state.artifacts.push(name),
+                            Err(e) => state.output_buffer.push_str(&format!("[artifacts] failed to save {}: {}\n", name, e)),
+                        }
+                        ctx.request_repaint();
+                    }
                     ExecutionUpdate::Exit(code) => {
                         state.is_running = false;
                         state.exit_code = Some(code);
                         state.kill_tx = None; // Clear kill channel
-                        
-                        state.is_running = false;
-                        state.exit_code = Some(code);
-                        state.kill_tx = None; // Clear kill channel
+                        self.search_outdated = true;
                         
                         // Save result
-                        let finished_at = chrono::Utc::now();
-                        let duration = finished_at.signed_duration_since(state.started_at).num_milliseconds() as u64;
-                        
-                        let result = switchboard_core::models::ExecutionResult {
-                            id: state.id,
-                            command_id: state._command_id,
-                            host_id: Uuid::nil(), // TODO: Track host ID if needed
-                            started_at: state.started_at,
-                            finished_at: Some(finished_at),
-                            exit_code: Some(code),
-                            duration_ms: Some(duration),
-                            status: if code == 0 { switchboard_core::models::ExecutionStatus::Completed } else { switchboard_core::models::ExecutionStatus::Failed },
-                            log_file: format!("{}.log.gz", state.id),
-                        };
-
-                        self.store.add_execution(&result, &state.output_buffer);
+                        if self.daemon.is_none() {
+                            let result = state.to_result(Some(code));
+                            self.store.add_execution(&result, &state.output_buffer);
+                            switchboard_core::notifications::run_finished(&self.store, &result, &state.output_buffer);
+                        }
                         
                         // Check workflow progress
                         self.check_workflow_progress(exec_id, code);
@@ -801,14 +3085,47 @@ impl App for SwitchboardApp {
                         ctx.request_repaint();
                     }
                 }
-            }
-        }
+            }
+        }
+
+        // Sidebar
+        egui::SidePanel::left("sidebar_panel")
+            .resizable(true)
+            .default_width(self.sidebar_width)
+            .show(ctx, |ui| {
+                let search_box = ui.add(
+                    egui::TextEdit::singleline(&mut self.search_query)
+                        .hint_text("🔍 Search commands and logs")
+                        .desired_width(f32::INFINITY),
+                );
+                if search_box.changed() {
+                    self.search_edited = Instant::now();
+                }
+                let query = self.search_query.trim().to_string();
+                if !query.is_empty() {
+                    self.refresh_search(ctx, &query);
+                    if self.search_running {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label(egui::RichText::new("Searching…").weak());
+                        });
+                    }
+                    if let Some(selection) = self.show_search_results(ui) {
+                        match selection {
+                            Selection::Command(id) => {
+                                if let Some(cmd) = self.store.get_command(&id) {
+                                    self.navigate_to(selection);
+                                    self.edited_command = Some(CommandEditState::from_command(&cmd));
+                                    self.edited_workflow = None;
+                                }
+                            }
+                            _ => self.navigate_to(selection),
+                        }
+                    }
+                    return;
+                }
+                ui.separator();
 
-        // Sidebar
-        egui::SidePanel::left("sidebar_panel")
-            .resizable(true)
-            .default_width(self.sidebar_width)
-            .show(ctx, |ui| {
                 // Top Half: Commands
                 ui.vertical(|ui| {
                     // Workflows Section
@@ -821,7 +3138,7 @@ impl App for SwitchboardApp {
                         });
                     });
                     let mut workflows = self.store.list_workflows();
-                    workflows.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+                    workflows.sort_by_key(|w| std::cmp::Reverse(w.created_at));
 
                     let mut ungrouped_wfs: Vec<&switchboard_core::models::Workflow> = Vec::new();
                     let mut wf_groups: BTreeMap<String, Vec<(&switchboard_core::models::Workflow, String)>> = BTreeMap::new();
@@ -873,12 +3190,12 @@ impl App for SwitchboardApp {
                         }
                     });
 
-                    if let Some(id) = wf_to_select {
-                        if let Some(wf) = self.store.get_workflow(&id) {
-                            self.navigate_to(Selection::Workflow(id));
-                            self.edited_workflow = Some(WorkflowEditState::from_workflow(&wf));
-                            self.edited_command = None;
-                        }
+                    if let Some(id) = wf_to_select
+                        && let Some(wf) = self.store.get_workflow(&id)
+                    {
+                        self.navigate_to(Selection::Workflow(id));
+                        self.edited_workflow = Some(WorkflowEditState::from_workflow(&wf));
+                        self.edited_command = None;
                     }
                     if let Some(id) = workflow_to_run_sidebar {
                         self.trigger_workflow_execution(id);
@@ -894,7 +3211,7 @@ impl App for SwitchboardApp {
                         });
                     });
                     let mut commands = self.store.list_commands();
-                    commands.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+                    commands.sort_by_key(|c| std::cmp::Reverse(c.created_at));
 
                     let mut ungrouped_cmds: Vec<&switchboard_core::models::Command> = Vec::new();
                     let mut cmd_groups: BTreeMap<String, Vec<(&switchboard_core::models::Command, String)>> = BTreeMap::new();
@@ -946,16 +3263,163 @@ impl App for SwitchboardApp {
                             }
                         });
 
-                    if let Some(id) = command_to_select {
-                        if let Some(cmd) = self.store.get_command(&id) {
-                            self.navigate_to(Selection::Command(id));
-                            self.edited_command = Some(CommandEditState::from_command(&cmd));
-                            self.edited_workflow = None;
-                        }
+                    if let Some(id) = command_to_select
+                        && let Some(cmd) = self.store.get_command(&id)
+                    {
+                        self.navigate_to(Selection::Command(id));
+                        self.edited_command = Some(CommandEditState::from_command(&cmd));
+                        self.edited_workflow = None;
                     }
                     if let Some(id) = command_to_run {
                         self.trigger_command_execution(id);
                     }
+
+                    let libraries = self.store.list_libraries();
+                    if !libraries.is_empty() {
+                        ui.separator();
+                        ui.label(egui::RichText::new("Library").strong());
+
+                        let mut library_item_to_select: Option<Selection> = None;
+                        let mut library_command_to_run: Option<Uuid> = None;
+                        let mut library_workflow_to_run: Option<Uuid> = None;
+                        egui::ScrollArea::vertical()
+                            .id_salt("sidebar_library_scroll")
+                            .max_height(150.0)
+                            .show(ui, |ui| {
+                                for library in &libraries {
+                                    let title = match &library.commit {
+                                        Some(commit) => format!("📚 {}  {}", library.source.name, short_commit(commit)),
+                                        None => format!("📚 {}", library.source.name),
+                                    };
+                                    egui::CollapsingHeader::new(title)
+                                        .id_salt(("sidebar_library", &library.source.path))
+                                        .default_open(true)
+                                        .show(ui, |ui| {
+                                            if let Some(e) = &library.error {
+                                                ui.label(egui::RichText::new(e).color(egui::Color32::from_rgb(255, 100, 100)));
+                                            }
+                                            for wf in &library.workflows {
+                                                ui.horizontal(|ui| {
+                                                    if ui.small_button("▶").clicked() {
+                                                        library_workflow_to_run = Some(wf.id);
+                                                    }
+                                                    let is_selected = matches!(self.active_selection, Some(Selection::Workflow(id)) if id == wf.id);
+                                                    if ui.selectable_label(is_selected, format!("⛓ {}", wf.name)).clicked() {
+                                                        library_item_to_select = Some(Selection::Workflow(wf.id));
+                                                    }
+                                                });
+                                            }
+                                            for cmd in &library.commands {
+                                                ui.horizontal(|ui| {
+                                                    if ui.small_button("▶").clicked() {
+                                                        library_command_to_run = Some(cmd.id);
+                                                    }
+                                                    let is_selected = matches!(self.active_selection, Some(Selection::Command(id)) if id == cmd.id);
+                                                    if ui.selectable_label(is_selected, &cmd.name).clicked() {
+                                                        library_item_to_select = Some(Selection::Command(cmd.id));
+                                                    }
+                                                });
+                                            }
+                                        })
+                                        .header_response
+                                        .on_hover_text(library.source.path.display().to_string());
+                                }
+                            });
+
+                        match library_item_to_select {
+                            Some(Selection::Command(id)) => {
+                                if let Some(cmd) = self.store.get_command(&id) {
+                                    self.navigate_to(Selection::Command(id));
+                                    self.edited_command = Some(CommandEditState::from_command(&cmd));
+                                    self.edited_workflow = None;
+                                }
+                            }
+                            Some(Selection::Workflow(id)) => {
+                                if let Some(wf) = self.store.get_workflow(&id) {
+                                    self.navigate_to(Selection::Workflow(id));
+                                    self.edited_workflow = Some(WorkflowEditState::from_workflow(&wf));
+                                    self.edited_command = None;
+                                }
+                            }
+                            _ => {}
+                        }
+                        if let Some(id) = library_command_to_run {
+                            self.trigger_command_execution(id);
+                        }
+                        if let Some(id) = library_workflow_to_run {
+                            self.trigger_workflow_execution(id);
+                        }
+                    }
+
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new("Hosts").strong());
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                             if ui.small_button("➕").clicked() {
+                                 self.create_new_host();
+                             }
+                        });
+                    });
+                    let mut hosts = self.store.list_hosts();
+                    hosts.sort_by(|a, b| a.name.cmp(&b.name));
+
+                    // A host shows up under each of its groups
+                    let mut ungrouped_hosts: Vec<&switchboard_core::Host> = Vec::new();
+                    let mut host_groups: BTreeMap<&str, Vec<&switchboard_core::Host>> = BTreeMap::new();
+                    for host in &hosts {
+                        if host.groups.is_empty() {
+                            ungrouped_hosts.push(host);
+                        }
+                        for group in &host.groups {
+                            host_groups.entry(group.as_str()).or_default().push(host);
+                        }
+                    }
+
+                    let mut host_to_select: Option<Uuid> = None;
+                    let render_host = |ui: &mut egui::Ui, host: &switchboard_core::Host, select: &mut Option<Uuid>| {
+                        ui.horizontal(|ui| {
+                            match self.host_tests.get(&host.id) {
+                                Some(HostTestStatus::Running) => { ui.add(egui::Spinner::new().size(12.0)); }
+                                Some(HostTestStatus::Done(report)) => match report.first_failure() {
+                                    None => { ui.label("🟢"); }
+                                    Some(e) => { ui.label("🔴").on_hover_text(e); }
+                                },
+                                None => { ui.label("⚪"); }
+                            }
+                            let is_selected = matches!(self.active_selection, Some(Selection::Host(id)) if id == host.id);
+                            let label = ui.selectable_label(is_selected, &host.name);
+                            let label = if host.tags.is_empty() { label } else { label.on_hover_text(host.tags.join(", ")) };
+                            if label.clicked() {
+                                *select = Some(host.id);
+                            }
+                        });
+                    };
+
+                    egui::ScrollArea::vertical()
+                        .id_salt("sidebar_hosts_scroll")
+                        .max_height(150.0)
+                        .show(ui, |ui| {
+                            for host in &ungrouped_hosts {
+                                render_host(ui, host, &mut host_to_select);
+                            }
+                            for (group_name, members) in &host_groups {
+                                egui::CollapsingHeader::new(*group_name)
+                                    .id_salt(("host_group", *group_name))
+                                    .default_open(true)
+                                    .show(ui, |ui| {
+                                        for host in members {
+                                            render_host(ui, host, &mut host_to_select);
+                                        }
+                                    });
+                            }
+                        });
+
+                    if let Some(id) = host_to_select
+                        && let Some(host) = self.store.get_host(&id)
+                    {
+                        self.navigate_to(Selection::Host(id));
+                        self.edited_host = Some(HostEditState::from_host(&host));
+                    }
                 });
 
 
@@ -1029,6 +3493,8 @@ impl App for SwitchboardApp {
         let mut jump_to_command = None;
         let mut need_save = false;
         let mut duplicate_cmd = false;
+        let mut fork_item = false;
+        let mut host_to_test = None;
 
         // Central Panel
         egui::CentralPanel::default().show(ctx, |ui| {
@@ -1037,15 +3503,17 @@ impl App for SwitchboardApp {
                 if ui.button("🏠 Home").clicked() {
                     self.save_current_command();
                     self.save_current_workflow();
+                    self.save_current_host();
                     self.navigation_history.clear();
                     self.active_selection = None;
                     self.edited_command = None;
                     self.edited_workflow = None;
+                    self.edited_host = None;
                 }
                 
                 // Show last 3 history items
                 let history_len = self.navigation_history.len();
-                let start_idx = if history_len > 3 { history_len - 3 } else { 0 };
+                let start_idx = history_len.saturating_sub(3);
                 
                 let mut jump_to_history_idx = None;
                 
@@ -1054,6 +3522,7 @@ impl App for SwitchboardApp {
                      let name = match selection {
                         Selection::Command(id) => self.store.get_command(id).map(|c| c.name).unwrap_or_else(|| "Command".into()),
                         Selection::Workflow(id) => self.store.get_workflow(id).map(|w| w.name).unwrap_or_else(|| "Workflow".into()),
+                        Selection::Host(id) => self.store.get_host(id).map(|h| h.name).unwrap_or_else(|| "Host".into()),
                         Selection::Execution(id) => self.executions.iter().find(|e| e.id == *id).map(|e| e.command_name.clone()).unwrap_or_else(|| "Execution".into()),
                      };
                      
@@ -1091,6 +3560,10 @@ impl App for SwitchboardApp {
                         Selection::Workflow(id) => {
                              let name = self.store.get_workflow(&id).map(|w| w.name).unwrap_or_else(|| "Unknown Workflow".into());
                              ui.label(egui::RichText::new(name).strong());
+                        }
+                        Selection::Host(id) => {
+                             let name = self.store.get_host(&id).map(|h| h.name).unwrap_or_else(|| "Unknown Host".into());
+                             ui.label(egui::RichText::new(name).strong());
                         }
                          Selection::Execution(id) => {
                             let name = self.executions.iter().find(|e| e.id == id).map(|e| e.command_name.clone()).unwrap_or_else(|| "Execution".into());
@@ -1100,29 +3573,38 @@ impl App for SwitchboardApp {
                 }
                 
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                    if !self.navigation_history.is_empty() {
-                         if ui.button("⬅ Back").clicked() {
-                             self.navigate_back();
-                         }
+                    if !self.navigation_history.is_empty()
+                        && ui.button("⬅ Back").clicked()
+                    {
+                        self.navigate_back();
                     }
                 });
             });
             ui.separator();
 
             match self.active_selection {
-                Some(Selection::Workflow(_wf_id)) => {
+                Some(Selection::Workflow(wf_id)) => {
+                    let library = self.store.library_of(&wf_id);
                     if let Some(edit_state) = &mut self.edited_workflow {
                          ui.horizontal(|ui| {
-                             ui.heading("Edit Workflow");
-                             if ui.button("▶ Run Workflow").clicked() {
-                                 if let Some(Selection::Workflow(id)) = self.active_selection {
-                                     workflow_to_run = Some(id);
-                                 }
+                             ui.heading(if library.is_some() { "Library Workflow" } else { "Edit Workflow" });
+                             if ui.button("▶ Run Workflow").clicked()
+                                 && let Some(Selection::Workflow(id)) = self.active_selection
+                             {
+                                 workflow_to_run = Some(id);
                              }
-                             if ui.button("🗑 Delete").clicked() {
+                             if library.is_some() {
+                                 if ui.button("🍴 Fork").on_hover_text("Copy into your workflows to change it").clicked() {
+                                     fork_item = true;
+                                 }
+                             } else if ui.button("🗑 Delete").clicked() {
                                  self.show_delete_confirmation = true;
                              }
                          });
+                         if let Some(library) = &library {
+                             library_banner(ui, library);
+                             ui.disable();
+                         }
                          ui.separator();
                          
                          ui.label("Name:");
@@ -1134,6 +3616,13 @@ impl App for SwitchboardApp {
                          if ui.text_edit_singleline(&mut edit_state.description).changed() {
                              need_save = true;
                          }
+
+                         ui.horizontal(|ui| {
+                             ui.label("Notify:");
+                             if notify_combo(ui, &mut edit_state.notify, "workflow_notify") {
+                                 need_save = true;
+                             }
+                         });
                          ui.separator();
                          
                          ui.collapsing("Environment Configuration (Overrides)", |ui| {
@@ -1141,6 +3630,9 @@ impl App for SwitchboardApp {
                             for (i, var) in edit_state.env_vars.iter_mut().enumerate() {
                                 ui.horizontal(|ui| {
                                     if ui.text_edit_singleline(&mut var.key).on_hover_text("Key").changed() { need_save = true; }
+                                    if !var.key.is_empty() && !switchboard_core::shell::is_env_key(&var.key) {
+                                        ui.label(egui::RichText::new("⚠").color(egui::Color32::from_rgb(255, 100, 100))).on_hover_text("Not a valid variable name: use letters, digits and underscores, not starting with a digit");
+                                    }
                                     ui.label("=");
                                     if ui.text_edit_singleline(&mut var.value).on_hover_text("Value").changed() { need_save = true; }
                                     if ui.checkbox(&mut var.ask_user, "Ask").on_hover_text("Ask user at runtime").changed() { need_save = true; }
@@ -1165,16 +3657,31 @@ impl App for SwitchboardApp {
                          ui.heading("Workflow Steps");
                          
                          // List current commands
-                         let all_commands = self.store.list_commands();
+                         let mut all_commands = self.store.list_commands();
+                         all_commands.extend(self.store.list_libraries().into_iter().flat_map(|l| l.commands));
                          
                          let mut to_remove_idx = None;
                          
                          for (idx, cmd_id) in edit_state.commands.iter().enumerate() {
                              if let Some(cmd) = all_commands.iter().find(|c| c.id == *cmd_id) {
+                                 let step_run_as = &mut edit_state.step_run_as[idx];
                                  ui.horizontal(|ui| {
-                                     if ui.small_button(format!("{}", cmd.name)).on_hover_text("Jump to Command").clicked() {
+                                     if ui.small_button(cmd.name.to_string()).on_hover_text("Jump to Command").clicked() {
                                          jump_to_command = Some(*cmd_id);
                                      }
+                                     let label = match (&step_run_as, &cmd.run_as) {
+                                         (Some(run_as), _) => format!("as {} (step)", run_as.user),
+                                         (None, Some(run_as)) => format!("as {}", run_as.user),
+                                         (None, None) => "Run as…".to_string(),
+                                     };
+                                     ui.menu_button(label, |ui| {
+                                         ui.label("Overrides the command's Run as for this step:");
+                                         egui::Grid::new(format!("step_run_as_{}", idx)).num_columns(2).show(ui, |ui| {
+                                             if run_as_rows(ui, step_run_as, &format!("step_run_as_{}", idx)) {
+                                                 need_save = true;
+                                             }
+                                         });
+                                     });
                                      if ui.small_button("❌").clicked() {
                                          to_remove_idx = Some(idx);
                                      }
@@ -1184,6 +3691,7 @@ impl App for SwitchboardApp {
                          
                          if let Some(idx) = to_remove_idx {
                              edit_state.commands.remove(idx);
+                             edit_state.step_run_as.remove(idx);
                              need_save = true;
                          }
                          
@@ -1193,38 +3701,58 @@ impl App for SwitchboardApp {
                                  for cmd in all_commands {
                                      if ui.selectable_label(false, &cmd.name).clicked() {
                                          edit_state.commands.push(cmd.id);
+                                         edit_state.step_run_as.push(None);
                                          need_save = true;
                                      }
                                  }
                              });
                     }
                 },
-                Some(Selection::Command(_cmd_id)) => {
+                Some(Selection::Command(cmd_id)) => {
 
                     // COMMAND EDITOR VIEW
+                    let all_hosts = self.store.list_hosts();
+                    let library = self.store.library_of(&cmd_id);
                     if let Some(edit_state) = &mut self.edited_command {
                         ui.horizontal(|ui| {
-                            ui.heading("Edit Command");
+                            ui.heading(if library.is_some() { "Library Command" } else { "Edit Command" });
                         });
                         
                         // Action menu bar
                         ui.horizontal(|ui| {
                             ui.spacing_mut().button_padding = egui::vec2(8.0, 4.0);
                             
-                            if ui.button("▶ Run").clicked() {
-                                if let Some(Selection::Command(id)) = self.active_selection {
-                                    command_to_run = Some(id);
-                                }
+                            if ui.button("▶ Run").clicked()
+                                && let Some(Selection::Command(id)) = self.active_selection
+                            {
+                                command_to_run = Some(id);
                             }
                             
+                            if library.is_some() {
+                                if ui.button("🍴 Fork").on_hover_text("Copy into your commands to change it").clicked() {
+                                    fork_item = true;
+                                }
+                                return;
+                            }
+
                             if ui.button("📋 Duplicate").clicked() {
                                 duplicate_cmd = true;
                             }
+
+                            if ui.button("🕘 History").clicked()
+                                && let Some(Selection::Command(id)) = self.active_selection
+                            {
+                                self.revision_view = Some(RevisionView { command_id: id, selected: None });
+                            }
                             
                             if ui.button("🗑 Delete").clicked() {
                                 self.show_delete_confirmation = true;
                             }
                         });
+                        if let Some(library) = &library {
+                            library_banner(ui, library);
+                            ui.disable();
+                        }
                         ui.separator();
 
                         egui::ScrollArea::vertical()
@@ -1245,16 +3773,40 @@ impl App for SwitchboardApp {
 
                                     ui.label("Execute:");
                                     ui.horizontal(|ui| {
-                                        if ui.checkbox(&mut edit_state.is_local, "Run Locally").changed() {
-                                            need_save = true;
-                                        }
+                                        use switchboard_core::HostKind;
+
+                                        let current = match &edit_state.target {
+                                            HostKind::Ssh if edit_state.is_local => "Local",
+                                            HostKind::Ssh => "SSH",
+                                            HostKind::Container(_) => "Container",
+                                            HostKind::Kubernetes(_) => "Kubernetes Pod",
+                                        };
+                                        egui::ComboBox::from_id_salt("command_target")
+                                            .selected_text(current)
+                                            .show_ui(ui, |ui| {
+                                                for option in ["Local", "SSH", "Container", "Kubernetes Pod"] {
+                                                    if ui.selectable_label(current == option, option).clicked() && current != option {
+                                                        edit_state.is_local = option == "Local";
+                                                        edit_state.target = match option {
+                                                            "Container" => HostKind::Container(Default::default()),
+                                                            "Kubernetes Pod" => HostKind::Kubernetes(Default::default()),
+                                                            _ => HostKind::Ssh,
+                                                        };
+                                                        need_save = true;
+                                                    }
+                                                }
+                                            });
                                         if ui.checkbox(&mut edit_state.background, "Run in background (nohup)").changed() {
                                             need_save = true;
                                         }
                                     });
                                     ui.end_row();
 
-                                    if !edit_state.is_local {
+                                    if edit_state.target != switchboard_core::HostKind::Ssh {
+                                        if host_kind_rows(ui, &mut edit_state.target, "command_container_runtime") {
+                                            need_save = true;
+                                        }
+                                    } else if !edit_state.is_local {
                                         ui.label("User:");
                                         if ui.text_edit_singleline(&mut edit_state.user).changed() {
                                             need_save = true;
@@ -1266,6 +3818,42 @@ impl App for SwitchboardApp {
                                             need_save = true;
                                         }
                                         ui.end_row();
+
+                                        ui.label("Targets:");
+                                        ui.horizontal(|ui| {
+                                            if ui.add(egui::TextEdit::singleline(&mut edit_state.host_selector).hint_text("role=web,env=staging")).changed() {
+                                                need_save = true;
+                                            }
+                                            ui.menu_button(format!("Hosts ({})", edit_state.target_hosts.len()), |ui| {
+                                                let mut hosts = all_hosts.clone();
+                                                hosts.sort_by(|a, b| a.name.cmp(&b.name));
+                                                if hosts.is_empty() {
+                                                    ui.label("No hosts yet");
+                                                }
+                                                for host in hosts {
+                                                    let mut picked = edit_state.target_hosts.contains(&host.id);
+                                                    if ui.checkbox(&mut picked, &host.name).changed() {
+                                                        edit_state.target_hosts.retain(|id| *id != host.id);
+                                                        if picked {
+                                                            edit_state.target_hosts.push(host.id);
+                                                        }
+                                                        need_save = true;
+                                                    }
+                                                }
+                                            });
+                                        });
+                                        ui.end_row();
+
+                                        let matched: Vec<&str> = all_hosts
+                                            .iter()
+                                            .filter(|h| edit_state.target_hosts.contains(&h.id) || h.matches_selector(&edit_state.host_selector))
+                                            .map(|h| h.name.as_str())
+                                            .collect();
+                                        if !matched.is_empty() {
+                                            ui.label("");
+                                            ui.label(egui::RichText::new(format!("Runs on {} (instead of Host)", matched.join(", "))).weak());
+                                            ui.end_row();
+                                        }
                                     }
                                     
                                     ui.label("Working Dir:");
@@ -1273,6 +3861,16 @@ impl App for SwitchboardApp {
                                         need_save = true;
                                     }
                                     ui.end_row();
+
+                                    if run_as_rows(ui, &mut edit_state.run_as, "command_run_as") {
+                                        need_save = true;
+                                    }
+
+                                    ui.label("Notify:");
+                                    if notify_combo(ui, &mut edit_state.notify, "command_notify") {
+                                        need_save = true;
+                                    }
+                                    ui.end_row();
                                 });
                                 
                                 ui.separator();
@@ -1281,6 +3879,9 @@ impl App for SwitchboardApp {
                                     for (i, var) in edit_state.env_vars.iter_mut().enumerate() {
                                         ui.horizontal(|ui| {
                                             if ui.text_edit_singleline(&mut var.key).on_hover_text("Key").changed() { need_save = true; }
+                                            if !var.key.is_empty() && !switchboard_core::shell::is_env_key(&var.key) {
+                                                ui.label(egui::RichText::new("⚠").color(egui::Color32::from_rgb(255, 100, 100))).on_hover_text("Not a valid variable name: use letters, digits and underscores, not starting with a digit");
+                                            }
                                             ui.label("=");
                                             if ui.text_edit_singleline(&mut var.value).on_hover_text("Value").changed() { need_save = true; }
                                             if ui.checkbox(&mut var.ask_user, "Ask").on_hover_text("Ask user at runtime").changed() { need_save = true; }
@@ -1301,6 +3902,57 @@ impl App for SwitchboardApp {
                                     }
                                 });
                                 
+                                ui.collapsing("File Transfers", |ui| {
+                                    ui.label("Upload before running (local → host):");
+                                    let mut remove_idx = None;
+                                    for (i, transfer) in edit_state.uploads.iter_mut().enumerate() {
+                                        ui.horizontal(|ui| {
+                                            if ui.text_edit_singleline(&mut transfer.local_path).on_hover_text("Local file or directory").changed() { need_save = true; }
+                                            if ui.small_button("📄").on_hover_text("Pick file").clicked()
+                                                && let Some(path) = rfd::FileDialog::new().pick_file()
+                                            {
+                                                transfer.local_path = path.display().to_string();
+                                                need_save = true;
+                                            }
+                                            if ui.small_button("📁").on_hover_text("Pick directory").clicked()
+                                                && let Some(path) = rfd::FileDialog::new().pick_folder()
+                                            {
+                                                transfer.local_path = path.display().to_string();
+                                                need_save = true;
+                                            }
+                                            ui.label("→");
+                                            if ui.text_edit_singleline(&mut transfer.remote_path).on_hover_text("Host path (relative to working dir)").changed() { need_save = true; }
+                                            if ui.button("❌").clicked() { remove_idx = Some(i); }
+                                        });
+                                    }
+                                    if let Some(i) = remove_idx {
+                                        edit_state.uploads.remove(i);
+                                        need_save = true;
+                                    }
+                                    if ui.button("➕ Add Upload").clicked() {
+                                        edit_state.uploads.push(Default::default());
+                                        need_save = true;
+                                    }
+
+                                    ui.add_space(6.0);
+                                    ui.label("Fetch artifacts after running (host paths):");
+                                    let mut remove_idx = None;
+                                    for (i, path) in edit_state.artifacts.iter_mut().enumerate() {
+                                        ui.horizontal(|ui| {
+                                            if ui.text_edit_singleline(path).on_hover_text("Host file or directory (relative to working dir)").changed() { need_save = true; }
+                                            if ui.button("❌").clicked() { remove_idx = Some(i); }
+                                        });
+                                    }
+                                    if let Some(i) = remove_idx {
+                                        edit_state.artifacts.remove(i);
+                                        need_save = true;
+                                    }
+                                    if ui.button("➕ Add Artifact").clicked() {
+                                        edit_state.artifacts.push(String::new());
+                                        need_save = true;
+                                    }
+                                });
+
                                 ui.separator();
                                 ui.label("Script (Bash):");
                                 
@@ -1321,13 +3973,13 @@ impl App for SwitchboardApp {
                 },
                 Some(Selection::Execution(exec_id)) => {
                     // Load logs if needed
-                    if let Some(state) = self.executions.iter_mut().find(|e| e.id == exec_id) {
-                        if !state.output_loaded && !state.is_running {
-                             if let Some(logs) = self.store.get_execution_log(&exec_id) {
-                                 state.output_buffer = logs;
-                                 state.output_loaded = true;
-                             }
-                        }
+                    if let Some(state) = self.executions.iter_mut().find(|e| e.id == exec_id)
+                        && !state.output_loaded && !state.is_running
+                        && let Some(logs) = self.store.get_execution_log(&exec_id)
+                    {
+                        state.output_buffer = logs;
+                        state.output_loaded = true;
+                        state.artifacts = self.store.list_artifacts(&exec_id);
                     }
 
                     // EXECUTION OUTPUT VIEW
@@ -1339,6 +3991,70 @@ impl App for SwitchboardApp {
                             if ui.small_button("📋 Copy ID").on_hover_text(exec_id.to_string()).clicked() {
                                 ui.output_mut(|o| o.commands.push(egui::OutputCommand::CopyText(exec_id.to_string())));
                             }
+                            let revision = state
+                                .revision
+                                .or_else(|| self.store.get_execution(&exec_id).and_then(|e| e.revision))
+                                .and_then(|id| self.store.get_revision(&id));
+                            if let Some(revision) = revision
+                                && ui.small_button(format!("🕘 r{}", revision.number)).on_hover_text("The command revision that ran").clicked()
+                            {
+                                self.revision_view = Some(RevisionView { command_id: revision.command_id, selected: Some(revision.id) });
+                            }
+                            let library_commit = state
+                                .library_commit
+                                .clone()
+                                .or_else(|| self.store.get_execution(&exec_id).and_then(|e| e.library_commit));
+                            if let Some(commit) = library_commit {
+                                ui.label(egui::RichText::new(format!("📚 {}", short_commit(&commit))).monospace().weak())
+                                    .on_hover_text(format!("Library commit {}", commit));
+                            }
+                            if !state.is_running && let Some(this_run) = self.store.get_execution(&exec_id) {
+                                let mut others = self.store.get_execution_history(&state._command_id);
+                                others.retain(|e| e.id != exec_id && e.finished_at.is_some());
+                                others.sort_by_key(|e| std::cmp::Reverse(e.started_at));
+                                let last_success = others
+                                    .iter()
+                                    .find(|e| e.exit_code == Some(0) && e.started_at < this_run.started_at)
+                                    .map(|e| e.id);
+                                ui.add_enabled_ui(!others.is_empty(), |ui| {
+                                    ui.menu_button("⇄ Compare", |ui| {
+                                        let mut compare_with = None;
+                                        if let Some(id) = last_success
+                                            && ui.button("With the last successful run before this one").clicked()
+                                        {
+                                            compare_with = Some(id);
+                                        }
+                                        ui.separator();
+                                        egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                                            for other in others.iter().take(50) {
+                                                let status = match other.exit_code {
+                                                    Some(0) => "✅",
+                                                    Some(_) => "❌",
+                                                    None => "⏹",
+                                                };
+                                                let time = other.started_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M");
+                                                if ui.button(format!("{} {}", status, time)).clicked() {
+                                                    compare_with = Some(other.id);
+                                                }
+                                            }
+                                        });
+                                        if let Some(other) = compare_with.and_then(|id| self.store.get_execution(&id)) {
+                                            let (old, new) = if other.started_at < this_run.started_at { (other.id, exec_id) } else { (exec_id, other.id) };
+                                            self.run_compare = Some(RunCompare {
+                                                old,
+                                                new,
+                                                normalize: true,
+                                                changes_only: true,
+                                                comparison: None,
+                                                outdated: true,
+                                                computing: None,
+                                                normalizers: self.store.get_settings().compare.normalizers,
+                                            });
+                                            ui.close();
+                                        }
+                                    });
+                                });
+                            }
                             ui.add_space(6.0);
 
                             if state.is_running {
@@ -1346,28 +4062,51 @@ impl App for SwitchboardApp {
                                 ui.label("Running");
                                 
                                 // Kill button
-                                if ui.button("⏹ Kill").clicked() {
-                                    if let Some(kill_tx) = &state.kill_tx {
-                                        let _ = kill_tx.send(());
-                                    }
+                                let kill_label = if state.background.is_some() { "⏹ Stop" } else { "⏹ Kill" };
+                                if ui.button(kill_label).clicked()
+                                    && let Some(kill_tx) = &state.kill_tx
+                                {
+                                    let _ = kill_tx.send(());
                                 }
                             } else if let Some(code) = state.exit_code {
                                 if code == 0 {
                                     ui.label(egui::RichText::new("✅ Success").color(egui::Color32::from_rgb(100, 200, 100)));
                                     
-                                    if state.is_local {
-                                        if ui.button("📂 Open Directory").clicked() {
-                                            let dir = state.working_directory.clone().unwrap_or_else(|| ".".to_string());
-                                            let _ = std::process::Command::new("open")
-                                                .arg(dir)
-                                                .spawn();
-                                        }
+                                    if state.is_local
+                                        && ui.button("📂 Open Directory").clicked()
+                                    {
+                                        let dir = state.working_directory.clone().unwrap_or_else(|| ".".to_string());
+                                        let _ = std::process::Command::new("open")
+                                            .arg(dir)
+                                            .spawn();
                                     }
                                 } else {
                                     ui.label(egui::RichText::new(format!("❌ Exit Code: {}", code)).color(egui::Color32::from_rgb(255, 100, 100)));
                                 }
                             }
+
+                            if let Some(job) = &state.background {
+                                ui.add_space(10.0);
+                                ui.label(egui::RichText::new(format!("PID {} · {}", job.pid, job.log_path)).weak());
+                            }
                         });
+
+                        if !state.artifacts.is_empty() {
+                            ui.horizontal(|ui| {
+                                egui::CollapsingHeader::new(format!("Artifacts ({})", state.artifacts.len()))
+                                    .id_salt("execution_artifacts")
+                                    .show(ui, |ui| {
+                                        for name in &state.artifacts {
+                                            ui.monospace(name);
+                                        }
+                                    });
+                                if ui.small_button("📂 Open Artifacts").clicked() {
+                                    let _ = std::process::Command::new("open")
+                                        .arg(self.store.artifacts_dir(&exec_id))
+                                        .spawn();
+                                }
+                            });
+                        }
                         ui.separator();
                         
                         egui::Frame::new()
@@ -1396,6 +4135,200 @@ impl App for SwitchboardApp {
                         ui.label("Execution not found");
                     }
                 },
+                Some(Selection::Host(host_id)) => {
+                    if let Some(edit_state) = &mut self.edited_host {
+                        use switchboard_core::HostKind;
+
+                        ui.horizontal(|ui| {
+                            ui.heading("Edit Host");
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.spacing_mut().button_padding = egui::vec2(8.0, 4.0);
+
+                            let testing = matches!(self.host_tests.get(&host_id), Some(HostTestStatus::Running));
+                            if ui.add_enabled(!testing, egui::Button::new("🔌 Test Connection")).clicked() {
+                                host_to_test = Some(host_id);
+                            }
+
+                            if ui.button("🗑 Delete").clicked() {
+                                self.show_delete_confirmation = true;
+                            }
+
+                            match self.host_tests.get(&host_id) {
+                                Some(HostTestStatus::Running) => { ui.add(egui::Spinner::new()); }
+                                Some(HostTestStatus::Done(report)) if report.succeeded() => {
+                                    ui.label(egui::RichText::new("✅ Reachable").color(egui::Color32::from_rgb(100, 200, 100)));
+                                }
+                                Some(HostTestStatus::Done(_)) => {
+                                    ui.label(egui::RichText::new("❌ Unreachable").color(egui::Color32::from_rgb(255, 100, 100)));
+                                }
+                                None => {}
+                            }
+                        });
+                        ui.separator();
+
+                        egui::Grid::new("host_grid").num_columns(2).spacing([10.0, 10.0]).show(ui, |ui| {
+                            ui.label("Name:");
+                            if ui.text_edit_singleline(&mut edit_state.name).changed() {
+                                need_save = true;
+                            }
+                            ui.end_row();
+
+                            ui.label("Type:");
+                            let current = match &edit_state.kind {
+                                HostKind::Ssh => "SSH",
+                                HostKind::Container(_) => "Container",
+                                HostKind::Kubernetes(_) => "Kubernetes Pod",
+                            };
+                            egui::ComboBox::from_id_salt("host_kind")
+                                .selected_text(current)
+                                .show_ui(ui, |ui| {
+                                    for option in ["SSH", "Container", "Kubernetes Pod"] {
+                                        if ui.selectable_label(current == option, option).clicked() && current != option {
+                                            edit_state.kind = match option {
+                                                "Container" => HostKind::Container(Default::default()),
+                                                "Kubernetes Pod" => HostKind::Kubernetes(Default::default()),
+                                                _ => HostKind::Ssh,
+                                            };
+                                            need_save = true;
+                                        }
+                                    }
+                                });
+                            ui.end_row();
+
+                            if edit_state.kind == HostKind::Ssh {
+                                ui.label("Hostname:");
+                                if ui.text_edit_singleline(&mut edit_state.hostname).changed() {
+                                    need_save = true;
+                                }
+                                ui.end_row();
+
+                                ui.label("Port:");
+                                if ui.add(egui::TextEdit::singleline(&mut edit_state.port).desired_width(60.0)).changed() {
+                                    need_save = true;
+                                }
+                                ui.end_row();
+
+                                ui.label("User:");
+                                if ui.text_edit_singleline(&mut edit_state.username).changed() {
+                                    need_save = true;
+                                }
+                                ui.end_row();
+
+                                ui.label("Auth:");
+                                ui.horizontal(|ui| {
+                                    for (choice, label) in [
+                                        (AuthChoice::Agent, "SSH Agent"),
+                                        (AuthChoice::KeyFile, "Key File"),
+                                        (AuthChoice::Password, "Password"),
+                                    ] {
+                                        if ui.radio_value(&mut edit_state.auth, choice, label).changed() {
+                                            need_save = true;
+                                        }
+                                    }
+                                });
+                                ui.end_row();
+
+                                match edit_state.auth {
+                                    AuthChoice::Agent => {}
+                                    AuthChoice::KeyFile => {
+                                        ui.label("Key File:");
+                                        ui.horizontal(|ui| {
+                                            if ui.text_edit_singleline(&mut edit_state.key_file).changed() {
+                                                need_save = true;
+                                            }
+                                            if ui.small_button("📄").on_hover_text("Pick key file").clicked()
+                                                && let Some(path) = rfd::FileDialog::new().pick_file()
+                                            {
+                                                edit_state.key_file = path.display().to_string();
+                                                need_save = true;
+                                            }
+                                        });
+                                        ui.end_row();
+                                    }
+                                    AuthChoice::Password => {
+                                        ui.label("Password:");
+                                        if ui.add(egui::TextEdit::singleline(&mut edit_state.password).password(true)).changed() {
+                                            need_save = true;
+                                        }
+                                        ui.end_row();
+                                    }
+                                }
+                            } else if host_kind_rows(ui, &mut edit_state.kind, "host_container_runtime") {
+                                need_save = true;
+                            }
+
+                            ui.label("Workspace:");
+                            if ui.add(egui::TextEdit::singleline(&mut edit_state.workspace_dir).hint_text(switchboard_core::RemoteWorkspace::default_dir())).on_hover_text("Directory on the host for run scripts and logs; each run gets a private subdirectory").changed() {
+                                need_save = true;
+                            }
+                            ui.end_row();
+
+                            ui.label("Old runs:");
+                            ui.horizontal(|ui| {
+                                use switchboard_core::LogCleanup;
+                                let selected = match edit_state.log_cleanup {
+                                    LogCleanup::KeepAll => "Keep",
+                                    LogCleanup::DeleteAfterRun => "Delete after each run",
+                                    LogCleanup::OlderThanDays(_) => "Delete after",
+                                };
+                                egui::ComboBox::from_id_salt("host_log_cleanup").selected_text(selected).show_ui(ui, |ui| {
+                                    if ui.selectable_value(&mut edit_state.log_cleanup, LogCleanup::KeepAll, "Keep").changed() {
+                                        need_save = true;
+                                    }
+                                    if ui.selectable_value(&mut edit_state.log_cleanup, LogCleanup::DeleteAfterRun, "Delete after each run").changed() {
+                                        need_save = true;
+                                    }
+                                    let by_age = matches!(edit_state.log_cleanup, LogCleanup::OlderThanDays(_));
+                                    if ui.selectable_label(by_age, "Delete after").clicked() && !by_age {
+                                        edit_state.log_cleanup = LogCleanup::default();
+                                        need_save = true;
+                                    }
+                                });
+                                if let LogCleanup::OlderThanDays(days) = &mut edit_state.log_cleanup
+                                    && ui.add(egui::DragValue::new(days).range(1..=365).suffix(" days")).changed()
+                                {
+                                    need_save = true;
+                                }
+                            });
+                            ui.end_row();
+
+                            ui.label("Groups:");
+                            if ui.add(egui::TextEdit::singleline(&mut edit_state.groups).hint_text("webservers, eu")).changed() {
+                                need_save = true;
+                            }
+                            ui.end_row();
+
+                            ui.label("Tags:");
+                            if ui.add(egui::TextEdit::singleline(&mut edit_state.tags).hint_text("role=web, env=staging")).changed() {
+                                need_save = true;
+                            }
+                            ui.end_row();
+                        });
+
+                        if let Some(HostTestStatus::Done(report)) = self.host_tests.get(&host_id) {
+                            ui.add_space(10.0);
+                            ui.separator();
+                            ui.label(egui::RichText::new("Diagnostics").strong());
+                            egui::Grid::new("host_diagnostics_grid").num_columns(3).spacing([10.0, 6.0]).striped(true).show(ui, |ui| {
+                                for check in &report.checks {
+                                    let (icon, color) = match check.status {
+                                        CheckStatus::Ok => ("✅", egui::Color32::from_rgb(100, 200, 100)),
+                                        CheckStatus::Warning => ("⚠", egui::Color32::from_rgb(230, 180, 60)),
+                                        CheckStatus::Failed => ("❌", egui::Color32::from_rgb(255, 100, 100)),
+                                    };
+                                    ui.label(egui::RichText::new(icon).color(color));
+                                    ui.label(&check.name);
+                                    ui.add(egui::Label::new(egui::RichText::new(&check.detail).monospace()).wrap());
+                                    ui.end_row();
+                                }
+                            });
+                        }
+                    } else {
+                        ui.label("Host not found");
+                    }
+                },
                 None => {
                     ui.centered_and_justified(|ui| {
                         ui.label("Select a command to edit, or a run to view output.");
@@ -1407,20 +4340,43 @@ impl App for SwitchboardApp {
         if need_save {
             self.save_current_command();
             self.save_current_workflow();
+            self.save_current_host();
+        }
+
+        if let Some(id) = host_to_test {
+            self.save_current_host();
+            self.test_host(id);
         }
 
-        if duplicate_cmd {
-            if let Some(Selection::Command(cmd_id)) = self.active_selection {
-                if let Some(cmd) = self.store.get_command(&cmd_id) {
-                    let new_id = Uuid::new_v4();
-                    let mut new_cmd = cmd.clone();
-                    new_cmd.id = new_id;
-                    new_cmd.name = format!("{} (Copy)", cmd.name);
-                    new_cmd.created_at = chrono::Utc::now();
-                    save_command(&self.store, &new_cmd);
-                    self.active_selection = Some(Selection::Command(new_id));
-                    self.edited_command = Some(CommandEditState::from_command(&new_cmd));
+        if duplicate_cmd
+            && let Some(Selection::Command(cmd_id)) = self.active_selection
+            && let Some(cmd) = self.store.get_command(&cmd_id)
+        {
+            let new_id = Uuid::new_v4();
+            let mut new_cmd = cmd.clone();
+            new_cmd.id = new_id;
+            new_cmd.name = format!("{} (Copy)", cmd.name);
+            new_cmd.created_at = chrono::Utc::now();
+            save_command(&self.store, &new_cmd);
+            self.active_selection = Some(Selection::Command(new_id));
+            self.edited_command = Some(CommandEditState::from_command(&new_cmd));
+        }
+
+        if fork_item {
+            match self.active_selection {
+                Some(Selection::Command(id)) => {
+                    if let Some(fork) = self.store.fork_command(&id) {
+                        self.navigate_to(Selection::Command(fork.id));
+                        self.edited_command = Some(CommandEditState::from_command(&fork));
+                    }
                 }
+                Some(Selection::Workflow(id)) => {
+                    if let Some(fork) = self.store.fork_workflow(&id) {
+                        self.navigate_to(Selection::Workflow(fork.id));
+                        self.edited_workflow = Some(WorkflowEditState::from_workflow(&fork));
+                    }
+                }
+                _ => {}
             }
         }
 
@@ -1432,11 +4388,11 @@ impl App for SwitchboardApp {
             self.trigger_workflow_execution(id);
         }
         
-        if let Some(cmd_id) = jump_to_command {
-            if let Some(cmd) = self.store.get_command(&cmd_id) {
-                 self.active_selection = Some(Selection::Command(cmd_id));
-                 self.edited_command = Some(CommandEditState::from_command(&cmd));
-            }
+        if let Some(cmd_id) = jump_to_command
+            && let Some(cmd) = self.store.get_command(&cmd_id)
+        {
+            self.active_selection = Some(Selection::Command(cmd_id));
+            self.edited_command = Some(CommandEditState::from_command(&cmd));
         }
     }
 }